@@ -0,0 +1,181 @@
+//! Runs the performance cases with `cargo bench --bench performance`.
+//!
+//! Every case must still produce its `.out`; the time spent between its
+//! `starttime()` and `stoptime()` calls is compared with the baseline left by
+//! the previous run, and a case more than `SYSY_BENCH_THRESHOLD` (0.2 by
+//! default) slower fails. The baseline is only replaced when nothing failed,
+//! or when `SYSY_BLESS=1` accepts the slowdowns.
+extern crate libtest_mimic;
+
+use inkwell::context::Context;
+use itertools::Itertools;
+use libtest_mimic::{run_tests, Arguments, Outcome, Test};
+use serde::{Deserialize, Serialize};
+use sysy::{
+  codegen::{passes::Pipeline, Generator},
+  difftest::run_executable_with_timeout,
+  emit::{emit_module, EmitKind},
+  parser::parse,
+  runtime::parse_timing_report,
+  util::{get_temp_path, OptLevel},
+};
+
+use std::{
+  collections::BTreeMap,
+  env,
+  ffi::OsStr,
+  fs,
+  path::{Path, PathBuf},
+  sync::{mpsc, Arc, Mutex},
+  time::Duration,
+};
+
+/// Performance cases are built the way the contest builds them.
+const OPT_LEVEL: OptLevel = OptLevel::O2;
+
+/// The bigger cases run for tens of seconds even when optimized.
+const RUN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Slowdowns below this are timer noise, whatever the ratio.
+const MIN_SLOWDOWN: Duration = Duration::from_millis(5);
+
+/// Timings of the last accepted run, in microseconds by case name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Baseline {
+  cases: BTreeMap<String, u64>,
+}
+
+fn baseline_path() -> PathBuf {
+  env::var_os("SYSY_BASELINE")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("target/performance-baseline.json"))
+}
+
+fn threshold() -> f64 {
+  env::var("SYSY_BENCH_THRESHOLD")
+    .ok()
+    .and_then(|threshold| threshold.parse().ok())
+    .unwrap_or(0.2)
+}
+
+fn main() {
+  let mut args = Arguments::from_args();
+  // cases running side by side would skew the timings against the baseline
+  args.test_threads = Some(1);
+
+  let baseline: Baseline = fs::read_to_string(baseline_path())
+    .ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default();
+  let baseline = Arc::new(baseline);
+  let timings = Arc::new(Mutex::new(BTreeMap::new()));
+  let conclusion = {
+    let timings = timings.clone();
+    run_tests(&args, collect_tests(), move |test| {
+      run_test(test, &baseline, &timings)
+    })
+  };
+  if !conclusion.has_failed() || env::var_os("SYSY_BLESS").is_some() {
+    let cases = std::mem::take(&mut *timings.lock().unwrap());
+    if !cases.is_empty() {
+      let json = serde_json::to_string_pretty(&Baseline { cases }).unwrap();
+      fs::write(baseline_path(), json + "\n").expect("can not write the baseline");
+    }
+  }
+  conclusion.exit();
+}
+
+fn collect_tests() -> Vec<Test<PathBuf>> {
+  let mut dir = env::current_dir().expect("invalid working directory");
+  dir.extend(["compiler2022", "runtime", "performance"]);
+  // the cases are not shipped with the repository
+  let entries = match fs::read_dir(&dir) {
+    Ok(entries) => entries,
+    Err(_) => return Vec::new(),
+  };
+  entries
+    .map(|entry| entry.expect("unexpected IO error").path())
+    .filter(|path| path.extension() == Some(OsStr::new("sy")))
+    .sorted()
+    .map(|path| Test {
+      name: path.file_stem().unwrap().to_string_lossy().to_string(),
+      kind: "performance".into(),
+      is_ignored: false,
+      is_bench: true,
+      data: path,
+    })
+    .collect()
+}
+
+fn run_test(
+  test: &Test<PathBuf>,
+  baseline: &Baseline,
+  timings: &Mutex<BTreeMap<String, u64>>,
+) -> Outcome {
+  let (rx, tx) = mpsc::channel();
+  let path = test.data.clone();
+  let handle = std::thread::Builder::new()
+    .stack_size(16 * 1024 * 1024)
+    .spawn(move || {
+      rx.send(run_case(&path)).unwrap();
+    });
+  handle.unwrap().join().unwrap();
+  let elapsed = match tx.recv().unwrap() {
+    Ok(elapsed) => elapsed,
+    Err(msg) => return Outcome::Failed { msg: Some(msg) },
+  };
+  let micros = elapsed.as_micros() as u64;
+  timings.lock().unwrap().insert(test.name.clone(), micros);
+  if let Some(&previous) = baseline.cases.get(&test.name) {
+    let previous = Duration::from_micros(previous);
+    if elapsed > previous.mul_f64(1.0 + threshold()) && elapsed - previous > MIN_SLOWDOWN {
+      return Outcome::Failed {
+        msg: Some(format!(
+          "took {elapsed:?}, {:.0}% slower than the baseline {previous:?}",
+          (elapsed.as_secs_f64() / previous.as_secs_f64() - 1.0) * 100.0
+        )),
+      };
+    }
+  }
+  Outcome::Measured {
+    avg: elapsed.as_nanos() as u64,
+    variance: 0,
+  }
+}
+
+/// Build and run one case, returning the time its timers measured.
+fn run_case(path: &Path) -> Result<Duration, String> {
+  let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  let tree = parse(&content).map_err(|e| format!("{e:?}"))?;
+  let name = path.to_string_lossy();
+  let ctx = Context::create();
+  let mut gen = Generator::new(&ctx, &name, &content);
+  gen.set_pipeline(Pipeline::for_level(OPT_LEVEL));
+  gen.gen(&tree).map_err(|e| format!("{e:?}"))?;
+  let exe_path = get_temp_path(path, "exe");
+  emit_module(&gen, EmitKind::Exe, &exe_path, OPT_LEVEL).map_err(|e| format!("{e:?}"))?;
+  let input = fs::read(path.with_extension("in")).unwrap_or_default();
+  let output = run_executable_with_timeout(&exe_path, &input, RUN_TIMEOUT);
+  fs::remove_file(&exe_path).ok();
+  let output = output.map_err(|e| format!("{e:?}"))?;
+
+  let expected = fs::read_to_string(path.with_extension("out")).map_err(|e| e.to_string())?;
+  let actual = format!(
+    "{}\n{}",
+    String::from_utf8_lossy(&output.stdout).trim(),
+    output.exit_code
+  );
+  let normalize = |text: &str| text.lines().map(|l| l.trim()).join("\n").trim().to_string();
+  if normalize(&expected) != normalize(&actual) {
+    return Err(format!(
+      "wrong output\nexpected:\n{}\nactual:\n{}",
+      normalize(&expected),
+      normalize(&actual)
+    ));
+  }
+  let timers = parse_timing_report(&output.timing);
+  if timers.is_empty() {
+    return Err(format!("no timer lines on stderr:\n{}", output.timing));
+  }
+  Ok(timers.iter().map(|timer| timer.elapsed).sum())
+}
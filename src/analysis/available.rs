@@ -0,0 +1,69 @@
+use super::{Analysis, BitSet, Cfg, Direction, FlowGraph, Universe};
+
+/// Expressions computed on every path and not killed since.
+pub struct AvailableExpressions<F: FlowGraph> {
+  pub exprs: Universe<F::Expr>,
+  // computed in the block and not killed after
+  gen: Vec<BitSet>,
+  // killed in the block
+  kill: Vec<BitSet>,
+}
+
+impl<F: FlowGraph> AvailableExpressions<F> {
+  pub fn new(cfg: &Cfg<F>) -> Self {
+    let func = cfg.func;
+    let mut exprs = Universe::default();
+    for &inst in cfg.insts.iter().flatten() {
+      if let Some(expr) = func.expression(inst) {
+        exprs.insert(expr);
+      }
+    }
+    let all = exprs
+      .items(&BitSet::full(exprs.len()))
+      .cloned()
+      .collect::<Vec<_>>();
+    let mut gen = vec![BitSet::new(exprs.len()); cfg.len()];
+    let mut kill = gen.clone();
+    for (block, insts) in cfg.insts.iter().enumerate() {
+      for &inst in insts {
+        if func.may_kill(inst) {
+          for (index, expr) in all.iter().enumerate() {
+            if func.kills(inst, expr) {
+              kill[block].insert(index);
+              gen[block].remove(index);
+            }
+          }
+        }
+        if let Some(expr) = func.expression(inst) {
+          gen[block].insert(exprs.insert(expr));
+        }
+      }
+    }
+    Self { exprs, gen, kill }
+  }
+}
+
+impl<F: FlowGraph> Analysis<F> for AvailableExpressions<F> {
+  type Fact = BitSet;
+  const DIRECTION: Direction = Direction::Forward;
+
+  fn boundary(&self, _cfg: &Cfg<F>) -> BitSet {
+    BitSet::new(self.exprs.len())
+  }
+
+  /// Every expression of the function.
+  fn top(&self, _cfg: &Cfg<F>) -> BitSet {
+    BitSet::full(self.exprs.len())
+  }
+
+  fn meet(&self, fact: &mut BitSet, other: &BitSet) {
+    fact.intersect_with(other);
+  }
+
+  fn transfer(&self, _cfg: &Cfg<F>, block: usize, fact: &BitSet) -> BitSet {
+    let mut available = fact.clone();
+    available.subtract(&self.kill[block]);
+    available.union_with(&self.gen[block]);
+    available
+  }
+}
@@ -0,0 +1,113 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// A set of indices below a fixed length, the facts of gen/kill analyses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitSet {
+  words: Vec<u64>,
+  len: usize,
+}
+
+impl BitSet {
+  pub fn new(len: usize) -> Self {
+    Self {
+      words: vec![0; len.div_ceil(64)],
+      len,
+    }
+  }
+
+  /// Every index below `len`.
+  pub fn full(len: usize) -> Self {
+    let mut set = Self {
+      words: vec![u64::MAX; len.div_ceil(64)],
+      len,
+    };
+    let rest = len % 64;
+    if rest != 0 {
+      *set.words.last_mut().unwrap() = (1 << rest) - 1;
+    }
+    set
+  }
+
+  pub fn contains(&self, index: usize) -> bool {
+    index < self.len && self.words[index / 64] & (1 << (index % 64)) != 0
+  }
+
+  pub fn insert(&mut self, index: usize) {
+    self.words[index / 64] |= 1 << (index % 64);
+  }
+
+  pub fn remove(&mut self, index: usize) {
+    self.words[index / 64] &= !(1 << (index % 64));
+  }
+
+  pub fn union_with(&mut self, other: &BitSet) {
+    for (word, other) in self.words.iter_mut().zip(&other.words) {
+      *word |= other;
+    }
+  }
+
+  pub fn intersect_with(&mut self, other: &BitSet) {
+    for (word, other) in self.words.iter_mut().zip(&other.words) {
+      *word &= other;
+    }
+  }
+
+  pub fn subtract(&mut self, other: &BitSet) {
+    for (word, other) in self.words.iter_mut().zip(&other.words) {
+      *word &= !other;
+    }
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+    (0..self.len).filter(|&index| self.contains(index))
+  }
+}
+
+/// Numbers what an analysis tracks, so that its facts can be bit sets.
+#[derive(Debug, Clone)]
+pub struct Universe<T> {
+  items: Vec<T>,
+  index: HashMap<T, usize>,
+}
+
+impl<T> Default for Universe<T> {
+  fn default() -> Self {
+    Self {
+      items: Vec::new(),
+      index: HashMap::new(),
+    }
+  }
+}
+
+impl<T: Clone + Eq + Hash> Universe<T> {
+  /// The number of `item`, given a new one if it has none yet.
+  pub fn insert(&mut self, item: T) -> usize {
+    if let Some(&index) = self.index.get(&item) {
+      return index;
+    }
+    self.items.push(item.clone());
+    self.index.insert(item, self.items.len() - 1);
+    self.items.len() - 1
+  }
+
+  pub fn index(&self, item: &T) -> Option<usize> {
+    self.index.get(item).copied()
+  }
+
+  pub fn len(&self) -> usize {
+    self.items.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.items.is_empty()
+  }
+
+  pub fn contains(&self, set: &BitSet, item: &T) -> bool {
+    self.index(item).is_some_and(|index| set.contains(index))
+  }
+
+  /// The items in `set`.
+  pub fn items<'a>(&'a self, set: &'a BitSet) -> impl Iterator<Item = &'a T> + 'a {
+    set.iter().map(|index| &self.items[index])
+  }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use super::FlowGraph;
+
+/// Blocks of a function numbered in layout order, the entry being 0, with
+/// their instructions and edges.
+pub struct Cfg<'f, F: FlowGraph> {
+  pub func: &'f F,
+  pub blocks: Vec<F::Block>,
+  pub insts: Vec<Vec<F::Inst>>,
+  pub succs: Vec<Vec<usize>>,
+  pub preds: Vec<Vec<usize>>,
+  index: HashMap<F::Block, usize>,
+}
+
+impl<'f, F: FlowGraph> Cfg<'f, F> {
+  pub fn new(func: &'f F) -> Self {
+    let blocks = func.blocks();
+    let index: HashMap<_, _> = blocks.iter().enumerate().map(|(i, &b)| (b, i)).collect();
+    let insts = blocks.iter().map(|&b| func.instructions(b)).collect();
+    let mut succs = vec![Vec::new(); blocks.len()];
+    let mut preds = vec![Vec::new(); blocks.len()];
+    for (i, &block) in blocks.iter().enumerate() {
+      for succ in func.successors(block) {
+        let s = index[&succ];
+        // both targets of a branch may be the same block
+        if !succs[i].contains(&s) {
+          succs[i].push(s);
+          preds[s].push(i);
+        }
+      }
+    }
+    Self {
+      func,
+      blocks,
+      insts,
+      succs,
+      preds,
+      index,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.blocks.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.blocks.is_empty()
+  }
+
+  /// The number of `block`, which must be in the function.
+  pub fn index(&self, block: F::Block) -> usize {
+    self.index[&block]
+  }
+
+  pub fn name(&self, block: usize) -> String {
+    self.func.block_name(self.blocks[block])
+  }
+
+  /// Blocks reachable from the entry in reverse postorder.
+  pub fn reverse_postorder(&self) -> Vec<usize> {
+    let mut visited = vec![false; self.len()];
+    let mut order = Vec::new();
+    if self.is_empty() {
+      return order;
+    }
+    // (block, successors already pushed)
+    let mut stack = vec![(0, false)];
+    while let Some((block, done)) = stack.pop() {
+      if done {
+        order.push(block);
+        continue;
+      }
+      if visited[block] {
+        continue;
+      }
+      visited[block] = true;
+      stack.push((block, true));
+      for &succ in self.succs[block].iter().rev() {
+        if !visited[succ] {
+          stack.push((succ, false));
+        }
+      }
+    }
+    order.reverse();
+    order
+  }
+}
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+
+use super::{Cfg, FlowGraph};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+  Forward,
+  Backward,
+}
+
+/// A dataflow problem over the blocks of a [`Cfg`], solved by [`solve`].
+pub trait Analysis<F: FlowGraph> {
+  type Fact: Clone + Eq;
+  const DIRECTION: Direction;
+
+  /// The fact at the entry, or after the exits when going backward.
+  fn boundary(&self, cfg: &Cfg<F>) -> Self::Fact;
+  /// The identity of [`Analysis::meet`], where every other fact starts.
+  fn top(&self, cfg: &Cfg<F>) -> Self::Fact;
+  fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact);
+  /// The fact on the other side of `block`, in the direction of the
+  /// analysis.
+  fn transfer(&self, cfg: &Cfg<F>, block: usize, fact: &Self::Fact) -> Self::Fact;
+  /// Meet `other`, the fact crossing the edge from `from` to `to`, into
+  /// `fact`. Analyses that see something on edges, like phis, hook in here.
+  fn meet_edge(
+    &self,
+    _cfg: &Cfg<F>,
+    _from: usize,
+    _to: usize,
+    fact: &mut Self::Fact,
+    other: &Self::Fact,
+  ) {
+    self.meet(fact, other);
+  }
+}
+
+/// Facts at the start and the end of every block, in program order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution<T> {
+  pub before: Vec<T>,
+  pub after: Vec<T>,
+}
+
+/// Iterate `analysis` to its fixpoint with a worklist.
+pub fn solve<F: FlowGraph, A: Analysis<F>>(cfg: &Cfg<F>, analysis: &A) -> Solution<A::Fact> {
+  let top = analysis.top(cfg);
+  let mut solution = Solution {
+    before: vec![top.clone(); cfg.len()],
+    after: vec![top.clone(); cfg.len()],
+  };
+  let forward = A::DIRECTION == Direction::Forward;
+  // reverse postorder visits predecessors first, unreachable blocks last
+  let mut order = cfg.reverse_postorder();
+  let mut queued = vec![false; cfg.len()];
+  for &block in &order {
+    queued[block] = true;
+  }
+  order.extend((0..cfg.len()).filter(|&block| !queued[block]));
+  if !forward {
+    order.reverse();
+  }
+  queued = vec![true; cfg.len()];
+  let mut worklist: VecDeque<usize> = order.into();
+  while let Some(block) = worklist.pop_front() {
+    queued[block] = false;
+    let (inputs, outputs) = if forward {
+      (&cfg.preds, &cfg.succs)
+    } else {
+      (&cfg.succs, &cfg.preds)
+    };
+    let at_boundary = if forward {
+      block == 0
+    } else {
+      cfg.succs[block].is_empty()
+    };
+    let mut input = if at_boundary {
+      analysis.boundary(cfg)
+    } else {
+      top.clone()
+    };
+    for &other in &inputs[block] {
+      if forward {
+        analysis.meet_edge(cfg, other, block, &mut input, &solution.after[other]);
+      } else {
+        analysis.meet_edge(cfg, block, other, &mut input, &solution.before[other]);
+      }
+    }
+    let output = analysis.transfer(cfg, block, &input);
+    let (input_slot, output_slot) = if forward {
+      (&mut solution.before[block], &mut solution.after[block])
+    } else {
+      (&mut solution.after[block], &mut solution.before[block])
+    };
+    *input_slot = input;
+    if *output_slot != output {
+      *output_slot = output;
+      for &next in &outputs[block] {
+        if !queued[next] {
+          queued[next] = true;
+          worklist.push_back(next);
+        }
+      }
+    }
+  }
+  solution
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeSet;
+
+use super::{Cfg, FlowGraph};
+
+/// Immediate dominators of the reachable blocks of a [`Cfg`], computed
+/// with Cooper, Harvey and Kennedy's iteration.
+#[derive(Debug, Clone)]
+pub struct DomTree {
+  idom: Vec<Option<usize>>,
+  reachable: Vec<bool>,
+  children: Vec<Vec<usize>>,
+}
+
+impl DomTree {
+  pub fn new<F: FlowGraph>(cfg: &Cfg<F>) -> Self {
+    let order = cfg.reverse_postorder();
+    let mut rpo_index = vec![usize::MAX; cfg.len()];
+    for (i, &block) in order.iter().enumerate() {
+      rpo_index[block] = i;
+    }
+    // by reverse postorder position, so that dominators come first
+    let mut idom: Vec<Option<usize>> = vec![None; order.len()];
+    if !order.is_empty() {
+      idom[0] = Some(0);
+    }
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for (i, &block) in order.iter().enumerate().skip(1) {
+        let mut new: Option<usize> = None;
+        for &pred in &cfg.preds[block] {
+          let p = rpo_index[pred];
+          if p == usize::MAX || idom[p].is_none() {
+            continue;
+          }
+          new = Some(match new {
+            None => p,
+            Some(mut a) => {
+              let mut b = p;
+              while a != b {
+                while a > b {
+                  a = idom[a].unwrap();
+                }
+                while b > a {
+                  b = idom[b].unwrap();
+                }
+              }
+              a
+            }
+          });
+        }
+        if new.is_some() && idom[i] != new {
+          idom[i] = new;
+          changed = true;
+        }
+      }
+    }
+    let mut tree = Self {
+      idom: vec![None; cfg.len()],
+      reachable: vec![false; cfg.len()],
+      children: vec![Vec::new(); cfg.len()],
+    };
+    for (i, &block) in order.iter().enumerate() {
+      tree.reachable[block] = true;
+      if i > 0 {
+        let parent = order[idom[i].unwrap()];
+        tree.idom[block] = Some(parent);
+        tree.children[parent].push(block);
+      }
+    }
+    tree
+  }
+
+  /// `None` for the entry and unreachable blocks.
+  pub fn idom(&self, block: usize) -> Option<usize> {
+    self.idom[block]
+  }
+
+  pub fn is_reachable(&self, block: usize) -> bool {
+    self.reachable[block]
+  }
+
+  /// Blocks immediately dominated by `block`.
+  pub fn children(&self, block: usize) -> &[usize] {
+    &self.children[block]
+  }
+
+  /// Whether every path from the entry to `b` goes through `a`; a block
+  /// dominates itself. Nothing dominates an unreachable block.
+  pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+    if !self.reachable[b] {
+      return false;
+    }
+    loop {
+      if a == b {
+        return true;
+      }
+      match self.idom[b] {
+        Some(parent) => b = parent,
+        None => return false,
+      }
+    }
+  }
+
+  /// The dominance frontier of every block: where its dominance ends.
+  pub fn frontiers<F: FlowGraph>(&self, cfg: &Cfg<F>) -> Vec<BTreeSet<usize>> {
+    let mut frontiers = vec![BTreeSet::new(); cfg.len()];
+    for block in 0..cfg.len() {
+      // everything between a predecessor and the immediate dominator of
+      // `block` dominates an edge into it but not `block` itself
+      for &pred in cfg.preds[block]
+        .iter()
+        .filter(|&&pred| self.reachable[pred])
+      {
+        let mut runner = pred;
+        while Some(runner) != self.idom[block] {
+          frontiers[runner].insert(block);
+          match self.idom[runner] {
+            Some(parent) => runner = parent,
+            None => break,
+          }
+        }
+      }
+    }
+    frontiers
+  }
+}
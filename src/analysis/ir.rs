@@ -0,0 +1,153 @@
+//! Our SSA IR seen as a flow graph: variables are the values instructions
+//! and parameters define, expressions are pure instructions and loads.
+use super::FlowGraph;
+use crate::ir::{BinOp, BlockId, CastOp, CmpOp, Function, Inst, InstId, Type, Value};
+
+/// An instruction without its identity, so that equal computations compare
+/// equal. Operands of commutative operations are sorted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
+  Binary(BinOp, Value, Value),
+  FNeg(Value),
+  Cmp(CmpOp, Value, Value),
+  Cast(CastOp, Value),
+  Gep(Value, Vec<Value>),
+  Load(Value),
+}
+
+impl Expr {
+  pub fn of(inst: &Inst) -> Option<Self> {
+    Some(match *inst {
+      Inst::Binary(op, lhs, rhs) if op.is_commutative() && rhs < lhs => Expr::Binary(op, rhs, lhs),
+      Inst::Binary(op, lhs, rhs) => Expr::Binary(op, lhs, rhs),
+      Inst::FNeg(val) => Expr::FNeg(val),
+      Inst::Cmp(op, lhs, rhs) if rhs < lhs => Expr::Cmp(op.swap(), rhs, lhs),
+      Inst::Cmp(op, lhs, rhs) => Expr::Cmp(op, lhs, rhs),
+      Inst::Cast(op, val) => Expr::Cast(op, val),
+      Inst::Gep { base, ref indices } => Expr::Gep(base, indices.clone()),
+      Inst::Load(ptr) => Expr::Load(ptr),
+      _ => return None,
+    })
+  }
+}
+
+/// The global, alloca or parameter `ptr` points into.
+fn base_object(func: &Function, mut ptr: Value) -> Value {
+  while let Value::Inst(id) = ptr {
+    match func.inst(id).kind {
+      Inst::Gep { base, .. } => ptr = base,
+      _ => break,
+    }
+  }
+  ptr
+}
+
+/// Whether writing through `a` may change what `b` points to. Distinct
+/// globals and allocas never overlap, and a parameter can not point into
+/// the frame of the function it is passed to.
+pub fn may_alias(func: &Function, a: Value, b: Value) -> bool {
+  let (a, b) = (base_object(func, a), base_object(func, b));
+  let known = |val: Value| match val {
+    Value::Global(_) | Value::Param(_) => true,
+    Value::Inst(id) => matches!(func.inst(id).kind, Inst::Alloca(_)),
+    _ => false,
+  };
+  if a == b || !known(a) || !known(b) {
+    return true;
+  }
+  matches!(
+    (a, b),
+    (Value::Param(_), Value::Param(_) | Value::Global(_)) | (Value::Global(_), Value::Param(_))
+  )
+}
+
+/// Calls of functions that may store to memory the caller can see.
+fn writes_memory(callee: &str) -> bool {
+  !matches!(
+    callee,
+    "getint"
+      | "getch"
+      | "getfloat"
+      | "putint"
+      | "putch"
+      | "putfloat"
+      | "putarray"
+      | "putfarray"
+      | "_sysy_starttime"
+      | "_sysy_stoptime"
+  )
+}
+
+impl FlowGraph for Function {
+  type Block = BlockId;
+  type Inst = InstId;
+  type Var = Value;
+  type Expr = Expr;
+
+  fn blocks(&self) -> Vec<BlockId> {
+    self.layout.clone()
+  }
+
+  fn block_name(&self, block: BlockId) -> String {
+    self.block(block).name.clone()
+  }
+
+  fn instructions(&self, block: BlockId) -> Vec<InstId> {
+    self.block(block).insts.clone()
+  }
+
+  fn successors(&self, block: BlockId) -> Vec<BlockId> {
+    Function::successors(self, block)
+  }
+
+  fn uses(&self, inst: InstId) -> Vec<Value> {
+    let kind = &self.inst(inst).kind;
+    if kind.is_phi() {
+      return Vec::new();
+    }
+    kind
+      .operands()
+      .into_iter()
+      .filter(|val| matches!(val, Value::Inst(_) | Value::Param(_)))
+      .collect()
+  }
+
+  fn defs(&self, inst: InstId) -> Vec<Value> {
+    match self.inst(inst).ty {
+      Type::Void => Vec::new(),
+      _ => vec![Value::Inst(inst)],
+    }
+  }
+
+  fn phi_uses(&self, inst: InstId) -> Vec<(BlockId, Value)> {
+    match &self.inst(inst).kind {
+      Inst::Phi(incoming) => incoming
+        .iter()
+        .filter(|(_, val)| matches!(val, Value::Inst(_) | Value::Param(_)))
+        .copied()
+        .collect(),
+      _ => Vec::new(),
+    }
+  }
+
+  fn expression(&self, inst: InstId) -> Option<Expr> {
+    Expr::of(&self.inst(inst).kind)
+  }
+
+  fn may_kill(&self, inst: InstId) -> bool {
+    matches!(self.inst(inst).kind, Inst::Store { .. } | Inst::Call { .. })
+  }
+
+  /// Values never change in SSA, only loads can be killed: by a store that
+  /// may alias them or a call that may write memory.
+  fn kills(&self, inst: InstId, expr: &Expr) -> bool {
+    let Expr::Load(loaded) = *expr else {
+      return false;
+    };
+    match &self.inst(inst).kind {
+      Inst::Store { ptr, .. } => may_alias(self, *ptr, loaded),
+      Inst::Call { callee, .. } => writes_memory(callee),
+      _ => false,
+    }
+  }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use super::{Analysis, BitSet, Cfg, Direction, FlowGraph, Universe};
+
+/// Variables that may be read before they are written again. A phi reads
+/// its operands at the end of the predecessor they come from.
+pub struct Liveness<F: FlowGraph> {
+  pub vars: Universe<F::Var>,
+  // read before any write in the block
+  gen: Vec<BitSet>,
+  // written in the block
+  kill: Vec<BitSet>,
+  // read by the phis of a block, by the edge they are read on
+  phi_uses: HashMap<(usize, usize), BitSet>,
+}
+
+impl<F: FlowGraph> Liveness<F> {
+  pub fn new(cfg: &Cfg<F>) -> Self {
+    let func = cfg.func;
+    let mut vars = Universe::default();
+    for &inst in cfg.insts.iter().flatten() {
+      let phi_vars = func.phi_uses(inst).into_iter().map(|(_, var)| var);
+      for var in func
+        .defs(inst)
+        .into_iter()
+        .chain(func.uses(inst))
+        .chain(phi_vars)
+      {
+        vars.insert(var);
+      }
+    }
+    let mut gen = vec![BitSet::new(vars.len()); cfg.len()];
+    let mut kill = gen.clone();
+    let mut phi_uses = HashMap::new();
+    for (block, insts) in cfg.insts.iter().enumerate() {
+      for &inst in insts.iter().rev() {
+        for var in func.defs(inst) {
+          let index = vars.insert(var);
+          kill[block].insert(index);
+          gen[block].remove(index);
+        }
+        for var in func.uses(inst) {
+          gen[block].insert(vars.insert(var));
+        }
+        for (pred, var) in func.phi_uses(inst) {
+          phi_uses
+            .entry((cfg.index(pred), block))
+            .or_insert_with(|| BitSet::new(vars.len()))
+            .insert(vars.insert(var));
+        }
+      }
+    }
+    Self {
+      vars,
+      gen,
+      kill,
+      phi_uses,
+    }
+  }
+}
+
+impl<F: FlowGraph> Analysis<F> for Liveness<F> {
+  type Fact = BitSet;
+  const DIRECTION: Direction = Direction::Backward;
+
+  fn boundary(&self, _cfg: &Cfg<F>) -> BitSet {
+    BitSet::new(self.vars.len())
+  }
+
+  fn top(&self, _cfg: &Cfg<F>) -> BitSet {
+    BitSet::new(self.vars.len())
+  }
+
+  fn meet(&self, fact: &mut BitSet, other: &BitSet) {
+    fact.union_with(other);
+  }
+
+  fn transfer(&self, _cfg: &Cfg<F>, block: usize, fact: &BitSet) -> BitSet {
+    let mut live = fact.clone();
+    live.subtract(&self.kill[block]);
+    live.union_with(&self.gen[block]);
+    live
+  }
+
+  fn meet_edge(&self, _cfg: &Cfg<F>, from: usize, to: usize, fact: &mut BitSet, other: &BitSet) {
+    fact.union_with(other);
+    if let Some(phi_uses) = self.phi_uses.get(&(from, to)) {
+      fact.union_with(phi_uses);
+    }
+  }
+}
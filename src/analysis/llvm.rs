@@ -0,0 +1,116 @@
+//! Functions the generator produces, read back through inkwell. Before
+//! mem2reg every local lives in an alloca, so the variables are those
+//! stack slots: a load reads one, a store writes it.
+use inkwell::{
+  basic_block::BasicBlock,
+  values::{BasicValue, FunctionValue, InstructionOpcode, InstructionValue},
+};
+
+use super::FlowGraph;
+
+/// The alloca operand `index` of `inst` points at directly, if any.
+fn slot(inst: InstructionValue, index: u32) -> Option<InstructionValue> {
+  let ptr = inst.get_operand(index)?.left()?;
+  let alloca = ptr.as_instruction_value()?;
+  (alloca.get_opcode() == InstructionOpcode::Alloca).then_some(alloca)
+}
+
+/// Whether `ptr` is `alloca` or an element of it.
+fn points_into<'ctx>(ptr: InstructionValue<'ctx>, alloca: InstructionValue<'ctx>) -> bool {
+  let mut ptr = ptr;
+  loop {
+    if ptr == alloca {
+      return true;
+    }
+    match ptr.get_opcode() {
+      InstructionOpcode::GetElementPtr | InstructionOpcode::BitCast => {
+        match ptr
+          .get_operand(0)
+          .and_then(|base| base.left())
+          .and_then(|base| base.as_instruction_value())
+        {
+          Some(base) => ptr = base,
+          None => return false,
+        }
+      }
+      _ => return false,
+    }
+  }
+}
+
+impl<'ctx> FlowGraph for FunctionValue<'ctx> {
+  type Block = BasicBlock<'ctx>;
+  type Inst = InstructionValue<'ctx>;
+  /// stack slots, by their alloca
+  type Var = InstructionValue<'ctx>;
+  /// loads, by the slot they read
+  type Expr = InstructionValue<'ctx>;
+
+  fn blocks(&self) -> Vec<BasicBlock<'ctx>> {
+    self.get_basic_blocks()
+  }
+
+  fn block_name(&self, block: BasicBlock<'ctx>) -> String {
+    block.get_name().to_string_lossy().into_owned()
+  }
+
+  fn instructions(&self, block: BasicBlock<'ctx>) -> Vec<InstructionValue<'ctx>> {
+    std::iter::successors(block.get_first_instruction(), |inst| {
+      inst.get_next_instruction()
+    })
+    .collect()
+  }
+
+  fn successors(&self, block: BasicBlock<'ctx>) -> Vec<BasicBlock<'ctx>> {
+    let Some(term) = block.get_terminator() else {
+      return Vec::new();
+    };
+    (0..term.get_num_operands())
+      .filter_map(|i| term.get_operand(i)?.right())
+      .collect()
+  }
+
+  fn uses(&self, inst: InstructionValue<'ctx>) -> Vec<InstructionValue<'ctx>> {
+    match inst.get_opcode() {
+      InstructionOpcode::Load => slot(inst, 0).into_iter().collect(),
+      _ => Vec::new(),
+    }
+  }
+
+  fn defs(&self, inst: InstructionValue<'ctx>) -> Vec<InstructionValue<'ctx>> {
+    match inst.get_opcode() {
+      InstructionOpcode::Store => slot(inst, 1).into_iter().collect(),
+      _ => Vec::new(),
+    }
+  }
+
+  fn expression(&self, inst: InstructionValue<'ctx>) -> Option<InstructionValue<'ctx>> {
+    match inst.get_opcode() {
+      InstructionOpcode::Load => slot(inst, 0),
+      _ => None,
+    }
+  }
+
+  fn may_kill(&self, inst: InstructionValue<'ctx>) -> bool {
+    matches!(
+      inst.get_opcode(),
+      InstructionOpcode::Store | InstructionOpcode::Call
+    )
+  }
+
+  /// A store to the slot, or a call that is passed its address.
+  fn kills(&self, inst: InstructionValue<'ctx>, alloca: &InstructionValue<'ctx>) -> bool {
+    match inst.get_opcode() {
+      InstructionOpcode::Store => slot(inst, 1) == Some(*alloca),
+      // the callee is the last operand
+      InstructionOpcode::Call => (0..inst.get_num_operands().saturating_sub(1)).any(|i| {
+        inst
+          .get_operand(i)
+          .and_then(|arg| arg.left())
+          .and_then(|arg| arg.as_instruction_value())
+          .is_some_and(|arg| points_into(arg, *alloca))
+      }),
+      _ => false,
+    }
+  }
+}
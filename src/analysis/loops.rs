@@ -0,0 +1,86 @@
+use super::{Cfg, DomTree, FlowGraph};
+
+/// A natural loop: a header dominating the sources of the edges back to
+/// it, with every block reaching them without going through the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+  pub header: usize,
+  /// sources of the back edges
+  pub latches: Vec<usize>,
+  /// blocks of the loop, the header included, in layout order
+  pub blocks: Vec<usize>,
+  /// the innermost loop containing this one
+  pub parent: Option<usize>,
+}
+
+impl Loop {
+  pub fn contains(&self, block: usize) -> bool {
+    self.blocks.binary_search(&block).is_ok()
+  }
+}
+
+/// The natural loops of a function, those sharing a header merged.
+#[derive(Debug, Clone)]
+pub struct LoopForest {
+  /// loops before those containing them
+  pub loops: Vec<Loop>,
+  innermost: Vec<Option<usize>>,
+}
+
+impl LoopForest {
+  pub fn new<F: FlowGraph>(cfg: &Cfg<F>, dom: &DomTree) -> Self {
+    let mut loops = Vec::new();
+    for header in cfg.reverse_postorder() {
+      let latches: Vec<usize> = cfg.preds[header]
+        .iter()
+        .copied()
+        .filter(|&pred| dom.dominates(header, pred))
+        .collect();
+      if latches.is_empty() {
+        continue;
+      }
+      let mut in_loop = vec![false; cfg.len()];
+      in_loop[header] = true;
+      let mut stack = latches.clone();
+      while let Some(block) = stack.pop() {
+        if !in_loop[block] {
+          in_loop[block] = true;
+          stack.extend(
+            cfg.preds[block]
+              .iter()
+              .filter(|&&pred| dom.is_reachable(pred)),
+          );
+        }
+      }
+      loops.push(Loop {
+        header,
+        latches,
+        blocks: (0..cfg.len()).filter(|&block| in_loop[block]).collect(),
+        parent: None,
+      });
+    }
+    // a loop nested in another has fewer blocks
+    loops.sort_by_key(|lp| lp.blocks.len());
+    for i in 0..loops.len() {
+      let header = loops[i].header;
+      loops[i].parent = (i + 1..loops.len()).find(|&outer| loops[outer].contains(header));
+    }
+    let mut innermost = vec![None; cfg.len()];
+    for (i, lp) in loops.iter().enumerate().rev() {
+      for &block in &lp.blocks {
+        innermost[block] = Some(i);
+      }
+    }
+    Self { loops, innermost }
+  }
+
+  /// The innermost loop `block` is in.
+  pub fn innermost(&self, block: usize) -> Option<usize> {
+    self.innermost[block]
+  }
+
+  /// Whether some loop is nested in loop `lp`.
+  pub fn is_innermost(&self, lp: usize) -> bool {
+    !self.loops.iter().any(|other| other.parent == Some(lp))
+  }
+}
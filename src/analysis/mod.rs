@@ -0,0 +1,59 @@
+//! Control flow graphs, dominators, loops and dataflow analyses, over our
+//! SSA IR as well as the LLVM functions the generator produces.
+mod available;
+mod bitset;
+mod cfg;
+mod dataflow;
+mod dominators;
+mod ir;
+mod liveness;
+mod llvm;
+mod loops;
+mod reaching;
+
+use std::{fmt::Debug, hash::Hash};
+
+pub use self::{
+  available::AvailableExpressions,
+  bitset::{BitSet, Universe},
+  cfg::Cfg,
+  dataflow::{solve, Analysis, Direction, Solution},
+  dominators::DomTree,
+  ir::{may_alias, Expr},
+  liveness::Liveness,
+  loops::{Loop, LoopForest},
+  reaching::ReachingDefinitions,
+};
+
+/// What the analyses need to know about a function.
+pub trait FlowGraph {
+  type Block: Copy + Eq + Hash + Debug;
+  type Inst: Copy + Eq + Hash + Debug;
+  /// what liveness and reaching definitions track
+  type Var: Clone + Eq + Hash + Debug;
+  /// what available expressions track
+  type Expr: Clone + Eq + Hash + Debug;
+
+  /// Blocks in layout order, the entry first.
+  fn blocks(&self) -> Vec<Self::Block>;
+  fn block_name(&self, block: Self::Block) -> String;
+  fn instructions(&self, block: Self::Block) -> Vec<Self::Inst>;
+  fn successors(&self, block: Self::Block) -> Vec<Self::Block>;
+
+  /// Variables read by `inst`, except by phis.
+  fn uses(&self, inst: Self::Inst) -> Vec<Self::Var>;
+  /// Variables written by `inst`.
+  fn defs(&self, inst: Self::Inst) -> Vec<Self::Var>;
+  /// Variables read by a phi, with the predecessor each one comes from.
+  fn phi_uses(&self, _inst: Self::Inst) -> Vec<(Self::Block, Self::Var)> {
+    Vec::new()
+  }
+  /// The expression `inst` computes, if it is one worth reusing.
+  fn expression(&self, inst: Self::Inst) -> Option<Self::Expr>;
+  /// Whether `expr` may compute something else after `inst`.
+  fn kills(&self, inst: Self::Inst, expr: &Self::Expr) -> bool;
+  /// Whether `inst` may kill any expression, so that others are skipped.
+  fn may_kill(&self, _inst: Self::Inst) -> bool {
+    true
+  }
+}
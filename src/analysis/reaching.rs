@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use super::{Analysis, BitSet, Cfg, Direction, FlowGraph, Universe};
+
+/// Writes that may be the last one to their variable, as the variable and
+/// the instruction writing it.
+pub struct ReachingDefinitions<F: FlowGraph> {
+  pub defs: Universe<(F::Var, F::Inst)>,
+  // the last write of each variable in the block
+  gen: Vec<BitSet>,
+  // the other writes of the variables the block writes
+  kill: Vec<BitSet>,
+}
+
+impl<F: FlowGraph> ReachingDefinitions<F> {
+  pub fn new(cfg: &Cfg<F>) -> Self {
+    let mut defs = Universe::default();
+    let mut by_var: HashMap<F::Var, Vec<usize>> = HashMap::new();
+    for &inst in cfg.insts.iter().flatten() {
+      for var in cfg.func.defs(inst) {
+        let index = defs.insert((var.clone(), inst));
+        by_var.entry(var).or_default().push(index);
+      }
+    }
+    let mut gen = vec![BitSet::new(defs.len()); cfg.len()];
+    let mut kill = gen.clone();
+    for (block, insts) in cfg.insts.iter().enumerate() {
+      let mut last = HashMap::new();
+      for &inst in insts {
+        for var in cfg.func.defs(inst) {
+          last.insert(var.clone(), defs.insert((var, inst)));
+        }
+      }
+      for (var, index) in last {
+        for &other in &by_var[&var] {
+          kill[block].insert(other);
+        }
+        gen[block].insert(index);
+      }
+      kill[block].subtract(&gen[block]);
+    }
+    Self { defs, gen, kill }
+  }
+}
+
+impl<F: FlowGraph> Analysis<F> for ReachingDefinitions<F> {
+  type Fact = BitSet;
+  const DIRECTION: Direction = Direction::Forward;
+
+  fn boundary(&self, _cfg: &Cfg<F>) -> BitSet {
+    BitSet::new(self.defs.len())
+  }
+
+  fn top(&self, _cfg: &Cfg<F>) -> BitSet {
+    BitSet::new(self.defs.len())
+  }
+
+  fn meet(&self, fact: &mut BitSet, other: &BitSet) {
+    fact.union_with(other);
+  }
+
+  fn transfer(&self, _cfg: &Cfg<F>, block: usize, fact: &BitSet) -> BitSet {
+    let mut reaching = fact.clone();
+    reaching.subtract(&self.kill[block]);
+    reaching.union_with(&self.gen[block]);
+    reaching
+  }
+}
@@ -1,18 +1,231 @@
-use clap::Parser;
+use clap::{ArgEnum, Parser, Subcommand};
+use sysy::{emit::EmitKind, fuzz::Oracle, util::OptLevel};
 
 #[derive(Parser, Debug)]
-#[clap(about, version, author)]
+#[clap(about, version, author, args_conflicts_with_subcommands(true))]
 pub struct Args {
+  #[clap(subcommand)]
+  pub command: Option<Command>,
   #[clap(help("input file path"))]
-  pub input: String,
+  pub input: Option<String>,
   #[clap(short, long, help("print ast"))]
   pub ast: bool,
   #[clap(short, long, help("print function prototypes"))]
   pub prototype: bool,
   #[clap(short, long, help("print global vars"))]
   pub global: bool,
-  #[clap(short, long, help("enable ir output"))]
+  #[clap(short, long, help("enable ir output, same as --emit=ir"))]
   pub ir_enable: bool,
-  #[clap(short, long, help("enable exe output"))]
+  #[clap(short, long, help("enable exe output, same as --emit=exe"))]
   pub exe_enable: bool,
+  #[clap(
+    long,
+    arg_enum,
+    use_value_delimiter(true),
+    value_name("KINDS"),
+    help("comma separated outputs to write next to the output stem")
+  )]
+  pub emit: Vec<EmitKind>,
+  #[clap(
+    long,
+    value_name("STEM"),
+    help("path prefix of --emit outputs, defaults to the input file name")
+  )]
+  pub out_stem: Option<String>,
+  #[clap(long, arg_enum, value_name("FORMAT"), help("emit symbol table"))]
+  pub symbols: Option<SymbolFormat>,
+  #[clap(
+    long,
+    value_name("PATH"),
+    requires("symbols"),
+    help("write symbol table to file instead of stdout")
+  )]
+  pub symbols_output: Option<String>,
+  #[clap(short = 'S', help("emit assembly"))]
+  pub assembly: bool,
+  #[clap(short = 'c', conflicts_with("assembly"), help("emit object file"))]
+  pub object: bool,
+  #[clap(
+    short,
+    long,
+    value_name("PATH"),
+    help("write the artifact to this path")
+  )]
+  pub output: Option<String>,
+  #[clap(
+    short = 'O',
+    arg_enum,
+    default_value("0"),
+    value_name("LEVEL"),
+    help("optimization level")
+  )]
+  pub opt_level: OptLevel,
+  #[clap(
+    long,
+    value_name("PASSES"),
+    help("comma separated llvm passes to run instead of the -O pipeline, such as mem2reg,gvn")
+  )]
+  pub passes: Option<String>,
+  #[clap(long, help("report the time spent in each optimization pass"))]
+  pub time_passes: bool,
+  #[clap(
+    long,
+    help(
+      "keep big local arrays of non-recursive functions in static storage instead of on the stack"
+    )
+  )]
+  pub static_arrays: bool,
+  #[clap(
+    long,
+    help("generate LLVM IR from our SSA IR instead of straight from the parse tree")
+  )]
+  pub via_ssa: bool,
+  #[clap(
+    long,
+    value_name("PASSES"),
+    help("comma separated passes of our own to run on the SSA IR instead of the -O pipeline, such as sccp,gvn,dce")
+  )]
+  pub ssa_passes: Option<String>,
+  #[clap(
+    long,
+    use_value_delimiter(true),
+    value_name("PASS"),
+    help("print the SSA IR to stderr after each run of these passes of our own")
+  )]
+  pub print_after: Vec<String>,
+  #[clap(
+    long("finline-limit"),
+    value_name("N"),
+    help("largest cost of a call our own inliner inlines, spelled -finline-limit=N as in gcc")
+  )]
+  pub inline_limit: Option<usize>,
+  #[clap(
+    long,
+    help("explain to stderr what our own passes decided, such as which calls got inlined")
+  )]
+  pub remarks: bool,
+  #[clap(
+    long,
+    value_name("TRIPLE"),
+    help("target triple such as armv7-unknown-linux-gnueabihf, defaults to the host")
+  )]
+  pub target: Option<String>,
+  #[clap(long, value_name("CPU"), help("target cpu, defaults to the host cpu"))]
+  pub cpu: Option<String>,
+  #[clap(
+    long,
+    value_name("FEATURES"),
+    help("target features such as +neon,-vfp2, defaults to the host features")
+  )]
+  pub features: Option<String>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolFormat {
+  Text,
+  Json,
+}
+
+/// How `sysy run` executes a program.
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+  Jit,
+  /// the reference tree-walking interpreter, no LLVM involved
+  Interp,
+  /// lower to our SSA IR and interpret that, no LLVM involved
+  Ssa,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  #[clap(about("run a program, feeding it stdin"))]
+  Run {
+    #[clap(help("input file path"))]
+    input: String,
+    #[clap(long, arg_enum, default_value("jit"), help("execution engine"))]
+    engine: Engine,
+  },
+  #[clap(about("compare programs built by sysy with the same source built as C by clang"))]
+  Difftest {
+    #[clap(
+      required(true),
+      help("input files, or directories to search for .sy files")
+    )]
+    inputs: Vec<String>,
+    #[clap(
+      short = 'O',
+      arg_enum,
+      default_value("0"),
+      value_name("LEVEL"),
+      help("optimization level")
+    )]
+    opt_level: OptLevel,
+  },
+  #[clap(about("compile random programs, looking for crashes and miscompilations"))]
+  Fuzz(FuzzArgs),
+  #[clap(about("shrink a program while it keeps failing the same way"))]
+  Reduce {
+    #[clap(help("input file path"))]
+    input: String,
+    #[clap(
+      short,
+      long,
+      value_name("PATH"),
+      help("where to write the reduced program, defaults to <input>.reduced.sy")
+    )]
+    output: Option<String>,
+    #[clap(
+      long,
+      arg_enum,
+      default_value("interp"),
+      help("where expected outputs come from")
+    )]
+    oracle: Oracle,
+    #[clap(
+      short = 'O',
+      arg_enum,
+      default_value("0"),
+      value_name("LEVEL"),
+      help("optimization level")
+    )]
+    opt_level: OptLevel,
+  },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FuzzArgs {
+  #[clap(long, help("seed of the first program, from the clock if not given"))]
+  pub seed: Option<u64>,
+  #[clap(long, default_value("100"), help("number of programs"))]
+  pub count: u64,
+  #[clap(
+    long,
+    arg_enum,
+    default_value("interp"),
+    help("where expected outputs come from")
+  )]
+  pub oracle: Oracle,
+  #[clap(
+    long,
+    value_name("DIR"),
+    default_value("fuzz-findings"),
+    help("directory to keep failing programs in")
+  )]
+  pub out_dir: String,
+  #[clap(
+    short = 'O',
+    arg_enum,
+    default_value("0"),
+    value_name("LEVEL"),
+    help("optimization level")
+  )]
+  pub opt_level: OptLevel,
+  #[clap(long, help("do not generate floats"))]
+  pub no_float: bool,
+  #[clap(long, help("do not generate arrays"))]
+  pub no_arrays: bool,
+  #[clap(long, help("write array dimensions with constant expressions"))]
+  pub const_exprs: bool,
+  #[clap(long, help("print the programs instead of testing them"))]
+  pub print: bool,
 }
@@ -0,0 +1,123 @@
+use inkwell::{
+  basic_block::BasicBlock,
+  values::{BasicValue, BasicValueEnum, IntValue},
+  FloatPredicate, IntPredicate,
+};
+use tree_sitter::Node;
+
+use super::{BaseType, Generator};
+use crate::{error::Result, parser::get_text};
+
+impl<'ctx> Generator<'ctx> {
+  /// Branch to `on_true` or `on_false` on `cond` without materializing its
+  /// value: comparisons feed the branch directly, `!` swaps the targets and
+  /// `&&`/`||` only evaluate their right operand when it decides.
+  pub(crate) fn generate_condition(
+    &self,
+    cond: Node,
+    on_true: BasicBlock<'ctx>,
+    on_false: BasicBlock<'ctx>,
+  ) -> Result<()> {
+    let op = cond
+      .child_by_field_name("operator")
+      .map(|op| get_text(op, self.file.content));
+    match (cond.kind(), op) {
+      ("parenthesized_expression", _) => {
+        self.generate_condition(cond.named_child(0).unwrap(), on_true, on_false)
+      }
+      ("unary_expression", Some("!")) => {
+        let argument = cond.child_by_field_name("argument").unwrap();
+        self.generate_condition(argument, on_false, on_true)
+      }
+      ("binary_expression", Some(op @ ("&&" | "||"))) => {
+        let left = cond.child_by_field_name("left").unwrap();
+        let right = cond.child_by_field_name("right").unwrap();
+        let current_block = self.builder.get_insert_block().unwrap();
+        let rhs_block = if op == "&&" {
+          let rhs_block = self
+            .context
+            .insert_basic_block_after(current_block, "and_rhs");
+          self.generate_condition(left, rhs_block, on_false)?;
+          rhs_block
+        } else {
+          let rhs_block = self
+            .context
+            .insert_basic_block_after(current_block, "or_rhs");
+          self.generate_condition(left, on_true, rhs_block)?;
+          rhs_block
+        };
+        self.builder.position_at_end(rhs_block);
+        self.generate_condition(right, on_true, on_false)
+      }
+      ("binary_expression", Some(op @ ("==" | "!=" | ">" | "<" | ">=" | "<="))) => {
+        let (lhs_t, lhs_v) = self.generate_expression(cond.child_by_field_name("left").unwrap())?;
+        let (rhs_t, rhs_v) =
+          self.generate_expression(cond.child_by_field_name("right").unwrap())?;
+        assert_eq!(lhs_t, rhs_t);
+        let flag = self.generate_comparison(op, &lhs_t, lhs_v, rhs_v);
+        self
+          .builder
+          .build_conditional_branch(flag, on_true, on_false);
+        Ok(())
+      }
+      _ => {
+        let (_ty, val) = self.generate_expression(cond)?;
+        let flag = self.build_is_true(val);
+        self
+          .builder
+          .build_conditional_branch(flag, on_true, on_false);
+        Ok(())
+      }
+    }
+  }
+
+  /// `val != 0` as an i1.
+  pub(crate) fn build_is_true(&self, val: BasicValueEnum<'ctx>) -> IntValue<'ctx> {
+    match val {
+      BasicValueEnum::IntValue(val) => self.builder.build_int_compare(
+        IntPredicate::NE,
+        val,
+        val.get_type().const_zero(),
+        "is_true",
+      ),
+      BasicValueEnum::FloatValue(val) => self.builder.build_float_compare(
+        FloatPredicate::UNE,
+        val,
+        val.get_type().const_zero(),
+        "is_true",
+      ),
+      val => unreachable!("{val:?} has no truth value"),
+    }
+  }
+
+  /// Value of a `&&` or `||` expression as an i32 0 or 1, for when it is
+  /// stored or passed rather than branched on.
+  pub(super) fn generate_logical_expression(
+    &self,
+    root: Node,
+  ) -> Result<(BaseType, BasicValueEnum<'ctx>)> {
+    let current_block = self.builder.get_insert_block().unwrap();
+    let true_block = self
+      .context
+      .insert_basic_block_after(current_block, "logic_true");
+    let false_block = self
+      .context
+      .insert_basic_block_after(true_block, "logic_false");
+    let end_block = self
+      .context
+      .insert_basic_block_after(false_block, "logic_end");
+    self.generate_condition(root, true_block, false_block)?;
+    self.builder.position_at_end(true_block);
+    self.builder.build_unconditional_branch(end_block);
+    self.builder.position_at_end(false_block);
+    self.builder.build_unconditional_branch(end_block);
+    self.builder.position_at_end(end_block);
+    let i32_type = self.context.i32_type();
+    let res = self.builder.build_phi(i32_type, "logic_res");
+    res.add_incoming(&[
+      (&i32_type.const_int(1, false), true_block),
+      (&i32_type.const_zero(), false_block),
+    ]);
+    Ok((BaseType::Int, res.as_basic_value()))
+  }
+}
@@ -1,5 +1,6 @@
 mod binary;
 mod call;
+mod condition;
 mod identifier;
 mod literal;
 mod subscript;
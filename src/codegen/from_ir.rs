@@ -0,0 +1,352 @@
+//! Translation of our SSA IR to LLVM, the backend of `--via-ssa`.
+use std::collections::HashMap;
+
+use inkwell::{
+  basic_block::BasicBlock,
+  module::Linkage,
+  types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum},
+  values::{BasicMetadataValueEnum, BasicValue, BasicValueEnum, PhiValue, PointerValue},
+  AddressSpace, FloatPredicate, IntPredicate,
+};
+use itertools::Itertools;
+
+use super::Generator;
+use crate::{
+  error::{Error, Result},
+  ir::{self, BinOp, CastOp, CmpOp, Inst, InstId, Type, Value},
+};
+
+impl<'ctx> Generator<'ctx> {
+  /// Generate the module from our IR instead of the parse tree.
+  pub fn gen_ir(&mut self, module: &ir::Module) -> Result<()> {
+    let globals = module
+      .globals
+      .iter()
+      .map(|global| self.ir_global(global))
+      .collect_vec();
+    for func in &module.functions {
+      self.ir_function_proto(func);
+    }
+    for func in module
+      .functions
+      .iter()
+      .filter(|func| !func.is_declaration())
+    {
+      self.ir_function(func, &globals)?;
+    }
+    self.run_module_passes()
+  }
+
+  fn ir_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+    match ty {
+      Type::I1 => self.context.bool_type().as_basic_type_enum(),
+      Type::I32 => self.context.i32_type().as_basic_type_enum(),
+      Type::F32 => self.context.f32_type().as_basic_type_enum(),
+      Type::Ptr(ty) => self
+        .ir_type(ty)
+        .ptr_type(AddressSpace::Generic)
+        .as_basic_type_enum(),
+      Type::Array(elem, len) => self
+        .ir_type(elem)
+        .array_type(*len as u32)
+        .as_basic_type_enum(),
+      Type::Void => unreachable!("void is not a value type"),
+    }
+  }
+
+  fn ir_constant(&self, val: Value) -> BasicValueEnum<'ctx> {
+    match val {
+      Value::Bool(v) => self.context.bool_type().const_int(v as u64, false).into(),
+      Value::Int(v) => self.context.i32_type().const_int(v as u64, true).into(),
+      Value::Float(bits) => self
+        .context
+        .f32_type()
+        .const_float(f32::from_bits(bits) as f64)
+        .into(),
+      _ => unreachable!("{val:?} is not a constant"),
+    }
+  }
+
+  fn ir_global(&mut self, global: &ir::Global) -> PointerValue<'ctx> {
+    let llvm_type = self.ir_type(&global.ty);
+    let initializer = match &global.ty {
+      Type::Array(..) => {
+        let dims = std::iter::successors(Some(&global.ty), |ty| ty.element())
+          .filter_map(|ty| match ty {
+            Type::Array(_, len) => Some(*len),
+            _ => None,
+          })
+          .collect_vec();
+        let items = global
+          .init
+          .iter()
+          .map(|&(index, val)| (index, self.ir_constant(val)))
+          .collect_vec();
+        self.const_sub_array(self.ir_type(global.ty.scalar()), &dims, 0, &items)
+      }
+      ty => self.ir_constant(global.init.first().map_or(Value::zero(ty), |(_, val)| *val)),
+    };
+    let global_value = self
+      .module
+      .add_global(initializer.get_type(), None, &global.name);
+    global_value.set_initializer(&initializer);
+    let ptr = global_value.as_pointer_value();
+    if initializer.get_type() == llvm_type {
+      return ptr;
+    }
+    // a sparse initializer, packed structs are only byte aligned
+    global_value.set_alignment(4);
+    ptr.const_cast(llvm_type.ptr_type(AddressSpace::Generic))
+  }
+
+  fn ir_function_proto(&mut self, func: &ir::Function) {
+    let params = func
+      .params
+      .iter()
+      .map(|param| BasicMetadataTypeEnum::from(self.ir_type(&param.ty)))
+      .collect_vec();
+    let fn_ty = match &func.ret {
+      Type::Void => self.context.void_type().fn_type(&params, false),
+      ty => self.ir_type(ty).fn_type(&params, false),
+    };
+    let linkage = func.is_declaration().then_some(Linkage::External);
+    self.module.add_function(&func.name, fn_ty, linkage);
+  }
+
+  fn ir_value(
+    &self,
+    func: &ir::Function,
+    globals: &[PointerValue<'ctx>],
+    values: &HashMap<InstId, BasicValueEnum<'ctx>>,
+    val: Value,
+  ) -> BasicValueEnum<'ctx> {
+    match val {
+      // only unreachable code uses values not generated yet
+      Value::Inst(id) => values
+        .get(&id)
+        .copied()
+        .unwrap_or_else(|| undef(self.ir_type(&func.inst(id).ty))),
+      Value::Param(i) => {
+        let fn_val = self.module.get_function(&func.name).unwrap();
+        fn_val.get_nth_param(i as u32).unwrap()
+      }
+      Value::Global(id) => globals[id.0 as usize].as_basic_value_enum(),
+      val => self.ir_constant(val),
+    }
+  }
+
+  fn ir_function(&mut self, func: &ir::Function, globals: &[PointerValue<'ctx>]) -> Result<()> {
+    let fn_val = self.module.get_function(&func.name).unwrap();
+    for (param, llvm_param) in func.params.iter().zip(fn_val.get_param_iter()) {
+      llvm_param.set_name(&param.name);
+    }
+    let blocks: HashMap<_, _> = func
+      .layout
+      .iter()
+      .map(|&block| {
+        let name = &func.block(block).name;
+        (block, self.context.append_basic_block(fn_val, name))
+      })
+      .collect();
+    // definitions come before uses in reverse postorder, except for phis
+    let reachable = func.reverse_postorder();
+    let order = reachable.iter().chain(
+      func
+        .layout
+        .iter()
+        .filter(|block| !reachable.contains(block)),
+    );
+    let mut values = HashMap::new();
+    let mut phis: Vec<(PhiValue<'ctx>, InstId)> = Vec::new();
+    for &block in order {
+      self.builder.position_at_end(blocks[&block]);
+      for &id in &func.block(block).insts {
+        let data = func.inst(id);
+        let value = |val| self.ir_value(func, globals, &values, val);
+        let res: Option<BasicValueEnum> = match &data.kind {
+          Inst::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (value(*lhs), value(*rhs));
+            Some(if op.is_float() {
+              let (l, r) = (lhs.into_float_value(), rhs.into_float_value());
+              match op {
+                BinOp::FAdd => self.builder.build_float_add(l, r, ""),
+                BinOp::FSub => self.builder.build_float_sub(l, r, ""),
+                BinOp::FMul => self.builder.build_float_mul(l, r, ""),
+                _ => self.builder.build_float_div(l, r, ""),
+              }
+              .into()
+            } else {
+              let (l, r) = (lhs.into_int_value(), rhs.into_int_value());
+              match op {
+                BinOp::Add => self.builder.build_int_add(l, r, ""),
+                BinOp::Sub => self.builder.build_int_sub(l, r, ""),
+                BinOp::Mul => self.builder.build_int_mul(l, r, ""),
+                BinOp::SDiv => self.builder.build_int_signed_div(l, r, ""),
+                BinOp::SRem => self.builder.build_int_signed_rem(l, r, ""),
+                BinOp::Shl => self.builder.build_left_shift(l, r, ""),
+                BinOp::AShr => self.builder.build_right_shift(l, r, true, ""),
+                BinOp::LShr => self.builder.build_right_shift(l, r, false, ""),
+                // through a 64 bit multiplication, which llvm turns into
+                // smull or the like
+                _ => {
+                  let i64_type = self.context.i64_type();
+                  let l = self.builder.build_int_s_extend(l, i64_type, "");
+                  let r = self.builder.build_int_s_extend(r, i64_type, "");
+                  let product = self.builder.build_int_mul(l, r, "");
+                  let high = self.builder.build_right_shift(
+                    product,
+                    i64_type.const_int(32, false),
+                    true,
+                    "",
+                  );
+                  self
+                    .builder
+                    .build_int_truncate(high, self.context.i32_type(), "")
+                }
+              }
+              .into()
+            })
+          }
+          Inst::FNeg(val) => Some(
+            self
+              .builder
+              .build_float_neg(value(*val).into_float_value(), "")
+              .into(),
+          ),
+          Inst::Cmp(op, lhs, rhs) => Some(match (value(*lhs), value(*rhs)) {
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+              let predicate = match op {
+                CmpOp::Eq => FloatPredicate::OEQ,
+                CmpOp::Ne => FloatPredicate::UNE,
+                CmpOp::Lt => FloatPredicate::OLT,
+                CmpOp::Le => FloatPredicate::OLE,
+                CmpOp::Gt => FloatPredicate::OGT,
+                CmpOp::Ge => FloatPredicate::OGE,
+              };
+              self.builder.build_float_compare(predicate, l, r, "").into()
+            }
+            (l, r) => {
+              let predicate = match op {
+                CmpOp::Eq => IntPredicate::EQ,
+                CmpOp::Ne => IntPredicate::NE,
+                CmpOp::Lt => IntPredicate::SLT,
+                CmpOp::Le => IntPredicate::SLE,
+                CmpOp::Gt => IntPredicate::SGT,
+                CmpOp::Ge => IntPredicate::SGE,
+              };
+              let (l, r) = (l.into_int_value(), r.into_int_value());
+              self.builder.build_int_compare(predicate, l, r, "").into()
+            }
+          }),
+          Inst::Cast(op, val) => {
+            let val = value(*val);
+            let i32_type = self.context.i32_type();
+            Some(match op {
+              CastOp::ZExt => self
+                .builder
+                .build_int_z_extend(val.into_int_value(), i32_type, "")
+                .into(),
+              CastOp::SiToFp => self
+                .builder
+                .build_signed_int_to_float(val.into_int_value(), self.context.f32_type(), "")
+                .into(),
+              CastOp::FpToSi => self
+                .builder
+                .build_float_to_signed_int(val.into_float_value(), i32_type, "")
+                .into(),
+            })
+          }
+          Inst::Alloca(ty) => Some(self.builder.build_alloca(self.ir_type(ty), "").into()),
+          Inst::Load(ptr) => Some(
+            self
+              .builder
+              .build_load(value(*ptr).into_pointer_value(), ""),
+          ),
+          Inst::Store { value: val, ptr } => {
+            self
+              .builder
+              .build_store(value(*ptr).into_pointer_value(), value(*val));
+            None
+          }
+          Inst::Gep { base, indices } => {
+            let indices = indices
+              .iter()
+              .map(|&index| value(index).into_int_value())
+              .collect_vec();
+            let base = value(*base).into_pointer_value();
+            Some(unsafe { self.builder.build_in_bounds_gep(base, &indices, "") }.into())
+          }
+          Inst::Call { callee, args } => {
+            let callee = self.module.get_function(callee).unwrap();
+            let args = args
+              .iter()
+              .map(|&arg| BasicMetadataValueEnum::from(value(arg)))
+              .collect_vec();
+            self
+              .builder
+              .build_call(callee, &args, "")
+              .try_as_basic_value()
+              .left()
+          }
+          Inst::Phi(_) => {
+            let phi = self.builder.build_phi(self.ir_type(&data.ty), "");
+            phis.push((phi, id));
+            Some(phi.as_basic_value())
+          }
+          Inst::Br(target) => {
+            self.builder.build_unconditional_branch(blocks[target]);
+            None
+          }
+          Inst::CondBr { cond, then, els } => {
+            let cond = value(*cond).into_int_value();
+            self
+              .builder
+              .build_conditional_branch(cond, blocks[then], blocks[els]);
+            None
+          }
+          Inst::Ret(val) => {
+            let val = val.map(value);
+            self
+              .builder
+              .build_return(val.as_ref().map(|val| val as &dyn BasicValue));
+            None
+          }
+        };
+        if let Some(res) = res {
+          values.insert(id, res);
+        }
+      }
+    }
+    for (phi, id) in phis {
+      let Inst::Phi(incoming) = &func.inst(id).kind else {
+        unreachable!()
+      };
+      let incoming: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)> = incoming
+        .iter()
+        .map(|&(block, val)| (self.ir_value(func, globals, &values, val), blocks[&block]))
+        .collect();
+      phi.add_incoming(
+        &incoming
+          .iter()
+          .map(|(val, block)| (val as &dyn BasicValue, *block))
+          .collect_vec(),
+      );
+    }
+    if !fn_val.verify(true) {
+      return Err(Error::LLVM(format!("invalid function `{}`", func.name)));
+    }
+    self.run_function_passes(fn_val);
+    Ok(())
+  }
+}
+
+fn undef(ty: BasicTypeEnum) -> BasicValueEnum {
+  match ty {
+    BasicTypeEnum::ArrayType(ty) => ty.get_undef().into(),
+    BasicTypeEnum::FloatType(ty) => ty.get_undef().into(),
+    BasicTypeEnum::IntType(ty) => ty.get_undef().into(),
+    BasicTypeEnum::PointerType(ty) => ty.get_undef().into(),
+    BasicTypeEnum::StructType(ty) => ty.get_undef().into(),
+    BasicTypeEnum::VectorType(ty) => ty.get_undef().into(),
+  }
+}
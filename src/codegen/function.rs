@@ -13,7 +13,10 @@ use itertools::Itertools;
 use miette::NamedSource;
 use tree_sitter::Node;
 
-use super::{BaseType, Generator, MBasicType};
+use super::{
+  symbol::{FunctionSymbol, ParamSymbol},
+  BaseType, Generator, MBasicType,
+};
 
 impl<'ctx> Generator<'ctx> {
   pub(super) fn generate_function_proto(&mut self, function: Node) -> Result<()> {
@@ -66,17 +69,30 @@ impl<'ctx> Generator<'ctx> {
       // TODO: va arg
       .fn_type(&meta_params, false);
     self.module.add_function(func_name_str, fn_ty, None);
-    self.function_map.insert(
-      func_name_str.to_string(),
-      (
-        MBasicType::new_with_base_mut(ret_type),
-        params
-          .into_iter()
-          .map(|(param_type, name)| (name, MBasicType::new_with_base_mut(param_type)))
-          .collect_vec(),
-        false,
-      ),
+    let proto = (
+      MBasicType::new_with_base_mut(ret_type),
+      params
+        .into_iter()
+        .map(|(param_type, name)| (name, MBasicType::new_with_base_mut(param_type)))
+        .collect_vec(),
+      false,
     );
+    self.symbol_table.functions.push(FunctionSymbol {
+      name: func_name_str.to_string(),
+      return_type: proto.0.to_string(),
+      params: proto
+        .1
+        .iter()
+        .map(|(name, ty)| ParamSymbol {
+          name: name.to_string(),
+          ty: ty.to_string(),
+        })
+        .collect_vec(),
+      is_va_arg: proto.2,
+      is_builtin: false,
+      location: Some(func_name.start_position().into()),
+    });
+    self.function_map.insert(func_name_str.to_string(), proto);
     Ok(())
   }
 
@@ -97,14 +113,8 @@ impl<'ctx> Generator<'ctx> {
 
     for ((name, ty), llvm_value) in func_params.into_iter().zip_eq(func.get_param_iter()) {
       llvm_value.set_name(name);
-      let builder = self.context.create_builder();
-      let func_entry = func.get_first_basic_block().unwrap();
-      match func_entry.get_first_instruction() {
-        Some(first_inst) => builder.position_before(&first_inst),
-        None => builder.position_at_end(func_entry),
-      }
       let llvm_type = ty.base_type.to_llvm_type(self.context);
-      let alloca = builder.build_alloca(llvm_type, name);
+      let alloca = self.build_entry_alloca(llvm_type, name);
       func_param_alloca.push(alloca);
       self.insert_to_val_map(&ty, name, alloca, param_list.range())?;
     }
@@ -138,6 +148,7 @@ impl<'ctx> Generator<'ctx> {
       func.print_to_stderr();
       unreachable!();
     }
+    self.run_function_passes(func);
 
     self.val_map_block_stack.pop();
     self.current_function = None;
@@ -276,6 +287,20 @@ impl<'ctx> Generator<'ctx> {
     ];
     for func @ (name, (ret_ty, params, is_va_arg)) in &functions {
       self.function_map.insert(func.0.to_string(), func.1.clone());
+      self.symbol_table.functions.push(FunctionSymbol {
+        name: name.to_string(),
+        return_type: ret_ty.to_string(),
+        params: params
+          .iter()
+          .map(|(name, ty)| ParamSymbol {
+            name: name.to_string(),
+            ty: ty.to_string(),
+          })
+          .collect_vec(),
+        is_va_arg: *is_va_arg,
+        is_builtin: true,
+        location: None,
+      });
       let llvm_params = params
         .iter()
         .map(|(_, param_type)| param_type.base_type.to_llvm_type(self.context))
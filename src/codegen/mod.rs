@@ -1,12 +1,17 @@
 mod dbg;
 mod expression;
+mod from_ir;
 mod function;
 mod global;
+pub mod passes;
+mod recursion;
 mod statememt;
+pub mod symbol;
+pub mod target;
 mod utils;
 mod var;
 use std::{
-  collections::{HashMap, VecDeque},
+  collections::{HashMap, HashSet, VecDeque},
   fmt::Display,
   path::Path,
 };
@@ -15,13 +20,23 @@ use inkwell::{
   basic_block::BasicBlock,
   builder::Builder,
   context::Context,
-  module::Module,
+  module::{FlagBehavior, Module},
+  passes::PassManager,
   types::{BasicType, BasicTypeEnum},
   values::{FunctionValue, PointerValue},
 };
 use tree_sitter::Tree;
 
-use crate::error::{Error, Result};
+use crate::{
+  error::{Error, Result},
+  util::OptLevel,
+};
+
+use self::{
+  passes::{Pass, PassTimings},
+  symbol::SymbolTable,
+  target::TargetSpec,
+};
 
 pub struct Generator<'ctx> {
   file: File<'ctx>,
@@ -44,6 +59,20 @@ pub struct Generator<'ctx> {
   // hashset for functions
   function_map: HashMap<String, (MBasicType, Vec<(&'ctx str, MBasicType)>, bool)>,
   // hashset for global variable
+
+  // functions and globals in source order, for reports
+  symbol_table: SymbolTable,
+  // machine the module is generated for
+  target: TargetSpec,
+  // optimizations run on each function as it is finished
+  function_passes: Vec<(Pass, PassManager<FunctionValue<'ctx>>)>,
+  // optimizations run on the finished module
+  module_passes: Vec<Pass>,
+  pass_timings: PassTimings,
+  // whether big local arrays of non-recursive functions are static
+  static_arrays: bool,
+  // functions that may be active more than once at a time
+  recursive_functions: HashSet<String>,
 }
 #[derive(Debug, Clone, PartialEq)]
 pub struct MBasicType {
@@ -152,16 +181,35 @@ impl TryFrom<&str> for BaseType {
 
 impl<'ctx> Generator<'ctx> {
   pub fn new(context: &'ctx Context, path: &'ctx str, content: &'ctx str) -> Generator<'ctx> {
+    Self::new_with_target(context, path, content, TargetSpec::host())
+      .expect("host target is always available")
+  }
+  pub fn new_with_target(
+    context: &'ctx Context,
+    path: &'ctx str,
+    content: &'ctx str,
+    target: TargetSpec,
+  ) -> Result<Generator<'ctx>> {
     let module_name = Path::new(path).file_stem().unwrap().to_str().unwrap();
     let file = File {
       content,
       name: path,
     };
     let module = context.create_module(module_name);
+    let machine = target.create_target_machine(OptLevel::O0)?;
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    if let Some(abi) = target.abi_name() {
+      module.add_metadata_flag(
+        "target-abi",
+        FlagBehavior::Error,
+        context.metadata_string(abi),
+      );
+    }
     let builder = context.create_builder();
     let global_variable_map = HashMap::new();
     let val_map_block_stack = vec![global_variable_map];
-    Generator {
+    Ok(Generator {
       file,
       context,
       module,
@@ -171,16 +219,39 @@ impl<'ctx> Generator<'ctx> {
       break_labels: VecDeque::new(),
       continue_labels: VecDeque::new(),
       function_map: HashMap::new(),
-    }
+      symbol_table: SymbolTable::default(),
+      target,
+      function_passes: Vec::new(),
+      module_passes: Vec::new(),
+      pass_timings: PassTimings::default(),
+      static_arrays: false,
+      recursive_functions: HashSet::new(),
+    })
   }
   pub fn gen(&mut self, ast: &Tree) -> Result<()> {
     let root = ast.root_node();
+    self.recursive_functions = recursion::recursive_functions(root, self.file.content);
     self.generate_builtin_function()?;
     self.generate_global_proto(root)?;
     self.generate_global_definition(root)?;
+    self.run_module_passes()?;
     Ok(())
   }
+  /// Keep big local arrays of functions that never recurse in static storage
+  /// instead of on the stack.
+  pub fn set_static_arrays(&mut self, enabled: bool) {
+    self.static_arrays = enabled;
+  }
+  pub fn module(&self) -> &Module<'ctx> {
+    &self.module
+  }
   pub fn write(&self, path: &str) {
     self.module.write_bitcode_to_path(Path::new(path));
   }
+  pub fn write_ir(&self, path: &Path) -> Result<()> {
+    self
+      .module
+      .print_to_file(path)
+      .map_err(|e| Error::LLVM(e.to_string()))
+  }
 }
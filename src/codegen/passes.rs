@@ -0,0 +1,377 @@
+//! Optimization pipelines on LLVM's legacy pass manager.
+//!
+//! Function passes run on each function as soon as it is generated, module
+//! passes once the whole module is. Every pass gets its own pass manager so
+//! the time it takes can be reported.
+use std::{
+  fmt,
+  time::{Duration, Instant},
+};
+
+use inkwell::{
+  passes::{PassManager, PassManagerSubType},
+  values::FunctionValue,
+};
+use itertools::Itertools;
+
+use super::Generator;
+use crate::{
+  error::{Error, Result},
+  util::OptLevel,
+};
+
+/// An LLVM pass, named as `--passes` and `opt` name it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+  Sroa,
+  Mem2Reg,
+  EarlyCse,
+  InstCombine,
+  Reassociate,
+  Gvn,
+  Sccp,
+  JumpThreading,
+  CorrelatedPropagation,
+  SimplifyCfg,
+  LoopRotate,
+  Licm,
+  IndVars,
+  LoopIdiom,
+  LoopDeletion,
+  LoopUnroll,
+  MemCpyOpt,
+  Dse,
+  Adce,
+  TailCallElim,
+  Inline,
+  IpSccp,
+  GlobalOpt,
+  GlobalDce,
+  DeadArgElim,
+  FunctionAttrs,
+  ConstMerge,
+}
+
+impl Pass {
+  pub const ALL: [Pass; 27] = [
+    Pass::Sroa,
+    Pass::Mem2Reg,
+    Pass::EarlyCse,
+    Pass::InstCombine,
+    Pass::Reassociate,
+    Pass::Gvn,
+    Pass::Sccp,
+    Pass::JumpThreading,
+    Pass::CorrelatedPropagation,
+    Pass::SimplifyCfg,
+    Pass::LoopRotate,
+    Pass::Licm,
+    Pass::IndVars,
+    Pass::LoopIdiom,
+    Pass::LoopDeletion,
+    Pass::LoopUnroll,
+    Pass::MemCpyOpt,
+    Pass::Dse,
+    Pass::Adce,
+    Pass::TailCallElim,
+    Pass::Inline,
+    Pass::IpSccp,
+    Pass::GlobalOpt,
+    Pass::GlobalDce,
+    Pass::DeadArgElim,
+    Pass::FunctionAttrs,
+    Pass::ConstMerge,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Pass::Sroa => "sroa",
+      Pass::Mem2Reg => "mem2reg",
+      Pass::EarlyCse => "early-cse",
+      Pass::InstCombine => "instcombine",
+      Pass::Reassociate => "reassociate",
+      Pass::Gvn => "gvn",
+      Pass::Sccp => "sccp",
+      Pass::JumpThreading => "jump-threading",
+      Pass::CorrelatedPropagation => "correlated-propagation",
+      Pass::SimplifyCfg => "simplifycfg",
+      Pass::LoopRotate => "loop-rotate",
+      Pass::Licm => "licm",
+      Pass::IndVars => "indvars",
+      Pass::LoopIdiom => "loop-idiom",
+      Pass::LoopDeletion => "loop-deletion",
+      Pass::LoopUnroll => "loop-unroll",
+      Pass::MemCpyOpt => "memcpyopt",
+      Pass::Dse => "dse",
+      Pass::Adce => "adce",
+      Pass::TailCallElim => "tailcallelim",
+      Pass::Inline => "inline",
+      Pass::IpSccp => "ipsccp",
+      Pass::GlobalOpt => "globalopt",
+      Pass::GlobalDce => "globaldce",
+      Pass::DeadArgElim => "deadargelim",
+      Pass::FunctionAttrs => "function-attrs",
+      Pass::ConstMerge => "constmerge",
+    }
+  }
+
+  /// Passes that look at more than one function at a time.
+  pub fn is_module_pass(self) -> bool {
+    matches!(
+      self,
+      Pass::Inline
+        | Pass::IpSccp
+        | Pass::GlobalOpt
+        | Pass::GlobalDce
+        | Pass::DeadArgElim
+        | Pass::FunctionAttrs
+        | Pass::ConstMerge
+    )
+  }
+
+  fn add_to<T: PassManagerSubType>(self, pm: &PassManager<T>) {
+    match self {
+      Pass::Sroa => pm.add_scalar_repl_aggregates_pass(),
+      Pass::Mem2Reg => pm.add_promote_memory_to_register_pass(),
+      Pass::EarlyCse => pm.add_early_cse_pass(),
+      Pass::InstCombine => pm.add_instruction_combining_pass(),
+      Pass::Reassociate => pm.add_reassociate_pass(),
+      Pass::Gvn => pm.add_gvn_pass(),
+      Pass::Sccp => pm.add_sccp_pass(),
+      Pass::JumpThreading => pm.add_jump_threading_pass(),
+      Pass::CorrelatedPropagation => pm.add_correlated_value_propagation_pass(),
+      Pass::SimplifyCfg => pm.add_cfg_simplification_pass(),
+      Pass::LoopRotate => pm.add_loop_rotate_pass(),
+      Pass::Licm => pm.add_licm_pass(),
+      Pass::IndVars => pm.add_ind_var_simplify_pass(),
+      Pass::LoopIdiom => pm.add_loop_idiom_pass(),
+      Pass::LoopDeletion => pm.add_loop_deletion_pass(),
+      Pass::LoopUnroll => pm.add_loop_unroll_pass(),
+      Pass::MemCpyOpt => pm.add_memcpy_optimize_pass(),
+      Pass::Dse => pm.add_dead_store_elimination_pass(),
+      Pass::Adce => pm.add_aggressive_dce_pass(),
+      Pass::TailCallElim => pm.add_tail_call_elimination_pass(),
+      Pass::Inline => pm.add_function_inlining_pass(),
+      Pass::IpSccp => pm.add_ipsccp_pass(),
+      Pass::GlobalOpt => pm.add_global_optimizer_pass(),
+      Pass::GlobalDce => pm.add_global_dce_pass(),
+      Pass::DeadArgElim => pm.add_dead_arg_elimination_pass(),
+      Pass::FunctionAttrs => pm.add_function_attrs_pass(),
+      Pass::ConstMerge => pm.add_constant_merge_pass(),
+    }
+  }
+}
+
+impl fmt::Display for Pass {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+/// Which passes run, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pipeline {
+  /// run on each function right after it is generated
+  pub function: Vec<Pass>,
+  /// run on the module after all functions are generated
+  pub module: Vec<Pass>,
+}
+
+impl Pipeline {
+  pub fn for_level(level: OptLevel) -> Self {
+    use Pass::*;
+    match level {
+      OptLevel::O0 => Self::default(),
+      OptLevel::O1 => Self {
+        function: vec![Mem2Reg, EarlyCse, InstCombine, SimplifyCfg, Adce],
+        module: vec![GlobalDce],
+      },
+      OptLevel::O2 => Self {
+        function: vec![
+          Sroa,
+          Mem2Reg,
+          EarlyCse,
+          InstCombine,
+          Reassociate,
+          Gvn,
+          Sccp,
+          JumpThreading,
+          CorrelatedPropagation,
+          SimplifyCfg,
+          LoopRotate,
+          Licm,
+          IndVars,
+          LoopIdiom,
+          LoopDeletion,
+          LoopUnroll,
+          InstCombine,
+          Dse,
+          Adce,
+          SimplifyCfg,
+        ],
+        // clean up after inlining
+        module: vec![
+          IpSccp,
+          GlobalOpt,
+          FunctionAttrs,
+          Inline,
+          DeadArgElim,
+          InstCombine,
+          Gvn,
+          SimplifyCfg,
+          GlobalDce,
+          ConstMerge,
+        ],
+      },
+      OptLevel::O3 => {
+        let mut pipeline = Self::for_level(OptLevel::O2);
+        let after_gvn = pipeline
+          .function
+          .iter()
+          .position(|&pass| pass == Gvn)
+          .unwrap()
+          + 1;
+        pipeline.function.insert(after_gvn, MemCpyOpt);
+        pipeline.function.insert(after_gvn, TailCallElim);
+        // inlined loops get another round
+        let after_inline = pipeline
+          .module
+          .iter()
+          .position(|&pass| pass == Inline)
+          .unwrap()
+          + 1;
+        pipeline.module.splice(
+          after_inline..after_inline,
+          [Sroa, Licm, IndVars, LoopUnroll],
+        );
+        pipeline
+      }
+    }
+  }
+
+  /// Parse a comma separated `--passes` list. The passes run once on the
+  /// whole module, in the order given.
+  pub fn parse(spec: &str) -> Result<Self> {
+    let module = spec
+      .split(',')
+      .map(str::trim)
+      .filter(|name| !name.is_empty())
+      .map(|name| {
+        Pass::ALL
+          .into_iter()
+          .find(|pass| pass.name() == name)
+          .ok_or_else(|| Error::UnknownPass {
+            name: name.to_string(),
+            known: Pass::ALL.iter().join(", "),
+          })
+      })
+      .collect::<Result<Vec<_>>>()?;
+    Ok(Self {
+      function: Vec::new(),
+      module,
+    })
+  }
+}
+
+/// Time spent in each pass, summed over all the functions it ran on.
+#[derive(Debug, Clone, Default)]
+pub struct PassTimings {
+  // (pass, total time, runs), in the order passes first ran
+  entries: Vec<(Pass, Duration, usize)>,
+}
+
+impl PassTimings {
+  fn record(&mut self, pass: Pass, elapsed: Duration) {
+    match self.entries.iter_mut().find(|(p, ..)| *p == pass) {
+      Some((_, total, runs)) => {
+        *total += elapsed;
+        *runs += 1;
+      }
+      None => self.entries.push((pass, elapsed, 1)),
+    }
+  }
+  pub fn total(&self) -> Duration {
+    self.entries.iter().map(|(_, elapsed, _)| *elapsed).sum()
+  }
+}
+
+impl fmt::Display for PassTimings {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let total = self.total().as_secs_f64().max(f64::EPSILON);
+    writeln!(f, "{:<24}{:>8}{:>12}{:>8}", "pass", "runs", "time", "%")?;
+    for (pass, elapsed, runs) in self
+      .entries
+      .iter()
+      .sorted_by_key(|(_, elapsed, _)| *elapsed)
+      .rev()
+    {
+      writeln!(
+        f,
+        "{:<24}{:>8}{:>10.3}ms{:>7.1}%",
+        pass.name(),
+        runs,
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_secs_f64() / total * 100.0
+      )?;
+    }
+    writeln!(
+      f,
+      "{:<32}{:>10.3}ms",
+      "total",
+      self.total().as_secs_f64() * 1000.0
+    )
+  }
+}
+
+impl<'ctx> Generator<'ctx> {
+  /// Choose the passes `gen` runs. Call it before `gen`; without it nothing
+  /// is optimized.
+  pub fn set_pipeline(&mut self, pipeline: Pipeline) {
+    self.function_passes = pipeline
+      .function
+      .iter()
+      .map(|&pass| {
+        assert!(
+          !pass.is_module_pass(),
+          "{pass} can not run on a single function"
+        );
+        let fpm = PassManager::create(&self.module);
+        pass.add_to(&fpm);
+        fpm.initialize();
+        (pass, fpm)
+      })
+      .collect();
+    self.module_passes = pipeline.module;
+  }
+
+  pub fn pass_timings(&self) -> &PassTimings {
+    &self.pass_timings
+  }
+
+  pub(super) fn run_function_passes(&mut self, func: FunctionValue<'ctx>) {
+    for (pass, fpm) in &self.function_passes {
+      let start = Instant::now();
+      fpm.run_on(&func);
+      self.pass_timings.record(*pass, start.elapsed());
+    }
+  }
+
+  pub(super) fn run_module_passes(&mut self) -> Result<()> {
+    if self.module_passes.is_empty() {
+      return Ok(());
+    }
+    // llvm passes assume valid input and may crash otherwise
+    self
+      .module
+      .verify()
+      .map_err(|e| Error::LLVM(e.to_string()))?;
+    for &pass in &self.module_passes {
+      let pm = PassManager::create(());
+      pass.add_to(&pm);
+      let start = Instant::now();
+      pm.run_on(&self.module);
+      self.pass_timings.record(pass, start.elapsed());
+    }
+    Ok(())
+  }
+}
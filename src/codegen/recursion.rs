@@ -0,0 +1,56 @@
+//! Which functions may be active more than once at a time.
+use std::collections::{HashMap, HashSet};
+
+use tree_sitter::Node;
+
+use crate::parser::get_text;
+
+/// Functions that can call themselves, directly or through other functions.
+pub(super) fn recursive_functions(root: Node, content: &str) -> HashSet<String> {
+  let mut cursor = root.walk();
+  let calls: HashMap<&str, HashSet<&str>> = root
+    .children(&mut cursor)
+    .filter(|node| node.kind() == "function_definition")
+    .map(|function| {
+      let name = get_text(function.child_by_field_name("name").unwrap(), content);
+      let mut callees = HashSet::new();
+      collect_callees(function, content, &mut callees);
+      (name, callees)
+    })
+    .collect();
+  calls
+    .keys()
+    .filter(|&&name| reaches(&calls, name, name))
+    .map(|name| name.to_string())
+    .collect()
+}
+
+fn collect_callees<'a>(node: Node, content: &'a str, callees: &mut HashSet<&'a str>) {
+  if node.kind() == "call_expression" {
+    callees.insert(get_text(
+      node.child_by_field_name("function").unwrap(),
+      content,
+    ));
+  }
+  let mut cursor = node.walk();
+  for child in node.children(&mut cursor) {
+    collect_callees(child, content, callees);
+  }
+}
+
+/// Whether a call chain leads from `from` to `to`.
+fn reaches(calls: &HashMap<&str, HashSet<&str>>, from: &str, to: &str) -> bool {
+  let mut visited = HashSet::new();
+  let mut stack = vec![from];
+  while let Some(function) = stack.pop() {
+    for &callee in calls.get(function).into_iter().flatten() {
+      if callee == to {
+        return true;
+      }
+      if visited.insert(callee) {
+        stack.push(callee);
+      }
+    }
+  }
+  false
+}
@@ -12,13 +12,7 @@ impl<'ctx> Generator<'ctx> {
     let cond = root.child_by_field_name("condition").unwrap();
     let consequence = root.child_by_field_name("consequence").unwrap();
     let alternative = root.child_by_field_name("alternative");
-    let cond_expr = self.generate_expression(cond)?.1.into_int_value();
-    let cond_expr_i1 = self
-      .builder
-      .build_int_cast(cond_expr, self.context.bool_type(), "cond_i1");
-    self
-      .builder
-      .build_conditional_branch(cond_expr_i1, consequence_block, alternative_block);
+    self.generate_condition(cond, consequence_block, alternative_block)?;
     self.builder.position_at_end(consequence_block);
     self.generate_statement(consequence)?;
     if self.no_terminator() {
@@ -19,15 +19,8 @@ impl<'ctx> Generator<'ctx> {
     self.builder.build_unconditional_branch(before_loop_block);
     
     self.builder.position_at_end(before_loop_block);
-    let cond_expr = self.generate_expression(cond)?.1.into_int_value();
-    let cond_expr_i1 = self
-      .builder
-      .build_int_cast(cond_expr, self.context.bool_type(), "cond_i1");
-      
-    self
-      .builder
-      .build_conditional_branch(cond_expr_i1, loop_body_block, after_loop_block);
-      
+    self.generate_condition(cond, loop_body_block, after_loop_block)?;
+
     self.builder.position_at_end(loop_body_block);
     self.generate_statement(body)?;
     if self.no_terminator() {
@@ -0,0 +1,137 @@
+use itertools::Itertools;
+use serde::Serialize;
+use tree_sitter::Point;
+
+/// Symbols collected while generating a module, kept in source order so the
+/// report is stable between runs.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SymbolTable {
+  pub functions: Vec<FunctionSymbol>,
+  pub globals: Vec<GlobalSymbol>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionSymbol {
+  pub name: String,
+  pub return_type: String,
+  pub params: Vec<ParamSymbol>,
+  pub is_va_arg: bool,
+  pub is_builtin: bool,
+  /// `None` for builtin functions, which have no source
+  pub location: Option<Location>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamSymbol {
+  pub name: String,
+  #[serde(rename = "type")]
+  pub ty: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlobalSymbol {
+  pub name: String,
+  #[serde(rename = "type")]
+  pub ty: String,
+  pub is_const: bool,
+  /// short summary of the initializer, `None` if zero initialized implicitly
+  pub init: Option<String>,
+  pub location: Location,
+}
+
+/// 1-based source position
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Location {
+  pub line: usize,
+  pub column: usize,
+}
+
+impl From<Point> for Location {
+  fn from(point: Point) -> Self {
+    Self {
+      line: point.row + 1,
+      column: point.column + 1,
+    }
+  }
+}
+
+impl std::fmt::Display for Location {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+const INIT_SUMMARY_LEN: usize = 32;
+
+/// Collapse whitespace of an initializer and cut it to a readable length.
+pub(super) fn summarize_init(text: &str) -> String {
+  let text = text.split_whitespace().join(" ");
+  if text.chars().count() > INIT_SUMMARY_LEN {
+    format!(
+      "{}...",
+      text.chars().take(INIT_SUMMARY_LEN).collect::<String>()
+    )
+  } else {
+    text
+  }
+}
+
+impl FunctionSymbol {
+  pub fn signature(&self) -> String {
+    let mut params = self
+      .params
+      .iter()
+      .map(|ParamSymbol { name, ty }| format!("{name}: {ty}"))
+      .collect_vec();
+    if self.is_va_arg {
+      params.push("...".to_string());
+    }
+    format!(
+      "{} ({}) -> {}",
+      self.name,
+      params.join(", "),
+      self.return_type
+    )
+  }
+}
+
+impl GlobalSymbol {
+  pub fn description(&self) -> String {
+    format!(
+      "{}: {}{}",
+      self.name,
+      if self.is_const { "const " } else { "" },
+      self.ty
+    )
+  }
+}
+
+impl SymbolTable {
+  pub fn to_text(&self) -> String {
+    let mut res = String::from("function protos:\n");
+    for func in &self.functions {
+      let origin = match func.location {
+        Some(location) => format!("@ {location}"),
+        None => "builtin".to_string(),
+      };
+      res.push_str(&format!("  {} {origin}\n", func.signature()));
+    }
+    res.push_str("global vars:\n");
+    for var in &self.globals {
+      let init = match &var.init {
+        Some(init) => format!(" = {init}"),
+        None => String::new(),
+      };
+      res.push_str(&format!(
+        "  {}{init} @ {}\n",
+        var.description(),
+        var.location
+      ));
+    }
+    res
+  }
+
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(self).unwrap()
+  }
+}
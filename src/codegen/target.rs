@@ -0,0 +1,130 @@
+use std::{path::Path, sync::Once};
+
+use inkwell::{
+  targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+  },
+  OptimizationLevel,
+};
+
+use super::Generator;
+use crate::{
+  error::{Error, Result},
+  util::OptLevel,
+};
+
+static INIT_TARGETS: Once = Once::new();
+
+pub(super) fn initialize_targets() {
+  INIT_TARGETS.call_once(|| Target::initialize_all(&InitializationConfig::default()));
+}
+
+/// Which machine to generate code for, `None` fields fall back to the host.
+#[derive(Debug, Clone, Default)]
+pub struct TargetSpec {
+  pub triple: Option<String>,
+  pub cpu: Option<String>,
+  pub features: Option<String>,
+}
+
+impl TargetSpec {
+  pub fn host() -> Self {
+    Self::default()
+  }
+  pub fn triple(&self) -> TargetTriple {
+    match &self.triple {
+      Some(triple) => TargetTriple::create(triple),
+      None => TargetMachine::get_default_triple(),
+    }
+  }
+  fn arch(&self) -> Option<&str> {
+    self
+      .triple
+      .as_deref()
+      .map(|triple| triple.split('-').next().unwrap())
+  }
+  /// Features of the contest boards, used when `--features` is not given.
+  fn default_features(&self) -> &'static str {
+    match self.arch() {
+      Some(arch) if arch.starts_with("armv7") => "+vfp3,+neon",
+      Some("riscv64") | Some("riscv32") => "+m,+a,+f,+d,+c",
+      _ => "",
+    }
+  }
+  /// ABI name for targets that pick it from the `target-abi` module flag.
+  pub fn abi_name(&self) -> Option<&'static str> {
+    match self.arch() {
+      Some("riscv64") => Some("lp64d"),
+      Some("riscv32") => Some("ilp32d"),
+      _ => None,
+    }
+  }
+  pub fn create_target_machine(&self, opt_level: OptLevel) -> Result<TargetMachine> {
+    initialize_targets();
+    let triple = self.triple();
+    let target = Target::from_triple(&triple).map_err(|e| Error::LLVM(e.to_string()))?;
+    let (cpu, features) = match &self.triple {
+      // host cpu and features are meaningless for another triple, an empty
+      // cpu lets llvm pick the generic one of the arch
+      Some(_) => (
+        self.cpu.clone().unwrap_or_default(),
+        self
+          .features
+          .clone()
+          .unwrap_or_else(|| self.default_features().to_string()),
+      ),
+      None => (
+        self
+          .cpu
+          .clone()
+          .unwrap_or_else(|| TargetMachine::get_host_cpu_name().to_string()),
+        self
+          .features
+          .clone()
+          .unwrap_or_else(|| TargetMachine::get_host_cpu_features().to_string()),
+      ),
+    };
+    target
+      .create_target_machine(
+        &triple,
+        &cpu,
+        &features,
+        opt_level.into(),
+        RelocMode::PIC,
+        CodeModel::Default,
+      )
+      .ok_or_else(|| {
+        Error::LLVM(format!(
+          "can not create target machine for {}",
+          triple.as_str().to_string_lossy()
+        ))
+      })
+  }
+}
+
+impl From<OptLevel> for OptimizationLevel {
+  fn from(level: OptLevel) -> Self {
+    match level {
+      OptLevel::O0 => OptimizationLevel::None,
+      OptLevel::O1 => OptimizationLevel::Less,
+      OptLevel::O2 => OptimizationLevel::Default,
+      OptLevel::O3 => OptimizationLevel::Aggressive,
+    }
+  }
+}
+
+impl<'ctx> Generator<'ctx> {
+  /// Write assembly or an object file for the generator's target without any
+  /// external tool.
+  pub fn write_machine_code(
+    &self,
+    opt_level: OptLevel,
+    file_type: FileType,
+    path: &Path,
+  ) -> Result<()> {
+    let machine = self.target.create_target_machine(opt_level)?;
+    machine
+      .write_to_file(&self.module, file_type, path)
+      .map_err(|e| Error::LLVM(e.to_string()))
+  }
+}
@@ -3,9 +3,10 @@ use crate::{
   parser::{to_source_span},
 };
 use inkwell::{
+  types::BasicType,
   values::{
     BasicValueEnum,
-    InstructionOpcode::{FPToSI, SIToFP},
+    InstructionOpcode::{self, FPToSI, SIToFP},
     PointerValue,
   },
 };
@@ -23,6 +24,30 @@ impl<'ctx> Generator<'ctx> {
     let terminator = block.unwrap().get_terminator();
     terminator.is_none()
   }
+  /// Allocate a local in the entry block of the current function, after the
+  /// allocas already there, so loops do not grow the stack and mem2reg can
+  /// promote it. Initialization stays at the insertion point.
+  pub(super) fn build_entry_alloca<T: BasicType<'ctx>>(
+    &self,
+    ty: T,
+    name: &str,
+  ) -> PointerValue<'ctx> {
+    let (func, _) = self
+      .current_function
+      .as_ref()
+      .expect("locals only exist in functions");
+    let entry = func.get_first_basic_block().unwrap();
+    let mut inst = entry.get_first_instruction();
+    while let Some(alloca) = inst.filter(|inst| inst.get_opcode() == InstructionOpcode::Alloca) {
+      inst = alloca.get_next_instruction();
+    }
+    let builder = self.context.create_builder();
+    match inst {
+      Some(inst) => builder.position_before(&inst),
+      None => builder.position_at_end(entry),
+    }
+    builder.build_alloca(ty, name)
+  }
   pub(super) fn insert_to_val_map(
     &mut self,
     var_type: &MBasicType,
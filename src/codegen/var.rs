@@ -2,12 +2,23 @@ use crate::{
   error::{Error, Result},
   parser::{get_text, to_source_span, useful_children},
 };
-use inkwell::{types::BasicType, values::BasicValueEnum};
+use inkwell::{
+  module::Linkage,
+  types::{BasicType, BasicTypeEnum},
+  values::{BasicValue, BasicValueEnum, PointerValue},
+  AddressSpace,
+};
 use itertools::Itertools;
 use miette::NamedSource;
 use tree_sitter::Node;
 
-use super::{BaseType, Generator, MBasicType};
+use super::{
+  symbol::{summarize_init, GlobalSymbol},
+  BaseType, Generator, MBasicType,
+};
+
+/// Local arrays at least this big may be moved to static storage.
+const STATIC_ARRAY_MIN_BYTES: u64 = 16 * 1024;
 
 impl<'ctx> Generator<'ctx> {
   fn generate_var_impl(
@@ -59,41 +70,55 @@ impl<'ctx> Generator<'ctx> {
     let llvm_type = ty.to_llvm_type(self.context);
 
     let init = declarator.child_by_field_name("init");
-    let initializer = if let Some(init) = init {
+    let init_summary = init.map(|init| summarize_init(get_text(init, self.file.content)));
+    let init = init.map(|init| {
       let mut cursor = init.walk();
-      let init = init
+      init
         .children(&mut cursor)
         .find(|c| c.kind() != "comment")
-        .unwrap();
-      match init.kind() {
-        "init_list" => self.generate_array_init_list(init, &ty)?,
-        "empty_init_list" => {
-          if let BaseType::Array(ty, dim) = &ty {
-            let mut llvm_ty = ty.to_llvm_type(self.context);
-            for i in dim.iter().rev() {
-              llvm_ty = llvm_ty.array_type(*i as u32).as_basic_type_enum();
-            }
-            llvm_ty.const_zero()
-          } else {
-            unreachable!()
-          }
-        }
-        _ => {
-          let (expr_ty, val) = self.generate_expression(init)?;
-          let casted = self.cast_value(&expr_ty, &val, &ty, declarator.range())?;
-          casted
-        }
+        .unwrap()
+    });
+    let is_list = init.map_or(false, |init| init.kind().ends_with("init_list"));
+    match (&ty, init) {
+      (BaseType::Array(..), Some(init)) if !is_list => {
+        return Err(self.invalid_initializer(init, "array initialized with a scalar"));
       }
-    } else {
-      llvm_type.const_zero()
-    };
+      (BaseType::Int | BaseType::Float, Some(init)) if is_list => {
+        return Err(self.invalid_initializer(init, "init list for a scalar"));
+      }
+      _ => {}
+    }
     if is_global {
-      let global_value = self.module.add_global(llvm_type, None, name_str);
+      let initializer = match init {
+        Some(init) if init.kind() == "init_list" => self.generate_array_init_list(init, &ty)?,
+        Some(init) if init.kind() == "empty_init_list" => llvm_type.const_zero(),
+        Some(init) => {
+          let (expr_ty, val) = self.generate_expression(init)?;
+          self.cast_value(&expr_ty, &val, &ty, declarator.range())?
+        }
+        None => llvm_type.const_zero(),
+      };
+      let global_value = self
+        .module
+        .add_global(initializer.get_type(), None, name_str);
       // global_value.set_linkage(Linkage::Common);
       // if is_const {
       //   global_value.set_constant(true);
       // }
       global_value.set_initializer(&initializer);
+      let mut ptr = global_value.as_pointer_value();
+      if initializer.get_type() != llvm_type {
+        // a sparse initializer, packed structs are only byte aligned
+        global_value.set_alignment(4);
+        ptr = ptr.const_cast(llvm_type.ptr_type(AddressSpace::Generic));
+      }
+      self.symbol_table.globals.push(GlobalSymbol {
+        name: name_str.to_string(),
+        ty: ty.to_string(),
+        is_const,
+        init: init_summary,
+        location: name.start_position().into(),
+      });
       self.val_map_block_stack[0].insert(
         name_str.to_string(),
         (
@@ -101,12 +126,29 @@ impl<'ctx> Generator<'ctx> {
             is_const,
             base_type: ty,
           },
-          global_value.as_pointer_value(),
+          ptr,
         ),
       );
     } else {
-      let local_value = self.builder.build_alloca(llvm_type, name_str);
-      self.builder.build_store(local_value, initializer);
+      let local_value = match &ty {
+        BaseType::Array(elem_ty, dims) => {
+          let local_value = self.local_array_storage(llvm_type, name_str, dims);
+          self.generate_local_array_init(local_value, elem_ty, dims, init)?;
+          local_value
+        }
+        _ => {
+          let local_value = self.build_entry_alloca(llvm_type, name_str);
+          let initializer = match init {
+            Some(init) => {
+              let (expr_ty, val) = self.generate_expression(init)?;
+              self.cast_value(&expr_ty, &val, &ty, declarator.range())?
+            }
+            None => llvm_type.const_zero(),
+          };
+          self.builder.build_store(local_value, initializer);
+          local_value
+        }
+      };
       self.insert_to_val_map(
         &MBasicType {
           is_const,
@@ -120,12 +162,205 @@ impl<'ctx> Generator<'ctx> {
     Ok(())
   }
 
+  fn invalid_initializer(&self, init: Node, message: &str) -> Error {
+    Error::InvalidInitializer {
+      src: NamedSource::new(self.file.name, self.file.content.to_string()),
+      range: to_source_span(init.range()),
+      message: message.to_string(),
+    }
+  }
+
+  /// The elements an init list gives, as (flat index, expression) pairs in
+  /// source order; the elements it leaves out are zero.
+  ///
+  /// A nested list starts at the next boundary of the largest sub-array the
+  /// current position is aligned to, and fills exactly that sub-array.
+  fn flatten_init_list<'n>(&self, list: Node<'n>, dims: &[i32]) -> Result<Vec<(usize, Node<'n>)>> {
+    let mut items = Vec::new();
+    let dims = dims.iter().map(|&d| d as usize).collect_vec();
+    self.flatten_init_list_at(list, 0, &dims, &mut items)?;
+    Ok(items)
+  }
+
+  fn flatten_init_list_at<'n>(
+    &self,
+    list: Node<'n>,
+    base: usize,
+    dims: &[usize],
+    items: &mut Vec<(usize, Node<'n>)>,
+  ) -> Result<()> {
+    let size = dims.iter().product::<usize>();
+    let mut pos = base;
+    let mut cursor = list.walk();
+    let children = useful_children(&list, &mut cursor)
+      .filter(|node| node.kind() != "comment")
+      .collect_vec();
+    for item in children {
+      if pos >= base + size {
+        return Err(self.invalid_initializer(item, "excess elements in initializer"));
+      }
+      match item.kind() {
+        "init_list" | "empty_init_list" => {
+          let k = (1..dims.len())
+            .find(|&k| (pos - base) % dims[k..].iter().product::<usize>() == 0)
+            .ok_or_else(|| self.invalid_initializer(item, "braces around scalar initializer"))?;
+          self.flatten_init_list_at(item, pos, &dims[k..], items)?;
+          pos += dims[k..].iter().product::<usize>();
+        }
+        _ => {
+          items.push((pos, item));
+          pos += 1;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Big arrays of functions that are never active twice live in a private
+  /// global when static arrays are enabled, everything else on the stack.
+  fn local_array_storage(
+    &self,
+    llvm_type: BasicTypeEnum<'ctx>,
+    name: &str,
+    dims: &[i32],
+  ) -> PointerValue<'ctx> {
+    let func = self.current_function.as_ref().unwrap().0;
+    let func_name = func.get_name().to_str().unwrap();
+    let bytes = dims.iter().map(|&d| d as u64).product::<u64>() * 4;
+    if !self.static_arrays
+      || bytes < STATIC_ARRAY_MIN_BYTES
+      || self.recursive_functions.contains(func_name)
+    {
+      return self.build_entry_alloca(llvm_type, name);
+    }
+    let global = self
+      .module
+      .add_global(llvm_type, None, &format!("{func_name}.{name}"));
+    global.set_linkage(Linkage::Internal);
+    global.set_initializer(&llvm_type.const_zero());
+    global.as_pointer_value()
+  }
+
+  /// Zero the whole array with one memset, then store only the elements the
+  /// init list gives a non-zero value.
+  fn generate_local_array_init(
+    &self,
+    ptr: PointerValue<'ctx>,
+    elem_ty: &BaseType,
+    dims: &[i32],
+    init: Option<Node>,
+  ) -> Result<()> {
+    // int and float are both 4 bytes
+    let bytes = dims.iter().map(|&d| d as u64).product::<u64>() * 4;
+    self
+      .builder
+      .build_memset(
+        ptr,
+        4,
+        self.context.i8_type().const_zero(),
+        self.context.i64_type().const_int(bytes, false),
+      )
+      .map_err(|e| Error::LLVM(e.to_string()))?;
+    let items = match init {
+      Some(list) if list.kind() == "init_list" => self.flatten_init_list(list, dims)?,
+      _ => Vec::new(),
+    };
+    for (pos, item) in items {
+      let (item_ty, val) = self.generate_expression(item)?;
+      let val = self.cast_value(&item_ty, &val, elem_ty, item.range())?;
+      if is_zero_constant(val) {
+        continue;
+      }
+      let mut rest = pos;
+      let mut indices = dims
+        .iter()
+        .rev()
+        .map(|&d| {
+          let index = rest % d as usize;
+          rest /= d as usize;
+          self.context.i32_type().const_int(index as u64, false)
+        })
+        .collect_vec();
+      indices.push(self.context.i32_type().const_zero());
+      indices.reverse();
+      let elem_ptr = unsafe { self.builder.build_in_bounds_gep(ptr, &indices, "init_elem") };
+      self.builder.build_store(elem_ptr, val);
+    }
+    Ok(())
+  }
+
+  /// Constant initializer of a global array. Its size follows the non-zero
+  /// elements: all-zero sub-arrays are `zeroinitializer`, and sub-arrays with
+  /// trailing zeros become packed structs ending in a `zeroinitializer`
+  /// array, which have the same layout as the array itself.
   fn generate_array_init_list(
     &self,
-    _init_list: Node,
-    _array_ty: &BaseType,
-  ) -> Result<BasicValueEnum> {
-    todo!()
+    init_list: Node,
+    array_ty: &BaseType,
+  ) -> Result<BasicValueEnum<'ctx>> {
+    let (elem_ty, dims) = match array_ty {
+      BaseType::Array(elem_ty, dims) => (elem_ty, dims),
+      _ => unreachable!(),
+    };
+    let mut items = Vec::new();
+    for (pos, item) in self.flatten_init_list(init_list, dims)? {
+      let (item_ty, val) = self.generate_expression(item)?;
+      let val = self.cast_value(&item_ty, &val, elem_ty, item.range())?;
+      if !is_zero_constant(val) {
+        items.push((pos, val));
+      }
+    }
+    let dims = dims.iter().map(|&d| d as usize).collect_vec();
+    Ok(self.const_sub_array(elem_ty.to_llvm_type(self.context), &dims, 0, &items))
+  }
+
+  /// Constant for the sub-array of `dims` starting at flat index `base`, from
+  /// the non-zero `items` in it, sorted by index.
+  pub(super) fn const_sub_array(
+    &self,
+    elem_ty: BasicTypeEnum<'ctx>,
+    dims: &[usize],
+    base: usize,
+    items: &[(usize, BasicValueEnum<'ctx>)],
+  ) -> BasicValueEnum<'ctx> {
+    let array_type = |dims: &[usize]| {
+      dims.iter().rev().fold(elem_ty, |ty, &d| {
+        ty.array_type(d as u32).as_basic_type_enum()
+      })
+    };
+    if items.is_empty() {
+      return array_type(dims).const_zero();
+    }
+    if dims.is_empty() {
+      return items[0].1;
+    }
+    let sub_ty = array_type(&dims[1..]);
+    let stride = dims[1..].iter().product::<usize>();
+    let last = (items.last().unwrap().0 - base) / stride;
+    let mut elements = (0..=last)
+      .map(|i| {
+        let start = base + i * stride;
+        let lo = items.partition_point(|(pos, _)| *pos < start);
+        let hi = items.partition_point(|(pos, _)| *pos < start + stride);
+        self.const_sub_array(elem_ty, &dims[1..], start, &items[lo..hi])
+      })
+      .collect_vec();
+    let trailing = dims[0] - last - 1;
+    if trailing == 0 && elements.iter().all(|element| element.get_type() == sub_ty) {
+      return const_array(sub_ty, &elements);
+    }
+    if trailing > 0 {
+      elements.push(
+        sub_ty
+          .array_type(trailing as u32)
+          .const_zero()
+          .as_basic_value_enum(),
+      );
+    }
+    self
+      .context
+      .const_struct(&elements, true)
+      .as_basic_value_enum()
   }
 
   pub(super) fn generate_global_var(&mut self, root: Node) -> Result<()> {
@@ -159,3 +394,41 @@ impl<'ctx> Generator<'ctx> {
     Ok(())
   }
 }
+
+/// A constant array of `values`, which all have type `elem_ty`.
+fn const_array<'ctx>(
+  elem_ty: BasicTypeEnum<'ctx>,
+  values: &[BasicValueEnum<'ctx>],
+) -> BasicValueEnum<'ctx> {
+  match elem_ty {
+    BasicTypeEnum::IntType(ty) => {
+      ty.const_array(&values.iter().map(|val| val.into_int_value()).collect_vec())
+    }
+    BasicTypeEnum::FloatType(ty) => ty.const_array(
+      &values
+        .iter()
+        .map(|val| val.into_float_value())
+        .collect_vec(),
+    ),
+    BasicTypeEnum::ArrayType(ty) => ty.const_array(
+      &values
+        .iter()
+        .map(|val| val.into_array_value())
+        .collect_vec(),
+    ),
+    _ => unreachable!(),
+  }
+  .as_basic_value_enum()
+}
+
+/// Whether `val` is a constant with all bits zero, which a memset already
+/// stores.
+fn is_zero_constant(val: BasicValueEnum) -> bool {
+  match val {
+    BasicValueEnum::IntValue(val) => val.get_zero_extended_constant() == Some(0),
+    BasicValueEnum::FloatValue(val) => {
+      matches!(val.get_constant(), Some((val, _)) if val == 0.0 && val.is_sign_positive())
+    }
+    _ => false,
+  }
+}
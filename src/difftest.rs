@@ -0,0 +1,197 @@
+//! Differential testing: run a program compiled by [`Generator`] against the
+//! same source compiled as C by clang, which serves as the oracle since SysY
+//! is a subset of C.
+use std::{
+  fmt,
+  io::{Read, Write},
+  path::Path,
+  process::{Command, Stdio},
+  thread::JoinHandle,
+  time::{Duration, Instant},
+};
+
+use inkwell::context::Context;
+
+use crate::{
+  codegen::{passes::Pipeline, Generator},
+  emit::{emit_module, EmitKind},
+  error::{Error, Result},
+  parser::parse,
+  runtime::ProgramOutput,
+  util::{get_temp_path, run_tool, OptLevel},
+};
+
+/// Where two runs of the same program part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+  /// first differing stdout line, 1-based; `None` past the end of output
+  Line {
+    line: usize,
+    expected: Option<String>,
+    actual: Option<String>,
+  },
+  ExitCode {
+    expected: i32,
+    actual: i32,
+  },
+}
+
+impl fmt::Display for Divergence {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn show(line: &Option<String>) -> String {
+      match line {
+        Some(line) => format!("`{line}`"),
+        None => "end of output".to_string(),
+      }
+    }
+    match self {
+      Divergence::Line {
+        line,
+        expected,
+        actual,
+      } => write!(
+        f,
+        "output differs at line {line}: expected {}, got {}",
+        show(expected),
+        show(actual)
+      ),
+      Divergence::ExitCode { expected, actual } => {
+        write!(f, "exit code differs: expected {expected}, got {actual}")
+      }
+    }
+  }
+}
+
+/// Compile `source` as C against `sylib.h` with clang.
+pub fn build_reference(source: &Path, exe_path: &Path) -> Result<()> {
+  run_tool(
+    "clang",
+    Command::new("clang")
+      // sylib.h defines its timer globals in the header
+      .args(["-fcommon", "-w", "-x", "c"])
+      .args(["-include", "./compiler2022/runtime/sylib.h"])
+      .arg(source)
+      .args(["-x", "none", "./compiler2022/runtime/sylib.c", "-o"])
+      .arg(exe_path),
+  )
+}
+
+/// How long a program may run before it is considered hanging.
+pub const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run an executable with `input` as stdin, collecting stdout, stderr and
+/// the exit status. It is killed after [`RUN_TIMEOUT`].
+pub fn run_executable(exe_path: &Path, input: &[u8]) -> Result<ProgramOutput> {
+  run_executable_with_timeout(exe_path, input, RUN_TIMEOUT)
+}
+
+/// [`run_executable`] for programs that are expected to run long.
+pub fn run_executable_with_timeout(
+  exe_path: &Path,
+  input: &[u8],
+  timeout: Duration,
+) -> Result<ProgramOutput> {
+  let tool_failed = |e: std::io::Error| Error::ToolFailed {
+    tool: exe_path.display().to_string(),
+    reason: e.to_string(),
+  };
+  let mut child = Command::new(exe_path)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(tool_failed)?;
+  // feed stdin and drain the pipes on other threads, so a chatty program
+  // can not block us; the program may exit without reading all its input
+  let mut stdin = child.stdin.take().unwrap();
+  let input = input.to_vec();
+  std::thread::spawn(move || stdin.write_all(&input));
+  let stdout = read_in_background(child.stdout.take().unwrap());
+  let stderr = read_in_background(child.stderr.take().unwrap());
+  let deadline = Instant::now() + timeout;
+  let status = loop {
+    if let Some(status) = child.try_wait().map_err(tool_failed)? {
+      break status;
+    }
+    if Instant::now() >= deadline {
+      child.kill().ok();
+      child.wait().ok();
+      return Err(Error::Timeout {
+        program: exe_path.display().to_string(),
+        seconds: timeout.as_secs(),
+      });
+    }
+    std::thread::sleep(Duration::from_millis(5));
+  };
+  let stdout = stdout.join().unwrap().map_err(tool_failed)?;
+  let stderr = stderr.join().unwrap().map_err(tool_failed)?;
+  Ok(ProgramOutput {
+    stdout,
+    // a program killed by a signal has no exit code
+    exit_code: status.code().unwrap_or(-1),
+    timing: String::from_utf8_lossy(&stderr).to_string(),
+  })
+}
+
+fn read_in_background(
+  mut pipe: impl Read + Send + 'static,
+) -> JoinHandle<std::io::Result<Vec<u8>>> {
+  std::thread::spawn(move || {
+    let mut buf = Vec::new();
+    pipe.read_to_end(&mut buf).map(|_| buf)
+  })
+}
+
+/// Compare stdout line by line, then the exit code.
+pub fn compare(expected: &ProgramOutput, actual: &ProgramOutput) -> Option<Divergence> {
+  let expected_out = String::from_utf8_lossy(&expected.stdout);
+  let actual_out = String::from_utf8_lossy(&actual.stdout);
+  let mut expected_lines = expected_out.lines();
+  let mut actual_lines = actual_out.lines();
+  for line in 1.. {
+    let (e, a) = (expected_lines.next(), actual_lines.next());
+    if e.is_none() && a.is_none() {
+      break;
+    }
+    if e != a {
+      return Some(Divergence::Line {
+        line,
+        expected: e.map(str::to_string),
+        actual: a.map(str::to_string),
+      });
+    }
+  }
+  if expected.exit_code != actual.exit_code {
+    return Some(Divergence::ExitCode {
+      expected: expected.exit_code,
+      actual: actual.exit_code,
+    });
+  }
+  None
+}
+
+/// Build `source` with both compilers, run them on `input` and compare.
+///
+/// Codegen recurses on the syntax tree, so call this on a big stack.
+pub fn difftest(source: &Path, input: &[u8], opt_level: OptLevel) -> Result<Option<Divergence>> {
+  let content = std::fs::read_to_string(source)?;
+  let tree = parse(&content)?;
+  let name = source.to_string_lossy();
+  let ctx = Context::create();
+  let mut gen = Generator::new(&ctx, &name, &content);
+  gen.set_pipeline(Pipeline::for_level(opt_level));
+  gen.gen(&tree)?;
+
+  let exe_path = get_temp_path(source, "exe");
+  let reference_path = get_temp_path(source, "ref.exe");
+  let res = (|| -> Result<Option<Divergence>> {
+    emit_module(&gen, EmitKind::Exe, &exe_path, opt_level)?;
+    build_reference(source, &reference_path)?;
+    let expected = run_executable(&reference_path, input)?;
+    let actual = run_executable(&exe_path, input)?;
+    Ok(compare(&expected, &actual))
+  })();
+  std::fs::remove_file(&exe_path).ok();
+  std::fs::remove_file(&reference_path).ok();
+  res
+}
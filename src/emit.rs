@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgEnum;
+use inkwell::targets::FileType;
+use tree_sitter::Tree;
+
+use crate::{
+  codegen::Generator,
+  error::Result,
+  ir,
+  parser::{dump_node_to_string, dump_tokens_to_string},
+  util::{get_temp_path, link_executable, OptLevel},
+};
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmitKind {
+  /// leaf tokens of the parse tree
+  Tokens,
+  /// the parse tree
+  Ast,
+  /// our SSA IR
+  Ssa,
+  /// textual LLVM IR
+  Ir,
+  /// LLVM bitcode
+  Bc,
+  /// target assembly
+  Asm,
+  /// object file
+  Obj,
+  /// executable linked with the sysy runtime
+  Exe,
+}
+
+impl EmitKind {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      EmitKind::Tokens => "tokens",
+      EmitKind::Ast => "ast",
+      EmitKind::Ssa => "sir",
+      EmitKind::Ir => "ll",
+      EmitKind::Bc => "bc",
+      EmitKind::Asm => "s",
+      EmitKind::Obj => "o",
+      EmitKind::Exe => "exe",
+    }
+  }
+  /// Whether this kind only needs the parse tree.
+  pub fn is_syntax(&self) -> bool {
+    matches!(self, EmitKind::Tokens | EmitKind::Ast)
+  }
+}
+
+/// `<stem>.<ext>`, keeping any dots already in the stem.
+pub fn get_output_path(stem: &Path, kind: EmitKind) -> PathBuf {
+  PathBuf::from(format!("{}.{}", stem.display(), kind.extension()))
+}
+
+pub fn emit_syntax(tree: &Tree, content: &str, kind: EmitKind, path: &Path) -> Result<()> {
+  let text = match kind {
+    EmitKind::Tokens => dump_tokens_to_string(&tree.root_node(), content),
+    EmitKind::Ast => dump_node_to_string(&tree.root_node(), content),
+    _ => unreachable!("{kind:?} is not a syntax output"),
+  };
+  std::fs::write(path, text)?;
+  Ok(())
+}
+
+pub fn emit_ssa(module: &ir::Module, path: &Path) -> Result<()> {
+  std::fs::write(path, module.to_string())?;
+  Ok(())
+}
+
+pub fn emit_module(
+  gen: &Generator,
+  kind: EmitKind,
+  path: &Path,
+  opt_level: OptLevel,
+) -> Result<()> {
+  match kind {
+    EmitKind::Ir => gen.write_ir(path),
+    EmitKind::Bc => {
+      gen.write(path.to_str().unwrap());
+      Ok(())
+    }
+    EmitKind::Asm => gen.write_machine_code(opt_level, FileType::Assembly, path),
+    EmitKind::Obj => gen.write_machine_code(opt_level, FileType::Object, path),
+    EmitKind::Exe => {
+      let tmp_obj = get_temp_path(path, "o");
+      let res = gen
+        .write_machine_code(opt_level, FileType::Object, &tmp_obj)
+        .and_then(|_| link_executable(&tmp_obj, path));
+      std::fs::remove_file(&tmp_obj).ok();
+      res
+    }
+    EmitKind::Tokens | EmitKind::Ast | EmitKind::Ssa => {
+      unreachable!("{kind:?} is not a module output")
+    }
+  }
+}
@@ -11,6 +11,24 @@ pub enum Error {
   #[error("io error")]
   #[diagnostic()]
   IO(#[from] std::io::Error),
+  #[error("function `main` not found")]
+  #[diagnostic()]
+  MainNotFound,
+  #[error("llvm error: {0}")]
+  #[diagnostic()]
+  LLVM(String),
+  #[error("failed to run `{tool}`: {reason}")]
+  #[diagnostic(help("make sure the tool is installed and in PATH"))]
+  ToolFailed { tool: String, reason: String },
+  #[error("`{program}` did not finish within {seconds} seconds")]
+  #[diagnostic()]
+  Timeout { program: String, seconds: u64 },
+  #[error("the input does not trigger any failure")]
+  #[diagnostic(help("only programs the compiler mishandles can be reduced"))]
+  NothingToReduce,
+  #[error("unknown pass `{name}`")]
+  #[diagnostic(help("known passes: {known}"))]
+  UnknownPass { name: String, known: String },
   #[error("unknown type")]
   #[diagnostic()]
   UnknownType(),
@@ -54,6 +72,15 @@ pub enum Error {
     #[label("can not found this function")]
     range: SourceSpan,
   },
+  #[error("runtime error: {message}")]
+  #[diagnostic()]
+  RuntimeError {
+    #[source_code]
+    src: NamedSource,
+    #[label("{message}")]
+    range: SourceSpan,
+    message: String,
+  },
   #[error("invalid cast")]
   #[diagnostic()]
   InvalidCast {
@@ -62,5 +89,32 @@ pub enum Error {
     #[label("invalid cast here")]
     range: SourceSpan,
   },
+  #[error("invalid initializer")]
+  #[diagnostic()]
+  InvalidInitializer {
+    #[source_code]
+    src: NamedSource,
+    #[label("{message}")]
+    range: SourceSpan,
+    message: String,
+  },
+  #[error("{message}")]
+  #[diagnostic()]
+  SemanticError {
+    #[source_code]
+    src: NamedSource,
+    #[label("{message}")]
+    range: SourceSpan,
+    message: String,
+  },
+  #[error("line {line}: {message}")]
+  #[diagnostic()]
+  IrParse { line: usize, message: String },
+  #[error("invalid IR in `{function}`: {message}")]
+  #[diagnostic()]
+  IrVerify { function: String, message: String },
+  #[error("IR execution failed: {0}")]
+  #[diagnostic()]
+  IrExec(String),
 }
 pub type Result<T> = std::result::Result<T, Error>;
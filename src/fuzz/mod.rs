@@ -0,0 +1,146 @@
+//! Random testing of the compiler with generated programs.
+mod program;
+
+use std::{
+  any::Any,
+  fmt,
+  panic::{self, AssertUnwindSafe},
+  path::Path,
+};
+
+use clap::ArgEnum;
+use inkwell::context::Context;
+
+use crate::{
+  codegen::{passes::Pipeline, Generator},
+  difftest::{build_reference, compare, run_executable, Divergence},
+  emit::{emit_module, EmitKind},
+  error::Result,
+  interp::{interpret_with_limits, Limits},
+  parser::parse,
+  util::{get_temp_path, OptLevel},
+};
+
+pub use self::program::{generate_program, GenConfig, Rng};
+
+/// Where the expected output of a program comes from.
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Oracle {
+  /// the reference interpreter, needs nothing outside the process
+  Interp,
+  /// the source built as C by clang
+  Clang,
+}
+
+/// Generated programs stay far below these, programs being reduced may not.
+const ORACLE_LIMITS: Limits = Limits {
+  steps: 100_000_000,
+  call_depth: 500,
+};
+
+/// What went wrong with one program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+  /// the program does not parse, a bug of whoever wrote it
+  InvalidProgram,
+  /// codegen panicked with this message
+  Panic(String),
+  /// codegen reported an error for a valid program
+  CompileError(String),
+  /// the generated module is rejected by the LLVM verifier
+  Verifier(String),
+  /// the oracle could not run the program
+  OracleError(String),
+  Mismatch(Divergence),
+}
+
+impl fmt::Display for Finding {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Finding::InvalidProgram => write!(f, "program does not parse"),
+      Finding::Panic(msg) => write!(f, "codegen panicked: {msg}"),
+      Finding::CompileError(msg) => write!(f, "codegen failed: {msg}"),
+      Finding::Verifier(msg) => write!(f, "module verification failed: {msg}"),
+      Finding::OracleError(msg) => write!(f, "oracle failed: {msg}"),
+      Finding::Mismatch(divergence) => write!(f, "{divergence}"),
+    }
+  }
+}
+
+/// The message a panic was raised with.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+  if let Some(msg) = payload.downcast_ref::<&str>() {
+    msg.to_string()
+  } else if let Some(msg) = payload.downcast_ref::<String>() {
+    msg.clone()
+  } else {
+    "unknown panic".to_string()
+  }
+}
+
+/// Compile `content`, turning panics and verifier failures into findings.
+///
+/// Codegen recurses on the syntax tree, so call this on a big stack.
+pub fn check_codegen<'ctx>(
+  ctx: &'ctx Context,
+  name: &'ctx str,
+  content: &'ctx str,
+  opt_level: OptLevel,
+) -> std::result::Result<Generator<'ctx>, Finding> {
+  let tree = match parse(content) {
+    Ok(tree) if !tree.root_node().has_error() => tree,
+    _ => return Err(Finding::InvalidProgram),
+  };
+  let res = panic::catch_unwind(AssertUnwindSafe(|| {
+    let mut gen = Generator::new(ctx, name, content);
+    gen.set_pipeline(Pipeline::for_level(opt_level));
+    gen.gen(&tree).map(|_| gen)
+  }));
+  let gen = match res {
+    Ok(Ok(gen)) => gen,
+    Ok(Err(e)) => return Err(Finding::CompileError(e.to_string())),
+    Err(payload) => return Err(Finding::Panic(panic_message(&*payload))),
+  };
+  if let Err(msg) = gen.module().verify() {
+    return Err(Finding::Verifier(msg.to_string()));
+  }
+  Ok(gen)
+}
+
+/// Compile the program at `path`, run it, and compare with the oracle.
+///
+/// Errors are problems of the environment, like a missing linker; problems
+/// of the compiler are findings.
+pub fn check_program(path: &Path, oracle: Oracle, opt_level: OptLevel) -> Result<Option<Finding>> {
+  let content = std::fs::read_to_string(path)?;
+  let name = path.to_string_lossy().to_string();
+  let ctx = Context::create();
+  let gen = match check_codegen(&ctx, &name, &content, opt_level) {
+    Ok(gen) => gen,
+    Err(finding) => return Ok(Some(finding)),
+  };
+  let exe_path = get_temp_path(path, "exe");
+  let reference_path = get_temp_path(path, "ref.exe");
+  let res = (|| -> Result<Option<Finding>> {
+    let expected = match oracle {
+      Oracle::Interp => {
+        let tree = parse(&content)?;
+        match interpret_with_limits(&name, &content, &tree, Vec::new(), ORACLE_LIMITS) {
+          Ok(output) => output,
+          Err(e) => return Ok(Some(Finding::OracleError(e.to_string()))),
+        }
+      }
+      Oracle::Clang => {
+        build_reference(path, &reference_path)?;
+        run_executable(&reference_path, &[])?
+      }
+    };
+    // run the compiled program out of process, a miscompilation may crash
+    emit_module(&gen, EmitKind::Exe, &exe_path, opt_level)?;
+    let actual = run_executable(&exe_path, &[])?;
+    Ok(compare(&expected, &actual).map(Finding::Mismatch))
+  })();
+  std::fs::remove_file(&exe_path).ok();
+  std::fs::remove_file(&reference_path).ok();
+  res
+}
@@ -0,0 +1,972 @@
+//! Random SysY programs in the spirit of Csmith: well typed and free of
+//! undefined behavior, so any two correct implementations agree on the
+//! output.
+//!
+//! Undefined behavior is ruled out by construction:
+//! - integer arithmetic goes through the `fz_*` helpers of the prelude, which
+//!   keep operands in a range that can not overflow;
+//! - array indices are literals, loop counters known to be in bounds, or
+//!   wrapped with `fz_idx`;
+//! - floats only become ints through `fz_f2i`, which clamps;
+//! - floats are printed through `fz_putfloat`, as the sign of a NaN depends
+//!   on which operand the hardware propagated;
+//! - float literals never appear as operands of arithmetic, where C would
+//!   compute in double;
+//! - calls to generated functions are whole statements outside loops, take
+//!   a decreasing `depth`, and their arguments have no side effects, so the
+//!   evaluation order C leaves unspecified does not matter.
+use std::collections::HashSet;
+
+use itertools::Itertools;
+
+use crate::runtime::format_hex_float;
+
+/// Knobs of the program generator.
+#[derive(Debug, Clone)]
+pub struct GenConfig {
+  pub max_functions: usize,
+  /// statements per block
+  pub max_statements: usize,
+  pub max_expr_depth: usize,
+  /// nesting of `if`, `while` and blocks
+  pub max_block_depth: usize,
+  pub max_array_dims: usize,
+  pub floats: bool,
+  pub arrays: bool,
+  /// write array dimensions with constants like `c1 + 1`
+  pub const_exprs: bool,
+}
+
+impl Default for GenConfig {
+  fn default() -> Self {
+    Self {
+      max_functions: 4,
+      max_statements: 6,
+      max_expr_depth: 3,
+      max_block_depth: 3,
+      max_array_dims: 3,
+      floats: true,
+      arrays: true,
+      const_exprs: false,
+    }
+  }
+}
+
+/// SplitMix64: tiny, and gives the same program for a seed everywhere.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+  pub fn new(seed: u64) -> Self {
+    Self(seed)
+  }
+  pub fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+  }
+  /// uniform in `0..n`
+  pub fn below(&mut self, n: usize) -> usize {
+    (self.next_u64() % n as u64) as usize
+  }
+  /// true with `percent`% probability
+  pub fn chance(&mut self, percent: usize) -> bool {
+    self.below(100) < percent
+  }
+  pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+    &items[self.below(items.len())]
+  }
+}
+
+const PRELUDE: &str = "int fz_ticks;
+int fz_tick(int v) {
+  fz_ticks = fz_ticks + 1;
+  return v;
+}
+int fz_add(int a, int b) {
+  return a % 1073741824 + b % 1073741824;
+}
+int fz_sub(int a, int b) {
+  return a % 1073741824 - b % 1073741824;
+}
+int fz_mul(int a, int b) {
+  return a % 46341 * (b % 46341);
+}
+int fz_div(int a, int b) {
+  if (b == 0 || a == -2147483647 - 1 && b == -1) {
+    return a;
+  }
+  return a / b;
+}
+int fz_mod(int a, int b) {
+  if (b == 0 || b == -1) {
+    return 0;
+  }
+  return a % b;
+}
+int fz_neg(int a) {
+  if (a == -2147483647 - 1) {
+    return a;
+  }
+  return -a;
+}
+int fz_idx(int i, int n) {
+  i = i % n;
+  if (i < 0) {
+    i = i + n;
+  }
+  return i;
+}
+";
+
+const FLOAT_PRELUDE: &str = "int fz_f2i(float f) {
+  int r = 0;
+  if (f < 1000000.0 && f > -1000000.0) {
+    r = f;
+  }
+  return r;
+}
+void fz_putfloat(float f) {
+  if (f != f) {
+    putch(78);
+    return;
+  }
+  putfloat(f);
+}
+";
+
+/// Whether `name` appears as an identifier in `text`.
+fn mentions(text: &str, name: &str) -> bool {
+  text
+    .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+    .any(|word| word == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty {
+  Int,
+  Float,
+}
+
+impl Ty {
+  fn name(self) -> &'static str {
+    match self {
+      Ty::Int => "int",
+      Ty::Float => "float",
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct VarInfo {
+  name: String,
+  ty: Ty,
+  /// empty for scalars; array parameters know their first dimension too
+  dims: Vec<usize>,
+  /// read only: constants, `depth` and loop counters
+  is_const: bool,
+  /// value of an int scalar constant, usable in dimensions
+  const_value: Option<i32>,
+  /// a loop counter, always below this bound where it can be read
+  counter_bound: Option<usize>,
+}
+
+impl VarInfo {
+  fn scalar(name: String, ty: Ty) -> Self {
+    Self {
+      name,
+      ty,
+      dims: Vec::new(),
+      is_const: false,
+      const_value: None,
+      counter_bound: None,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct FuncInfo {
+  name: String,
+  /// `None` for void
+  ret: Option<Ty>,
+  /// parameters after `depth`; arrays carry their full shape
+  params: Vec<(Ty, Vec<usize>)>,
+}
+
+struct ProgramGenerator {
+  rng: Rng,
+  config: GenConfig,
+  out: String,
+  indent: usize,
+  scopes: Vec<Vec<VarInfo>>,
+  functions: Vec<FuncInfo>,
+  next_id: usize,
+  /// return type of the function being generated
+  ret: Option<Ty>,
+  in_main: bool,
+  loop_depth: usize,
+  block_depth: usize,
+  /// calls to generated functions the current function may still make
+  calls_left: usize,
+}
+
+/// Generate a program from `seed`; the same seed and config always give the
+/// same program.
+pub fn generate_program(seed: u64, config: &GenConfig) -> String {
+  let mut gen = ProgramGenerator {
+    rng: Rng::new(seed),
+    config: config.clone(),
+    out: String::new(),
+    indent: 0,
+    scopes: vec![Vec::new()],
+    functions: Vec::new(),
+    next_id: 0,
+    ret: None,
+    in_main: false,
+    loop_depth: 0,
+    block_depth: 0,
+    calls_left: 0,
+  };
+  gen.program(seed);
+  gen.out
+}
+
+impl ProgramGenerator {
+  fn line(&mut self, text: impl AsRef<str>) {
+    for _ in 0..self.indent {
+      self.out.push_str("  ");
+    }
+    self.out.push_str(text.as_ref());
+    self.out.push('\n');
+  }
+
+  fn fresh(&mut self, prefix: &str) -> String {
+    self.next_id += 1;
+    format!("{prefix}{}", self.next_id)
+  }
+
+  fn random_ty(&mut self) -> Ty {
+    if self.config.floats && self.rng.chance(30) {
+      Ty::Float
+    } else {
+      Ty::Int
+    }
+  }
+
+  /// Variables in scope, inner ones hiding outer ones of the same name.
+  fn visible(&self) -> Vec<VarInfo> {
+    let mut seen = HashSet::new();
+    let mut res = Vec::new();
+    for scope in self.scopes.iter().rev() {
+      for var in scope.iter().rev() {
+        if seen.insert(var.name.clone()) {
+          res.push(var.clone());
+        }
+      }
+    }
+    res
+  }
+
+  fn declare(&mut self, var: VarInfo) {
+    self.scopes.last_mut().unwrap().push(var);
+  }
+
+  fn program(&mut self, seed: u64) {
+    self.line(format!("// generated by `sysy fuzz --seed {seed}`"));
+    self.out.push_str(PRELUDE);
+    if self.config.floats {
+      self.out.push_str(FLOAT_PRELUDE);
+    }
+    self.globals();
+    let functions = self.rng.below(self.config.max_functions + 1);
+    for _ in 0..functions {
+      self.function();
+    }
+    self.main();
+  }
+
+  // ---- declarations ----
+
+  fn globals(&mut self) {
+    for _ in 0..1 + self.rng.below(3) {
+      let name = self.fresh("c");
+      let ty = self.random_ty();
+      let (init, const_value) = match ty {
+        Ty::Int => {
+          let value = if self.rng.chance(60) {
+            1 + self.rng.below(4) as i32
+          } else {
+            self.rng.below(100) as i32
+          };
+          (value.to_string(), Some(value))
+        }
+        Ty::Float => (self.float_literal(), None),
+      };
+      self.line(format!("const {} {name} = {init};", ty.name()));
+      self.declare(VarInfo {
+        is_const: true,
+        const_value,
+        ..VarInfo::scalar(name, ty)
+      });
+    }
+    // always have scalars of both types to read from
+    let mut types = vec![Ty::Int];
+    if self.config.floats {
+      types.push(Ty::Float);
+    }
+    for _ in 0..self.rng.below(4) {
+      let ty = self.random_ty();
+      types.push(ty);
+    }
+    for ty in types {
+      let name = self.fresh("g");
+      let dims = if self.config.arrays && self.rng.chance(40) {
+        self.random_dims()
+      } else {
+        Vec::new()
+      };
+      let mut decl = format!("{} {name}{}", ty.name(), self.dims_text(&dims));
+      if dims.is_empty() {
+        if self.rng.chance(70) {
+          let init = self.literal(ty);
+          decl.push_str(&format!(" = {init}"));
+        }
+      } else if self.rng.chance(70) {
+        let init = self.init_list(ty, &dims, true);
+        decl.push_str(&format!(" = {init}"));
+      }
+      self.line(format!("{decl};"));
+      self.declare(VarInfo {
+        dims,
+        ..VarInfo::scalar(name, ty)
+      });
+    }
+  }
+
+  fn random_dims(&mut self) -> Vec<usize> {
+    let n = 1 + self.rng.below(self.config.max_array_dims.max(1));
+    (0..n).map(|_| 1 + self.rng.below(4)).collect_vec()
+  }
+
+  /// `[2][3]`, possibly spelled with visible int constants.
+  fn dims_text(&mut self, dims: &[usize]) -> String {
+    let consts = self
+      .visible()
+      .into_iter()
+      .filter(|var| var.const_value.is_some())
+      .collect_vec();
+    dims
+      .iter()
+      .map(|&dim| {
+        if self.config.const_exprs && self.rng.chance(50) {
+          for var in &consts {
+            let value = var.const_value.unwrap();
+            if value == dim as i32 {
+              return format!("[{}]", var.name);
+            }
+            if value > 0 && value < dim as i32 {
+              return format!("[{} + {}]", var.name, dim as i32 - value);
+            }
+          }
+        }
+        format!("[{dim}]")
+      })
+      .join("")
+  }
+
+  /// A braced initializer for an array of `dims`.
+  ///
+  /// Nested lists and flattened rows are mixed, but a flattened row is
+  /// always complete, so braces only ever open at a sub-array boundary,
+  /// where SysY and C agree on what they initialize.
+  fn init_list(&mut self, ty: Ty, dims: &[usize], constant: bool) -> String {
+    if self.rng.chance(10) {
+      return "{}".to_string();
+    }
+    let count = 1 + self.rng.below(dims[0]);
+    let mut items = Vec::new();
+    for _ in 0..count {
+      if dims.len() == 1 {
+        items.push(self.init_value(ty, constant));
+      } else if self.rng.chance(60) {
+        items.push(self.init_list(ty, &dims[1..], constant));
+      } else {
+        let row = dims[1..].iter().product::<usize>();
+        for _ in 0..row {
+          items.push(self.init_value(ty, constant));
+        }
+      }
+    }
+    format!("{{{}}}", items.join(", "))
+  }
+
+  fn init_value(&mut self, ty: Ty, constant: bool) -> String {
+    if constant {
+      self.literal(ty)
+    } else {
+      self.expr(ty, self.config.max_expr_depth.saturating_sub(1), false)
+    }
+  }
+
+  fn local_declaration(&mut self) {
+    // sometimes hide a variable of an enclosing scope, keeping its type so
+    // the scalars there are to read stay the same
+    let shadowed = if self.block_depth > 0 && self.rng.chance(20) {
+      let current = self
+        .scopes
+        .last()
+        .unwrap()
+        .iter()
+        .map(|var| var.name.clone())
+        .collect::<HashSet<_>>();
+      let candidates = self
+        .visible()
+        .into_iter()
+        .filter(|var| {
+          // `depth` bounds the recursion and counters bound the loops
+          var.name != "depth" && var.counter_bound.is_none() && !current.contains(&var.name)
+        })
+        .collect_vec();
+      (!candidates.is_empty()).then(|| self.rng.pick(&candidates).clone())
+    } else {
+      None
+    };
+    let ty = match &shadowed {
+      Some(var) => var.ty,
+      None => self.random_ty(),
+    };
+    let is_const = self.rng.chance(10);
+    let dims = if self.config.arrays && self.rng.chance(30) {
+      self.random_dims()
+    } else {
+      Vec::new()
+    };
+    let dims_text = self.dims_text(&dims);
+    // locals are never read before initialization
+    let init = if !dims.is_empty() {
+      self.init_list(ty, &dims, is_const)
+    } else if is_const {
+      self.literal(ty)
+    } else {
+      self.expr(ty, self.config.max_expr_depth, false)
+    };
+    // in C the new name is already in scope in its own initializer
+    let name = match shadowed {
+      Some(var) if !mentions(&init, &var.name) => var.name,
+      _ => self.fresh("v"),
+    };
+    let qualifier = if is_const { "const " } else { "" };
+    self.line(format!(
+      "{qualifier}{} {name}{dims_text} = {init};",
+      ty.name()
+    ));
+    self.declare(VarInfo {
+      dims,
+      is_const,
+      ..VarInfo::scalar(name, ty)
+    });
+  }
+
+  // ---- functions ----
+
+  fn function(&mut self) {
+    let name = self.fresh("f");
+    let ret = match self.rng.below(4) {
+      0 => None,
+      1 if self.config.floats => Some(Ty::Float),
+      _ => Some(Ty::Int),
+    };
+    let mut params = Vec::new();
+    for _ in 0..self.rng.below(4) {
+      let ty = self.random_ty();
+      params.push((ty, Vec::new()));
+    }
+    if self.config.arrays && self.rng.chance(40) {
+      let ty = self.random_ty();
+      let n = 1 + self.rng.below(self.config.max_array_dims.clamp(1, 2));
+      let dims = (0..n).map(|_| 1 + self.rng.below(4)).collect_vec();
+      params.push((ty, dims));
+    }
+    let info = FuncInfo {
+      name: name.clone(),
+      ret,
+      params: params.clone(),
+    };
+    // registered first, so the body can recurse
+    self.functions.push(info);
+
+    let mut scope = vec![VarInfo {
+      is_const: true,
+      ..VarInfo::scalar("depth".to_string(), Ty::Int)
+    }];
+    let mut params_text = vec!["int depth".to_string()];
+    for (ty, dims) in params {
+      let param = self.fresh("p");
+      match dims.split_first() {
+        Some((_, rest)) => params_text.push(format!(
+          "{} {param}[]{}",
+          ty.name(),
+          rest.iter().map(|dim| format!("[{dim}]")).join("")
+        )),
+        None => params_text.push(format!("{} {param}", ty.name())),
+      }
+      scope.push(VarInfo {
+        dims,
+        ..VarInfo::scalar(param, ty)
+      });
+    }
+    let ret_name = ret.map_or("void", Ty::name);
+    self.line(format!("{ret_name} {name}({}) {{", params_text.join(", ")));
+    self.indent += 1;
+    self.scopes.push(scope);
+    self.ret = ret;
+    self.in_main = false;
+    self.calls_left = 3;
+    self.loop_depth = 0;
+    self.block_depth = 0;
+
+    self.line("if (depth <= 0) {");
+    self.indent += 1;
+    self.return_statement();
+    self.indent -= 1;
+    self.line("}");
+    for _ in 0..1 + self.rng.below(self.config.max_statements) {
+      self.statement();
+    }
+    self.return_statement();
+
+    self.scopes.pop();
+    self.indent -= 1;
+    self.line("}");
+  }
+
+  fn main(&mut self) {
+    self.line("int main() {");
+    self.indent += 1;
+    self.scopes.push(Vec::new());
+    self.ret = Some(Ty::Int);
+    self.in_main = true;
+    self.calls_left = 6;
+    self.loop_depth = 0;
+    self.block_depth = 0;
+    for _ in 0..1 + self.rng.below(self.config.max_statements * 2) {
+      self.statement();
+    }
+    // everything that could have changed ends up in the output
+    let globals = self.scopes[0]
+      .iter()
+      .filter(|var| !var.is_const)
+      .cloned()
+      .collect_vec();
+    for var in globals {
+      let put = match var.ty {
+        Ty::Int => "putint",
+        Ty::Float => "fz_putfloat",
+      };
+      let indices = var
+        .dims
+        .iter()
+        .map(|&dim| (0..dim).map(|i| format!("[{i}]")).collect_vec())
+        .multi_cartesian_product()
+        .collect_vec();
+      if var.dims.is_empty() {
+        self.line(format!("{put}({});", var.name));
+      } else {
+        for index in indices {
+          self.line(format!("{put}({}{});", var.name, index.join("")));
+          self.line("putch(32);");
+        }
+      }
+      self.line("putch(10);");
+    }
+    self.line("putint(fz_ticks);");
+    self.line("putch(10);");
+    self.return_statement();
+    self.scopes.pop();
+    self.indent -= 1;
+    self.line("}");
+  }
+
+  // ---- statements ----
+
+  fn statement(&mut self) {
+    let nested = self.block_depth < self.config.max_block_depth;
+    match self.rng.below(16) {
+      0 | 1 => self.local_declaration(),
+      2..=4 => self.assignment(),
+      5 | 6 => self.output(),
+      7 if nested => self.if_statement(),
+      8 if nested => self.while_statement(),
+      9 if nested => {
+        self.line("{");
+        self.nested_block();
+        self.line("}");
+      }
+      10 if self.loop_depth > 0 => {
+        let cond = self.cond(1);
+        let keyword = if self.rng.chance(50) {
+          "break"
+        } else {
+          "continue"
+        };
+        self.line(format!("if ({cond}) {keyword};"));
+      }
+      11 | 12 => self.call_statement(),
+      13 if self.rng.chance(20) => {
+        let cond = self.cond(1);
+        self.line(format!("if ({cond}) {{"));
+        self.indent += 1;
+        self.return_statement();
+        self.indent -= 1;
+        self.line("}");
+      }
+      14 => {
+        if self.rng.chance(70) {
+          let ty = self.random_ty();
+          let expr = self.expr(ty, self.config.max_expr_depth, false);
+          self.line(format!("{expr};"));
+        } else {
+          self.line(";");
+        }
+      }
+      _ => self.assignment(),
+    }
+  }
+
+  fn nested_block(&mut self) {
+    self.indent += 1;
+    self.block_depth += 1;
+    self.scopes.push(Vec::new());
+    for _ in 0..1 + self.rng.below(self.config.max_statements) {
+      self.statement();
+    }
+    self.scopes.pop();
+    self.block_depth -= 1;
+    self.indent -= 1;
+  }
+
+  fn return_statement(&mut self) {
+    match self.ret {
+      Some(ty) => {
+        let expr = self.expr(ty, self.config.max_expr_depth, false);
+        self.line(format!("return {expr};"));
+      }
+      None => self.line("return;"),
+    }
+  }
+
+  fn assignment(&mut self) {
+    let targets = self
+      .visible()
+      .into_iter()
+      .filter(|var| !var.is_const && var.counter_bound.is_none())
+      .collect_vec();
+    if targets.is_empty() {
+      return self.output();
+    }
+    let var = self.rng.pick(&targets).clone();
+    let lhs = self.element(&var, 1);
+    // int to float conversion is always defined, the other way is not
+    let rhs = if var.ty == Ty::Float && self.rng.chance(20) {
+      self.expr(Ty::Int, self.config.max_expr_depth, false)
+    } else {
+      self.expr(var.ty, self.config.max_expr_depth, false)
+    };
+    self.line(format!("{lhs} = {rhs};"));
+  }
+
+  fn output(&mut self) {
+    // no `putfarray`, float arrays may hold NaNs
+    let arrays = self
+      .visible()
+      .into_iter()
+      .filter(|var| var.ty == Ty::Int && !var.dims.is_empty())
+      .collect_vec();
+    if !arrays.is_empty() && self.rng.chance(20) {
+      let var = self.rng.pick(&arrays).clone();
+      let (last, leading) = var.dims.split_last().unwrap();
+      let indices = leading
+        .iter()
+        .map(|&dim| format!("[{}]", self.index(dim, 1)))
+        .join("");
+      let n = 1 + self.rng.below(*last);
+      self.line(format!("putarray({n}, {}{indices});", var.name));
+      return;
+    }
+    match self.random_ty() {
+      Ty::Int => {
+        let expr = self.expr(Ty::Int, self.config.max_expr_depth, false);
+        self.line(format!("putint({expr});"));
+        self.line("putch(32);");
+      }
+      Ty::Float => {
+        let expr = self.expr(Ty::Float, self.config.max_expr_depth, false);
+        self.line(format!("fz_putfloat({expr});"));
+        self.line("putch(10);");
+      }
+    }
+  }
+
+  fn if_statement(&mut self) {
+    let cond = self.cond(self.config.max_expr_depth);
+    self.line(format!("if ({cond}) {{"));
+    self.nested_block();
+    if self.rng.chance(40) {
+      self.line("} else {");
+      self.nested_block();
+    }
+    self.line("}");
+  }
+
+  /// A counted loop; the counter is bumped first, so `continue` can not
+  /// skip it.
+  fn while_statement(&mut self) {
+    let counter = self.fresh("i");
+    let bound = 1 + self.rng.below(5);
+    self.line(format!("int {counter} = 0;"));
+    self.declare(VarInfo {
+      is_const: true,
+      // 1..=bound inside the body, bound after the loop
+      counter_bound: Some(bound + 1),
+      ..VarInfo::scalar(counter.clone(), Ty::Int)
+    });
+    self.line(format!("while ({counter} < {bound}) {{"));
+    self.indent += 1;
+    self.line(format!("{counter} = {counter} + 1;"));
+    self.indent -= 1;
+    self.loop_depth += 1;
+    self.nested_block();
+    self.loop_depth -= 1;
+    self.line("}");
+  }
+
+  fn call_statement(&mut self) {
+    if self.loop_depth > 0 || self.calls_left == 0 || self.functions.is_empty() {
+      return self.output();
+    }
+    let function = self.rng.pick(&self.functions).clone();
+    let depth = if self.in_main {
+      self.rng.below(4).to_string()
+    } else {
+      "depth - 1".to_string()
+    };
+    let mut args = vec![depth];
+    for (ty, dims) in &function.params {
+      if dims.is_empty() {
+        let arg = self.expr(*ty, 2, false);
+        args.push(arg);
+      } else {
+        match self.array_arg(*ty, dims) {
+          Some(arg) => args.push(arg),
+          None => return self.output(),
+        }
+      }
+    }
+    self.calls_left -= 1;
+    let call = format!("{}({})", function.name, args.join(", "));
+    let ty = match function.ret {
+      Some(ty) => ty,
+      None => return self.line(format!("{call};")),
+    };
+    let targets = self
+      .visible()
+      .into_iter()
+      .filter(|var| {
+        var.ty == ty && var.dims.is_empty() && !var.is_const && var.counter_bound.is_none()
+      })
+      .collect_vec();
+    if targets.is_empty() || self.rng.chance(30) {
+      let name = self.fresh("v");
+      self.line(format!("{} {name} = {call};", ty.name()));
+      self.declare(VarInfo::scalar(name, ty));
+    } else {
+      let target = self.rng.pick(&targets).name.clone();
+      self.line(format!("{target} = {call};"));
+    }
+  }
+
+  /// An array, or a part of one, that fits a parameter of shape `dims`.
+  fn array_arg(&mut self, ty: Ty, dims: &[usize]) -> Option<String> {
+    let candidates = self
+      .visible()
+      .into_iter()
+      .filter(|var| {
+        if var.ty != ty || var.dims.len() < dims.len() {
+          return false;
+        }
+        let offset = var.dims.len() - dims.len();
+        var.dims[offset] >= dims[0] && var.dims[offset + 1..] == dims[1..]
+      })
+      .collect_vec();
+    if candidates.is_empty() {
+      return None;
+    }
+    let var = self.rng.pick(&candidates).clone();
+    let leading = &var.dims[..var.dims.len() - dims.len()];
+    let indices = leading
+      .iter()
+      .map(|&dim| format!("[{}]", self.index(dim, 1)))
+      .join("");
+    Some(format!("{}{indices}", var.name))
+  }
+
+  // ---- expressions ----
+
+  fn cond(&mut self, depth: usize) -> String {
+    let ty = self.random_ty();
+    self.expr(ty, depth, false)
+  }
+
+  /// `in_arith` is set for operands of arithmetic, where float literals are
+  /// not allowed.
+  fn expr(&mut self, ty: Ty, depth: usize, in_arith: bool) -> String {
+    match ty {
+      Ty::Int => self.int_expr(depth),
+      Ty::Float => self.float_expr(depth, in_arith),
+    }
+  }
+
+  fn int_expr(&mut self, depth: usize) -> String {
+    if depth == 0 || self.rng.chance(25) {
+      return self.leaf(Ty::Int, false);
+    }
+    let d = depth - 1;
+    match self.rng.below(9) {
+      0..=2 => {
+        let op = self
+          .rng
+          .pick(&["fz_add", "fz_sub", "fz_mul", "fz_div", "fz_mod"])
+          .to_string();
+        format!("{op}({}, {})", self.int_expr(d), self.int_expr(d))
+      }
+      3 | 4 => {
+        let op = self
+          .rng
+          .pick(&["<", ">", "<=", ">=", "==", "!="])
+          .to_string();
+        let (lhs_ty, rhs_ty) = (self.random_ty(), self.random_ty());
+        format!(
+          "({} {op} {})",
+          self.expr(lhs_ty, d, false),
+          self.expr(rhs_ty, d, false)
+        )
+      }
+      5 => {
+        // the ticks tell whether the right side was evaluated
+        let op = if self.rng.chance(50) { "&&" } else { "||" };
+        format!("({} {op} fz_tick({}))", self.cond(d), self.int_expr(d))
+      }
+      6 => format!("!{}", self.cond(d)),
+      7 => {
+        if self.rng.chance(50) {
+          format!("fz_neg({})", self.int_expr(d))
+        } else {
+          format!("-{}", self.int_literal())
+        }
+      }
+      _ if self.config.floats => format!("fz_f2i({})", self.float_expr(d, false)),
+      _ => format!("fz_add({}, {})", self.int_expr(d), self.int_expr(d)),
+    }
+  }
+
+  fn float_expr(&mut self, depth: usize, in_arith: bool) -> String {
+    if depth == 0 || self.rng.chance(30) {
+      return self.leaf(Ty::Float, in_arith);
+    }
+    let d = depth - 1;
+    if self.rng.chance(15) {
+      return format!("-({})", self.float_expr(d, in_arith));
+    }
+    let op = self.rng.pick(&["+", "-", "*", "/"]).to_string();
+    let float = self.float_expr(d, true);
+    let other = if self.rng.chance(50) {
+      self.float_expr(d, true)
+    } else {
+      self.int_expr(d)
+    };
+    if self.rng.chance(50) {
+      format!("({float} {op} {other})")
+    } else {
+      format!("({other} {op} {float})")
+    }
+  }
+
+  /// A variable, an array element or a literal.
+  fn leaf(&mut self, ty: Ty, in_arith: bool) -> String {
+    let vars = self
+      .visible()
+      .into_iter()
+      .filter(|var| var.ty == ty)
+      .collect_vec();
+    let literal_ok = ty == Ty::Int || !in_arith;
+    if !vars.is_empty() && (!literal_ok || self.rng.chance(70)) {
+      let var = self.rng.pick(&vars).clone();
+      return self.element(&var, 1);
+    }
+    self.literal(ty)
+  }
+
+  /// `var` itself, or one of its elements for arrays.
+  fn element(&mut self, var: &VarInfo, depth: usize) -> String {
+    let indices = var
+      .dims
+      .iter()
+      .map(|&dim| format!("[{}]", self.index(dim, depth)))
+      .join("");
+    format!("{}{indices}", var.name)
+  }
+
+  /// An index in `0..dim`.
+  fn index(&mut self, dim: usize, depth: usize) -> String {
+    let counters = self
+      .visible()
+      .into_iter()
+      .filter(|var| matches!(var.counter_bound, Some(bound) if bound <= dim))
+      .collect_vec();
+    match self.rng.below(3) {
+      0 if !counters.is_empty() => self.rng.pick(&counters).name.clone(),
+      1 => format!("fz_idx({}, {dim})", self.int_expr(depth)),
+      _ => self.rng.below(dim).to_string(),
+    }
+  }
+
+  fn literal(&mut self, ty: Ty) -> String {
+    match ty {
+      Ty::Int if self.rng.chance(20) => format!("-{}", self.int_literal()),
+      Ty::Int => self.int_literal(),
+      Ty::Float => self.float_literal(),
+    }
+  }
+
+  fn int_literal(&mut self) -> String {
+    match self.rng.below(10) {
+      0 => self
+        .rng
+        .pick(&["2147483647", "1073741824", "46341", "65536", "0"])
+        .to_string(),
+      1 => format!("0x{:x}", self.rng.below(1 << 16)),
+      2 => format!("0{:o}", 1 + self.rng.below(512)),
+      3 => self.rng.below(100000).to_string(),
+      _ => self.rng.below(21).to_string(),
+    }
+  }
+
+  /// A multiple of 1/4, exact in float and double alike.
+  fn float_literal(&mut self) -> String {
+    let value = self.rng.below(200) as f64 / 4.0;
+    match self.rng.below(4) {
+      0 if value != 0.0 => format_hex_float(value),
+      1 => format!("{value:e}"),
+      _ => {
+        let text = value.to_string();
+        if text.contains('.') {
+          text
+        } else {
+          format!("{text}.0")
+        }
+      }
+    }
+  }
+}
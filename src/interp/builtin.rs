@@ -0,0 +1,133 @@
+use itertools::Itertools;
+use tree_sitter::Node;
+
+use super::{Interpreter, ScalarType, Value, Var};
+use crate::error::Result;
+
+impl<'a> Interpreter<'a> {
+  /// Run a `sylib.h` function, `None` if `name` is not one.
+  pub(super) fn call_builtin(
+    &mut self,
+    name: &str,
+    args: &[Node<'a>],
+    call_site: Node<'a>,
+  ) -> Result<Option<Value>> {
+    let arity = match name {
+      "getint" | "getch" | "getfloat" | "starttime" | "stoptime" => 0,
+      "getarray" | "getfarray" | "putint" | "putch" | "putfloat" | "_sysy_starttime"
+      | "_sysy_stoptime" => 1,
+      "putarray" | "putfarray" => 2,
+      _ => return Ok(None),
+    };
+    if args.len() != arity {
+      return Err(self.runtime_error(
+        call_site,
+        format!("{name} expects {arity} arguments, got {}", args.len()),
+      ));
+    }
+    // the macros in sylib.h pass `__LINE__`
+    let line = call_site.start_position().row as i32 + 1;
+    let res = match name {
+      "getint" => Value::Int(self.runtime.getint()),
+      "getch" => Value::Int(self.runtime.getch()),
+      "getfloat" => Value::Float(self.runtime.getfloat()),
+      "getarray" | "getfarray" => {
+        let ty = if name == "getarray" {
+          ScalarType::Int
+        } else {
+          ScalarType::Float
+        };
+        let array = self.array_arg(args[0], ty)?;
+        let n = match ty {
+          ScalarType::Int => {
+            let mut buf = vec![0; array.len()];
+            let n = self.runtime.getarray(&mut buf);
+            for (i, v) in buf.into_iter().enumerate().take(n.max(0) as usize) {
+              array.store_at(i, Value::Int(v));
+            }
+            n
+          }
+          ScalarType::Float => {
+            let mut buf = vec![0.0; array.len()];
+            let n = self.runtime.getfarray(&mut buf);
+            for (i, v) in buf.into_iter().enumerate().take(n.max(0) as usize) {
+              array.store_at(i, Value::Float(v));
+            }
+            n
+          }
+        };
+        if n as usize > array.len() {
+          return Err(self.runtime_error(call_site, format!("{n} elements overflow the array")));
+        }
+        Value::Int(n)
+      }
+      "putint" => {
+        let v = self.eval(args[0])?.as_int();
+        self.runtime.putint(v);
+        Value::Int(0)
+      }
+      "putch" => {
+        let v = self.eval(args[0])?.as_int();
+        self.runtime.putch(v);
+        Value::Int(0)
+      }
+      "putfloat" => {
+        let v = self.eval(args[0])?.as_float();
+        self.runtime.putfloat(v);
+        Value::Int(0)
+      }
+      "putarray" | "putfarray" => {
+        let n = self.eval(args[0])?.as_int();
+        let ty = if name == "putarray" {
+          ScalarType::Int
+        } else {
+          ScalarType::Float
+        };
+        let array = self.array_arg(args[1], ty)?;
+        if n < 0 || n as usize > array.len() {
+          return Err(self.runtime_error(args[0], format!("{n} elements overflow the array")));
+        }
+        let values = (0..n as usize).map(|i| array.load_at(i)).collect_vec();
+        match ty {
+          ScalarType::Int => {
+            let values = values.into_iter().map(Value::as_int).collect_vec();
+            self.runtime.putarray(n, &values);
+          }
+          ScalarType::Float => {
+            let values = values.into_iter().map(Value::as_float).collect_vec();
+            self.runtime.putfarray(n, &values);
+          }
+        }
+        Value::Int(0)
+      }
+      "starttime" => {
+        self.runtime.starttime(line);
+        Value::Int(0)
+      }
+      "stoptime" => {
+        self.runtime.stoptime(line);
+        Value::Int(0)
+      }
+      "_sysy_starttime" => {
+        let line = self.eval(args[0])?.as_int();
+        self.runtime.starttime(line);
+        Value::Int(0)
+      }
+      "_sysy_stoptime" => {
+        let line = self.eval(args[0])?.as_int();
+        self.runtime.stoptime(line);
+        Value::Int(0)
+      }
+      _ => unreachable!(),
+    };
+    Ok(Some(res))
+  }
+
+  fn array_arg(&mut self, arg: Node<'a>, ty: ScalarType) -> Result<Var> {
+    let array = self.lvalue(arg)?;
+    if !array.is_array() || array.ty != ty {
+      return Err(self.runtime_error(arg, "argument is not an array of the right type"));
+    }
+    Ok(array)
+  }
+}
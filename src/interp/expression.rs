@@ -0,0 +1,174 @@
+use tree_sitter::Node;
+
+use super::{Interpreter, ScalarType, Value, Var};
+use crate::{
+  error::{Error, Result},
+  parser::to_source_span,
+  runtime::parse_c_float,
+};
+
+impl<'a> Interpreter<'a> {
+  pub(super) fn eval(&mut self, root: Node<'a>) -> Result<Value> {
+    match root.kind() {
+      "binary_expression" => self.eval_binary(root),
+      "unary_expression" => self.eval_unary(root),
+      "subscript_expression" | "identifier" => {
+        let var = self.lvalue(root)?;
+        if var.is_array() {
+          return Err(self.runtime_error(root, "array used as a value"));
+        }
+        Ok(var.load())
+      }
+      "call_expression" => self.eval_call(root),
+      "int_literal" => {
+        let lit: i64 =
+          parse_int::parse(self.text(root)).map_err(|_| Error::ParseLiteralFailed {
+            src: self.src(),
+            range: to_source_span(root.range()),
+          })?;
+        Ok(Value::Int(lit as i32))
+      }
+      "float_literal" => {
+        let lit = parse_c_float(self.text(root)).ok_or_else(|| Error::ParseLiteralFailed {
+          src: self.src(),
+          range: to_source_span(root.range()),
+        })?;
+        Ok(Value::Float(lit as f32))
+      }
+      "parenthesized_expression" => {
+        let child = self.children(root)[0];
+        self.eval(child)
+      }
+      kind => Err(self.runtime_error(root, format!("unknown expression kind {kind}"))),
+    }
+  }
+
+  /// The variable or sub-array an identifier or subscript refers to.
+  pub(super) fn lvalue(&mut self, root: Node<'a>) -> Result<Var> {
+    match root.kind() {
+      "identifier" => self.lookup(root),
+      "subscript_expression" => {
+        let mut var = self.lookup(root.child_by_field_name("argument").unwrap())?;
+        let indices = root.child_by_field_name("indices").unwrap();
+        for index in self.children(indices) {
+          let i = self.eval(index)?.as_int();
+          var = var
+            .index(i)
+            .ok_or_else(|| self.runtime_error(index, format!("index {i} out of bounds")))?;
+        }
+        Ok(var)
+      }
+      "parenthesized_expression" => {
+        let child = self.children(root)[0];
+        self.lvalue(child)
+      }
+      _ => Err(self.runtime_error(root, "expression is not a variable")),
+    }
+  }
+
+  fn eval_unary(&mut self, root: Node<'a>) -> Result<Value> {
+    let op = self.text(root.child_by_field_name("operator").unwrap());
+    let val = self.eval(root.child_by_field_name("argument").unwrap())?;
+    Ok(match (op, val) {
+      ("+", val) => val,
+      ("-", Value::Int(v)) => Value::Int(v.wrapping_neg()),
+      ("-", Value::Float(v)) => Value::Float(-v),
+      ("!", val) => Value::Int(!val.is_true() as i32),
+      (op, _) => return Err(self.runtime_error(root, format!("unknown unary operator {op}"))),
+    })
+  }
+
+  fn eval_binary(&mut self, root: Node<'a>) -> Result<Value> {
+    let left = root.child_by_field_name("left").unwrap();
+    let op = self.text(root.child_by_field_name("operator").unwrap());
+    let right = root.child_by_field_name("right").unwrap();
+    match op {
+      "&&" => {
+        let res = self.eval(left)?.is_true() && self.eval(right)?.is_true();
+        return Ok(Value::Int(res as i32));
+      }
+      "||" => {
+        let res = self.eval(left)?.is_true() || self.eval(right)?.is_true();
+        return Ok(Value::Int(res as i32));
+      }
+      _ => {}
+    }
+    let lhs = self.eval(left)?;
+    let rhs = self.eval(right)?;
+    Ok(match (lhs, rhs) {
+      (Value::Int(l), Value::Int(r)) => match op {
+        "+" => Value::Int(l.wrapping_add(r)),
+        "-" => Value::Int(l.wrapping_sub(r)),
+        "*" => Value::Int(l.wrapping_mul(r)),
+        "/" | "%" if r == 0 => return Err(self.runtime_error(root, "division by zero")),
+        "/" => Value::Int(l.wrapping_div(r)),
+        "%" => Value::Int(l.wrapping_rem(r)),
+        _ => Value::Int(compare(op, l, r) as i32),
+      },
+      // usual arithmetic conversions: any float operand makes both float
+      (lhs, rhs) => {
+        let (l, r) = (lhs.as_float(), rhs.as_float());
+        match op {
+          "+" => Value::Float(l + r),
+          "-" => Value::Float(l - r),
+          "*" => Value::Float(l * r),
+          "/" => Value::Float(l / r),
+          "%" => return Err(self.runtime_error(root, "% is invalid for float")),
+          _ => Value::Int(compare(op, l, r) as i32),
+        }
+      }
+    })
+  }
+
+  fn eval_call(&mut self, root: Node<'a>) -> Result<Value> {
+    let function = root.child_by_field_name("function").unwrap();
+    let name = self.text(function);
+    let args = self.children(root.child_by_field_name("arguments").unwrap());
+    if let Some(res) = self.call_builtin(name, &args, root)? {
+      return Ok(res);
+    }
+    if !self.functions.contains_key(name) {
+      return Err(Error::FunctionNotFound {
+        src: self.src(),
+        range: to_source_span(function.range()),
+      });
+    }
+    let function_node = self.functions[name];
+    let params = self.children(function_node.child_by_field_name("param").unwrap());
+    // arrays are passed by reference, scalars by value
+    let mut arg_vars = Vec::new();
+    for (i, arg) in args.into_iter().enumerate() {
+      let is_array = params
+        .get(i)
+        .and_then(|param| param.child_by_field_name("array"))
+        .is_some();
+      if is_array {
+        let var = self.lvalue(arg)?;
+        if !var.is_array() {
+          return Err(self.runtime_error(arg, "scalar passed as an array"));
+        }
+        arg_vars.push(var);
+      } else {
+        let val = self.eval(arg)?;
+        let ty = match val {
+          Value::Int(_) => ScalarType::Int,
+          Value::Float(_) => ScalarType::Float,
+        };
+        arg_vars.push(Var::scalar(ty, val));
+      }
+    }
+    self.call_function(name, arg_vars, root)
+  }
+}
+
+fn compare<T: PartialOrd>(op: &str, l: T, r: T) -> bool {
+  match op {
+    "==" => l == r,
+    "!=" => l != r,
+    ">" => l > r,
+    "<" => l < r,
+    ">=" => l >= r,
+    "<=" => l <= r,
+    op => unreachable!("invalid operator {op}"),
+  }
+}
@@ -0,0 +1,260 @@
+//! A reference interpreter walking the parse tree directly, independent of
+//! LLVM, used to check what a program is supposed to do.
+mod builtin;
+mod expression;
+mod statement;
+mod value;
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use miette::NamedSource;
+use tree_sitter::{Node, Tree};
+
+use crate::{
+  error::{Error, Result},
+  parser::{get_text, to_source_span, useful_children},
+  runtime::{ProgramOutput, Runtime},
+};
+
+pub use self::value::{ScalarType, Value, Var};
+
+pub struct Interpreter<'a> {
+  name: &'a str,
+  content: &'a str,
+  functions: HashMap<&'a str, Node<'a>>,
+  // scopes[0] holds the globals, the rest belong to the running function
+  scopes: Vec<HashMap<&'a str, Var>>,
+  runtime: Runtime,
+  limits: Option<Limits>,
+  steps: u64,
+  call_depth: usize,
+}
+
+/// Bounds on a run, so a program that does not terminate fails instead of
+/// hanging or overflowing the stack.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+  /// statements executed
+  pub steps: u64,
+  /// nested calls of user functions
+  pub call_depth: usize,
+}
+
+/// How a statement finished.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+  Normal,
+  Break,
+  Continue,
+  Return(Option<Value>),
+}
+
+/// Run `main` of an already parsed program with `input` as stdin.
+pub fn interpret(
+  name: &str,
+  content: &str,
+  tree: &Tree,
+  input: impl Into<Vec<u8>>,
+) -> Result<ProgramOutput> {
+  let mut interpreter = Interpreter::new(name, content, input);
+  run(&mut interpreter, tree)
+}
+
+/// Like [`interpret`], but fail with a runtime error once `limits` are hit.
+pub fn interpret_with_limits(
+  name: &str,
+  content: &str,
+  tree: &Tree,
+  input: impl Into<Vec<u8>>,
+  limits: Limits,
+) -> Result<ProgramOutput> {
+  let mut interpreter = Interpreter::new(name, content, input);
+  interpreter.limits = Some(limits);
+  run(&mut interpreter, tree)
+}
+
+fn run<'a>(interpreter: &mut Interpreter<'a>, tree: &'a Tree) -> Result<ProgramOutput> {
+  interpreter.load(tree.root_node())?;
+  let ret = interpreter.call_main(tree.root_node())?;
+  let runtime = std::mem::take(&mut interpreter.runtime);
+  Ok(runtime.finish(ret))
+}
+
+impl<'a> Interpreter<'a> {
+  pub fn new(name: &'a str, content: &'a str, input: impl Into<Vec<u8>>) -> Self {
+    Self {
+      name,
+      content,
+      functions: HashMap::new(),
+      scopes: vec![HashMap::new()],
+      runtime: Runtime::new(input),
+      limits: None,
+      steps: 0,
+      call_depth: 0,
+    }
+  }
+
+  fn text(&self, node: Node<'a>) -> &'a str {
+    get_text(node, self.content)
+  }
+
+  fn src(&self) -> NamedSource {
+    NamedSource::new(self.name, self.content.to_string())
+  }
+
+  fn runtime_error(&self, node: Node, message: impl Into<String>) -> Error {
+    Error::RuntimeError {
+      src: self.src(),
+      range: to_source_span(node.range()),
+      message: message.into(),
+    }
+  }
+
+  /// Children that matter for evaluation, comments dropped.
+  fn children(&self, node: Node<'a>) -> Vec<Node<'a>> {
+    let mut cursor = node.walk();
+    useful_children(&node, &mut cursor)
+      .filter(|child| child.kind() != "comment")
+      .collect_vec()
+  }
+
+  /// Register functions and initialize globals in source order.
+  fn load(&mut self, root: Node<'a>) -> Result<()> {
+    for node in self.children(root) {
+      match node.kind() {
+        "function_definition" => {
+          let name = node.child_by_field_name("name").unwrap();
+          let name_str = self.text(name);
+          if self.functions.contains_key(name_str) || self.scopes[0].contains_key(name_str) {
+            return Err(Error::DuplicateSymbol {
+              src: self.src(),
+              range: to_source_span(name.range()),
+            });
+          }
+          self.functions.insert(name_str, node);
+        }
+        "declaration" => self.declare(node)?,
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+
+  fn call_main(&mut self, root: Node<'a>) -> Result<i32> {
+    if !self.functions.contains_key("main") {
+      return Err(Error::MainNotFound);
+    }
+    Ok(self.call_function("main", Vec::new(), root)?.as_int())
+  }
+
+  fn lookup(&self, identifier: Node<'a>) -> Result<Var> {
+    let name = self.text(identifier);
+    for scope in self.scopes.iter().rev() {
+      if let Some(var) = scope.get(name) {
+        return Ok(var.clone());
+      }
+    }
+    Err(Error::VariableNotFound {
+      src: self.src(),
+      range: to_source_span(identifier.range()),
+    })
+  }
+
+  fn define(&mut self, name: Node<'a>, var: Var) -> Result<()> {
+    let name_str = self.text(name);
+    let is_global = self.scopes.len() == 1;
+    if self.scopes.last().unwrap().contains_key(name_str)
+      || is_global && self.functions.contains_key(name_str)
+    {
+      return Err(Error::DuplicateSymbol {
+        src: self.src(),
+        range: to_source_span(name.range()),
+      });
+    }
+    self.scopes.last_mut().unwrap().insert(name_str, var);
+    Ok(())
+  }
+
+  /// Evaluate array dimensions, which are constant expressions.
+  fn dimensions(&mut self, dims: Vec<Node<'a>>) -> Result<Vec<usize>> {
+    dims
+      .into_iter()
+      .map(|dim| {
+        let len = self.eval(dim)?.as_int();
+        if len < 0 {
+          return Err(self.runtime_error(dim, format!("negative array length {len}")));
+        }
+        Ok(len as usize)
+      })
+      .try_collect()
+  }
+
+  /// Call a user function with already evaluated arguments.
+  fn call_function(&mut self, name: &str, args: Vec<Var>, call_site: Node<'a>) -> Result<Value> {
+    let function = self.functions[name];
+    let ret_type = self.text(function.child_by_field_name("return_type").unwrap());
+    let params = self.children(function.child_by_field_name("param").unwrap());
+    if params.len() != args.len() {
+      return Err(self.runtime_error(
+        call_site,
+        format!(
+          "{name} expects {} arguments, got {}",
+          params.len(),
+          args.len()
+        ),
+      ));
+    }
+    if matches!(self.limits, Some(limits) if self.call_depth >= limits.call_depth) {
+      return Err(self.runtime_error(call_site, "call depth limit exceeded"));
+    }
+    let saved_scopes = self.scopes.split_off(1);
+    self.scopes.push(HashMap::new());
+    self.call_depth += 1;
+    let res = self.bind_params_and_run(function, params, args);
+    self.call_depth -= 1;
+    self.scopes.truncate(1);
+    self.scopes.extend(saved_scopes);
+    let ret = match res? {
+      Flow::Return(Some(value)) => value,
+      _ => Value::Int(0),
+    };
+    Ok(match ret_type {
+      "void" => Value::Int(0),
+      ty => ret.cast(ScalarType::try_from(ty)?),
+    })
+  }
+
+  fn bind_params_and_run(
+    &mut self,
+    function: Node<'a>,
+    params: Vec<Node<'a>>,
+    args: Vec<Var>,
+  ) -> Result<Flow> {
+    for (param, arg) in params.into_iter().zip(args) {
+      let ty = ScalarType::try_from(self.text(param.child_by_field_name("type").unwrap()))?;
+      let name = param.child_by_field_name("name").unwrap();
+      let var = match param.child_by_field_name("array") {
+        Some(array) => {
+          let dims = self
+            .children(array)
+            .into_iter()
+            .filter(|dim| dim.kind() == "parameter_array_dimension")
+            .map(|dim| self.children(dim)[0])
+            .collect_vec();
+          let mut dims = self.dimensions(dims)?;
+          // the first dimension is unknown, so whatever fits in the storage
+          let stride = dims.iter().product::<usize>().max(1);
+          dims.insert(0, arg.len() / stride);
+          if arg.ty != ty {
+            return Err(self.runtime_error(param, "array argument of another element type"));
+          }
+          Var { dims, ..arg }
+        }
+        None => Var::scalar(ty, arg.load()),
+      };
+      self.define(name, var)?;
+    }
+    self.exec(function.child_by_field_name("body").unwrap())
+  }
+}
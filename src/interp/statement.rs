@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use tree_sitter::Node;
+
+use super::{Flow, Interpreter, ScalarType, Var};
+use crate::{
+  error::{Error, Result},
+  parser::to_source_span,
+};
+
+impl<'a> Interpreter<'a> {
+  pub(super) fn exec(&mut self, root: Node<'a>) -> Result<Flow> {
+    self.steps += 1;
+    if matches!(self.limits, Some(limits) if self.steps > limits.steps) {
+      return Err(self.runtime_error(root, "step limit exceeded"));
+    }
+    match root.kind() {
+      "compound_statement" => {
+        self.scopes.push(HashMap::new());
+        let mut flow = Flow::Normal;
+        for stmt in self.children(root) {
+          flow = self.exec(stmt)?;
+          if flow != Flow::Normal {
+            break;
+          }
+        }
+        self.scopes.pop();
+        return Ok(flow);
+      }
+      "expression_statement" => {
+        if let Some(expr) = self.children(root).first() {
+          self.eval(*expr)?;
+        }
+      }
+      "if_statement" => {
+        let cond = root.child_by_field_name("condition").unwrap();
+        if self.eval(cond)?.is_true() {
+          return self.exec(root.child_by_field_name("consequence").unwrap());
+        } else if let Some(alternative) = root.child_by_field_name("alternative") {
+          return self.exec(alternative);
+        }
+      }
+      "while_statement" => {
+        let cond = root.child_by_field_name("condition").unwrap();
+        let body = root.child_by_field_name("body").unwrap();
+        while self.eval(cond)?.is_true() {
+          match self.exec(body)? {
+            Flow::Break => break,
+            flow @ Flow::Return(_) => return Ok(flow),
+            Flow::Normal | Flow::Continue => {}
+          }
+        }
+      }
+      "assignment" => {
+        let left = root.child_by_field_name("left").unwrap();
+        let right = root.child_by_field_name("right").unwrap();
+        let var = self.lvalue(left)?;
+        if var.is_array() {
+          return Err(self.runtime_error(left, "can not assign to an array"));
+        }
+        let value = self.eval(right)?;
+        var.store(value);
+      }
+      "declaration" => self.declare(root)?,
+      "break_statement" => {
+        self.check_in_loop(root)?;
+        return Ok(Flow::Break);
+      }
+      "continue_statement" => {
+        self.check_in_loop(root)?;
+        return Ok(Flow::Continue);
+      }
+      "return_statement" => {
+        let value = match root.child_by_field_name("return_value") {
+          Some(value) => Some(self.eval(value)?),
+          None => None,
+        };
+        return Ok(Flow::Return(value));
+      }
+      "comment" => {}
+      kind => return Err(self.runtime_error(root, format!("unknown statement kind {kind}"))),
+    }
+    Ok(Flow::Normal)
+  }
+
+  fn check_in_loop(&self, root: Node<'a>) -> Result<()> {
+    let mut node = root;
+    while let Some(parent) = node.parent() {
+      match parent.kind() {
+        "while_statement" => return Ok(()),
+        "function_definition" => break,
+        _ => node = parent,
+      }
+    }
+    Err(Error::KeywordNotInLoop {
+      src: self.src(),
+      range: to_source_span(root.range()),
+    })
+  }
+
+  /// Define the variables of a global or local declaration.
+  pub(super) fn declare(&mut self, root: Node<'a>) -> Result<()> {
+    let ty = ScalarType::try_from(self.text(root.child_by_field_name("type").unwrap()))?;
+    let declarators = self
+      .children(root)
+      .into_iter()
+      .filter(|node| node.kind() == "declarator")
+      .collect_vec();
+    for declarator in declarators {
+      let dims = match declarator.child_by_field_name("dimension") {
+        Some(dimension) => {
+          let dims = self.children(dimension);
+          self.dimensions(dims)?
+        }
+        None => Vec::new(),
+      };
+      let var = Var::new(ty, dims);
+      if let Some(init) = declarator.child_by_field_name("init") {
+        let init = self.children(init)[0];
+        match init.kind() {
+          "init_list" | "empty_init_list" => {
+            if !var.is_array() {
+              return Err(self.runtime_error(init, "init list for a scalar"));
+            }
+            let dims = var.dims.clone();
+            self.fill_list(&var, 0, &dims, init)?;
+          }
+          _ => {
+            if var.is_array() {
+              return Err(self.runtime_error(init, "array initialized with a scalar"));
+            }
+            let value = self.eval(init)?;
+            var.store(value);
+          }
+        }
+      }
+      // the initializer can not see the variable itself
+      self.define(declarator.child_by_field_name("name").unwrap(), var)?;
+    }
+    Ok(())
+  }
+
+  /// Fill `var[base..base + product(dims)]` from a braced list, the rest
+  /// stays zero.
+  ///
+  /// A nested list starts at the next boundary of the largest sub-array the
+  /// current position is aligned to, and fills exactly that sub-array.
+  fn fill_list(&mut self, var: &Var, base: usize, dims: &[usize], list: Node<'a>) -> Result<()> {
+    let size = dims.iter().product::<usize>();
+    let mut pos = base;
+    for item in self.children(list) {
+      if pos >= base + size {
+        return Err(self.runtime_error(item, "excess elements in initializer"));
+      }
+      match item.kind() {
+        "init_list" | "empty_init_list" => {
+          let k = (1..dims.len())
+            .find(|&k| (pos - base) % dims[k..].iter().product::<usize>() == 0)
+            .ok_or_else(|| self.runtime_error(item, "braces around scalar initializer"))?;
+          self.fill_list(var, pos, &dims[k..], item)?;
+          pos += dims[k..].iter().product::<usize>();
+        }
+        _ => {
+          let value = self.eval(item)?;
+          var.store_at(pos, value);
+          pos += 1;
+        }
+      }
+    }
+    Ok(())
+  }
+}
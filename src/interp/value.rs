@@ -0,0 +1,134 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarType {
+  Int,
+  Float,
+}
+
+impl TryFrom<&str> for ScalarType {
+  type Error = Error;
+
+  fn try_from(value: &str) -> Result<Self> {
+    match value {
+      "int" => Ok(ScalarType::Int),
+      "float" => Ok(ScalarType::Float),
+      _ => Err(Error::UnknownType()),
+    }
+  }
+}
+
+/// A SysY scalar: 32-bit wrapping int or single precision float.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+  Int(i32),
+  Float(f32),
+}
+
+impl Value {
+  pub fn zero(ty: ScalarType) -> Self {
+    match ty {
+      ScalarType::Int => Value::Int(0),
+      ScalarType::Float => Value::Float(0.0),
+    }
+  }
+  pub fn as_int(self) -> i32 {
+    match self {
+      Value::Int(v) => v,
+      Value::Float(v) => v as i32,
+    }
+  }
+  pub fn as_float(self) -> f32 {
+    match self {
+      Value::Int(v) => v as f32,
+      Value::Float(v) => v,
+    }
+  }
+  pub fn is_true(self) -> bool {
+    match self {
+      Value::Int(v) => v != 0,
+      Value::Float(v) => v != 0.0,
+    }
+  }
+  pub fn cast(self, ty: ScalarType) -> Self {
+    match ty {
+      ScalarType::Int => Value::Int(self.as_int()),
+      ScalarType::Float => Value::Float(self.as_float()),
+    }
+  }
+}
+
+/// A variable, or a part of an array reached through subscripts.
+///
+/// Arrays are flattened in row-major order, and array arguments share the
+/// storage of the caller like C pointers do.
+#[derive(Debug, Clone)]
+pub struct Var {
+  pub ty: ScalarType,
+  pub storage: Rc<RefCell<Vec<Value>>>,
+  pub offset: usize,
+  /// remaining dimensions, empty for a scalar; for array parameters the
+  /// first one is whatever the caller passed
+  pub dims: Vec<usize>,
+}
+
+impl Var {
+  pub fn new(ty: ScalarType, dims: Vec<usize>) -> Self {
+    let len = dims.iter().product();
+    Self {
+      ty,
+      storage: Rc::new(RefCell::new(vec![Value::zero(ty); len])),
+      offset: 0,
+      dims,
+    }
+  }
+  pub fn scalar(ty: ScalarType, value: Value) -> Self {
+    let var = Self::new(ty, Vec::new());
+    var.store(value);
+    var
+  }
+  pub fn is_array(&self) -> bool {
+    !self.dims.is_empty()
+  }
+  /// scalars between one index of the first dimension and the next
+  pub fn stride(&self) -> usize {
+    self.dims.iter().skip(1).product()
+  }
+  /// `self[index]`, `None` when out of the storage
+  pub fn index(&self, index: i32) -> Option<Var> {
+    if self.dims.is_empty() || index < 0 {
+      return None;
+    }
+    let offset = self.offset + index as usize * self.stride();
+    if offset >= self.storage.borrow().len() {
+      return None;
+    }
+    Some(Var {
+      ty: self.ty,
+      storage: self.storage.clone(),
+      offset,
+      dims: self.dims[1..].to_vec(),
+    })
+  }
+  /// Number of scalars from this position to the end of the storage.
+  pub fn len(&self) -> usize {
+    self.storage.borrow().len() - self.offset
+  }
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+  pub fn load(&self) -> Value {
+    self.storage.borrow()[self.offset]
+  }
+  pub fn store(&self, value: Value) {
+    self.storage.borrow_mut()[self.offset] = value.cast(self.ty);
+  }
+  pub fn load_at(&self, i: usize) -> Value {
+    self.storage.borrow()[self.offset + i]
+  }
+  pub fn store_at(&self, i: usize, value: Value) {
+    self.storage.borrow_mut()[self.offset + i] = value.cast(self.ty);
+  }
+}
@@ -0,0 +1,382 @@
+//! A direct interpreter of the IR, to check lowering and passes against the
+//! expected outputs without going through LLVM.
+use super::{BinOp, BlockId, CastOp, Function, Inst, Module, Type, Value};
+use crate::{
+  error::{Error, Result},
+  interp::Limits,
+  runtime::{ProgramOutput, Runtime},
+};
+
+/// Run `main` of `module` on `input`.
+pub fn execute(module: &Module, input: impl Into<Vec<u8>>) -> Result<ProgramOutput> {
+  run(module, input, None)
+}
+
+/// Like [`execute`], but fail once `limits` are hit, steps counting
+/// instructions.
+pub fn execute_with_limits(
+  module: &Module,
+  input: impl Into<Vec<u8>>,
+  limits: Limits,
+) -> Result<ProgramOutput> {
+  run(module, input, Some(limits))
+}
+
+fn run(
+  module: &Module,
+  input: impl Into<Vec<u8>>,
+  limits: Option<Limits>,
+) -> Result<ProgramOutput> {
+  let mut machine = Machine {
+    module,
+    runtime: Runtime::new(input),
+    regions: Vec::new(),
+    limits,
+    steps: 0,
+    call_depth: 0,
+  };
+  for global in &module.globals {
+    let mut cells = vec![zero(global.ty.scalar()); global.ty.size()];
+    for &(index, val) in &global.init {
+      cells[index] = constant(val);
+    }
+    machine.regions.push(cells);
+  }
+  let main = module
+    .function("main")
+    .filter(|func| !func.is_declaration())
+    .ok_or(Error::MainNotFound)?;
+  let ret = match machine.call(main, Vec::new())? {
+    Some(Cell::Int(ret)) => ret,
+    _ => 0,
+  };
+  Ok(machine.runtime.finish(ret))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+  Bool(bool),
+  Int(i32),
+  Float(f32),
+  /// region and offset in scalars
+  Ptr(usize, usize),
+}
+
+fn zero(ty: &Type) -> Cell {
+  match ty {
+    Type::F32 => Cell::Float(0.0),
+    _ => Cell::Int(0),
+  }
+}
+
+fn constant(val: Value) -> Cell {
+  match val {
+    Value::Bool(v) => Cell::Bool(v),
+    Value::Int(v) => Cell::Int(v),
+    Value::Float(bits) => Cell::Float(f32::from_bits(bits)),
+    _ => unreachable!(),
+  }
+}
+
+fn error(message: impl Into<String>) -> Error {
+  Error::IrExec(message.into())
+}
+
+struct Machine<'a> {
+  module: &'a Module,
+  runtime: Runtime,
+  // globals first, then the allocas of the active calls
+  regions: Vec<Vec<Cell>>,
+  limits: Option<Limits>,
+  steps: u64,
+  call_depth: usize,
+}
+
+struct Frame<'a> {
+  func: &'a Function,
+  args: Vec<Cell>,
+  values: Vec<Option<Cell>>,
+}
+
+impl<'a> Frame<'a> {
+  fn get(&self, val: Value) -> Result<Cell> {
+    Ok(match val {
+      Value::Inst(id) => self.values[id.0 as usize]
+        .ok_or_else(|| error(format!("{} reads an undefined value", self.func.name)))?,
+      Value::Param(i) => self.args[i],
+      Value::Global(id) => Cell::Ptr(id.0 as usize, 0),
+      val => constant(val),
+    })
+  }
+}
+
+impl<'a> Machine<'a> {
+  fn cell(&mut self, ptr: Cell) -> Result<&mut Cell> {
+    let Cell::Ptr(region, offset) = ptr else {
+      return Err(error("access through a non-pointer"));
+    };
+    self
+      .regions
+      .get_mut(region)
+      .and_then(|cells| cells.get_mut(offset))
+      .ok_or_else(|| error("out of bounds access"))
+  }
+
+  fn call(&mut self, func: &'a Function, args: Vec<Cell>) -> Result<Option<Cell>> {
+    if func.is_declaration() {
+      return self.builtin(&func.name, args);
+    }
+    if matches!(self.limits, Some(limits) if self.call_depth >= limits.call_depth) {
+      return Err(error("call depth limit exceeded"));
+    }
+    self.call_depth += 1;
+    let ret = self.run(func, args);
+    self.call_depth -= 1;
+    ret
+  }
+
+  fn run(&mut self, func: &'a Function, args: Vec<Cell>) -> Result<Option<Cell>> {
+    let stack_top = self.regions.len();
+    let mut frame = Frame {
+      func,
+      args,
+      values: vec![None; func.insts.len()],
+    };
+    let mut prev: Option<BlockId> = None;
+    let mut block = func.entry();
+    let ret = 'exec: loop {
+      let insts = &func.block(block).insts;
+      self.steps += insts.len() as u64;
+      if matches!(self.limits, Some(limits) if self.steps > limits.steps) {
+        return Err(error("step limit exceeded"));
+      }
+      // phis read their operands before any of them is written
+      let phis = insts
+        .iter()
+        .take_while(|&&id| func.inst(id).kind.is_phi())
+        .map(|&id| {
+          let Inst::Phi(incoming) = &func.inst(id).kind else {
+            unreachable!()
+          };
+          let (_, val) = incoming
+            .iter()
+            .find(|(pred, _)| Some(*pred) == prev)
+            .ok_or_else(|| {
+              error(format!(
+                "phi without a value for the edge into {}",
+                func.block(block).name
+              ))
+            })?;
+          Ok((id, frame.get(*val)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+      let phi_count = phis.len();
+      for (id, val) in phis {
+        frame.values[id.0 as usize] = Some(val);
+      }
+      for &id in &insts[phi_count..] {
+        let data = func.inst(id);
+        let res = match &data.kind {
+          Inst::Binary(op, lhs, rhs) => Some(binary(*op, frame.get(*lhs)?, frame.get(*rhs)?)?),
+          Inst::FNeg(val) => match frame.get(*val)? {
+            Cell::Float(v) => Some(Cell::Float(-v)),
+            _ => return Err(error("fneg of a non-float")),
+          },
+          Inst::Cmp(op, lhs, rhs) => Some(Cell::Bool(match (frame.get(*lhs)?, frame.get(*rhs)?) {
+            (Cell::Int(l), Cell::Int(r)) => op.eval(l, r),
+            (Cell::Float(l), Cell::Float(r)) => op.eval(l, r),
+            (Cell::Bool(l), Cell::Bool(r)) => op.eval(l, r),
+            _ => return Err(error("comparison of mismatched values")),
+          })),
+          Inst::Cast(op, val) => Some(match (op, frame.get(*val)?) {
+            (CastOp::ZExt, Cell::Bool(v)) => Cell::Int(v as i32),
+            (CastOp::SiToFp, Cell::Int(v)) => Cell::Float(v as f32),
+            (CastOp::FpToSi, Cell::Float(v)) => Cell::Int(v as i32),
+            _ => return Err(error("cast of a mismatched value")),
+          }),
+          Inst::Alloca(ty) => {
+            self.regions.push(vec![zero(ty.scalar()); ty.size()]);
+            Some(Cell::Ptr(self.regions.len() - 1, 0))
+          }
+          Inst::Load(ptr) => Some(*self.cell(frame.get(*ptr)?)?),
+          Inst::Store { value, ptr } => {
+            let value = frame.get(*value)?;
+            *self.cell(frame.get(*ptr)?)? = value;
+            None
+          }
+          Inst::Gep { base, indices } => {
+            let Cell::Ptr(region, mut offset) = frame.get(*base)? else {
+              return Err(error("gep of a non-pointer"));
+            };
+            let mut ty = self.module.value_type(func, *base);
+            for index in indices {
+              let Cell::Int(index) = frame.get(*index)? else {
+                return Err(error("gep index is not an integer"));
+              };
+              ty = match ty {
+                Type::Ptr(elem) | Type::Array(elem, _) => *elem,
+                _ => return Err(error("gep into a scalar")),
+              };
+              offset = offset.wrapping_add((index as isize * ty.size() as isize) as usize);
+            }
+            Some(Cell::Ptr(region, offset))
+          }
+          Inst::Call { callee, args } => {
+            let target = self
+              .module
+              .function(callee)
+              .ok_or_else(|| error(format!("call of unknown function {callee}")))?;
+            let args = args
+              .iter()
+              .map(|&arg| frame.get(arg))
+              .collect::<Result<_>>()?;
+            self.call(target, args)?
+          }
+          Inst::Phi(_) => return Err(error("phi after other instructions")),
+          Inst::Br(target) => {
+            prev = Some(block);
+            block = *target;
+            continue 'exec;
+          }
+          Inst::CondBr { cond, then, els } => {
+            prev = Some(block);
+            block = match frame.get(*cond)? {
+              Cell::Bool(true) => *then,
+              Cell::Bool(false) => *els,
+              _ => return Err(error("branch on a non-boolean")),
+            };
+            continue 'exec;
+          }
+          Inst::Ret(val) => break 'exec val.map(|val| frame.get(val)).transpose()?,
+        };
+        frame.values[id.0 as usize] = res;
+      }
+      return Err(error(format!(
+        "{} falls off its end",
+        func.block(block).name
+      )));
+    };
+    self.regions.truncate(stack_top);
+    Ok(ret)
+  }
+
+  fn builtin(&mut self, name: &str, args: Vec<Cell>) -> Result<Option<Cell>> {
+    let int = |i: usize| match args.get(i) {
+      Some(Cell::Int(v)) => Ok(*v),
+      _ => Err(error(format!("{name} expects an int argument"))),
+    };
+    let rt = &mut self.runtime;
+    Ok(match name {
+      "getint" => Some(Cell::Int(rt.getint())),
+      "getch" => Some(Cell::Int(rt.getch())),
+      "getfloat" => Some(Cell::Float(rt.getfloat())),
+      "putint" => {
+        rt.putint(int(0)?);
+        None
+      }
+      "putch" => {
+        rt.putch(int(0)?);
+        None
+      }
+      "putfloat" => match args.first() {
+        Some(Cell::Float(v)) => {
+          rt.putfloat(*v);
+          None
+        }
+        _ => return Err(error("putfloat expects a float argument")),
+      },
+      "_sysy_starttime" => {
+        rt.starttime(int(0)?);
+        None
+      }
+      "_sysy_stoptime" => {
+        rt.stoptime(int(0)?);
+        None
+      }
+      "getarray" | "getfarray" => {
+        let Some(&Cell::Ptr(region, offset)) = args.first() else {
+          return Err(error(format!("{name} expects an array")));
+        };
+        let cells = self
+          .regions
+          .get_mut(region)
+          .and_then(|cells| cells.get_mut(offset..))
+          .ok_or_else(|| error("out of bounds access"))?;
+        let n = if name == "getarray" {
+          let mut values = vec![0; cells.len()];
+          let n = self.runtime.getarray(&mut values);
+          for (cell, v) in cells.iter_mut().zip(values).take(n.max(0) as usize) {
+            *cell = Cell::Int(v);
+          }
+          n
+        } else {
+          let mut values = vec![0.0; cells.len()];
+          let n = self.runtime.getfarray(&mut values);
+          for (cell, v) in cells.iter_mut().zip(values).take(n.max(0) as usize) {
+            *cell = Cell::Float(v);
+          }
+          n
+        };
+        Some(Cell::Int(n))
+      }
+      "putarray" | "putfarray" => {
+        let n = int(0)?;
+        let Some(&Cell::Ptr(region, offset)) = args.get(1) else {
+          return Err(error(format!("{name} expects an array")));
+        };
+        let cells = self
+          .regions
+          .get(region)
+          .and_then(|cells| cells.get(offset..offset + n.max(0) as usize))
+          .ok_or_else(|| error("out of bounds access"))?;
+        if name == "putarray" {
+          let values: Vec<i32> = cells
+            .iter()
+            .map(|cell| match cell {
+              Cell::Int(v) => *v,
+              _ => 0,
+            })
+            .collect();
+          self.runtime.putarray(n, &values);
+        } else {
+          let values: Vec<f32> = cells
+            .iter()
+            .map(|cell| match cell {
+              Cell::Float(v) => *v,
+              _ => 0.0,
+            })
+            .collect();
+          self.runtime.putfarray(n, &values);
+        }
+        None
+      }
+      _ => return Err(error(format!("call of undefined function {name}"))),
+    })
+  }
+}
+
+fn binary(op: BinOp, lhs: Cell, rhs: Cell) -> Result<Cell> {
+  Ok(match (lhs, rhs) {
+    (Cell::Int(l), Cell::Int(r)) => Cell::Int(match op {
+      BinOp::Add => l.wrapping_add(r),
+      BinOp::Sub => l.wrapping_sub(r),
+      BinOp::Mul => l.wrapping_mul(r),
+      BinOp::SDiv | BinOp::SRem if r == 0 => return Err(error("division by zero")),
+      BinOp::SDiv => l.wrapping_div(r),
+      BinOp::SRem => l.wrapping_rem(r),
+      BinOp::Shl => l.wrapping_shl(r as u32),
+      BinOp::AShr => l.wrapping_shr(r as u32),
+      BinOp::LShr => (l as u32).wrapping_shr(r as u32) as i32,
+      BinOp::SMulH => ((l as i64 * r as i64) >> 32) as i32,
+      _ => return Err(error(format!("{} of integers", op.name()))),
+    }),
+    (Cell::Float(l), Cell::Float(r)) => Cell::Float(match op {
+      BinOp::FAdd => l + r,
+      BinOp::FSub => l - r,
+      BinOp::FMul => l * r,
+      BinOp::FDiv => l / r,
+      _ => return Err(error(format!("{} of floats", op.name()))),
+    }),
+    _ => return Err(error(format!("{} of mismatched values", op.name()))),
+  })
+}
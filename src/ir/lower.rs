@@ -0,0 +1,1339 @@
+//! Lowering from the parse tree.
+//!
+//! SSA form is built on the fly as in Braun et al., "Simple and Efficient
+//! Construction of Static Single Assignment Form": scalar locals and
+//! parameters never touch memory, their definitions are tracked per block
+//! and phis are placed where reads meet several definitions. Semantics
+//! follow the reference interpreter in [`crate::interp`].
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use miette::NamedSource;
+use tree_sitter::{Node, Tree};
+
+use super::{
+  BinOp, BlockId, CastOp, CmpOp, Function, Global, GlobalId, Inst, InstId, Module, Param, Type,
+  Value,
+};
+use crate::{
+  error::{Error, Result},
+  parser::{get_text, to_source_span, useful_children},
+  runtime::parse_c_float,
+};
+
+/// Local arrays up to this many elements are initialized one store per
+/// element, bigger ones are zeroed in a loop first.
+const UNROLLED_INIT_MAX: usize = 16;
+
+/// Lower a parsed program to a module.
+pub fn lower(name: &str, content: &str, tree: &Tree) -> Result<Module> {
+  let mut lowerer = Lowerer {
+    name,
+    content,
+    module: Module::default(),
+    signatures: HashMap::new(),
+    builtins: Vec::new(),
+    scopes: vec![HashMap::new()],
+    func: Function::default(),
+    block: BlockId(0),
+    ssa: Ssa::default(),
+    loops: Vec::new(),
+  };
+  lowerer.program(tree.root_node())?;
+  let mut module = lowerer.module;
+  for name in lowerer.builtins {
+    let (ret, params) = builtin(name).unwrap();
+    let params = params
+      .into_iter()
+      .map(|ty| Param {
+        name: String::new(),
+        ty,
+      })
+      .collect();
+    module.functions.push(Function::new(name, ret, params));
+  }
+  Ok(module)
+}
+
+/// Return and parameter types of the `sylib.h` functions.
+fn builtin(name: &str) -> Option<(Type, Vec<Type>)> {
+  let int_array = Type::I32.ptr();
+  let float_array = Type::F32.ptr();
+  Some(match name {
+    "getint" | "getch" => (Type::I32, vec![]),
+    "getfloat" => (Type::F32, vec![]),
+    "getarray" => (Type::I32, vec![int_array]),
+    "getfarray" => (Type::I32, vec![float_array]),
+    "putint" | "putch" | "_sysy_starttime" | "_sysy_stoptime" => (Type::Void, vec![Type::I32]),
+    "putfloat" => (Type::Void, vec![Type::F32]),
+    "putarray" => (Type::Void, vec![Type::I32, int_array]),
+    "putfarray" => (Type::Void, vec![Type::I32, float_array]),
+    _ => return None,
+  })
+}
+
+type VarId = usize;
+
+#[derive(Debug, Clone)]
+enum Symbol {
+  /// a scalar local in SSA form
+  Var(VarId),
+  /// a scalar known at compile time
+  Const(Value),
+  /// a global scalar of the given type
+  Global(GlobalId, Type),
+  /// an array at `ptr`; a parameter points to its first element, so its
+  /// first dimension is unknown
+  Array {
+    ptr: Value,
+    dims: Vec<usize>,
+    is_param: bool,
+  },
+}
+
+/// The bookkeeping of SSA construction for one function.
+#[derive(Debug, Default)]
+struct Ssa {
+  var_types: Vec<Type>,
+  // the value of each variable at the end of each block, once known
+  defs: HashMap<(VarId, BlockId), Value>,
+  // blocks whose predecessors are all known
+  sealed: HashSet<BlockId>,
+  preds: HashMap<BlockId, Vec<BlockId>>,
+  // phis placed in unsealed blocks, filled once they are sealed
+  incomplete: HashMap<BlockId, Vec<(VarId, InstId)>>,
+  // phis whose operands are not all there yet
+  pending: HashSet<InstId>,
+  // what removed trivial phis were replaced with
+  replaced: HashMap<InstId, Value>,
+}
+
+struct Lowerer<'a> {
+  name: &'a str,
+  content: &'a str,
+  module: Module,
+  // return and parameter types of the user functions seen so far
+  signatures: HashMap<String, (Type, Vec<Type>)>,
+  // builtins called, in the order of their first call
+  builtins: Vec<&'static str>,
+  // scopes[0] holds the globals
+  scopes: Vec<HashMap<&'a str, Symbol>>,
+  func: Function,
+  block: BlockId,
+  ssa: Ssa,
+  // (continue target, break target) of the enclosing loops
+  loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a> Lowerer<'a> {
+  fn text(&self, node: Node<'a>) -> &'a str {
+    get_text(node, self.content)
+  }
+
+  fn src(&self) -> NamedSource {
+    NamedSource::new(self.name, self.content.to_string())
+  }
+
+  fn error(&self, node: Node, message: impl Into<String>) -> Error {
+    Error::SemanticError {
+      src: self.src(),
+      range: to_source_span(node.range()),
+      message: message.into(),
+    }
+  }
+
+  fn invalid_initializer(&self, node: Node, message: &str) -> Error {
+    Error::InvalidInitializer {
+      src: self.src(),
+      range: to_source_span(node.range()),
+      message: message.to_string(),
+    }
+  }
+
+  fn children(&self, node: Node<'a>) -> Vec<Node<'a>> {
+    let mut cursor = node.walk();
+    useful_children(&node, &mut cursor)
+      .filter(|child| child.kind() != "comment")
+      .collect_vec()
+  }
+
+  fn lookup(&self, identifier: Node<'a>) -> Result<Symbol> {
+    let name = self.text(identifier);
+    for scope in self.scopes.iter().rev() {
+      if let Some(symbol) = scope.get(name) {
+        return Ok(symbol.clone());
+      }
+    }
+    Err(Error::VariableNotFound {
+      src: self.src(),
+      range: to_source_span(identifier.range()),
+    })
+  }
+
+  fn define(&mut self, name: Node<'a>, symbol: Symbol) -> Result<()> {
+    let name_str = self.text(name);
+    let is_global = self.scopes.len() == 1;
+    if self.scopes.last().unwrap().contains_key(name_str)
+      || is_global && self.signatures.contains_key(name_str)
+    {
+      return Err(Error::DuplicateSymbol {
+        src: self.src(),
+        range: to_source_span(name.range()),
+      });
+    }
+    self.scopes.last_mut().unwrap().insert(name_str, symbol);
+    Ok(())
+  }
+
+  fn scalar_type(&self, node: Node<'a>) -> Result<Type> {
+    match self.text(node) {
+      "int" => Ok(Type::I32),
+      "float" => Ok(Type::F32),
+      _ => Err(Error::UnknownType()),
+    }
+  }
+
+  fn program(&mut self, root: Node<'a>) -> Result<()> {
+    for node in self.children(root) {
+      match node.kind() {
+        "function_definition" => self.function(node)?,
+        "declaration" => self.declaration(node)?,
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+
+  //>>>>>>>>>>>>>>>>>>>>>>>>
+  //   Blocks and values
+  //<<<<<<<<<<<<<<<<<<<<<<<<
+
+  /// A block that is laid out once code is put into it.
+  fn new_block(&mut self, name: &str) -> BlockId {
+    let block = self.func.create_block(name);
+    self.ssa.preds.insert(block, Vec::new());
+    block
+  }
+
+  fn switch_to(&mut self, block: BlockId) {
+    self.func.layout.push(block);
+    self.block = block;
+  }
+
+  /// Continue in a block nothing branches to, after `return`, `break` or
+  /// `continue`. It is dropped once the function is done.
+  fn switch_to_dead_block(&mut self) {
+    let block = self.new_block("dead");
+    self.seal(block);
+    self.switch_to(block);
+  }
+
+  /// Whether the current block can not be reached. Branches out of it are
+  /// left out, so they do not add predecessors elsewhere.
+  fn is_dead(&self) -> bool {
+    self.block != self.func.entry()
+      && self.ssa.sealed.contains(&self.block)
+      && self.ssa.preds[&self.block].is_empty()
+  }
+
+  fn emit(&mut self, kind: Inst, ty: Type) -> Value {
+    Value::Inst(self.func.push(self.block, kind, ty))
+  }
+
+  fn jump(&mut self, target: BlockId) {
+    if self.is_dead() {
+      return;
+    }
+    self.emit(Inst::Br(target), Type::Void);
+    self.ssa.preds.get_mut(&target).unwrap().push(self.block);
+  }
+
+  fn branch(&mut self, cond: Value, then: BlockId, els: BlockId) {
+    if self.is_dead() {
+      return;
+    }
+    self.emit(Inst::CondBr { cond, then, els }, Type::Void);
+    self.ssa.preds.get_mut(&then).unwrap().push(self.block);
+    self.ssa.preds.get_mut(&els).unwrap().push(self.block);
+  }
+
+  fn ty(&self, val: Value) -> Type {
+    self.module.value_type(&self.func, val)
+  }
+
+  /// Stack storage, placed with the other allocas at the top of the entry
+  /// block.
+  fn alloca(&mut self, ty: Type) -> Value {
+    let entry = self.func.entry();
+    let pos = self
+      .func
+      .block(entry)
+      .insts
+      .iter()
+      .take_while(|&&id| matches!(self.func.inst(id).kind, Inst::Alloca(_)))
+      .count();
+    let ptr_ty = ty.clone().ptr();
+    Value::Inst(self.func.insert(entry, pos, Inst::Alloca(ty), ptr_ty))
+  }
+
+  /// Convert between `i1`, `i32` and `f32` as C does, folding constants.
+  fn convert(&mut self, val: Value, to: &Type) -> Value {
+    let from = self.ty(val);
+    if from == *to {
+      return val;
+    }
+    match (val, to) {
+      (Value::Bool(v), Type::I32) => Value::Int(v as i32),
+      (Value::Bool(v), Type::F32) => Value::float(v as i32 as f32),
+      (Value::Int(v), Type::F32) => Value::float(v as f32),
+      (Value::Float(bits), Type::I32) => Value::Int(f32::from_bits(bits) as i32),
+      (_, Type::I32) if from == Type::I1 => self.emit(Inst::Cast(CastOp::ZExt, val), Type::I32),
+      (_, Type::I32) => self.emit(Inst::Cast(CastOp::FpToSi, val), Type::I32),
+      (_, Type::F32) => {
+        let val = self.convert(val, &Type::I32);
+        self.emit(Inst::Cast(CastOp::SiToFp, val), Type::F32)
+      }
+      _ => unreachable!("can not convert {from} to {to}"),
+    }
+  }
+
+  //>>>>>>>>>>>>>>>>>>>>>>>>
+  //    SSA construction
+  //<<<<<<<<<<<<<<<<<<<<<<<<
+
+  fn new_var(&mut self, ty: Type) -> VarId {
+    self.ssa.var_types.push(ty);
+    self.ssa.var_types.len() - 1
+  }
+
+  fn write_var(&mut self, var: VarId, block: BlockId, val: Value) {
+    self.ssa.defs.insert((var, block), val);
+  }
+
+  fn read_var(&mut self, var: VarId, block: BlockId) -> Value {
+    match self.ssa.defs.get(&(var, block)) {
+      Some(val) => *val,
+      None => self.read_var_recursive(var, block),
+    }
+  }
+
+  fn read_var_recursive(&mut self, var: VarId, block: BlockId) -> Value {
+    let preds = self.ssa.preds[&block].clone();
+    let val = if !self.ssa.sealed.contains(&block) {
+      let phi = self.new_phi(var, block);
+      self
+        .ssa
+        .incomplete
+        .entry(block)
+        .or_default()
+        .push((var, phi));
+      Value::Inst(phi)
+    } else if preds.is_empty() {
+      // read before any assignment, or in unreachable code
+      Value::zero(&self.ssa.var_types[var])
+    } else if preds.len() == 1 {
+      // follow the chain of single predecessors; one that comes back around
+      // is a cycle nothing enters, as a loop in unreachable code is
+      let mut chain = vec![block];
+      let mut pred = preds[0];
+      while !chain.contains(&pred)
+        && !self.ssa.defs.contains_key(&(var, pred))
+        && self.ssa.sealed.contains(&pred)
+        && self.ssa.preds[&pred].len() == 1
+      {
+        chain.push(pred);
+        pred = self.ssa.preds[&pred][0];
+      }
+      let val = if chain.contains(&pred) {
+        Value::zero(&self.ssa.var_types[var])
+      } else {
+        self.read_var(var, pred)
+      };
+      for block in chain {
+        self.write_var(var, block, val);
+      }
+      val
+    } else {
+      // the phi breaks cycles through loops
+      let phi = self.new_phi(var, block);
+      self.write_var(var, block, Value::Inst(phi));
+      self.add_phi_operands(var, phi, block)
+    };
+    self.write_var(var, block, val);
+    val
+  }
+
+  fn new_phi(&mut self, var: VarId, block: BlockId) -> InstId {
+    let pos = self
+      .func
+      .block(block)
+      .insts
+      .iter()
+      .take_while(|&&id| self.func.inst(id).kind.is_phi())
+      .count();
+    let ty = self.ssa.var_types[var].clone();
+    let phi = self.func.insert(block, pos, Inst::Phi(Vec::new()), ty);
+    self.ssa.pending.insert(phi);
+    phi
+  }
+
+  fn add_phi_operands(&mut self, var: VarId, phi: InstId, block: BlockId) -> Value {
+    for pred in self.ssa.preds[&block].clone() {
+      let val = self.read_var(var, pred);
+      if let Inst::Phi(incoming) = &mut self.func.inst_mut(phi).kind {
+        incoming.push((pred, val));
+      }
+    }
+    self.ssa.pending.remove(&phi);
+    self.try_remove_trivial_phi(phi, block)
+  }
+
+  /// Replace a phi merging a single value with that value, then retry the
+  /// phis that used it.
+  fn try_remove_trivial_phi(&mut self, phi: InstId, block: BlockId) -> Value {
+    let Inst::Phi(incoming) = &self.func.inst(phi).kind else {
+      unreachable!()
+    };
+    let mut same = None;
+    for &(_, val) in incoming {
+      if Some(val) == same || val == Value::Inst(phi) {
+        continue;
+      }
+      if same.is_some() {
+        return Value::Inst(phi);
+      }
+      same = Some(val);
+    }
+    let same = same.unwrap_or_else(|| Value::zero(&self.func.inst(phi).ty));
+    let users = self
+      .func
+      .layout
+      .iter()
+      .flat_map(|&block| {
+        self
+          .func
+          .block(block)
+          .insts
+          .iter()
+          .map(move |&id| (block, id))
+      })
+      .filter(|&(_, id)| id != phi && !self.ssa.pending.contains(&id))
+      .filter(|&(_, id)| {
+        let kind = &self.func.inst(id).kind;
+        kind.is_phi() && kind.operands().contains(&Value::Inst(phi))
+      })
+      .collect_vec();
+    self.func.remove_inst(block, phi);
+    self.func.replace_all_uses(Value::Inst(phi), same);
+    for def in self.ssa.defs.values_mut() {
+      if *def == Value::Inst(phi) {
+        *def = same;
+      }
+    }
+    self.ssa.replaced.insert(phi, same);
+    for (user_block, user) in users {
+      if self.func.block(user_block).insts.contains(&user) {
+        self.try_remove_trivial_phi(user, user_block);
+      }
+    }
+    self.resolve(same)
+  }
+
+  /// Follow the replacements of removed phis.
+  fn resolve(&self, mut val: Value) -> Value {
+    while let Some(next) = val.as_inst().and_then(|id| self.ssa.replaced.get(&id)) {
+      val = *next;
+    }
+    val
+  }
+
+  fn seal(&mut self, block: BlockId) {
+    for (var, phi) in self.ssa.incomplete.remove(&block).unwrap_or_default() {
+      self.add_phi_operands(var, phi, block);
+    }
+    self.ssa.sealed.insert(block);
+  }
+
+  //>>>>>>>>>>>>>>>>>>>>>>>>
+  //       Functions
+  //<<<<<<<<<<<<<<<<<<<<<<<<
+
+  fn function(&mut self, node: Node<'a>) -> Result<()> {
+    let name = node.child_by_field_name("name").unwrap();
+    let name_str = self.text(name);
+    let ret = match self.text(node.child_by_field_name("return_type").unwrap()) {
+      "void" => Type::Void,
+      _ => self.scalar_type(node.child_by_field_name("return_type").unwrap())?,
+    };
+    if self.signatures.contains_key(name_str) || self.scopes[0].contains_key(name_str) {
+      return Err(Error::DuplicateSymbol {
+        src: self.src(),
+        range: to_source_span(name.range()),
+      });
+    }
+    let mut params = Vec::new();
+    let mut symbols = Vec::new();
+    for (i, param) in self
+      .children(node.child_by_field_name("param").unwrap())
+      .into_iter()
+      .filter(|param| param.kind() == "parameter")
+      .enumerate()
+    {
+      let elem = self.scalar_type(param.child_by_field_name("type").unwrap())?;
+      let param_name = param.child_by_field_name("name").unwrap();
+      let (ty, symbol) = match param.child_by_field_name("array") {
+        Some(array) => {
+          let mut dims = vec![0];
+          for dim in self.children(array) {
+            if dim.kind() == "parameter_array_dimension" {
+              dims.push(self.dimension(self.children(dim)[0])?);
+            }
+          }
+          let ty = Type::array(elem, &dims[1..]).ptr();
+          let symbol = Symbol::Array {
+            ptr: Value::Param(i),
+            dims,
+            is_param: true,
+          };
+          (ty, symbol)
+        }
+        None => (elem, Symbol::Var(i)),
+      };
+      params.push(Param {
+        name: self.text(param_name).to_string(),
+        ty,
+      });
+      symbols.push((param_name, symbol));
+    }
+    self.signatures.insert(
+      name_str.to_string(),
+      (
+        ret.clone(),
+        params.iter().map(|param| param.ty.clone()).collect(),
+      ),
+    );
+
+    self.func = Function::new(name_str, ret.clone(), params);
+    self.ssa = Ssa::default();
+    let entry = self.new_block("entry");
+    self.switch_to(entry);
+    self.seal(entry);
+    self.scopes.push(HashMap::new());
+    for (param_name, symbol) in symbols {
+      if let Symbol::Var(i) = symbol {
+        let ty = self.func.params[i].ty.clone();
+        let var = self.new_var(ty);
+        self.write_var(var, entry, Value::Param(i));
+        self.define(param_name, Symbol::Var(var))?;
+      } else {
+        self.define(param_name, symbol)?;
+      }
+    }
+    self.statement(node.child_by_field_name("body").unwrap())?;
+    self.scopes.pop();
+    // falling off the end returns zero, as in the interpreter
+    if !self.is_dead() {
+      let val = (ret != Type::Void).then(|| Value::zero(&ret));
+      self.emit(Inst::Ret(val), Type::Void);
+    }
+    let mut func = std::mem::take(&mut self.func);
+    func.remove_unreachable_blocks();
+    func.remove_trivial_phis();
+    self.module.functions.push(func);
+    Ok(())
+  }
+
+  //>>>>>>>>>>>>>>>>>>>>>>>>
+  //      Declarations
+  //<<<<<<<<<<<<<<<<<<<<<<<<
+
+  /// Evaluate an array length.
+  fn dimension(&self, node: Node<'a>) -> Result<usize> {
+    let len = match self.const_eval(node)? {
+      Some(Value::Int(len)) => len,
+      Some(Value::Float(bits)) => f32::from_bits(bits) as i32,
+      _ => return Err(self.error(node, "array length is not a constant")),
+    };
+    if len < 0 {
+      return Err(self.error(node, format!("negative array length {len}")));
+    }
+    Ok(len as usize)
+  }
+
+  fn declaration(&mut self, node: Node<'a>) -> Result<()> {
+    let is_const = node.child_by_field_name("const").is_some();
+    let ty = self.scalar_type(node.child_by_field_name("type").unwrap())?;
+    let is_global = self.scopes.len() == 1;
+    for declarator in self.children(node) {
+      if declarator.kind() != "declarator" {
+        continue;
+      }
+      let name = declarator.child_by_field_name("name").unwrap();
+      let dims = match declarator.child_by_field_name("dimension") {
+        Some(dimension) => self
+          .children(dimension)
+          .into_iter()
+          .map(|dim| self.dimension(dim))
+          .try_collect()?,
+        None => Vec::new(),
+      };
+      let init = declarator
+        .child_by_field_name("init")
+        .map(|init| self.children(init)[0]);
+      let is_list = init.map_or(false, |init| init.kind().ends_with("init_list"));
+      let symbol = match (dims.is_empty(), init) {
+        (true, Some(init)) if is_list => {
+          return Err(self.invalid_initializer(init, "init list for a scalar"))
+        }
+        (false, Some(init)) if !is_list => {
+          return Err(self.invalid_initializer(init, "array initialized with a scalar"))
+        }
+        (true, _) if is_global => {
+          let val = match init {
+            Some(init) => {
+              let val = self.const_eval(init)?.ok_or_else(|| {
+                self.invalid_initializer(init, "global initializer is not a constant")
+              })?;
+              convert_const(val, &ty)
+            }
+            None => Value::zero(&ty),
+          };
+          if is_const {
+            Symbol::Const(val)
+          } else {
+            let init = if val == Value::zero(&ty) {
+              Vec::new()
+            } else {
+              vec![(0, val)]
+            };
+            Symbol::Global(self.add_global(name, ty.clone(), init, false), ty.clone())
+          }
+        }
+        (true, _) => {
+          let known = match init {
+            Some(init) if is_const => self.const_eval(init)?,
+            _ => None,
+          };
+          match known {
+            Some(val) => Symbol::Const(convert_const(val, &ty)),
+            None => {
+              let val = match init {
+                Some(init) => {
+                  let val = self.expression(init)?;
+                  self.convert(val, &ty)
+                }
+                None => Value::zero(&ty),
+              };
+              let var = self.new_var(ty.clone());
+              self.write_var(var, self.block, val);
+              Symbol::Var(var)
+            }
+          }
+        }
+        (false, _) => {
+          let elems = match init {
+            Some(list) => self.flatten_init_list(list, &dims)?,
+            None => Vec::new(),
+          };
+          let ptr = if is_global {
+            let mut values = Vec::new();
+            for (index, elem) in elems {
+              let val = self.const_eval(elem)?.ok_or_else(|| {
+                self.invalid_initializer(elem, "global initializer is not a constant")
+              })?;
+              let val = convert_const(val, &ty);
+              if val != Value::zero(&ty) {
+                values.push((index, val));
+              }
+            }
+            let array_ty = Type::array(ty.clone(), &dims);
+            Value::Global(self.add_global(name, array_ty, values, is_const))
+          } else {
+            let ptr = self.alloca(Type::array(ty.clone(), &dims));
+            if init.is_some() {
+              self.init_local_array(ptr, &ty, &dims, elems)?;
+            }
+            ptr
+          };
+          Symbol::Array {
+            ptr,
+            dims,
+            is_param: false,
+          }
+        }
+      };
+      // the initializer can not see the variable itself
+      self.define(name, symbol)?;
+    }
+    Ok(())
+  }
+
+  fn add_global(
+    &mut self,
+    name: Node<'a>,
+    ty: Type,
+    init: Vec<(usize, Value)>,
+    is_const: bool,
+  ) -> GlobalId {
+    self.module.globals.push(Global {
+      name: self.text(name).to_string(),
+      ty,
+      init,
+      is_const,
+    });
+    GlobalId(self.module.globals.len() as u32 - 1)
+  }
+
+  /// The elements of a braced list by their row-major index, as the
+  /// interpreter fills them.
+  fn flatten_init_list(&self, list: Node<'a>, dims: &[usize]) -> Result<Vec<(usize, Node<'a>)>> {
+    let mut elems = Vec::new();
+    self.flatten_init_list_at(list, 0, dims, &mut elems)?;
+    Ok(elems)
+  }
+
+  fn flatten_init_list_at(
+    &self,
+    list: Node<'a>,
+    base: usize,
+    dims: &[usize],
+    elems: &mut Vec<(usize, Node<'a>)>,
+  ) -> Result<()> {
+    let size = dims.iter().product::<usize>();
+    let mut pos = base;
+    for item in self.children(list) {
+      if pos >= base + size {
+        return Err(self.invalid_initializer(item, "excess elements in initializer"));
+      }
+      if item.kind().ends_with("init_list") {
+        let k = (1..dims.len())
+          .find(|&k| (pos - base) % dims[k..].iter().product::<usize>() == 0)
+          .ok_or_else(|| self.invalid_initializer(item, "braces around scalar initializer"))?;
+        self.flatten_init_list_at(item, pos, &dims[k..], elems)?;
+        pos += dims[k..].iter().product::<usize>();
+      } else {
+        elems.push((pos, item));
+        pos += 1;
+      }
+    }
+    Ok(())
+  }
+
+  /// Store an initializer into a local array, zeroing what it leaves out.
+  fn init_local_array(
+    &mut self,
+    ptr: Value,
+    ty: &Type,
+    dims: &[usize],
+    elems: Vec<(usize, Node<'a>)>,
+  ) -> Result<()> {
+    let size = dims.iter().product::<usize>();
+    let mut values = Vec::new();
+    for (index, elem) in elems {
+      let val = self.expression(elem)?;
+      values.push((index, self.convert(val, ty)));
+    }
+    // the first element, indexed as a flat array from there
+    let first = self.emit(
+      Inst::Gep {
+        base: ptr,
+        indices: vec![Value::Int(0); dims.len() + 1],
+      },
+      ty.clone().ptr(),
+    );
+    let store_at = |this: &mut Self, index: Value, value: Value| {
+      let elem_ptr = this.emit(
+        Inst::Gep {
+          base: first,
+          indices: vec![index],
+        },
+        ty.clone().ptr(),
+      );
+      this.emit(
+        Inst::Store {
+          value,
+          ptr: elem_ptr,
+        },
+        Type::Void,
+      );
+    };
+    if size <= UNROLLED_INIT_MAX {
+      let mut all = vec![Value::zero(ty); size];
+      for (index, val) in values {
+        all[index] = val;
+      }
+      for (index, val) in all.into_iter().enumerate() {
+        store_at(self, Value::Int(index as i32), val);
+      }
+      return Ok(());
+    }
+    let i = self.new_var(Type::I32);
+    self.write_var(i, self.block, Value::Int(0));
+    let cond = self.new_block("zero_loop");
+    let body = self.new_block("zero_body");
+    let end = self.new_block("after_zero");
+    self.jump(cond);
+    self.switch_to(cond);
+    let index = self.read_var(i, cond);
+    let in_bounds = self.emit(
+      Inst::Cmp(CmpOp::Lt, index, Value::Int(size as i32)),
+      Type::I1,
+    );
+    self.branch(in_bounds, body, end);
+    self.seal(body);
+    self.seal(end);
+    self.switch_to(body);
+    store_at(self, index, Value::zero(ty));
+    let next = self.emit(Inst::Binary(BinOp::Add, index, Value::Int(1)), Type::I32);
+    self.write_var(i, body, next);
+    self.jump(cond);
+    self.seal(cond);
+    self.switch_to(end);
+    for (index, val) in values {
+      if val != Value::zero(ty) {
+        store_at(self, Value::Int(index as i32), val);
+      }
+    }
+    Ok(())
+  }
+
+  //>>>>>>>>>>>>>>>>>>>>>>>>
+  //       Statements
+  //<<<<<<<<<<<<<<<<<<<<<<<<
+
+  fn statement(&mut self, node: Node<'a>) -> Result<()> {
+    match node.kind() {
+      "compound_statement" => {
+        self.scopes.push(HashMap::new());
+        for stmt in self.children(node) {
+          self.statement(stmt)?;
+        }
+        self.scopes.pop();
+      }
+      "expression_statement" => {
+        if let Some(expr) = self.children(node).first() {
+          self.expression(*expr)?;
+        }
+      }
+      "if_statement" => {
+        let cond = node.child_by_field_name("condition").unwrap();
+        let alternative = node.child_by_field_name("alternative");
+        let then = self.new_block("if_block");
+        let els = alternative.map(|_| self.new_block("else_block"));
+        let end = self.new_block("after_block");
+        self.condition(cond, then, els.unwrap_or(end))?;
+        self.seal(then);
+        self.switch_to(then);
+        self.statement(node.child_by_field_name("consequence").unwrap())?;
+        self.jump(end);
+        if let (Some(els), Some(alternative)) = (els, alternative) {
+          self.seal(els);
+          self.switch_to(els);
+          self.statement(alternative)?;
+          self.jump(end);
+        }
+        self.seal(end);
+        self.switch_to(end);
+      }
+      "while_statement" => {
+        let cond = self.new_block("before_loop");
+        let body = self.new_block("loop_body");
+        let end = self.new_block("after_loop");
+        self.jump(cond);
+        self.switch_to(cond);
+        self.condition(node.child_by_field_name("condition").unwrap(), body, end)?;
+        self.seal(body);
+        self.switch_to(body);
+        self.loops.push((cond, end));
+        self.statement(node.child_by_field_name("body").unwrap())?;
+        self.loops.pop();
+        self.jump(cond);
+        self.seal(cond);
+        self.seal(end);
+        self.switch_to(end);
+      }
+      "assignment" => {
+        let left = node.child_by_field_name("left").unwrap();
+        let right = node.child_by_field_name("right").unwrap();
+        self.assign(left, right)?;
+      }
+      "declaration" => self.declaration(node)?,
+      "break_statement" | "continue_statement" => {
+        let &(continue_target, break_target) =
+          self.loops.last().ok_or_else(|| Error::KeywordNotInLoop {
+            src: self.src(),
+            range: to_source_span(node.range()),
+          })?;
+        if node.kind() == "break_statement" {
+          self.jump(break_target);
+        } else {
+          self.jump(continue_target);
+        }
+        self.switch_to_dead_block();
+      }
+      "return_statement" => {
+        let ret = self.func.ret.clone();
+        let val = match node.child_by_field_name("return_value") {
+          Some(value) => {
+            let val = self.expression(value)?;
+            (ret != Type::Void).then(|| self.convert(val, &ret))
+          }
+          None => (ret != Type::Void).then(|| Value::zero(&ret)),
+        };
+        if !self.is_dead() {
+          self.emit(Inst::Ret(val), Type::Void);
+        }
+        self.switch_to_dead_block();
+      }
+      "comment" => {}
+      kind => return Err(self.error(node, format!("unknown statement kind {kind}"))),
+    }
+    Ok(())
+  }
+
+  fn assign(&mut self, left: Node<'a>, right: Node<'a>) -> Result<()> {
+    if left.kind() == "identifier" {
+      match self.lookup(left)? {
+        Symbol::Var(var) => {
+          let ty = self.ssa.var_types[var].clone();
+          let val = self.expression(right)?;
+          let val = self.convert(val, &ty);
+          self.write_var(var, self.block, val);
+          return Ok(());
+        }
+        Symbol::Const(_) => return Err(self.error(left, "can not assign to a constant")),
+        _ => {}
+      }
+    }
+    let (ptr, ty) = self.scalar_address(left)?;
+    let val = self.expression(right)?;
+    let value = self.convert(val, &ty);
+    self.emit(Inst::Store { value, ptr }, Type::Void);
+    Ok(())
+  }
+
+  //>>>>>>>>>>>>>>>>>>>>>>>>
+  //      Expressions
+  //<<<<<<<<<<<<<<<<<<<<<<<<
+
+  fn literal(&self, node: Node<'a>) -> Result<Value> {
+    let parsed = match node.kind() {
+      "int_literal" => parse_int::parse::<i64>(self.text(node))
+        .ok()
+        .map(|lit| Value::Int(lit as i32)),
+      _ => parse_c_float(self.text(node)).map(|lit| Value::float(lit as f32)),
+    };
+    parsed.ok_or_else(|| Error::ParseLiteralFailed {
+      src: self.src(),
+      range: to_source_span(node.range()),
+    })
+  }
+
+  /// The value of a constant expression, `None` if it is not one.
+  fn const_eval(&self, node: Node<'a>) -> Result<Option<Value>> {
+    Ok(match node.kind() {
+      "int_literal" | "float_literal" => Some(self.literal(node)?),
+      "parenthesized_expression" => self.const_eval(self.children(node)[0])?,
+      "identifier" => match self.lookup(node)? {
+        Symbol::Const(val) => Some(val),
+        _ => None,
+      },
+      "unary_expression" => {
+        let op = self.text(node.child_by_field_name("operator").unwrap());
+        match self.const_eval(node.child_by_field_name("argument").unwrap())? {
+          Some(val) => fold_unary(op, val),
+          None => None,
+        }
+      }
+      "binary_expression" => {
+        let op = self.text(node.child_by_field_name("operator").unwrap());
+        let lhs = self.const_eval(node.child_by_field_name("left").unwrap())?;
+        let rhs = self.const_eval(node.child_by_field_name("right").unwrap())?;
+        match (lhs, rhs) {
+          (Some(lhs), Some(rhs)) => fold_binary(op, lhs, rhs),
+          _ => None,
+        }
+      }
+      _ => None,
+    })
+  }
+
+  /// An `i32` or `f32` value.
+  fn expression(&mut self, node: Node<'a>) -> Result<Value> {
+    match node.kind() {
+      "int_literal" | "float_literal" => self.literal(node),
+      "parenthesized_expression" => self.expression(self.children(node)[0]),
+      "identifier" => match self.lookup(node)? {
+        Symbol::Var(var) => Ok(self.read_var(var, self.block)),
+        Symbol::Const(val) => Ok(val),
+        Symbol::Global(id, ty) => Ok(self.emit(Inst::Load(Value::Global(id)), ty)),
+        Symbol::Array { .. } => Err(self.error(node, "array used as a value")),
+      },
+      "subscript_expression" => {
+        let (ptr, ty) = self.scalar_address(node)?;
+        Ok(self.emit(Inst::Load(ptr), ty))
+      }
+      "unary_expression" => {
+        let op = self.text(node.child_by_field_name("operator").unwrap());
+        let val = self.expression(node.child_by_field_name("argument").unwrap())?;
+        if let Some(folded) = fold_unary(op, val) {
+          return Ok(folded);
+        }
+        let ty = self.ty(val);
+        Ok(match (op, &ty) {
+          ("+", _) => val,
+          ("-", Type::I32) => self.emit(Inst::Binary(BinOp::Sub, Value::Int(0), val), ty),
+          ("-", _) => self.emit(Inst::FNeg(val), ty),
+          ("!", _) => {
+            let is_zero = self.emit(Inst::Cmp(CmpOp::Eq, val, Value::zero(&ty)), Type::I1);
+            self.convert(is_zero, &Type::I32)
+          }
+          (op, _) => return Err(self.error(node, format!("unknown unary operator {op}"))),
+        })
+      }
+      "binary_expression" => {
+        let op = self.text(node.child_by_field_name("operator").unwrap());
+        if matches!(op, "&&" | "||") {
+          return self.logical_value(node);
+        }
+        if comparison(op).is_some() {
+          let cond = self.truth(node)?;
+          return Ok(self.convert(cond, &Type::I32));
+        }
+        let lhs = self.expression(node.child_by_field_name("left").unwrap())?;
+        let rhs = self.expression(node.child_by_field_name("right").unwrap())?;
+        let (lhs, rhs, ty) = self.unify(lhs, rhs);
+        let op = match (op, &ty) {
+          ("+", Type::I32) => BinOp::Add,
+          ("-", Type::I32) => BinOp::Sub,
+          ("*", Type::I32) => BinOp::Mul,
+          ("/", Type::I32) => BinOp::SDiv,
+          ("%", Type::I32) => BinOp::SRem,
+          ("+", _) => BinOp::FAdd,
+          ("-", _) => BinOp::FSub,
+          ("*", _) => BinOp::FMul,
+          ("/", _) => BinOp::FDiv,
+          ("%", _) => return Err(self.error(node, "% is invalid for float")),
+          (op, _) => return Err(self.error(node, format!("unknown binary operator {op}"))),
+        };
+        Ok(self.emit(Inst::Binary(op, lhs, rhs), ty))
+      }
+      "call_expression" => self.call(node),
+      kind => Err(self.error(node, format!("unknown expression kind {kind}"))),
+    }
+  }
+
+  /// Usual arithmetic conversions: any float operand makes both float.
+  fn unify(&mut self, lhs: Value, rhs: Value) -> (Value, Value, Type) {
+    let ty = if self.ty(lhs) == Type::F32 || self.ty(rhs) == Type::F32 {
+      Type::F32
+    } else {
+      Type::I32
+    };
+    let lhs = self.convert(lhs, &ty);
+    let rhs = self.convert(rhs, &ty);
+    (lhs, rhs, ty)
+  }
+
+  /// An `i1` telling whether `node` is non-zero.
+  fn truth(&mut self, node: Node<'a>) -> Result<Value> {
+    if node.kind() == "binary_expression" {
+      let op = self.text(node.child_by_field_name("operator").unwrap());
+      if let Some(cmp) = comparison(op) {
+        let lhs = self.expression(node.child_by_field_name("left").unwrap())?;
+        let rhs = self.expression(node.child_by_field_name("right").unwrap())?;
+        let (lhs, rhs, _) = self.unify(lhs, rhs);
+        if let Some(Value::Int(res)) = fold_binary(op, lhs, rhs) {
+          return Ok(Value::Bool(res != 0));
+        }
+        return Ok(self.emit(Inst::Cmp(cmp, lhs, rhs), Type::I1));
+      }
+    }
+    let val = self.expression(node)?;
+    Ok(match val {
+      Value::Int(v) => Value::Bool(v != 0),
+      Value::Float(bits) => Value::Bool(f32::from_bits(bits) != 0.0),
+      val => {
+        let zero = Value::zero(&self.ty(val));
+        self.emit(Inst::Cmp(CmpOp::Ne, val, zero), Type::I1)
+      }
+    })
+  }
+
+  /// Branch on a condition, short-circuiting `&&`, `||` and `!` into
+  /// control flow.
+  fn condition(&mut self, node: Node<'a>, on_true: BlockId, on_false: BlockId) -> Result<()> {
+    match node.kind() {
+      "parenthesized_expression" => {
+        return self.condition(self.children(node)[0], on_true, on_false);
+      }
+      "unary_expression" if self.text(node.child_by_field_name("operator").unwrap()) == "!" => {
+        let argument = node.child_by_field_name("argument").unwrap();
+        return self.condition(argument, on_false, on_true);
+      }
+      "binary_expression" => {
+        let op = self.text(node.child_by_field_name("operator").unwrap());
+        if matches!(op, "&&" | "||") {
+          let rhs = self.new_block(if op == "&&" { "and_rhs" } else { "or_rhs" });
+          let left = node.child_by_field_name("left").unwrap();
+          if op == "&&" {
+            self.condition(left, rhs, on_false)?;
+          } else {
+            self.condition(left, on_true, rhs)?;
+          }
+          self.seal(rhs);
+          self.switch_to(rhs);
+          return self.condition(
+            node.child_by_field_name("right").unwrap(),
+            on_true,
+            on_false,
+          );
+        }
+      }
+      _ => {}
+    }
+    match self.truth(node)? {
+      Value::Bool(true) => self.jump(on_true),
+      Value::Bool(false) => self.jump(on_false),
+      cond => self.branch(cond, on_true, on_false),
+    }
+    Ok(())
+  }
+
+  /// `&&` or `||` as a value: 1 or 0 merged after the branches.
+  fn logical_value(&mut self, node: Node<'a>) -> Result<Value> {
+    let res = self.new_var(Type::I32);
+    let on_true = self.new_block("logic_true");
+    let on_false = self.new_block("logic_false");
+    let end = self.new_block("logic_end");
+    self.condition(node, on_true, on_false)?;
+    for (block, val) in [(on_true, 1), (on_false, 0)] {
+      self.seal(block);
+      self.switch_to(block);
+      self.write_var(res, block, Value::Int(val));
+      self.jump(end);
+    }
+    self.seal(end);
+    self.switch_to(end);
+    Ok(self.read_var(res, end))
+  }
+
+  /// The address of an array element, and its type.
+  fn scalar_address(&mut self, node: Node<'a>) -> Result<(Value, Type)> {
+    let (ptr, dims) = self.array_address(node)?;
+    if !dims.is_empty() {
+      return Err(self.error(node, "array used as a value"));
+    }
+    let ty = self.ty(ptr).pointee().unwrap().clone();
+    Ok((ptr, ty))
+  }
+
+  /// What an identifier or subscript names: the address of a scalar, or of
+  /// the first element of a sub-array, whose dimensions are returned.
+  fn array_address(&mut self, node: Node<'a>) -> Result<(Value, Vec<usize>)> {
+    let (identifier, subscripts) = match node.kind() {
+      "identifier" => (node, Vec::new()),
+      "subscript_expression" => (
+        node.child_by_field_name("argument").unwrap(),
+        self.children(node.child_by_field_name("indices").unwrap()),
+      ),
+      "parenthesized_expression" => return self.array_address(self.children(node)[0]),
+      _ => return Err(self.error(node, "expression is not a variable")),
+    };
+    let (ptr, dims, is_param) = match self.lookup(identifier)? {
+      Symbol::Array {
+        ptr,
+        dims,
+        is_param,
+      } => (ptr, dims, is_param),
+      Symbol::Global(id, _) if subscripts.is_empty() => return Ok((Value::Global(id), Vec::new())),
+      _ if subscripts.is_empty() => {
+        return Err(self.error(node, "a scalar local has no address"));
+      }
+      _ => return Err(self.error(identifier, "subscript of a scalar")),
+    };
+    if subscripts.len() > dims.len() {
+      return Err(self.error(node, "too many subscripts"));
+    }
+    let mut indices = if is_param {
+      vec![]
+    } else {
+      vec![Value::Int(0)]
+    };
+    for subscript in &subscripts {
+      let index = self.expression(*subscript)?;
+      indices.push(self.convert(index, &Type::I32));
+    }
+    let rest = dims[subscripts.len()..].to_vec();
+    if !rest.is_empty() {
+      indices.push(Value::Int(0));
+    }
+    if is_param && indices == [Value::Int(0)] {
+      return Ok((ptr, rest));
+    }
+    let ty = self.ty(ptr).gep_result(indices.len()).unwrap();
+    Ok((self.emit(Inst::Gep { base: ptr, indices }, ty), rest))
+  }
+
+  fn call(&mut self, node: Node<'a>) -> Result<Value> {
+    let function = node.child_by_field_name("function").unwrap();
+    let mut name = self.text(function);
+    let mut args = self.children(node.child_by_field_name("arguments").unwrap());
+    let mut extra = Vec::new();
+    // the macros in sylib.h pass `__LINE__`
+    if matches!(name, "starttime" | "stoptime") && args.is_empty() {
+      name = if name == "starttime" {
+        "_sysy_starttime"
+      } else {
+        "_sysy_stoptime"
+      };
+      extra.push(Value::Int(node.start_position().row as i32 + 1));
+    }
+    let (ret, params) = match builtin(name) {
+      Some(signature) => {
+        let name = [
+          "getint",
+          "getch",
+          "getfloat",
+          "getarray",
+          "getfarray",
+          "putint",
+          "putch",
+        ]
+        .into_iter()
+        .chain([
+          "putfloat",
+          "putarray",
+          "putfarray",
+          "_sysy_starttime",
+          "_sysy_stoptime",
+        ])
+        .find(|&builtin| builtin == name)
+        .unwrap();
+        if !self.builtins.contains(&name) {
+          self.builtins.push(name);
+        }
+        signature
+      }
+      None => self
+        .signatures
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::FunctionNotFound {
+          src: self.src(),
+          range: to_source_span(function.range()),
+        })?,
+    };
+    if !extra.is_empty() {
+      args.clear();
+    }
+    if args.len() + extra.len() != params.len() {
+      return Err(self.error(
+        node,
+        format!(
+          "{name} expects {} arguments, got {}",
+          params.len(),
+          args.len()
+        ),
+      ));
+    }
+    let mut values = extra;
+    for (arg, param) in args.into_iter().zip(&params) {
+      let val = if param.pointee().is_some() {
+        let (ptr, _) = self.array_address(arg)?;
+        if self.ty(ptr) != *param {
+          return Err(self.error(arg, "argument is not an array of the right type"));
+        }
+        ptr
+      } else {
+        let val = self.expression(arg)?;
+        self.convert(val, param)
+      };
+      values.push(val);
+    }
+    let is_void = ret == Type::Void;
+    let res = self.emit(
+      Inst::Call {
+        callee: name.to_string(),
+        args: values,
+      },
+      ret,
+    );
+    // a void call used as a value reads as zero, as in the interpreter
+    Ok(if is_void { Value::Int(0) } else { res })
+  }
+}
+
+fn comparison(op: &str) -> Option<CmpOp> {
+  Some(match op {
+    "==" => CmpOp::Eq,
+    "!=" => CmpOp::Ne,
+    "<" => CmpOp::Lt,
+    "<=" => CmpOp::Le,
+    ">" => CmpOp::Gt,
+    ">=" => CmpOp::Ge,
+    _ => return None,
+  })
+}
+
+fn convert_const(val: Value, to: &Type) -> Value {
+  match (val, to) {
+    (Value::Int(v), Type::F32) => Value::float(v as f32),
+    (Value::Float(bits), Type::I32) => Value::Int(f32::from_bits(bits) as i32),
+    (val, _) => val,
+  }
+}
+
+fn fold_unary(op: &str, val: Value) -> Option<Value> {
+  Some(match (op, val) {
+    ("+", Value::Int(_) | Value::Float(_)) => val,
+    ("-", Value::Int(v)) => Value::Int(v.wrapping_neg()),
+    ("-", Value::Float(bits)) => Value::float(-f32::from_bits(bits)),
+    ("!", Value::Int(v)) => Value::Int((v == 0) as i32),
+    ("!", Value::Float(bits)) => Value::Int((f32::from_bits(bits) == 0.0) as i32),
+    _ => return None,
+  })
+}
+
+/// Evaluate a binary operator on constants as the interpreter does, `None`
+/// for operands that are not constants or a division by zero.
+fn fold_binary(op: &str, lhs: Value, rhs: Value) -> Option<Value> {
+  let as_float = |val| match val {
+    Value::Int(v) => Some(v as f32),
+    Value::Float(bits) => Some(f32::from_bits(bits)),
+    _ => None,
+  };
+  let truth = |val| as_float(val).map(|v| v != 0.0);
+  match op {
+    "&&" => return Some(Value::Int((truth(lhs)? && truth(rhs)?) as i32)),
+    "||" => return Some(Value::Int((truth(lhs)? || truth(rhs)?) as i32)),
+    _ => {}
+  }
+  if let Some(cmp) = comparison(op) {
+    let res = match (lhs, rhs) {
+      (Value::Int(l), Value::Int(r)) => cmp.eval(l, r),
+      _ => cmp.eval(as_float(lhs)?, as_float(rhs)?),
+    };
+    return Some(Value::Int(res as i32));
+  }
+  Some(match (lhs, rhs) {
+    (Value::Int(l), Value::Int(r)) => Value::Int(match op {
+      "+" => l.wrapping_add(r),
+      "-" => l.wrapping_sub(r),
+      "*" => l.wrapping_mul(r),
+      "/" if r != 0 => l.wrapping_div(r),
+      "%" if r != 0 => l.wrapping_rem(r),
+      _ => return None,
+    }),
+    _ => {
+      let (l, r) = (as_float(lhs)?, as_float(rhs)?);
+      Value::float(match op {
+        "+" => l + r,
+        "-" => l - r,
+        "*" => l * r,
+        "/" => l / r,
+        _ => return None,
+      })
+    }
+  })
+}
@@ -0,0 +1,649 @@
+//! Our own mid-level IR, independent of LLVM.
+//!
+//! A function is a graph of basic blocks holding typed instructions in SSA
+//! form: scalar locals are virtual registers joined by phis, arrays live in
+//! memory reached through `gep`, `load` and `store`. It is lowered from the
+//! parse tree by [`lower`], prints and parses as text for tests, is checked
+//! by [`verify`], and runs either through LLVM
+//! ([`crate::codegen::Generator::gen_ir`]) or through [`execute`].
+mod exec;
+mod lower;
+mod parser;
+mod printer;
+mod verify;
+
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+pub use self::{
+  exec::{execute, execute_with_limits},
+  lower::lower,
+  parser::parse_module,
+  verify::verify,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Type {
+  #[default]
+  Void,
+  I1,
+  I32,
+  F32,
+  Ptr(Box<Type>),
+  /// element type and length
+  Array(Box<Type>, usize),
+}
+
+impl Type {
+  pub fn ptr(self) -> Type {
+    Type::Ptr(Box::new(self))
+  }
+  pub fn pointee(&self) -> Option<&Type> {
+    match self {
+      Type::Ptr(ty) => Some(ty),
+      _ => None,
+    }
+  }
+  /// Build `[d0 x [d1 x ... elem]]`.
+  pub fn array(elem: Type, dims: &[usize]) -> Type {
+    dims
+      .iter()
+      .rev()
+      .fold(elem, |ty, &len| Type::Array(Box::new(ty), len))
+  }
+  /// The type one `gep` index further in, `None` for scalars.
+  pub fn element(&self) -> Option<&Type> {
+    match self {
+      Type::Array(elem, _) => Some(elem),
+      _ => None,
+    }
+  }
+  /// The scalar at the bottom of nested arrays.
+  pub fn scalar(&self) -> &Type {
+    match self {
+      Type::Array(elem, _) => elem.scalar(),
+      ty => ty,
+    }
+  }
+  /// The type `gep` gives for this base pointer type and number of indices.
+  pub fn gep_result(&self, indices: usize) -> Option<Type> {
+    let mut ty = self.pointee()?;
+    for _ in 1..indices {
+      ty = ty.element()?;
+    }
+    Some(ty.clone().ptr())
+  }
+  /// Number of scalars in a value of this type.
+  pub fn size(&self) -> usize {
+    match self {
+      Type::Array(elem, len) => elem.size() * len,
+      _ => 1,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GlobalId(pub u32);
+
+/// An operand: a constant, or something defined elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Value {
+  Bool(bool),
+  Int(i32),
+  /// bit pattern of an `f32`, so values can be hashed and compared
+  Float(u32),
+  /// the result of an instruction
+  Inst(InstId),
+  /// a parameter of the function, by position
+  Param(usize),
+  /// the address of a global
+  Global(GlobalId),
+}
+
+impl Value {
+  pub fn float(v: f32) -> Self {
+    Value::Float(v.to_bits())
+  }
+  pub fn zero(ty: &Type) -> Self {
+    match ty {
+      Type::I1 => Value::Bool(false),
+      Type::F32 => Value::float(0.0),
+      _ => Value::Int(0),
+    }
+  }
+  pub fn is_const(self) -> bool {
+    self.const_type().is_some()
+  }
+  pub fn const_type(self) -> Option<Type> {
+    match self {
+      Value::Bool(_) => Some(Type::I1),
+      Value::Int(_) => Some(Type::I32),
+      Value::Float(_) => Some(Type::F32),
+      _ => None,
+    }
+  }
+  pub fn as_inst(self) -> Option<InstId> {
+    match self {
+      Value::Inst(id) => Some(id),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+  Add,
+  Sub,
+  Mul,
+  SDiv,
+  SRem,
+  Shl,
+  /// arithmetic shift right
+  AShr,
+  /// logical shift right
+  LShr,
+  /// the high half of the 64 bit signed product
+  SMulH,
+  FAdd,
+  FSub,
+  FMul,
+  FDiv,
+}
+
+impl BinOp {
+  pub const ALL: [BinOp; 13] = [
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::SDiv,
+    BinOp::SRem,
+    BinOp::Shl,
+    BinOp::AShr,
+    BinOp::LShr,
+    BinOp::SMulH,
+    BinOp::FAdd,
+    BinOp::FSub,
+    BinOp::FMul,
+    BinOp::FDiv,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      BinOp::Add => "add",
+      BinOp::Sub => "sub",
+      BinOp::Mul => "mul",
+      BinOp::SDiv => "sdiv",
+      BinOp::SRem => "srem",
+      BinOp::Shl => "shl",
+      BinOp::AShr => "ashr",
+      BinOp::LShr => "lshr",
+      BinOp::SMulH => "smulh",
+      BinOp::FAdd => "fadd",
+      BinOp::FSub => "fsub",
+      BinOp::FMul => "fmul",
+      BinOp::FDiv => "fdiv",
+    }
+  }
+  pub fn is_float(self) -> bool {
+    matches!(self, BinOp::FAdd | BinOp::FSub | BinOp::FMul | BinOp::FDiv)
+  }
+  pub fn is_commutative(self) -> bool {
+    matches!(
+      self,
+      BinOp::Add | BinOp::Mul | BinOp::SMulH | BinOp::FAdd | BinOp::FMul
+    )
+  }
+}
+
+/// Comparisons, signed for `i32` and ordered for `f32` except `ne`, which
+/// also holds for NaN as in C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmpOp {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl CmpOp {
+  pub const ALL: [CmpOp; 6] = [
+    CmpOp::Eq,
+    CmpOp::Ne,
+    CmpOp::Lt,
+    CmpOp::Le,
+    CmpOp::Gt,
+    CmpOp::Ge,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      CmpOp::Eq => "eq",
+      CmpOp::Ne => "ne",
+      CmpOp::Lt => "lt",
+      CmpOp::Le => "le",
+      CmpOp::Gt => "gt",
+      CmpOp::Ge => "ge",
+    }
+  }
+  /// The comparison with its operands swapped.
+  pub fn swap(self) -> Self {
+    match self {
+      CmpOp::Lt => CmpOp::Gt,
+      CmpOp::Le => CmpOp::Ge,
+      CmpOp::Gt => CmpOp::Lt,
+      CmpOp::Ge => CmpOp::Le,
+      op => op,
+    }
+  }
+  pub fn eval<T: PartialOrd>(self, l: T, r: T) -> bool {
+    match self {
+      CmpOp::Eq => l == r,
+      CmpOp::Ne => l != r,
+      CmpOp::Lt => l < r,
+      CmpOp::Le => l <= r,
+      CmpOp::Gt => l > r,
+      CmpOp::Ge => l >= r,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CastOp {
+  /// `i1` to `i32`
+  ZExt,
+  /// `i32` to `f32`
+  SiToFp,
+  /// `f32` to `i32`, rounding toward zero
+  FpToSi,
+}
+
+impl CastOp {
+  pub const ALL: [CastOp; 3] = [CastOp::ZExt, CastOp::SiToFp, CastOp::FpToSi];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      CastOp::ZExt => "zext",
+      CastOp::SiToFp => "sitofp",
+      CastOp::FpToSi => "fptosi",
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+  Binary(BinOp, Value, Value),
+  FNeg(Value),
+  /// `i1` result
+  Cmp(CmpOp, Value, Value),
+  Cast(CastOp, Value),
+  /// stack storage of the given type
+  Alloca(Type),
+  Load(Value),
+  Store {
+    value: Value,
+    ptr: Value,
+  },
+  /// address arithmetic as in LLVM: the first index steps over whole
+  /// pointees, the rest go into arrays
+  Gep {
+    base: Value,
+    indices: Vec<Value>,
+  },
+  Call {
+    callee: String,
+    args: Vec<Value>,
+  },
+  /// one incoming value per predecessor
+  Phi(Vec<(BlockId, Value)>),
+  Br(BlockId),
+  CondBr {
+    cond: Value,
+    then: BlockId,
+    els: BlockId,
+  },
+  Ret(Option<Value>),
+}
+
+impl Inst {
+  pub fn operands(&self) -> Vec<Value> {
+    match self {
+      Inst::Binary(_, lhs, rhs) | Inst::Cmp(_, lhs, rhs) => vec![*lhs, *rhs],
+      Inst::FNeg(val) | Inst::Cast(_, val) | Inst::Load(val) => vec![*val],
+      Inst::Alloca(_) | Inst::Br(_) | Inst::Ret(None) => Vec::new(),
+      Inst::Store { value, ptr } => vec![*value, *ptr],
+      Inst::Gep { base, indices } => std::iter::once(*base)
+        .chain(indices.iter().copied())
+        .collect(),
+      Inst::Call { args, .. } => args.clone(),
+      Inst::Phi(incoming) => incoming.iter().map(|(_, val)| *val).collect(),
+      Inst::CondBr { cond, .. } => vec![*cond],
+      Inst::Ret(Some(val)) => vec![*val],
+    }
+  }
+  pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+    match self {
+      Inst::Binary(_, lhs, rhs) | Inst::Cmp(_, lhs, rhs) => vec![lhs, rhs],
+      Inst::FNeg(val) | Inst::Cast(_, val) | Inst::Load(val) => vec![val],
+      Inst::Alloca(_) | Inst::Br(_) | Inst::Ret(None) => Vec::new(),
+      Inst::Store { value, ptr } => vec![value, ptr],
+      Inst::Gep { base, indices } => std::iter::once(base).chain(indices.iter_mut()).collect(),
+      Inst::Call { args, .. } => args.iter_mut().collect(),
+      Inst::Phi(incoming) => incoming.iter_mut().map(|(_, val)| val).collect(),
+      Inst::CondBr { cond, .. } => vec![cond],
+      Inst::Ret(Some(val)) => vec![val],
+    }
+  }
+  pub fn successors(&self) -> Vec<BlockId> {
+    match self {
+      Inst::Br(target) => vec![*target],
+      Inst::CondBr { then, els, .. } => vec![*then, *els],
+      _ => Vec::new(),
+    }
+  }
+  pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+    match self {
+      Inst::Br(target) => vec![target],
+      Inst::CondBr { then, els, .. } => vec![then, els],
+      _ => Vec::new(),
+    }
+  }
+  pub fn is_terminator(&self) -> bool {
+    matches!(self, Inst::Br(_) | Inst::CondBr { .. } | Inst::Ret(_))
+  }
+  pub fn is_phi(&self) -> bool {
+    matches!(self, Inst::Phi(_))
+  }
+  /// Whether removing the instruction can change what the program does,
+  /// even when its result is unused.
+  pub fn has_side_effects(&self) -> bool {
+    matches!(self, Inst::Store { .. } | Inst::Call { .. }) || self.is_terminator()
+  }
+}
+
+/// An instruction and the type of its result, `void` for none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstData {
+  pub kind: Inst,
+  pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+  pub name: String,
+  pub insts: Vec<InstId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+  pub name: String,
+  pub ty: Type,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Function {
+  pub name: String,
+  pub ret: Type,
+  pub params: Vec<Param>,
+  /// every instruction ever created; only those listed in a block of
+  /// `layout` are part of the function
+  pub insts: Vec<InstData>,
+  pub blocks: Vec<Block>,
+  /// blocks in print order, the entry first; empty for declarations
+  pub layout: Vec<BlockId>,
+  // next suffix tried for each block name, to keep names unique
+  block_names: HashMap<String, usize>,
+  used_names: HashSet<String>,
+}
+
+impl Function {
+  pub fn new(name: impl Into<String>, ret: Type, params: Vec<Param>) -> Self {
+    Self {
+      name: name.into(),
+      ret,
+      params,
+      ..Default::default()
+    }
+  }
+  pub fn is_declaration(&self) -> bool {
+    self.layout.is_empty()
+  }
+  pub fn entry(&self) -> BlockId {
+    self.layout[0]
+  }
+  pub fn inst(&self, id: InstId) -> &InstData {
+    &self.insts[id.0 as usize]
+  }
+  pub fn inst_mut(&mut self, id: InstId) -> &mut InstData {
+    &mut self.insts[id.0 as usize]
+  }
+  pub fn block(&self, id: BlockId) -> &Block {
+    &self.blocks[id.0 as usize]
+  }
+  pub fn block_mut(&mut self, id: BlockId) -> &mut Block {
+    &mut self.blocks[id.0 as usize]
+  }
+
+  /// Append a block, suffixing `name` with a number if it is taken.
+  pub fn add_block(&mut self, name: &str) -> BlockId {
+    let id = self.create_block(name);
+    self.layout.push(id);
+    id
+  }
+
+  /// A block that is not in the layout yet.
+  pub fn create_block(&mut self, name: &str) -> BlockId {
+    let next = self.block_names.entry(name.to_string()).or_insert(0);
+    let mut unique = name.to_string();
+    while self.used_names.contains(&unique) {
+      *next += 1;
+      unique = format!("{name}{next}");
+    }
+    self.used_names.insert(unique.clone());
+    self.blocks.push(Block {
+      name: unique,
+      insts: Vec::new(),
+    });
+    BlockId(self.blocks.len() as u32 - 1)
+  }
+
+  /// Create an instruction outside of any block.
+  pub fn create_inst(&mut self, kind: Inst, ty: Type) -> InstId {
+    self.insts.push(InstData { kind, ty });
+    InstId(self.insts.len() as u32 - 1)
+  }
+
+  pub fn push(&mut self, block: BlockId, kind: Inst, ty: Type) -> InstId {
+    let id = self.create_inst(kind, ty);
+    self.block_mut(block).insts.push(id);
+    id
+  }
+
+  pub fn insert(&mut self, block: BlockId, pos: usize, kind: Inst, ty: Type) -> InstId {
+    let id = self.create_inst(kind, ty);
+    self.block_mut(block).insts.insert(pos, id);
+    id
+  }
+
+  pub fn terminator(&self, block: BlockId) -> Option<InstId> {
+    let last = *self.block(block).insts.last()?;
+    self.inst(last).kind.is_terminator().then_some(last)
+  }
+
+  pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+    match self.terminator(block) {
+      Some(term) => self.inst(term).kind.successors(),
+      None => Vec::new(),
+    }
+  }
+
+  /// Predecessors of every block, indexed by block id. A block branching
+  /// twice to the same target is listed once.
+  pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+    let mut preds = vec![Vec::new(); self.blocks.len()];
+    for &block in &self.layout {
+      for succ in self.successors(block) {
+        let list: &mut Vec<BlockId> = &mut preds[succ.0 as usize];
+        if !list.contains(&block) {
+          list.push(block);
+        }
+      }
+    }
+    preds
+  }
+
+  /// The block holding each instruction of the layout.
+  pub fn inst_blocks(&self) -> HashMap<InstId, BlockId> {
+    self
+      .layout
+      .iter()
+      .flat_map(|&block| {
+        self
+          .block(block)
+          .insts
+          .iter()
+          .map(move |&inst| (inst, block))
+      })
+      .collect()
+  }
+
+  /// Instructions of the layout, block by block.
+  pub fn inst_ids(&self) -> impl Iterator<Item = InstId> + '_ {
+    self
+      .layout
+      .iter()
+      .flat_map(|&block| self.block(block).insts.iter().copied())
+  }
+
+  /// Blocks reachable from the entry in reverse postorder.
+  pub fn reverse_postorder(&self) -> Vec<BlockId> {
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    if self.is_declaration() {
+      return order;
+    }
+    // (block, successors already pushed)
+    let mut stack = vec![(self.entry(), false)];
+    while let Some((block, done)) = stack.pop() {
+      if done {
+        order.push(block);
+        continue;
+      }
+      if !visited.insert(block) {
+        continue;
+      }
+      stack.push((block, true));
+      for succ in self.successors(block).into_iter().rev() {
+        if !visited.contains(&succ) {
+          stack.push((succ, false));
+        }
+      }
+    }
+    order.reverse();
+    order
+  }
+
+  pub fn replace_all_uses(&mut self, from: Value, to: Value) {
+    for id in self.inst_ids().collect::<Vec<_>>() {
+      for operand in self.inst_mut(id).kind.operands_mut() {
+        if *operand == from {
+          *operand = to;
+        }
+      }
+    }
+  }
+
+  /// Take an instruction out of its block, leaving its uses alone.
+  pub fn remove_inst(&mut self, block: BlockId, inst: InstId) {
+    self.block_mut(block).insts.retain(|&id| id != inst);
+  }
+
+  /// Drop blocks the entry can not reach, and their incoming phi values.
+  pub fn remove_unreachable_blocks(&mut self) -> bool {
+    let reachable: HashSet<_> = self.reverse_postorder().into_iter().collect();
+    if reachable.len() == self.layout.len() {
+      return false;
+    }
+    self.layout.retain(|block| reachable.contains(block));
+    for id in self.inst_ids().collect::<Vec<_>>() {
+      if let Inst::Phi(incoming) = &mut self.inst_mut(id).kind {
+        incoming.retain(|(pred, _)| reachable.contains(pred));
+      }
+    }
+    true
+  }
+
+  /// Replace phis whose incoming values are all the same, ignoring the phi
+  /// itself, with that value.
+  pub fn remove_trivial_phis(&mut self) -> bool {
+    let mut changed = false;
+    let mut again = true;
+    while again {
+      again = false;
+      for block in self.layout.clone() {
+        for id in self.block(block).insts.clone() {
+          let Inst::Phi(incoming) = &self.inst(id).kind else {
+            break;
+          };
+          let values = incoming
+            .iter()
+            .map(|(_, val)| *val)
+            .filter(|&val| val != Value::Inst(id))
+            .unique()
+            .collect_vec();
+          if let [val] = values[..] {
+            self.remove_inst(block, id);
+            self.replace_all_uses(Value::Inst(id), val);
+            again = true;
+            changed = true;
+          }
+        }
+      }
+    }
+    changed
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+  pub name: String,
+  pub ty: Type,
+  /// non-zero scalars by their row-major index, the rest is zero
+  pub init: Vec<(usize, Value)>,
+  pub is_const: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+  pub globals: Vec<Global>,
+  pub functions: Vec<Function>,
+}
+
+impl Module {
+  pub fn global(&self, id: GlobalId) -> &Global {
+    &self.globals[id.0 as usize]
+  }
+  pub fn function(&self, name: &str) -> Option<&Function> {
+    self.functions.iter().find(|func| func.name == name)
+  }
+  pub fn function_mut(&mut self, name: &str) -> Option<&mut Function> {
+    self.functions.iter_mut().find(|func| func.name == name)
+  }
+  /// The type of `value` used in `func`.
+  pub fn value_type(&self, func: &Function, value: Value) -> Type {
+    match value {
+      Value::Bool(_) | Value::Int(_) | Value::Float(_) => value.const_type().unwrap(),
+      Value::Inst(id) => func.inst(id).ty.clone(),
+      Value::Param(i) => func.params[i].ty.clone(),
+      Value::Global(id) => self.global(id).ty.clone().ptr(),
+    }
+  }
+}
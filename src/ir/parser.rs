@@ -0,0 +1,568 @@
+//! Reads back what the printer writes, one item or instruction per line.
+//! `;` starts a comment. Values and blocks may be used before the line that
+//! defines them.
+use std::collections::HashMap;
+
+use super::{
+  BinOp, Block, BlockId, CastOp, CmpOp, Function, Global, GlobalId, Inst, InstId, Module, Param,
+  Type, Value,
+};
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Ident(String),
+  /// `%name`
+  Local(String),
+  /// `@name`
+  Global(String),
+  Num(String),
+  Punct(char),
+}
+
+fn is_name_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn tokenize(line: &str) -> std::result::Result<Vec<Token>, String> {
+  let chars: Vec<char> = line.chars().collect();
+  let mut tokens = Vec::new();
+  let mut i = 0;
+  let take_name = |i: &mut usize| {
+    let start = *i;
+    while *i < chars.len() && is_name_char(chars[*i]) {
+      *i += 1;
+    }
+    chars[start..*i].iter().collect::<String>()
+  };
+  while i < chars.len() {
+    let c = chars[i];
+    match c {
+      ';' => break,
+      c if c.is_whitespace() => i += 1,
+      '%' | '@' => {
+        i += 1;
+        let name = take_name(&mut i);
+        if name.is_empty() {
+          return Err(format!("expected a name after `{c}`"));
+        }
+        tokens.push(if c == '%' {
+          Token::Local(name)
+        } else {
+          Token::Global(name)
+        });
+      }
+      '=' | ',' | '(' | ')' | '[' | ']' | '{' | '}' | ':' | '*' => {
+        tokens.push(Token::Punct(c));
+        i += 1;
+      }
+      c if c.is_ascii_digit() || c == '-' => {
+        let start = i;
+        i += 1;
+        while i < chars.len()
+          && (chars[i].is_ascii_alphanumeric()
+            || chars[i] == '.'
+            || matches!(chars[i], '+' | '-') && matches!(chars[i - 1], 'e' | 'E'))
+        {
+          i += 1;
+        }
+        tokens.push(Token::Num(chars[start..i].iter().collect()));
+      }
+      c if is_name_char(c) => tokens.push(Token::Ident(take_name(&mut i))),
+      c => return Err(format!("unexpected character `{c}`")),
+    }
+  }
+  Ok(tokens)
+}
+
+/// Parse a whole module.
+pub fn parse_module(text: &str) -> Result<Module> {
+  let mut parser = Parser {
+    module: Module::default(),
+    globals: HashMap::new(),
+    line: 0,
+  };
+  let mut lines = text.lines().enumerate();
+  while let Some((i, line)) = lines.next() {
+    parser.line = i + 1;
+    let tokens = tokenize(line).map_err(|message| parser.error(message))?;
+    let mut cursor = Cursor::new(&tokens);
+    match cursor.peek() {
+      None => {}
+      Some(Token::Global(_)) => parser.global(&mut cursor)?,
+      Some(Token::Ident(kw)) if kw == "declare" => {
+        let func = parser.signature(&mut cursor, false)?;
+        parser.module.functions.push(func);
+      }
+      Some(Token::Ident(kw)) if kw == "define" => {
+        let func = parser.signature(&mut cursor, true)?;
+        cursor.expect_punct('{').map_err(|e| parser.error(e))?;
+        let func = parser.body(func, &mut lines)?;
+        parser.module.functions.push(func);
+      }
+      Some(_) => return Err(parser.error("expected a global or a function")),
+    }
+    if let Some(token) = cursor.peek() {
+      return Err(parser.error(format!("unexpected {token:?} at the end of the line")));
+    }
+  }
+  Ok(parser.module)
+}
+
+struct Cursor<'t> {
+  tokens: &'t [Token],
+  pos: usize,
+}
+
+type Expect<T> = std::result::Result<T, String>;
+
+impl<'t> Cursor<'t> {
+  fn new(tokens: &'t [Token]) -> Self {
+    Self { tokens, pos: 0 }
+  }
+  fn peek(&self) -> Option<&'t Token> {
+    self.tokens.get(self.pos)
+  }
+  fn next(&mut self) -> Expect<&'t Token> {
+    let token = self.peek().ok_or("unexpected end of line")?;
+    self.pos += 1;
+    Ok(token)
+  }
+  fn eat_punct(&mut self, c: char) -> bool {
+    if self.peek() == Some(&Token::Punct(c)) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+  fn expect_punct(&mut self, c: char) -> Expect<()> {
+    match self.next()? {
+      Token::Punct(p) if *p == c => Ok(()),
+      token => Err(format!("expected `{c}`, found {token:?}")),
+    }
+  }
+  fn ident(&mut self) -> Expect<&'t str> {
+    match self.next()? {
+      Token::Ident(name) => Ok(name),
+      token => Err(format!("expected a name, found {token:?}")),
+    }
+  }
+  fn expect_ident(&mut self, kw: &str) -> Expect<()> {
+    match self.next()? {
+      Token::Ident(name) if name == kw => Ok(()),
+      token => Err(format!("expected `{kw}`, found {token:?}")),
+    }
+  }
+  fn global_name(&mut self) -> Expect<&'t str> {
+    match self.next()? {
+      Token::Global(name) => Ok(name),
+      token => Err(format!("expected `@name`, found {token:?}")),
+    }
+  }
+  fn number<T: std::str::FromStr>(&mut self) -> Expect<T> {
+    match self.next()? {
+      Token::Num(text) => text.parse().map_err(|_| format!("invalid number `{text}`")),
+      token => Err(format!("expected a number, found {token:?}")),
+    }
+  }
+
+  fn ty(&mut self) -> Expect<Type> {
+    let mut ty = match self.next()? {
+      Token::Ident(name) => match name.as_str() {
+        "void" => Type::Void,
+        "i1" => Type::I1,
+        "i32" => Type::I32,
+        "f32" => Type::F32,
+        name => return Err(format!("unknown type `{name}`")),
+      },
+      Token::Punct('[') => {
+        let len = self.number()?;
+        self.expect_ident("x")?;
+        let elem = self.ty()?;
+        self.expect_punct(']')?;
+        Type::Array(Box::new(elem), len)
+      }
+      token => return Err(format!("expected a type, found {token:?}")),
+    };
+    while self.eat_punct('*') {
+      ty = ty.ptr();
+    }
+    Ok(ty)
+  }
+
+  /// A constant of type `ty`.
+  fn constant(&mut self, ty: &Type) -> Expect<Value> {
+    let text = match self.next()? {
+      Token::Num(text) | Token::Ident(text) => text,
+      token => return Err(format!("expected a constant, found {token:?}")),
+    };
+    let val = match ty {
+      Type::I1 => text.parse().ok().map(Value::Bool),
+      Type::I32 => text.parse().ok().map(Value::Int),
+      Type::F32 => text.parse().ok().map(Value::float),
+      _ => None,
+    };
+    val.ok_or_else(|| format!("`{text}` is not a constant of type {ty}"))
+  }
+}
+
+struct Parser {
+  module: Module,
+  globals: HashMap<String, GlobalId>,
+  line: usize,
+}
+
+/// Where the values and blocks of one function are defined.
+struct Scope {
+  values: HashMap<String, InstId>,
+  // placeholders handed out for uses before the definition
+  forward: HashMap<String, InstId>,
+  blocks: HashMap<String, BlockId>,
+  // line of the first use of each block, until its label is seen
+  undefined_blocks: HashMap<String, usize>,
+}
+
+impl Parser {
+  fn error(&self, message: impl Into<String>) -> Error {
+    Error::IrParse {
+      line: self.line,
+      message: message.into(),
+    }
+  }
+
+  fn global(&mut self, cursor: &mut Cursor) -> Result<()> {
+    let res = (|| -> Expect<Global> {
+      let name = cursor.global_name()?.to_string();
+      cursor.expect_punct('=')?;
+      let is_const = match cursor.ident()? {
+        "global" => false,
+        "constant" => true,
+        kw => return Err(format!("expected `global` or `constant`, found `{kw}`")),
+      };
+      let ty = cursor.ty()?;
+      let scalar = ty.scalar().clone();
+      let mut init = Vec::new();
+      if matches!(ty, Type::Array(..)) {
+        if cursor.eat_punct('[') {
+          while !cursor.eat_punct(']') {
+            if !init.is_empty() {
+              cursor.expect_punct(',')?;
+            }
+            let index = cursor.number()?;
+            cursor.expect_punct(':')?;
+            init.push((index, cursor.constant(&scalar)?));
+          }
+        } else {
+          cursor.expect_ident("zeroinitializer")?;
+        }
+      } else {
+        let val = cursor.constant(&ty)?;
+        if val != Value::zero(&ty) {
+          init.push((0, val));
+        }
+      }
+      Ok(Global {
+        name,
+        ty,
+        init,
+        is_const,
+      })
+    })();
+    let global = res.map_err(|e| self.error(e))?;
+    if self.globals.contains_key(&global.name) {
+      return Err(self.error(format!("@{} is defined twice", global.name)));
+    }
+    let id = GlobalId(self.module.globals.len() as u32);
+    self.globals.insert(global.name.clone(), id);
+    self.module.globals.push(global);
+    Ok(())
+  }
+
+  /// `declare ty @name(ty, ...)` or `define ty @name(ty %a, ...)`
+  fn signature(&mut self, cursor: &mut Cursor, named: bool) -> Result<Function> {
+    let res = (|| -> Expect<Function> {
+      cursor.ident()?;
+      let ret = cursor.ty()?;
+      let name = cursor.global_name()?;
+      cursor.expect_punct('(')?;
+      let mut params = Vec::new();
+      while !cursor.eat_punct(')') {
+        if !params.is_empty() {
+          cursor.expect_punct(',')?;
+        }
+        let ty = cursor.ty()?;
+        let name = if named {
+          match cursor.next()? {
+            Token::Local(name) => name.clone(),
+            token => return Err(format!("expected a parameter name, found {token:?}")),
+          }
+        } else {
+          String::new()
+        };
+        params.push(Param { name, ty });
+      }
+      Ok(Function::new(name, ret, params))
+    })();
+    let func = res.map_err(|e| self.error(e))?;
+    if self.module.function(&func.name).is_some() {
+      return Err(self.error(format!("@{} is defined twice", func.name)));
+    }
+    Ok(func)
+  }
+
+  fn body<'a>(
+    &mut self,
+    mut func: Function,
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+  ) -> Result<Function> {
+    let mut scope = Scope {
+      values: HashMap::new(),
+      forward: HashMap::new(),
+      blocks: HashMap::new(),
+      undefined_blocks: HashMap::new(),
+    };
+    let mut current = None;
+    loop {
+      let (i, line) = lines
+        .next()
+        .ok_or_else(|| self.error(format!("@{} is not closed by `}}`", func.name)))?;
+      self.line = i + 1;
+      let tokens = tokenize(line).map_err(|message| self.error(message))?;
+      match tokens.as_slice() {
+        [] => continue,
+        [Token::Punct('}')] => break,
+        [Token::Ident(name), Token::Punct(':')] => {
+          let block = scope.block(&mut func, name, self.line);
+          if scope.undefined_blocks.remove(name).is_none() {
+            return Err(self.error(format!("block {name} is defined twice")));
+          }
+          func.layout.push(block);
+          current = Some(block);
+        }
+        _ => {
+          let block = current.ok_or_else(|| self.error("instruction outside of a block"))?;
+          let mut cursor = Cursor::new(&tokens);
+          let (name, data) = self
+            .inst(&mut func, &mut scope, &mut cursor)
+            .map_err(|e| self.error(e))?;
+          if let Some(token) = cursor.peek() {
+            return Err(self.error(format!("unexpected {token:?} at the end of the line")));
+          }
+          let ty = data.1.clone();
+          let id = func.push(block, data.0, data.1);
+          if let Some(name) = name {
+            if ty == Type::Void {
+              return Err(self.error(format!("%{name} names an instruction without a result")));
+            }
+            if scope.values.insert(name.clone(), id).is_some() {
+              return Err(self.error(format!("%{name} is defined twice")));
+            }
+          }
+        }
+      }
+    }
+    if let Some((name, line)) = scope.undefined_blocks.iter().min_by_key(|(_, line)| **line) {
+      self.line = *line;
+      return Err(self.error(format!("block {name} is never defined")));
+    }
+    for (name, placeholder) in scope.forward {
+      let id = scope
+        .values
+        .get(&name)
+        .ok_or_else(|| self.error(format!("%{name} is never defined")))?;
+      func.replace_all_uses(Value::Inst(placeholder), Value::Inst(*id));
+    }
+    Ok(func)
+  }
+
+  /// An operand; `ty` is only needed to read constants.
+  fn value(
+    &self,
+    scope: &mut Scope,
+    cursor: &mut Cursor,
+    func: &Function,
+    ty: &Type,
+  ) -> Expect<Value> {
+    match cursor.peek() {
+      Some(Token::Local(name)) => {
+        cursor.next()?;
+        if let Some(i) = func.params.iter().position(|param| &param.name == name) {
+          return Ok(Value::Param(i));
+        }
+        Ok(Value::Inst(scope.value(name)))
+      }
+      Some(Token::Global(name)) => {
+        cursor.next()?;
+        let id = self
+          .globals
+          .get(name)
+          .ok_or_else(|| format!("unknown global @{name}"))?;
+        Ok(Value::Global(*id))
+      }
+      _ => cursor.constant(ty),
+    }
+  }
+
+  /// An instruction and the name of its result.
+  fn inst(
+    &self,
+    func: &mut Function,
+    scope: &mut Scope,
+    cursor: &mut Cursor,
+  ) -> Expect<(Option<String>, (Inst, Type))> {
+    let name = match cursor.peek() {
+      Some(Token::Local(name)) => {
+        cursor.next()?;
+        cursor.expect_punct('=')?;
+        Some(name.clone())
+      }
+      _ => None,
+    };
+    let line = self.line;
+    let opcode = cursor.ident()?;
+    let kind = if let Some(op) = BinOp::ALL.into_iter().find(|op| op.name() == opcode) {
+      let ty = cursor.ty()?;
+      let lhs = self.value(scope, cursor, func, &ty)?;
+      cursor.expect_punct(',')?;
+      let rhs = self.value(scope, cursor, func, &ty)?;
+      (Inst::Binary(op, lhs, rhs), ty)
+    } else if let Some(op) = CastOp::ALL.into_iter().find(|op| op.name() == opcode) {
+      let from = cursor.ty()?;
+      let val = self.value(scope, cursor, func, &from)?;
+      cursor.expect_ident("to")?;
+      (Inst::Cast(op, val), cursor.ty()?)
+    } else {
+      match opcode {
+        "fneg" => {
+          let ty = cursor.ty()?;
+          (Inst::FNeg(self.value(scope, cursor, func, &ty)?), ty)
+        }
+        "icmp" | "fcmp" => {
+          let op = cursor.ident()?;
+          let op = CmpOp::ALL
+            .into_iter()
+            .find(|cmp| cmp.name() == op)
+            .ok_or_else(|| format!("unknown comparison `{op}`"))?;
+          let ty = cursor.ty()?;
+          if (ty == Type::F32) != (opcode == "fcmp") {
+            return Err(format!("{opcode} can not compare {ty}"));
+          }
+          let lhs = self.value(scope, cursor, func, &ty)?;
+          cursor.expect_punct(',')?;
+          let rhs = self.value(scope, cursor, func, &ty)?;
+          (Inst::Cmp(op, lhs, rhs), Type::I1)
+        }
+        "alloca" => {
+          let ty = cursor.ty()?;
+          (Inst::Alloca(ty.clone()), ty.ptr())
+        }
+        "load" => {
+          let ty = cursor.ty()?;
+          let ptr = self.value(scope, cursor, func, &ty.clone().ptr())?;
+          (Inst::Load(ptr), ty)
+        }
+        "store" => {
+          let ty = cursor.ty()?;
+          let val = self.value(scope, cursor, func, &ty)?;
+          cursor.expect_punct(',')?;
+          let ptr = self.value(scope, cursor, func, &ty.ptr())?;
+          (Inst::Store { value: val, ptr }, Type::Void)
+        }
+        "gep" => {
+          let base_ty = cursor.ty()?;
+          let base = self.value(scope, cursor, func, &base_ty)?;
+          let mut indices = Vec::new();
+          while cursor.eat_punct(',') {
+            indices.push(self.value(scope, cursor, func, &Type::I32)?);
+          }
+          let ty = base_ty
+            .gep_result(indices.len())
+            .ok_or_else(|| format!("can not index {base_ty} {} times", indices.len()))?;
+          (Inst::Gep { base, indices }, ty)
+        }
+        "call" => {
+          let ty = cursor.ty()?;
+          let callee = cursor.global_name()?.to_string();
+          cursor.expect_punct('(')?;
+          let mut args = Vec::new();
+          while !cursor.eat_punct(')') {
+            if !args.is_empty() {
+              cursor.expect_punct(',')?;
+            }
+            let arg_ty = cursor.ty()?;
+            args.push(self.value(scope, cursor, func, &arg_ty)?);
+          }
+          (Inst::Call { callee, args }, ty)
+        }
+        "phi" => {
+          let ty = cursor.ty()?;
+          let mut incoming = Vec::new();
+          loop {
+            cursor.expect_punct('[')?;
+            let val = self.value(scope, cursor, func, &ty)?;
+            cursor.expect_punct(',')?;
+            let block = cursor.ident()?;
+            let block = scope.block(func, block, line);
+            cursor.expect_punct(']')?;
+            incoming.push((block, val));
+            if !cursor.eat_punct(',') {
+              break;
+            }
+          }
+          (Inst::Phi(incoming), ty)
+        }
+        "br" if cursor.tokens.get(cursor.pos + 1).is_none() => {
+          let target = cursor.ident()?;
+          (Inst::Br(scope.block(func, target, line)), Type::Void)
+        }
+        "br" => {
+          let ty = cursor.ty()?;
+          let cond = self.value(scope, cursor, func, &ty)?;
+          cursor.expect_punct(',')?;
+          let then = scope.block(func, cursor.ident()?, line);
+          cursor.expect_punct(',')?;
+          let els = scope.block(func, cursor.ident()?, line);
+          (Inst::CondBr { cond, then, els }, Type::Void)
+        }
+        "ret" => {
+          let ty = cursor.ty()?;
+          let val = match ty {
+            Type::Void => None,
+            ty => Some(self.value(scope, cursor, func, &ty)?),
+          };
+          (Inst::Ret(val), Type::Void)
+        }
+        opcode => return Err(format!("unknown instruction `{opcode}`")),
+      }
+    };
+    Ok((name, kind))
+  }
+}
+
+impl Scope {
+  /// The instruction named `name`, or a placeholder until it is defined.
+  fn value(&mut self, name: &str) -> InstId {
+    if let Some(id) = self.values.get(name) {
+      return *id;
+    }
+    let next = InstId(u32::MAX - self.forward.len() as u32);
+    *self.forward.entry(name.to_string()).or_insert(next)
+  }
+
+  /// The block named `name`, created out of the layout on first use.
+  fn block(&mut self, func: &mut Function, name: &str, line: usize) -> BlockId {
+    if let Some(id) = self.blocks.get(name) {
+      return *id;
+    }
+    func.used_names.insert(name.to_string());
+    func.blocks.push(Block {
+      name: name.to_string(),
+      insts: Vec::new(),
+    });
+    let id = BlockId(func.blocks.len() as u32 - 1);
+    self.blocks.insert(name.to_string(), id);
+    self.undefined_blocks.insert(name.to_string(), line);
+    id
+  }
+}
@@ -0,0 +1,224 @@
+//! The textual form, close to LLVM's:
+//!
+//! ```text
+//! @n = global i32 5
+//! @a = constant [4 x i32] [1: 7, 3: -2]
+//!
+//! declare void @putint(i32)
+//!
+//! define i32 @f(i32 %x, i32* %a) {
+//! entry:
+//!   %0 = icmp lt i32 %x, 10
+//!   br i1 %0, then, end
+//! then:
+//!   %1 = gep i32* %a, %x
+//!   %2 = load i32 %1
+//!   br end
+//! end:
+//!   %3 = phi i32 [%2, then], [0, entry]
+//!   ret i32 %3
+//! }
+//! ```
+//!
+//! Results are numbered in order, parameters keep their names, and array
+//! initializers list only their non-zero elements by row-major index.
+use std::{collections::HashMap, fmt};
+
+use itertools::Itertools;
+
+use super::{BlockId, Function, Global, Inst, InstId, Module, Type, Value};
+
+impl fmt::Display for Type {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Type::Void => f.write_str("void"),
+      Type::I1 => f.write_str("i1"),
+      Type::I32 => f.write_str("i32"),
+      Type::F32 => f.write_str("f32"),
+      Type::Ptr(ty) => write!(f, "{ty}*"),
+      Type::Array(elem, len) => write!(f, "[{len} x {elem}]"),
+    }
+  }
+}
+
+impl fmt::Display for Module {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for global in &self.globals {
+      writeln!(f, "{}", self.global_to_string(global))?;
+    }
+    for (i, func) in self.functions.iter().enumerate() {
+      if i > 0 || !self.globals.is_empty() {
+        writeln!(f)?;
+      }
+      f.write_str(&self.function_to_string(func))?;
+    }
+    Ok(())
+  }
+}
+
+impl Module {
+  fn global_to_string(&self, global: &Global) -> String {
+    let kind = if global.is_const {
+      "constant"
+    } else {
+      "global"
+    };
+    let init = match &global.ty {
+      Type::Array(..) if global.init.is_empty() => "zeroinitializer".to_string(),
+      Type::Array(..) => format!(
+        "[{}]",
+        global
+          .init
+          .iter()
+          .map(|(i, val)| format!("{i}: {}", constant(*val)))
+          .join(", ")
+      ),
+      ty => constant(global.init.first().map_or(Value::zero(ty), |(_, val)| *val)),
+    };
+    format!("@{} = {kind} {} {init}", global.name, global.ty)
+  }
+
+  pub fn function_to_string(&self, func: &Function) -> String {
+    if func.is_declaration() {
+      return format!(
+        "declare {} @{}({})\n",
+        func.ret,
+        func.name,
+        func.params.iter().map(|param| &param.ty).join(", ")
+      );
+    }
+    let printer = Printer::new(self, func);
+    let mut out = format!(
+      "define {} @{}({}) {{\n",
+      func.ret,
+      func.name,
+      func
+        .params
+        .iter()
+        .map(|param| format!("{} %{}", param.ty, param.name))
+        .join(", ")
+    );
+    for &block in &func.layout {
+      out.push_str(&format!("{}:\n", func.block(block).name));
+      for &inst in &func.block(block).insts {
+        out.push_str(&format!("  {}\n", printer.inst(inst)));
+      }
+    }
+    out.push_str("}\n");
+    out
+  }
+}
+
+fn constant(val: Value) -> String {
+  match val {
+    Value::Bool(v) => v.to_string(),
+    Value::Int(v) => v.to_string(),
+    // `{:?}` keeps a `.0` or exponent on every float and round-trips
+    Value::Float(bits) => format!("{:?}", f32::from_bits(bits)),
+    _ => unreachable!("{val:?} is not a constant"),
+  }
+}
+
+struct Printer<'a> {
+  module: &'a Module,
+  func: &'a Function,
+  numbers: HashMap<InstId, usize>,
+}
+
+impl<'a> Printer<'a> {
+  fn new(module: &'a Module, func: &'a Function) -> Self {
+    let numbers = func
+      .inst_ids()
+      .filter(|&id| func.inst(id).ty != Type::Void)
+      .enumerate()
+      .map(|(n, id)| (id, n))
+      .collect();
+    Self {
+      module,
+      func,
+      numbers,
+    }
+  }
+
+  fn value(&self, val: Value) -> String {
+    match val {
+      Value::Inst(id) => match self.numbers.get(&id) {
+        Some(n) => format!("%{n}"),
+        // only an invalid function uses instructions out of its blocks
+        None => format!("%removed.{}", id.0),
+      },
+      Value::Param(i) => format!("%{}", self.func.params[i].name),
+      Value::Global(id) => format!("@{}", self.module.global(id).name),
+      val => constant(val),
+    }
+  }
+
+  fn typed(&self, val: Value) -> String {
+    format!(
+      "{} {}",
+      self.module.value_type(self.func, val),
+      self.value(val)
+    )
+  }
+
+  fn block(&self, block: BlockId) -> &str {
+    &self.func.block(block).name
+  }
+
+  fn inst(&self, id: InstId) -> String {
+    let data = self.func.inst(id);
+    let body = match &data.kind {
+      Inst::Binary(op, lhs, rhs) => {
+        format!("{} {}, {}", op.name(), self.typed(*lhs), self.value(*rhs))
+      }
+      Inst::FNeg(val) => format!("fneg {}", self.typed(*val)),
+      Inst::Cmp(op, lhs, rhs) => {
+        let ty = self.module.value_type(self.func, *lhs);
+        let kind = if ty == Type::F32 { "fcmp" } else { "icmp" };
+        format!(
+          "{kind} {} {}, {}",
+          op.name(),
+          self.typed(*lhs),
+          self.value(*rhs)
+        )
+      }
+      Inst::Cast(op, val) => format!("{} {} to {}", op.name(), self.typed(*val), data.ty),
+      Inst::Alloca(ty) => format!("alloca {ty}"),
+      Inst::Load(ptr) => format!("load {} {}", data.ty, self.value(*ptr)),
+      Inst::Store { value, ptr } => {
+        format!("store {}, {}", self.typed(*value), self.value(*ptr))
+      }
+      Inst::Gep { base, indices } => format!(
+        "gep {}, {}",
+        self.typed(*base),
+        indices.iter().map(|&index| self.value(index)).join(", ")
+      ),
+      Inst::Call { callee, args } => format!(
+        "call {} @{callee}({})",
+        data.ty,
+        args.iter().map(|&arg| self.typed(arg)).join(", ")
+      ),
+      Inst::Phi(incoming) => format!(
+        "phi {} {}",
+        data.ty,
+        incoming
+          .iter()
+          .map(|&(block, val)| format!("[{}, {}]", self.value(val), self.block(block)))
+          .join(", ")
+      ),
+      Inst::Br(target) => format!("br {}", self.block(*target)),
+      Inst::CondBr { cond, then, els } => format!(
+        "br {}, {}, {}",
+        self.typed(*cond),
+        self.block(*then),
+        self.block(*els)
+      ),
+      Inst::Ret(None) => "ret void".to_string(),
+      Inst::Ret(Some(val)) => format!("ret {}", self.typed(*val)),
+    };
+    match self.numbers.get(&id) {
+      Some(n) => format!("%{n} = {body}"),
+      None => body,
+    }
+  }
+}
@@ -0,0 +1,282 @@
+//! Structural and type checks, so that a broken lowering or pass fails here
+//! instead of in LLVM or at run time.
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use super::{BlockId, CastOp, Function, Inst, InstId, Module, Type, Value};
+use crate::{
+  analysis::{Cfg, DomTree},
+  error::{Error, Result},
+};
+
+/// Check every global and function of `module`.
+pub fn verify(module: &Module) -> Result<()> {
+  let error = |function: &str, message: String| Error::IrVerify {
+    function: function.to_string(),
+    message,
+  };
+  if let Some(name) = module
+    .globals
+    .iter()
+    .map(|global| &global.name)
+    .duplicates()
+    .next()
+  {
+    return Err(error("", format!("global @{name} is defined twice")));
+  }
+  if let Some(name) = module
+    .functions
+    .iter()
+    .map(|func| &func.name)
+    .duplicates()
+    .next()
+  {
+    return Err(error(name, "defined twice".to_string()));
+  }
+  for global in &module.globals {
+    for &(index, val) in &global.init {
+      if index >= global.ty.size() {
+        return Err(error(
+          "",
+          format!("@{} has no element {index}", global.name),
+        ));
+      }
+      if val.const_type().as_ref() != Some(global.ty.scalar()) {
+        return Err(error(
+          "",
+          format!("@{} is initialized with {val:?}", global.name),
+        ));
+      }
+    }
+  }
+  for func in &module.functions {
+    Verifier::new(module, func)
+      .check()
+      .map_err(|message| error(&func.name, message))?;
+  }
+  Ok(())
+}
+
+struct Verifier<'a> {
+  module: &'a Module,
+  func: &'a Function,
+  // block and position of each instruction
+  places: HashMap<InstId, (BlockId, usize)>,
+  preds: Vec<Vec<BlockId>>,
+}
+
+type Check = std::result::Result<(), String>;
+
+impl<'a> Verifier<'a> {
+  fn new(module: &'a Module, func: &'a Function) -> Self {
+    Self {
+      module,
+      func,
+      places: HashMap::new(),
+      preds: func.predecessors(),
+    }
+  }
+
+  fn ty(&self, val: Value) -> Type {
+    self.module.value_type(self.func, val)
+  }
+
+  fn check(&mut self) -> Check {
+    let func = self.func;
+    if func.is_declaration() {
+      return Ok(());
+    }
+    if let Some(block) = func.layout.iter().duplicates().next() {
+      return Err(format!("{} is laid out twice", func.block(*block).name));
+    }
+    for &block in &func.layout {
+      let insts = &func.block(block).insts;
+      let name = &func.block(block).name;
+      if func.terminator(block).is_none() {
+        return Err(format!("{name} does not end with a terminator"));
+      }
+      for (pos, &inst) in insts.iter().enumerate() {
+        if self.places.insert(inst, (block, pos)).is_some() {
+          return Err(format!("an instruction appears twice, again in {name}"));
+        }
+        let kind = &func.inst(inst).kind;
+        if kind.is_terminator() && pos + 1 != insts.len() {
+          return Err(format!("{name} has a terminator before its end"));
+        }
+        if kind.is_phi() && pos > 0 && !func.inst(insts[pos - 1]).kind.is_phi() {
+          return Err(format!("{name} has a phi after other instructions"));
+        }
+      }
+    }
+    if !self.preds[func.entry().0 as usize].is_empty() {
+      return Err("the entry block has predecessors".to_string());
+    }
+    let laid_out: HashSet<_> = func.layout.iter().copied().collect();
+    for &block in &func.layout {
+      for &inst in &func.block(block).insts {
+        self
+          .check_inst(block, inst, &laid_out)
+          .map_err(|message| format!("in {}: {message}", func.block(block).name))?;
+      }
+    }
+    self.check_dominance()
+  }
+
+  fn check_inst(&self, block: BlockId, id: InstId, laid_out: &HashSet<BlockId>) -> Check {
+    let data = self.func.inst(id);
+    for operand in data.kind.operands() {
+      match operand {
+        Value::Inst(def) if !self.places.contains_key(&def) => {
+          return Err(format!(
+            "{:?} uses an instruction out of the function",
+            data.kind
+          ));
+        }
+        Value::Param(i) if i >= self.func.params.len() => {
+          return Err(format!("{:?} uses a missing parameter", data.kind));
+        }
+        Value::Global(global) if global.0 as usize >= self.module.globals.len() => {
+          return Err(format!("{:?} uses a missing global", data.kind));
+        }
+        _ => {}
+      }
+    }
+    for target in data.kind.successors() {
+      if !laid_out.contains(&target) {
+        return Err(format!("{:?} branches out of the function", data.kind));
+      }
+    }
+    let expect = |ok: bool, what: &str| -> Check {
+      if ok {
+        Ok(())
+      } else {
+        Err(format!("{what} in {:?}: {}", data.kind, data.ty))
+      }
+    };
+    match &data.kind {
+      Inst::Binary(op, lhs, rhs) => {
+        let ty = if op.is_float() { Type::F32 } else { Type::I32 };
+        expect(
+          self.ty(*lhs) == ty && self.ty(*rhs) == ty && data.ty == ty,
+          "operands of another type",
+        )
+      }
+      Inst::FNeg(val) => expect(
+        self.ty(*val) == Type::F32 && data.ty == Type::F32,
+        "not a float",
+      ),
+      Inst::Cmp(_, lhs, rhs) => {
+        let ty = self.ty(*lhs);
+        expect(
+          matches!(ty, Type::I1 | Type::I32 | Type::F32)
+            && self.ty(*rhs) == ty
+            && data.ty == Type::I1,
+          "bad comparison",
+        )
+      }
+      Inst::Cast(op, val) => {
+        let (from, to) = match op {
+          CastOp::ZExt => (Type::I1, Type::I32),
+          CastOp::SiToFp => (Type::I32, Type::F32),
+          CastOp::FpToSi => (Type::F32, Type::I32),
+        };
+        expect(self.ty(*val) == from && data.ty == to, "bad cast")
+      }
+      Inst::Alloca(ty) => expect(data.ty == ty.clone().ptr(), "bad alloca type"),
+      Inst::Load(ptr) => expect(
+        matches!(data.ty, Type::I1 | Type::I32 | Type::F32)
+          && self.ty(*ptr) == data.ty.clone().ptr(),
+        "bad load",
+      ),
+      Inst::Store { value, ptr } => expect(
+        data.ty == Type::Void && self.ty(*ptr) == self.ty(*value).ptr(),
+        "bad store",
+      ),
+      Inst::Gep { base, indices } => expect(
+        !indices.is_empty()
+          && indices.iter().all(|&index| self.ty(index) == Type::I32)
+          && self.ty(*base).gep_result(indices.len()).as_ref() == Some(&data.ty),
+        "bad gep",
+      ),
+      Inst::Call { callee, args } => {
+        let target = self
+          .module
+          .function(callee)
+          .ok_or_else(|| format!("call of unknown function @{callee}"))?;
+        expect(
+          target.ret == data.ty
+            && target.params.len() == args.len()
+            && target
+              .params
+              .iter()
+              .zip(args)
+              .all(|(param, &arg)| param.ty == self.ty(arg)),
+          "call does not match the signature",
+        )
+      }
+      Inst::Phi(incoming) => {
+        let preds = &self.preds[block.0 as usize];
+        let blocks = incoming.iter().map(|(pred, _)| *pred).collect_vec();
+        if blocks.len() != preds.len() || !preds.iter().all(|pred| blocks.contains(pred)) {
+          return Err(format!("{:?} does not match the predecessors", data.kind));
+        }
+        expect(
+          incoming.iter().all(|(_, val)| self.ty(*val) == data.ty),
+          "incoming value of another type",
+        )
+      }
+      Inst::Br(_) => Ok(()),
+      Inst::CondBr { cond, .. } => expect(self.ty(*cond) == Type::I1, "condition is not i1"),
+      Inst::Ret(val) => expect(
+        val.map_or(Type::Void, |val| self.ty(val)) == self.func.ret,
+        "returns another type",
+      ),
+    }
+  }
+
+  /// Every use is dominated by its definition. Uses in unreachable blocks
+  /// are not checked, as nothing dominates them.
+  fn check_dominance(&self) -> Check {
+    let func = self.func;
+    let cfg = Cfg::new(func);
+    let dom = DomTree::new(&cfg);
+    let reachable = |block: BlockId| dom.is_reachable(cfg.index(block));
+    let dominates = |a: BlockId, b: BlockId| dom.dominates(cfg.index(a), cfg.index(b));
+    for &block in &func.layout {
+      if !reachable(block) {
+        continue;
+      }
+      for (pos, &id) in func.block(block).insts.iter().enumerate() {
+        let kind = &func.inst(id).kind;
+        let uses = match kind {
+          Inst::Phi(incoming) => incoming.clone(),
+          _ => kind
+            .operands()
+            .into_iter()
+            .map(|val| (block, val))
+            .collect(),
+        };
+        for (at, val) in uses {
+          let Some(def) = val.as_inst() else { continue };
+          let (def_block, def_pos) = self.places[&def];
+          let ok = if kind.is_phi() {
+            !reachable(at) || dominates(def_block, at)
+          } else if def_block == block {
+            def_pos < pos
+          } else {
+            dominates(def_block, block)
+          };
+          if !ok {
+            return Err(format!(
+              "a use in {} is not dominated by its definition in {}",
+              func.block(block).name,
+              func.block(def_block).name
+            ));
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,121 @@
+//! Run a generated module in-process, with the runtime library implemented in
+//! Rust instead of linking `sylib.c`.
+use std::cell::RefCell;
+
+use inkwell::{execution_engine::ExecutionEngine, OptimizationLevel};
+
+use crate::{
+  codegen::Generator,
+  error::{Error, Result},
+  runtime::{ProgramOutput, Runtime},
+};
+
+thread_local! {
+  // the jitted code calls back into the runtime on the thread running it
+  static RUNTIME: RefCell<Runtime> = RefCell::new(Runtime::default());
+}
+
+fn with_runtime<T>(f: impl FnOnce(&mut Runtime) -> T) -> T {
+  RUNTIME.with(|rt| f(&mut rt.borrow_mut()))
+}
+
+// `void` is lowered to `i8` by the generator, so these return a dummy `i8`.
+extern "C" fn sysy_getint() -> i32 {
+  with_runtime(|rt| rt.getint())
+}
+extern "C" fn sysy_getch() -> i32 {
+  with_runtime(|rt| rt.getch())
+}
+extern "C" fn sysy_getfloat() -> f32 {
+  with_runtime(|rt| rt.getfloat())
+}
+extern "C" fn sysy_getarray(a: *mut i32) -> i32 {
+  with_runtime(|rt| {
+    let n = rt.getint();
+    let a = unsafe { std::slice::from_raw_parts_mut(a, n.max(0) as usize) };
+    for v in a {
+      *v = rt.getint();
+    }
+    n
+  })
+}
+extern "C" fn sysy_getfarray(a: *mut f32) -> i32 {
+  with_runtime(|rt| {
+    let n = rt.getint();
+    let a = unsafe { std::slice::from_raw_parts_mut(a, n.max(0) as usize) };
+    for v in a {
+      *v = rt.getfloat();
+    }
+    n
+  })
+}
+extern "C" fn sysy_putint(a: i32) -> i8 {
+  with_runtime(|rt| rt.putint(a));
+  0
+}
+extern "C" fn sysy_putch(a: i32) -> i8 {
+  with_runtime(|rt| rt.putch(a));
+  0
+}
+extern "C" fn sysy_putfloat(a: f32) -> i8 {
+  with_runtime(|rt| rt.putfloat(a));
+  0
+}
+extern "C" fn sysy_putarray(n: i32, a: *const i32) -> i8 {
+  let a = unsafe { std::slice::from_raw_parts(a, n.max(0) as usize) };
+  with_runtime(|rt| rt.putarray(n, a));
+  0
+}
+extern "C" fn sysy_putfarray(n: i32, a: *const f32) -> i8 {
+  let a = unsafe { std::slice::from_raw_parts(a, n.max(0) as usize) };
+  with_runtime(|rt| rt.putfarray(n, a));
+  0
+}
+extern "C" fn sysy_starttime(lineno: i32) -> i8 {
+  with_runtime(|rt| rt.starttime(lineno));
+  0
+}
+extern "C" fn sysy_stoptime(lineno: i32) -> i8 {
+  with_runtime(|rt| rt.stoptime(lineno));
+  0
+}
+
+// `putf` is variadic, which can not be defined in stable rust; the grammar
+// has no string literal to call it with anyway.
+fn runtime_functions() -> [(&'static str, usize); 12] {
+  [
+    ("getint", sysy_getint as usize),
+    ("getch", sysy_getch as usize),
+    ("getfloat", sysy_getfloat as usize),
+    ("getarray", sysy_getarray as usize),
+    ("getfarray", sysy_getfarray as usize),
+    ("putint", sysy_putint as usize),
+    ("putch", sysy_putch as usize),
+    ("putfloat", sysy_putfloat as usize),
+    ("putarray", sysy_putarray as usize),
+    ("putfarray", sysy_putfarray as usize),
+    ("_sysy_starttime", sysy_starttime as usize),
+    ("_sysy_stoptime", sysy_stoptime as usize),
+  ]
+}
+
+/// JIT compile the generator's module and run `main` with `input` as stdin.
+pub fn run_main(gen: &Generator, input: impl Into<Vec<u8>>) -> Result<ProgramOutput> {
+  ExecutionEngine::link_in_mc_jit();
+  // the engine takes the module over, keep the generator's one usable
+  let module = gen.module().clone();
+  let engine = module
+    .create_jit_execution_engine(OptimizationLevel::None)
+    .map_err(|e| Error::LLVM(e.to_string()))?;
+  for (name, addr) in runtime_functions() {
+    if let Some(function) = module.get_function(name) {
+      engine.add_global_mapping(&function, addr);
+    }
+  }
+  let main = unsafe { engine.get_function::<unsafe extern "C" fn() -> i32>("main") }
+    .map_err(|_| Error::MainNotFound)?;
+  RUNTIME.with(|rt| *rt.borrow_mut() = Runtime::new(input));
+  let ret = unsafe { main.call() };
+  let runtime = RUNTIME.with(|rt| rt.replace(Runtime::default()));
+  Ok(runtime.finish(ret))
+}
@@ -1,5 +1,15 @@
+pub mod analysis;
 #[allow(clippy::type_complexity)]
 pub mod codegen;
+pub mod difftest;
+pub mod emit;
 pub mod error;
+pub mod fuzz;
+pub mod interp;
+pub mod ir;
+pub mod jit;
+pub mod opt;
 pub mod parser;
+pub mod reduce;
+pub mod runtime;
 pub mod util;
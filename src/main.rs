@@ -1,63 +1,370 @@
 mod cli;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
-use std::{path::Path, process::Command};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use clap::Parser;
-use cli::Args;
+use clap::{CommandFactory, ErrorKind, Parser};
+use cli::{Args, Command, Engine, FuzzArgs, SymbolFormat};
 use inkwell::context::Context;
+use itertools::Itertools;
 use miette::{IntoDiagnostic, Result};
 use sysy::parser::{dump_node, parse};
 use sysy::{
-  codegen::Generator,
-  util::{compile_with_clang, get_bc_exe_path},
+  codegen::{passes::Pipeline, target::TargetSpec, Generator},
+  difftest::difftest as difftest_file,
+  emit::{emit_module, emit_ssa, emit_syntax, get_output_path, EmitKind},
+  fuzz::{check_program, generate_program, GenConfig, Oracle},
+  interp::{interpret, Limits},
+  ir,
+  jit::run_main,
+  opt,
+  reduce::reduce_program,
+  util::OptLevel,
 };
 fn main() -> Result<()> {
+  let mut args = cli::Args::parse_from(std::env::args().map(gcc_style_flag));
+  match args.command.take() {
+    Some(Command::Run { input, engine }) => run(input, engine),
+    Some(Command::Difftest { inputs, opt_level }) => difftest(inputs, opt_level),
+    Some(Command::Fuzz(fuzz_args)) => fuzz(fuzz_args),
+    Some(Command::Reduce {
+      input,
+      output,
+      oracle,
+      opt_level,
+    }) => reduce(input, output, oracle, opt_level),
+    None => compile(args),
+  }
+}
+
+/// Accept `-finline-limit` spelled with a single dash as gcc does, such as
+/// `-finline-limit=100`. Anything else is left to clap.
+fn gcc_style_flag(arg: String) -> String {
+  if arg == "-finline-limit" || arg.starts_with("-finline-limit=") {
+    format!("-{arg}")
+  } else {
+    arg
+  }
+}
+
+/// Our IR is run with a native call per call in the program, so calls nest
+/// only as deep as fits the stack of [`with_big_stack`].
+const SSA_CALL_DEPTH: usize = 10_000;
+
+/// Codegen and the interpreter recurse on the syntax tree, so run them with a
+/// big stack.
+fn with_big_stack<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+  let (rx, tx) = mpsc::channel();
+  let handle = {
+    std::thread::Builder::new()
+      .stack_size(1024 * 1024 * 16)
+      .name("gen".to_string())
+      .spawn(move || {
+        rx.send(f()).unwrap();
+      })
+  };
+  handle.unwrap().join().unwrap();
+  tx.recv().unwrap()
+}
+
+fn run(input: String, engine: Engine) -> Result<()> {
+  let file = std::fs::read_to_string(&input).into_diagnostic()?;
+  let tree = parse(&file)?;
+  let mut stdin = Vec::new();
+  std::io::stdin().read_to_end(&mut stdin).into_diagnostic()?;
+  let output = with_big_stack(move || match engine {
+    Engine::Jit => {
+      let ctx = Context::create();
+      let mut gen = Generator::new(&ctx, &input, &file);
+      gen.gen(&tree)?;
+      Ok(run_main(&gen, stdin)?)
+    }
+    Engine::Interp => Ok(interpret(&input, &file, &tree, stdin)?),
+    Engine::Ssa => {
+      let module = ir::lower(&input, &file, &tree)?;
+      ir::verify(&module)?;
+      let limits = Limits {
+        steps: u64::MAX,
+        call_depth: SSA_CALL_DEPTH,
+      };
+      Ok(ir::execute_with_limits(&module, stdin, limits)?)
+    }
+  })?;
+  std::io::stdout()
+    .write_all(&output.stdout)
+    .into_diagnostic()?;
+  std::io::stdout().flush().into_diagnostic()?;
+  eprint!("{}", output.timing);
+  std::process::exit(output.exit_code)
+}
+
+/// `.sy` files among `inputs`, directories searched recursively.
+fn collect_sources(inputs: Vec<String>) -> Result<Vec<PathBuf>> {
+  fn visit(path: PathBuf, res: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+      let mut entries = std::fs::read_dir(&path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+      entries.sort();
+      for entry in entries {
+        if entry.is_dir() || entry.extension().map_or(false, |ext| ext == "sy") {
+          visit(entry, res)?;
+        }
+      }
+    } else {
+      res.push(path);
+    }
+    Ok(())
+  }
+  let mut res = Vec::new();
+  for input in inputs {
+    visit(PathBuf::from(input), &mut res).into_diagnostic()?;
+  }
+  Ok(res)
+}
+
+fn difftest(inputs: Vec<String>, opt_level: OptLevel) -> Result<()> {
+  let mut failed = 0;
+  let sources = collect_sources(inputs)?;
+  for source in &sources {
+    let input = std::fs::read(source.with_extension("in")).unwrap_or_default();
+    let path = source.clone();
+    match with_big_stack(move || Ok(difftest_file(&path, &input, opt_level)?)) {
+      Ok(None) => println!("{}: ok", source.display()),
+      Ok(Some(divergence)) => {
+        failed += 1;
+        println!("{}: {divergence}", source.display());
+      }
+      Err(e) => {
+        failed += 1;
+        println!("{}: error", source.display());
+        eprintln!("{e:?}");
+      }
+    }
+  }
+  println!("{} passed, {failed} failed", sources.len() - failed);
+  if failed > 0 {
+    std::process::exit(1);
+  }
+  Ok(())
+}
+
+fn fuzz(args: FuzzArgs) -> Result<()> {
+  let FuzzArgs {
+    seed,
+    count,
+    oracle,
+    out_dir,
+    opt_level,
+    no_float,
+    no_arrays,
+    const_exprs,
+    print,
+  } = args;
+  let seed = seed.unwrap_or_else(|| {
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_secs()
+  });
+  let config = GenConfig {
+    floats: !no_float,
+    arrays: !no_arrays,
+    const_exprs,
+    ..Default::default()
+  };
+  if print {
+    for seed in seed..seed + count {
+      print!("{}", generate_program(seed, &config));
+    }
+    return Ok(());
+  }
+  std::fs::create_dir_all(&out_dir).into_diagnostic()?;
+  // codegen panics become findings, keep them from flooding the terminal
+  let default_hook = std::panic::take_hook();
+  std::panic::set_hook(Box::new(|_| {}));
+  let mut findings = 0;
+  for seed in seed..seed + count {
+    let path = Path::new(&out_dir).join(format!("fuzz-{seed}.sy"));
+    std::fs::write(&path, generate_program(seed, &config)).into_diagnostic()?;
+    let program = path.clone();
+    match with_big_stack(move || Ok(check_program(&program, oracle, opt_level)?))? {
+      Some(finding) => {
+        findings += 1;
+        println!("{}: {finding}", path.display());
+      }
+      None => std::fs::remove_file(&path).into_diagnostic()?,
+    }
+  }
+  std::panic::set_hook(default_hook);
+  println!("{count} programs, {findings} findings, kept in {out_dir}");
+  Ok(())
+}
+
+fn reduce(
+  input: String,
+  output: Option<String>,
+  oracle: Oracle,
+  opt_level: OptLevel,
+) -> Result<()> {
+  let output = output
+    .map(PathBuf::from)
+    .unwrap_or_else(|| Path::new(&input).with_extension("reduced.sy"));
+  let before = std::fs::metadata(&input).into_diagnostic()?.len();
+  let default_hook = std::panic::take_hook();
+  std::panic::set_hook(Box::new(|_| {}));
+  let path = PathBuf::from(&input);
+  let reduction = with_big_stack(move || Ok(reduce_program(&path, oracle, opt_level)?));
+  std::panic::set_hook(default_hook);
+  let reduction = reduction?;
+  std::fs::write(&output, &reduction.source).into_diagnostic()?;
+  println!("{input}: {}", reduction.finding);
+  println!(
+    "{before} -> {} bytes after {} tests, written to {}",
+    reduction.source.len(),
+    reduction.tests,
+    output.display()
+  );
+  Ok(())
+}
+
+fn compile(args: Args) -> Result<()> {
   let Args {
+    command: _,
     input,
     ast,
     prototype,
     global,
     ir_enable,
     exe_enable,
-  } = cli::Args::parse();
+    mut emit,
+    out_stem,
+    symbols,
+    symbols_output,
+    assembly,
+    object,
+    output,
+    opt_level,
+    passes,
+    time_passes,
+    static_arrays,
+    via_ssa,
+    ssa_passes,
+    print_after,
+    inline_limit,
+    remarks,
+    target,
+    cpu,
+    features,
+  } = args;
+  let input = input.unwrap_or_else(|| {
+    Args::command()
+      .error(
+        ErrorKind::MissingRequiredArgument,
+        "the input file path is required",
+      )
+      .exit()
+  });
+  if ir_enable {
+    emit.push(EmitKind::Ir);
+  }
+  if exe_enable {
+    emit.push(EmitKind::Exe);
+  }
+  let emit = emit.into_iter().unique().collect_vec();
+  let stem = out_stem
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from(Path::new(&input).file_stem().unwrap()));
+  let spec = TargetSpec {
+    triple: target,
+    cpu,
+    features,
+  };
+  let pipeline = match &passes {
+    Some(passes) => Pipeline::parse(passes)?,
+    None => Pipeline::for_level(opt_level),
+  };
+  let ssa_pipeline = match &ssa_passes {
+    Some(passes) => opt::Pipeline::parse(passes)?,
+    None => opt::Pipeline::for_level(opt_level),
+  };
+  let print_after = print_after
+    .iter()
+    .map(|name| opt::Pass::from_name(name))
+    .collect::<sysy::error::Result<Vec<_>>>()?;
   let file = std::fs::read_to_string(&input).into_diagnostic()?;
   let tree = parse(&file)?;
   if ast {
     dump_node(&tree.root_node(), &file);
   }
-  let (rx, tx) = mpsc::channel();
-  let handle = {
-    std::thread::Builder::new()
-      .stack_size(1024 * 1024 * 16)
-      .name("gen".to_string())
-      .spawn(move || {
-        let res = || -> Result<()> {
-          let ctx = Context::create();
-          let mut gen = Generator::new(&ctx, &input, &file);
-          gen.gen(&tree)?;
-          if prototype {
-            gen.print_function_proto();
-          }
-          if global {
-            gen.print_global_var();
-          }
-          let base = Path::new(&input);
-          let (bc_path, exe_path) = get_bc_exe_path(base);
-          if ir_enable || exe_enable {
-            gen.write(&bc_path);
-          }
-          if ir_enable {
-            Command::new("llvm-dis").arg(&bc_path).output().unwrap();
-          }
-          if exe_enable {
-            compile_with_clang(&bc_path, &exe_path);
-          }
-          Ok(())
-        }();
-        rx.send(res).unwrap();
-      })
-  };
-  handle.unwrap().join().unwrap();
-  tx.recv().unwrap()?;
-  Ok(())
+  for &kind in emit.iter().filter(|kind| kind.is_syntax()) {
+    emit_syntax(&tree, &file, kind, &get_output_path(&stem, kind))?;
+  }
+  with_big_stack(move || {
+    let ctx = Context::create();
+    let mut gen = Generator::new_with_target(&ctx, &input, &file, spec)?;
+    gen.set_pipeline(pipeline);
+    gen.set_static_arrays(static_arrays);
+    let wants_ssa = emit.contains(&EmitKind::Ssa);
+    let ssa = if via_ssa || wants_ssa || !print_after.is_empty() || remarks {
+      let mut module = ir::lower(&input, &file, &tree)?;
+      ir::verify(&module)?;
+      let mut pm = opt::PassManager::new(ssa_pipeline);
+      pm.set_print_after(print_after);
+      pm.set_inline_limit(inline_limit.unwrap_or(opt::DEFAULT_INLINE_LIMIT));
+      pm.set_remarks(remarks);
+      pm.run(&mut module, &mut std::io::stderr())?;
+      Some(module)
+    } else {
+      None
+    };
+    match &ssa {
+      Some(module) if via_ssa => gen.gen_ir(module)?,
+      _ => gen.gen(&tree)?,
+    }
+    if let (Some(module), true) = (&ssa, wants_ssa) {
+      emit_ssa(module, &get_output_path(&stem, EmitKind::Ssa))?;
+    }
+    if time_passes {
+      eprint!("{}", gen.pass_timings());
+    }
+    if prototype {
+      gen.print_function_proto();
+    }
+    if global {
+      gen.print_global_var();
+    }
+    if let Some(format) = symbols {
+      let table = gen.symbol_table();
+      let report = match format {
+        SymbolFormat::Text => table.to_text(),
+        SymbolFormat::Json => table.to_json(),
+      };
+      match &symbols_output {
+        Some(path) => std::fs::write(path, report).into_diagnostic()?,
+        None => println!("{report}"),
+      }
+    }
+    if assembly || object || output.is_some() {
+      let kind = if assembly {
+        EmitKind::Asm
+      } else if object {
+        EmitKind::Obj
+      } else {
+        EmitKind::Exe
+      };
+      let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| get_output_path(&stem, kind));
+      emit_module(&gen, kind, &output, opt_level)?;
+    }
+    for &kind in emit
+      .iter()
+      .filter(|&&kind| !kind.is_syntax() && kind != EmitKind::Ssa)
+    {
+      emit_module(&gen, kind, &get_output_path(&stem, kind), opt_level)?;
+    }
+    Ok(())
+  })
 }
@@ -0,0 +1,131 @@
+//! Which functions of a module call which, and which of them may recurse.
+use std::collections::HashMap;
+
+use crate::ir::{Inst, Module};
+
+pub struct CallGraph {
+  names: Vec<String>,
+  /// functions called by each function, once each
+  callees: Vec<Vec<usize>>,
+  /// strongly connected components, every callee's before its callers'
+  sccs: Vec<Vec<usize>>,
+  recursive: Vec<bool>,
+}
+
+impl CallGraph {
+  pub fn new(module: &Module) -> Self {
+    let index: HashMap<&str, usize> = module
+      .functions
+      .iter()
+      .enumerate()
+      .map(|(i, func)| (func.name.as_str(), i))
+      .collect();
+    let callees = module
+      .functions
+      .iter()
+      .map(|func| {
+        let mut callees = Vec::new();
+        for id in func.inst_ids() {
+          if let Inst::Call { callee, .. } = &func.inst(id).kind {
+            let callee = index[callee.as_str()];
+            if !callees.contains(&callee) {
+              callees.push(callee);
+            }
+          }
+        }
+        callees
+      })
+      .collect();
+    let mut graph = Self {
+      names: module
+        .functions
+        .iter()
+        .map(|func| func.name.clone())
+        .collect(),
+      callees,
+      sccs: Vec::new(),
+      recursive: vec![false; module.functions.len()],
+    };
+    graph.find_sccs();
+    graph
+  }
+
+  /// Tarjan's algorithm, which finds components callees first.
+  fn find_sccs(&mut self) {
+    struct State {
+      next: usize,
+      order: Vec<Option<usize>>,
+      low: Vec<usize>,
+      stack: Vec<usize>,
+      on_stack: Vec<bool>,
+    }
+    fn visit(graph: &mut CallGraph, state: &mut State, func: usize) {
+      state.order[func] = Some(state.next);
+      state.low[func] = state.next;
+      state.next += 1;
+      state.stack.push(func);
+      state.on_stack[func] = true;
+      for callee in graph.callees[func].clone() {
+        match state.order[callee] {
+          None => {
+            visit(graph, state, callee);
+            state.low[func] = state.low[func].min(state.low[callee]);
+          }
+          Some(order) if state.on_stack[callee] => {
+            state.low[func] = state.low[func].min(order);
+          }
+          Some(_) => {}
+        }
+      }
+      if Some(state.low[func]) != state.order[func] {
+        return;
+      }
+      let mut scc = Vec::new();
+      loop {
+        let member = state.stack.pop().unwrap();
+        state.on_stack[member] = false;
+        scc.push(member);
+        if member == func {
+          break;
+        }
+      }
+      let recursive = scc.len() > 1 || graph.callees[func].contains(&func);
+      for &member in &scc {
+        graph.recursive[member] = recursive;
+      }
+      graph.sccs.push(scc);
+    }
+    let count = self.names.len();
+    let mut state = State {
+      next: 0,
+      order: vec![None; count],
+      low: vec![0; count],
+      stack: Vec::new(),
+      on_stack: vec![false; count],
+    };
+    for func in 0..count {
+      if state.order[func].is_none() {
+        visit(self, &mut state, func);
+      }
+    }
+  }
+
+  /// Function names, every callee before its callers unless they call each
+  /// other.
+  pub fn bottom_up(&self) -> impl Iterator<Item = &str> + '_ {
+    self
+      .sccs
+      .iter()
+      .flatten()
+      .map(|&func| self.names[func].as_str())
+  }
+
+  /// Whether a call to `name` may be made while another is active.
+  pub fn is_recursive(&self, name: &str) -> bool {
+    self
+      .names
+      .iter()
+      .position(|func| func == name)
+      .is_some_and(|func| self.recursive[func])
+  }
+}
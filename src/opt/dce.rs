@@ -0,0 +1,32 @@
+//! Dead code elimination. Instructions with side effects are live, and so
+//! is whatever a live instruction uses; the rest goes, cycles of phis that
+//! only feed each other included.
+use std::collections::HashSet;
+
+use crate::ir::{Function, Value};
+
+pub fn run(func: &mut Function) -> bool {
+  let mut live = HashSet::new();
+  let mut work = func
+    .inst_ids()
+    .filter(|&id| func.inst(id).kind.has_side_effects())
+    .collect::<Vec<_>>();
+  while let Some(id) = work.pop() {
+    if !live.insert(id) {
+      continue;
+    }
+    for operand in func.inst(id).kind.operands() {
+      if let Value::Inst(def) = operand {
+        work.push(def);
+      }
+    }
+  }
+  let mut changed = false;
+  for block in func.layout.clone() {
+    let insts = &mut func.block_mut(block).insts;
+    let before = insts.len();
+    insts.retain(|id| live.contains(id));
+    changed |= insts.len() != before;
+  }
+  changed
+}
@@ -0,0 +1,159 @@
+//! Division and remainder by constants without dividing: by a power of two
+//! with shifts, by anything else with a multiplication by a magic number,
+//! as in Hacker's Delight, both rounding towards zero as `sdiv` does.
+use std::collections::HashMap;
+
+use crate::ir::{BinOp, Function, Inst, InstId, Type, Value};
+
+pub fn run(func: &mut Function) -> bool {
+  let mut replaced: HashMap<InstId, Value> = HashMap::new();
+  for block in func.layout.clone() {
+    let mut expander = Expander {
+      func: &mut *func,
+      insts: Vec::new(),
+      quotients: HashMap::new(),
+    };
+    for id in expander.func.block(block).insts.clone() {
+      match expander.func.inst(id).kind {
+        Inst::Binary(op @ (BinOp::SDiv | BinOp::SRem), x, Value::Int(d))
+          if d != 0 && d != i32::MIN =>
+        {
+          let val = if op == BinOp::SDiv {
+            expander.divide(x, d)
+          } else {
+            expander.remainder(x, d)
+          };
+          replaced.insert(id, val);
+        }
+        _ => expander.insts.push(id),
+      }
+    }
+    func.block_mut(block).insts = expander.insts;
+  }
+  if replaced.is_empty() {
+    return false;
+  }
+  for id in func.inst_ids().collect::<Vec<_>>() {
+    for operand in func.inst_mut(id).kind.operands_mut() {
+      // dividing by one replaces an instruction with what may be replaced
+      while let Some(&val) = operand.as_inst().and_then(|def| replaced.get(&def)) {
+        *operand = val;
+      }
+    }
+  }
+  true
+}
+
+/// The instructions of a block, divisions by constants expanded.
+struct Expander<'a> {
+  func: &'a mut Function,
+  insts: Vec<InstId>,
+  /// quotients worked out so far, which `x / d` and `x % d` share
+  quotients: HashMap<(Value, u32), Value>,
+}
+
+impl Expander<'_> {
+  fn emit(&mut self, kind: Inst) -> Value {
+    let id = self.func.create_inst(kind, Type::I32);
+    self.insts.push(id);
+    Value::Inst(id)
+  }
+
+  fn divide(&mut self, x: Value, d: i32) -> Value {
+    match d {
+      1 => x,
+      -1 => self.emit(Inst::Binary(BinOp::Sub, Value::Int(0), x)),
+      _ => {
+        let q = self.quotient(x, d.unsigned_abs());
+        if d < 0 {
+          self.emit(Inst::Binary(BinOp::Sub, Value::Int(0), q))
+        } else {
+          q
+        }
+      }
+    }
+  }
+
+  fn remainder(&mut self, x: Value, d: i32) -> Value {
+    // the sign of the remainder is that of `x` alone
+    let d = d.unsigned_abs();
+    if d == 1 {
+      return Value::Int(0);
+    }
+    let q = self.quotient(x, d);
+    let multiple = if d.is_power_of_two() {
+      self.emit(Inst::Binary(
+        BinOp::Shl,
+        q,
+        Value::Int(d.trailing_zeros() as i32),
+      ))
+    } else {
+      self.emit(Inst::Binary(BinOp::Mul, q, Value::Int(d as i32)))
+    };
+    self.emit(Inst::Binary(BinOp::Sub, x, multiple))
+  }
+
+  fn quotient(&mut self, x: Value, d: u32) -> Value {
+    if let Some(&q) = self.quotients.get(&(x, d)) {
+      return q;
+    }
+    let q = self.expand_quotient(x, d);
+    self.quotients.insert((x, d), q);
+    q
+  }
+
+  /// `x / d` for `2 <= d < 2^31`.
+  fn expand_quotient(&mut self, x: Value, d: u32) -> Value {
+    let int = |v: u32| Value::Int(v as i32);
+    if d.is_power_of_two() {
+      // a negative `x` is rounded towards zero by adding `d - 1` first
+      let k = d.trailing_zeros();
+      let sign = self.emit(Inst::Binary(BinOp::AShr, x, int(31)));
+      let bias = self.emit(Inst::Binary(BinOp::LShr, sign, int(32 - k)));
+      let biased = self.emit(Inst::Binary(BinOp::Add, x, bias));
+      return self.emit(Inst::Binary(BinOp::AShr, biased, int(k)));
+    }
+    let (magic, shift) = magic(d);
+    let mut q = self.emit(Inst::Binary(BinOp::SMulH, x, Value::Int(magic)));
+    if magic < 0 {
+      q = self.emit(Inst::Binary(BinOp::Add, q, x));
+    }
+    if shift > 0 {
+      q = self.emit(Inst::Binary(BinOp::AShr, q, int(shift)));
+    }
+    // plus one for a negative `x`, rounding towards zero
+    let sign = self.emit(Inst::Binary(BinOp::LShr, x, int(31)));
+    self.emit(Inst::Binary(BinOp::Add, q, sign))
+  }
+}
+
+/// The magic number and shift dividing by `d`, for `2 <= d < 2^31` not a
+/// power of two.
+fn magic(d: u32) -> (i32, u32) {
+  const TWO31: u32 = 1 << 31;
+  // the largest multiple of `d`, less one, below 2^31
+  let anc = TWO31 - 1 - TWO31 % d;
+  let mut p = 31;
+  let (mut q1, mut r1) = (TWO31 / anc, TWO31 % anc);
+  let (mut q2, mut r2) = (TWO31 / d, TWO31 % d);
+  loop {
+    p += 1;
+    q1 = q1.wrapping_mul(2);
+    r1 = r1.wrapping_mul(2);
+    if r1 >= anc {
+      q1 = q1.wrapping_add(1);
+      r1 = r1.wrapping_sub(anc);
+    }
+    q2 = q2.wrapping_mul(2);
+    r2 = r2.wrapping_mul(2);
+    if r2 >= d {
+      q2 = q2.wrapping_add(1);
+      r2 = r2.wrapping_sub(d);
+    }
+    let delta = d - r2;
+    if !(q1 < delta || (q1 == delta && r1 == 0)) {
+      break;
+    }
+  }
+  (q2.wrapping_add(1) as i32, p - 32)
+}
@@ -0,0 +1,86 @@
+//! Dead store elimination: stores to a local array nothing ever reads,
+//! and stores overwritten later in their block before anything may read
+//! them.
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+  analysis::may_alias,
+  ir::{Function, Inst, InstId, Value},
+};
+
+/// Calls of builtins that never read memory the caller can see.
+fn reads_memory(callee: &str) -> bool {
+  !matches!(
+    callee,
+    "getint"
+      | "getch"
+      | "getfloat"
+      | "putint"
+      | "putch"
+      | "putfloat"
+      | "_sysy_starttime"
+      | "_sysy_stoptime"
+  )
+}
+
+pub fn run(func: &mut Function) -> bool {
+  let mut dead = unread_allocas(func);
+  for &block in &func.layout {
+    // pointers stored to further down, with nothing reading them since
+    let mut overwritten: Vec<Value> = Vec::new();
+    for &id in func.block(block).insts.iter().rev() {
+      match &func.inst(id).kind {
+        Inst::Store { ptr, .. } => {
+          if overwritten.contains(ptr) {
+            dead.insert(id);
+          } else {
+            overwritten.push(*ptr);
+          }
+        }
+        Inst::Load(ptr) => overwritten.retain(|&other| !may_alias(func, other, *ptr)),
+        Inst::Call { callee, .. } if reads_memory(callee) => overwritten.clear(),
+        _ => {}
+      }
+    }
+  }
+  for block in func.layout.clone() {
+    func.block_mut(block).insts.retain(|id| !dead.contains(id));
+  }
+  !dead.is_empty()
+}
+
+/// Allocas only ever stored to, with the geps into them and the stores.
+fn unread_allocas(func: &Function) -> HashSet<InstId> {
+  let mut users: HashMap<InstId, Vec<InstId>> = HashMap::new();
+  for id in func.inst_ids() {
+    for operand in func.inst(id).kind.operands() {
+      if let Value::Inst(def) = operand {
+        users.entry(def).or_default().push(id);
+      }
+    }
+  }
+  let mut dead = HashSet::new();
+  for alloca in func.inst_ids() {
+    if !matches!(func.inst(alloca).kind, Inst::Alloca(_)) {
+      continue;
+    }
+    let mut derived = vec![alloca];
+    let mut read = false;
+    let mut i = 0;
+    while i < derived.len() && !read {
+      let ptr = Value::Inst(derived[i]);
+      for &user in users.get(&derived[i]).into_iter().flatten() {
+        match &func.inst(user).kind {
+          Inst::Gep { base, .. } if *base == ptr => derived.push(user),
+          Inst::Store { value, .. } if *value != ptr => derived.push(user),
+          _ => read = true,
+        }
+      }
+      i += 1;
+    }
+    if !read {
+      dead.extend(derived);
+    }
+  }
+  dead
+}
@@ -0,0 +1,198 @@
+//! Global value numbering over the dominator tree. A pure instruction
+//! computing what a dominating one already has is replaced by it, and so is
+//! a load of memory that a dominating load or store still holds the value
+//! of on every path.
+use std::collections::HashMap;
+
+use crate::{
+  analysis::{solve, Analysis, BitSet, Cfg, Direction, DomTree, Expr, FlowGraph, Universe},
+  ir::{Function, Inst, InstId, Value},
+};
+
+/// Loads and stores whose memory still holds what they read or wrote: the
+/// instruction is on every path and nothing may have written the memory
+/// since.
+struct Holding {
+  accesses: Universe<InstId>,
+  /// what each access reads or writes, as the load it makes redundant
+  exprs: Vec<Expr>,
+  gen: Vec<BitSet>,
+  kill: Vec<BitSet>,
+}
+
+impl Holding {
+  fn new(cfg: &Cfg<Function>) -> Self {
+    let func = cfg.func;
+    let mut accesses = Universe::default();
+    let mut exprs = Vec::new();
+    for &id in cfg.insts.iter().flatten() {
+      if let Inst::Load(ptr) | Inst::Store { ptr, .. } = func.inst(id).kind {
+        accesses.insert(id);
+        exprs.push(Expr::Load(ptr));
+      }
+    }
+    let mut holding = Self {
+      accesses,
+      exprs,
+      gen: Vec::new(),
+      kill: Vec::new(),
+    };
+    for insts in &cfg.insts {
+      let mut gen = BitSet::new(holding.accesses.len());
+      let mut kill = BitSet::new(holding.accesses.len());
+      for &id in insts {
+        holding.step(func, id, &mut gen, Some(&mut kill));
+      }
+      holding.gen.push(gen);
+      holding.kill.push(kill);
+    }
+    holding
+  }
+
+  /// Update `fact` past `inst`, noting what it kills in `kill`.
+  fn step(&self, func: &Function, inst: InstId, fact: &mut BitSet, mut kill: Option<&mut BitSet>) {
+    if func.may_kill(inst) {
+      for (index, expr) in self.exprs.iter().enumerate() {
+        if func.kills(inst, expr) {
+          fact.remove(index);
+          if let Some(kill) = kill.as_deref_mut() {
+            kill.insert(index);
+          }
+        }
+      }
+    }
+    if let Some(index) = self.accesses.index(&inst) {
+      fact.insert(index);
+    }
+  }
+}
+
+impl Analysis<Function> for Holding {
+  type Fact = BitSet;
+  const DIRECTION: Direction = Direction::Forward;
+
+  fn boundary(&self, _cfg: &Cfg<Function>) -> BitSet {
+    BitSet::new(self.accesses.len())
+  }
+
+  fn top(&self, _cfg: &Cfg<Function>) -> BitSet {
+    BitSet::full(self.accesses.len())
+  }
+
+  fn meet(&self, fact: &mut BitSet, other: &BitSet) {
+    fact.intersect_with(other);
+  }
+
+  fn transfer(&self, _cfg: &Cfg<Function>, block: usize, fact: &BitSet) -> BitSet {
+    let mut holding = fact.clone();
+    holding.subtract(&self.kill[block]);
+    holding.union_with(&self.gen[block]);
+    holding
+  }
+}
+
+/// Scoped tables of the values available in the dominator tree walk.
+#[derive(Default)]
+struct Tables {
+  exprs: HashMap<Expr, Value>,
+  /// the latest access of each address, with the value it reads or writes
+  memory: HashMap<Value, (InstId, Value)>,
+  // previous entries, to restore when leaving a subtree
+  expr_log: Vec<(Expr, Option<Value>)>,
+  memory_log: Vec<(Value, Option<(InstId, Value)>)>,
+}
+
+impl Tables {
+  fn define(&mut self, expr: Expr, val: Value) {
+    let old = self.exprs.insert(expr.clone(), val);
+    self.expr_log.push((expr, old));
+  }
+
+  fn access(&mut self, ptr: Value, inst: InstId, val: Value) {
+    let old = self.memory.insert(ptr, (inst, val));
+    self.memory_log.push((ptr, old));
+  }
+
+  fn restore(&mut self, (exprs, memory): (usize, usize)) {
+    for (expr, old) in self.expr_log.drain(exprs..).rev() {
+      match old {
+        Some(val) => self.exprs.insert(expr, val),
+        None => self.exprs.remove(&expr),
+      };
+    }
+    for (ptr, old) in self.memory_log.drain(memory..).rev() {
+      match old {
+        Some(access) => self.memory.insert(ptr, access),
+        None => self.memory.remove(&ptr),
+      };
+    }
+  }
+}
+
+pub fn run(func: &mut Function) -> bool {
+  let replaced = {
+    let cfg = Cfg::new(&*func);
+    let dom = DomTree::new(&cfg);
+    let holding = Holding::new(&cfg);
+    let solution = solve(&cfg, &holding);
+    let mut replaced: HashMap<InstId, Value> = HashMap::new();
+    let mut tables = Tables::default();
+    // (block, table sizes to restore once its subtree is done)
+    let mut stack = vec![(0, None)];
+    while let Some((block, marks)) = stack.pop() {
+      if let Some(marks) = marks {
+        tables.restore(marks);
+        continue;
+      }
+      stack.push((
+        block,
+        Some((tables.expr_log.len(), tables.memory_log.len())),
+      ));
+      stack.extend(dom.children(block).iter().map(|&child| (child, None)));
+      let mut fact = solution.before[block].clone();
+      for &id in &cfg.insts[block] {
+        let mut kind = func.inst(id).kind.clone();
+        for operand in kind.operands_mut() {
+          if let Value::Inst(def) = operand {
+            *operand = replaced.get(def).copied().unwrap_or(*operand);
+          }
+        }
+        match kind {
+          Inst::Load(ptr) => match tables.memory.get(&ptr) {
+            Some(&(access, val)) if fact.contains(holding.accesses.index(&access).unwrap()) => {
+              replaced.insert(id, val);
+            }
+            _ => tables.access(ptr, id, Value::Inst(id)),
+          },
+          Inst::Store { value, ptr } => tables.access(ptr, id, value),
+          _ => {
+            if let Some(expr) = Expr::of(&kind) {
+              match tables.exprs.get(&expr) {
+                Some(&val) => {
+                  replaced.insert(id, val);
+                }
+                None => tables.define(expr, Value::Inst(id)),
+              }
+            }
+          }
+        }
+        holding.step(func, id, &mut fact, None);
+      }
+    }
+    replaced
+  };
+  for id in func.inst_ids().collect::<Vec<_>>() {
+    for operand in func.inst_mut(id).kind.operands_mut() {
+      if let Value::Inst(def) = operand {
+        *operand = replaced.get(def).copied().unwrap_or(*operand);
+      }
+    }
+  }
+  for block in func.layout.clone() {
+    func
+      .block_mut(block)
+      .insts
+      .retain(|id| !replaced.contains_key(id));
+  }
+  !replaced.is_empty()
+}
@@ -0,0 +1,222 @@
+//! Inlining of calls to small functions. Functions are visited callees
+//! first, so what was inlined into a callee counts towards its size.
+//!
+//! The cost of a call is the size of the callee less what inlining it
+//! saves: the call and its arguments, every use of a parameter given a
+//! constant, which later passes fold, and the whole callee for the last
+//! call left to it. Calls to recursive functions are never inlined.
+use std::collections::HashMap;
+
+use super::{call_graph::CallGraph, rename_incoming, Pass, Remark};
+use crate::ir::{BlockId, Function, Inst, InstId, Module, Type, Value};
+
+/// The cost up to which calls are inlined unless `--finline-limit` says
+/// otherwise.
+pub const DEFAULT_LIMIT: usize = 100;
+
+pub fn run(module: &mut Module, limit: usize, remarks: &mut Vec<Remark>) -> bool {
+  let graph = CallGraph::new(module);
+  // call sites left to each function
+  let mut calls: HashMap<String, usize> = HashMap::new();
+  for func in &module.functions {
+    count_calls(func, &mut calls);
+  }
+  let mut inlined = Vec::new();
+  for name in graph.bottom_up().map(str::to_string).collect::<Vec<_>>() {
+    let index = module
+      .functions
+      .iter()
+      .position(|func| func.name == name)
+      .unwrap();
+    let mut caller = std::mem::take(&mut module.functions[index]);
+    let sites = caller
+      .inst_ids()
+      .filter(|&id| matches!(caller.inst(id).kind, Inst::Call { .. }))
+      .collect::<Vec<_>>();
+    for call in sites {
+      let Inst::Call { callee, args } = &caller.inst(call).kind else {
+        unreachable!()
+      };
+      let callee = callee.clone();
+      let mut remark = |message| {
+        remarks.push(Remark {
+          pass: Pass::Inline,
+          function: name.clone(),
+          message,
+        })
+      };
+      if graph.is_recursive(&callee) {
+        remark(format!("not inlined @{callee}: it is recursive"));
+        continue;
+      }
+      let Some(target) = module
+        .function(&callee)
+        .filter(|func| !func.is_declaration())
+      else {
+        continue;
+      };
+      let cost = cost(target, args, calls[&callee] == 1);
+      if cost > limit as isize {
+        remark(format!(
+          "not inlined @{callee}: cost {cost} > limit {limit}"
+        ));
+        continue;
+      }
+      remark(format!("inlined @{callee}: cost {cost} <= limit {limit}"));
+      inline_call(&mut caller, call, target);
+      count_calls(target, &mut calls);
+      *calls.get_mut(&callee).unwrap() -= 1;
+      inlined.push(callee);
+    }
+    module.functions[index] = caller;
+  }
+  module.functions.retain(|func| {
+    let unused = func.name != "main" && inlined.contains(&func.name) && calls[&func.name] == 0;
+    if unused {
+      remarks.push(Remark {
+        pass: Pass::Inline,
+        function: func.name.clone(),
+        message: "removed: every call to it was inlined".to_string(),
+      });
+    }
+    !unused
+  });
+  !inlined.is_empty()
+}
+
+fn count_calls(func: &Function, calls: &mut HashMap<String, usize>) {
+  for id in func.inst_ids() {
+    if let Inst::Call { callee, .. } = &func.inst(id).kind {
+      *calls.entry(callee.clone()).or_default() += 1;
+    }
+  }
+}
+
+/// The size of `callee` less what inlining a call to it with `args` saves.
+fn cost(callee: &Function, args: &[Value], last_call: bool) -> isize {
+  let mut size = 0;
+  let mut param_uses = vec![0; args.len()];
+  for id in callee.inst_ids() {
+    let kind = &callee.inst(id).kind;
+    size += match kind {
+      Inst::Phi(_) | Inst::Alloca(_) | Inst::Br(_) | Inst::Ret(_) => 0,
+      Inst::Call { args, .. } => 1 + args.len(),
+      _ => 1,
+    };
+    for operand in kind.operands() {
+      if let Value::Param(i) = operand {
+        param_uses[i] += 1;
+      }
+    }
+  }
+  let mut saved = 1 + args.len();
+  saved += args
+    .iter()
+    .zip(param_uses)
+    .filter(|(arg, _)| arg.is_const())
+    .map(|(_, uses)| uses)
+    .sum::<usize>();
+  if last_call {
+    saved += size;
+  }
+  size as isize - saved as isize
+}
+
+/// Replace `call` in `func` with a copy of the body of `callee`, whose
+/// allocas join those at the top of the entry block.
+fn inline_call(func: &mut Function, call: InstId, callee: &Function) {
+  let block = func.inst_blocks()[&call];
+  let Inst::Call { args, .. } = func.inst(call).kind.clone() else {
+    unreachable!()
+  };
+  // what follows the call moves to a block of its own
+  let after = func.create_block(&format!("after_{}", callee.name));
+  let insts = &mut func.block_mut(block).insts;
+  let pos = insts.iter().position(|&id| id == call).unwrap();
+  let rest = insts.split_off(pos + 1);
+  insts.pop();
+  func.block_mut(after).insts = rest;
+  for succ in func.successors(after) {
+    rename_incoming(func, succ, block, after);
+  }
+  let blocks: HashMap<BlockId, BlockId> = callee
+    .layout
+    .iter()
+    .map(|&b| {
+      let name = format!("{}_{}", callee.name, callee.block(b).name);
+      (b, func.create_block(&name))
+    })
+    .collect();
+  let insts: HashMap<InstId, InstId> = callee
+    .inst_ids()
+    .map(|id| {
+      let data = callee.inst(id);
+      (id, func.create_inst(data.kind.clone(), data.ty.clone()))
+    })
+    .collect();
+  let mut allocas = Vec::new();
+  let mut returns = Vec::new();
+  for &b in &callee.layout {
+    let copy = blocks[&b];
+    for id in &callee.block(b).insts {
+      let id = insts[id];
+      let kind = &mut func.inst_mut(id).kind;
+      for operand in kind.operands_mut() {
+        *operand = match *operand {
+          Value::Inst(def) => Value::Inst(insts[&def]),
+          Value::Param(i) => args[i],
+          val => val,
+        };
+      }
+      for succ in kind.successors_mut() {
+        *succ = blocks[succ];
+      }
+      match kind {
+        Inst::Phi(incoming) => {
+          for (pred, _) in incoming {
+            *pred = blocks[pred];
+          }
+        }
+        Inst::Ret(val) => {
+          returns.push((copy, *val));
+          *kind = Inst::Br(after);
+        }
+        Inst::Alloca(_) => {
+          allocas.push(id);
+          continue;
+        }
+        _ => {}
+      }
+      func.block_mut(copy).insts.push(id);
+    }
+  }
+  func.push(block, Inst::Br(blocks[&callee.entry()]), Type::Void);
+  let entry = func.entry();
+  let pos = func
+    .block(entry)
+    .insts
+    .iter()
+    .take_while(|&&id| matches!(func.inst(id).kind, Inst::Alloca(_)))
+    .count();
+  func.block_mut(entry).insts.splice(pos..pos, allocas);
+  let pos = func.layout.iter().position(|&b| b == block).unwrap() + 1;
+  func.layout.splice(
+    pos..pos,
+    callee.layout.iter().map(|b| blocks[b]).chain([after]),
+  );
+  let ty = func.inst(call).ty.clone();
+  if ty != Type::Void {
+    let result = match returns[..] {
+      [] => Value::zero(&ty),
+      [(_, Some(val))] => val,
+      _ => {
+        let incoming = returns
+          .into_iter()
+          .map(|(pred, val)| (pred, val.unwrap()))
+          .collect();
+        Value::Inst(func.insert(after, 0, Inst::Phi(incoming), ty))
+      }
+    };
+    func.replace_all_uses(Value::Inst(call), result);
+  }
+}
@@ -0,0 +1,225 @@
+//! Loop strength reduction: a multiplication of an induction variable by
+//! something the loop does not change, and an address into an array
+//! indexed by an induction variable, become induction variables of their
+//! own, stepped by an addition or a `gep` every iteration instead of
+//! computed anew. Addresses share the pointer to the row the induction
+//! variable picks, and go on from there with the indices after it.
+use std::collections::{HashMap, HashSet};
+
+use super::loops::{find_loops, Induction, Loop};
+use crate::{
+  analysis::Expr,
+  ir::{BinOp, Function, Inst, InstId, Module, Type, Value},
+};
+
+/// The most induction variables added to a loop, each taking a register
+/// all through it. Addresses come first.
+const MAX_INDUCTIONS: usize = 4;
+
+/// Over a whole module, whose globals give the types of the rows in them.
+pub fn run(module: &mut Module) -> bool {
+  let mut changed = false;
+  for i in 0..module.functions.len() {
+    if module.functions[i].is_declaration() {
+      continue;
+    }
+    let mut func = std::mem::take(&mut module.functions[i]);
+    let (loops, created) = find_loops(&mut func);
+    changed |= created;
+    for lp in &loops {
+      let mut budget = MAX_INDUCTIONS;
+      changed |= reduce_geps(module, &mut func, lp, &mut budget);
+      changed |= reduce_muls(&mut func, lp, &mut budget);
+    }
+    module.functions[i] = func;
+  }
+  changed
+}
+
+fn reduce_muls(func: &mut Function, lp: &Loop, budget: &mut usize) -> bool {
+  let inductions = lp.inductions(func);
+  let insts = lp.insts(func);
+  let mut candidates = Vec::new();
+  for &block in &lp.blocks {
+    for &id in &func.block(block).insts {
+      let Inst::Binary(BinOp::Mul, l, r) = func.inst(id).kind else {
+        continue;
+      };
+      let (iv, factor) = match (induction(&inductions, l), induction(&inductions, r)) {
+        (Some(iv), None) if is_invariant(&insts, r) => (iv, r),
+        (None, Some(iv)) if is_invariant(&insts, l) => (iv, l),
+        _ => continue,
+      };
+      if matches!(factor, Value::Int(-1..=1)) {
+        continue;
+      }
+      candidates.push((id, iv, factor));
+    }
+  }
+  let mut changed = false;
+  let mut reduced: HashMap<Expr, InstId> = HashMap::new();
+  for (id, iv, factor) in candidates {
+    let expr = Expr::of(&func.inst(id).kind).unwrap();
+    let phi = match reduced.get(&expr) {
+      Some(&phi) => phi,
+      None if *budget == 0 => continue,
+      None => {
+        *budget -= 1;
+        let iv = &inductions[iv];
+        let init = multiply(func, lp, iv.init, factor);
+        let step = multiply(func, lp, Value::Int(iv.step), factor);
+        let phi = func.insert(lp.header, 0, Inst::Phi(Vec::new()), Type::I32);
+        let next = lp.insert_after(
+          func,
+          iv.update,
+          Inst::Binary(BinOp::Add, Value::Inst(phi), step),
+          Type::I32,
+        );
+        func.inst_mut(phi).kind =
+          Inst::Phi(vec![(lp.preheader, init), (lp.latch, Value::Inst(next))]);
+        reduced.insert(expr, phi);
+        phi
+      }
+    };
+    replace(func, lp, id, Value::Inst(phi));
+    changed = true;
+  }
+  changed
+}
+
+fn reduce_geps(module: &Module, func: &mut Function, lp: &Loop, budget: &mut usize) -> bool {
+  let inductions = lp.inductions(func);
+  let insts = lp.insts(func);
+  let mut candidates = Vec::new();
+  for &block in &lp.blocks {
+    for &id in &func.block(block).insts {
+      let Inst::Gep { base, indices } = &func.inst(id).kind else {
+        continue;
+      };
+      // a pointer stepped by one index is no cheaper than the gep
+      if indices.len() < 2 || !is_invariant(&insts, *base) {
+        continue;
+      }
+      let mut varying = indices
+        .iter()
+        .enumerate()
+        .filter(|(_, &index)| !is_invariant(&insts, index));
+      let (Some((k, &index)), None) = (varying.next(), varying.next()) else {
+        continue;
+      };
+      let Some((iv, offset)) = index_start(func, &inductions, &insts, index) else {
+        continue;
+      };
+      candidates.push((id, k, iv, offset));
+    }
+  }
+  let mut changed = false;
+  // the addresses up to the varying index, which geps differing in the
+  // indices after it share
+  let mut reduced: HashMap<Expr, InstId> = HashMap::new();
+  for (id, k, iv, offset) in candidates {
+    let Inst::Gep { base, indices } = func.inst(id).kind.clone() else {
+      unreachable!()
+    };
+    let prefix = Expr::Gep(base, indices[..=k].to_vec());
+    let phi = match reduced.get(&prefix) {
+      Some(&phi) => phi,
+      None if *budget == 0 => continue,
+      None => {
+        *budget -= 1;
+        let ty = module.value_type(func, base).gep_result(k + 1).unwrap();
+        let mut start = indices[..=k].to_vec();
+        start[k] = match offset {
+          Some(kind) => lp.emit_before(func, kind, Type::I32),
+          None => inductions[iv].init,
+        };
+        let start = lp.emit_before(
+          func,
+          Inst::Gep {
+            base,
+            indices: start,
+          },
+          ty.clone(),
+        );
+        let phi = func.insert(lp.header, 0, Inst::Phi(Vec::new()), ty.clone());
+        let next = lp.insert_after(
+          func,
+          inductions[iv].update,
+          Inst::Gep {
+            base: Value::Inst(phi),
+            indices: vec![Value::Int(inductions[iv].step)],
+          },
+          ty,
+        );
+        func.inst_mut(phi).kind =
+          Inst::Phi(vec![(lp.preheader, start), (lp.latch, Value::Inst(next))]);
+        reduced.insert(prefix, phi);
+        phi
+      }
+    };
+    if k + 1 == indices.len() {
+      replace(func, lp, id, Value::Inst(phi));
+    } else {
+      // the pointer goes on from where the phi points
+      let rest = [Value::Int(0)]
+        .into_iter()
+        .chain(indices[k + 1..].iter().copied());
+      func.inst_mut(id).kind = Inst::Gep {
+        base: Value::Inst(phi),
+        indices: rest.collect(),
+      };
+    }
+    changed = true;
+  }
+  changed
+}
+
+/// The induction variable `index` moves with, and how its start differs
+/// from that of the variable: `None` if `index` is the variable, or else
+/// by some loop invariant added or subtracted.
+fn index_start(
+  func: &Function,
+  inductions: &[Induction],
+  insts: &HashSet<InstId>,
+  index: Value,
+) -> Option<(usize, Option<Inst>)> {
+  if let Some(iv) = induction(inductions, index) {
+    return Some((iv, None));
+  }
+  let Inst::Binary(op @ (BinOp::Add | BinOp::Sub), l, r) = func.inst(index.as_inst()?).kind else {
+    return None;
+  };
+  match (induction(inductions, l), induction(inductions, r)) {
+    (Some(iv), None) if is_invariant(insts, r) => {
+      Some((iv, Some(Inst::Binary(op, inductions[iv].init, r))))
+    }
+    (None, Some(iv)) if op == BinOp::Add && is_invariant(insts, l) => {
+      Some((iv, Some(Inst::Binary(op, l, inductions[iv].init))))
+    }
+    _ => None,
+  }
+}
+
+/// `l * r` worked out before the loop, unless one of them is zero or one.
+fn multiply(func: &mut Function, lp: &Loop, l: Value, r: Value) -> Value {
+  match (l, r) {
+    (Value::Int(0), _) | (_, Value::Int(0)) => Value::Int(0),
+    (Value::Int(1), val) | (val, Value::Int(1)) => val,
+    _ => lp.emit_before(func, Inst::Binary(BinOp::Mul, l, r), Type::I32),
+  }
+}
+
+fn induction(inductions: &[Induction], val: Value) -> Option<usize> {
+  inductions.iter().position(|iv| Value::Inst(iv.phi) == val)
+}
+
+fn is_invariant(insts: &HashSet<InstId>, val: Value) -> bool {
+  !matches!(val, Value::Inst(id) if insts.contains(&id))
+}
+
+fn replace(func: &mut Function, lp: &Loop, inst: InstId, val: Value) {
+  func.replace_all_uses(Value::Inst(inst), val);
+  for &block in &lp.blocks {
+    func.remove_inst(block, inst);
+  }
+}
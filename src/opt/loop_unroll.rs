@@ -0,0 +1,274 @@
+//! Unrolling of innermost loops whose trip count is known: fully, one copy
+//! of the body per iteration, when the copies stay small, or else by a
+//! factor dividing the trip count, so only one copy in every few tests
+//! whether to leave.
+use std::collections::{HashMap, HashSet};
+
+use super::loops::{find_loops, Loop};
+use crate::ir::{BlockId, Function, Inst, InstId, Value};
+
+/// Loops are unrolled fully if the copies take at most this many
+/// instructions,
+const FULL_SIZE: usize = 256;
+/// or else by the largest factor up to `MAX_FACTOR` whose copies take at
+/// most this many.
+const PARTIAL_SIZE: usize = 64;
+const MAX_FACTOR: usize = 8;
+/// The most iterations the trip count is worked out for, by stepping the
+/// induction variable.
+const MAX_TRIP_COUNT: usize = 1 << 16;
+
+pub fn run(func: &mut Function) -> bool {
+  let mut changed = false;
+  // headers of loops unrolled by a factor or left alone, which must not
+  // be looked at again
+  let mut done = HashSet::new();
+  loop {
+    let (loops, created) = find_loops(func);
+    changed |= created;
+    let Some((lp, trip)) = loops
+      .iter()
+      .filter(|lp| lp.innermost && !done.contains(&lp.header))
+      .find_map(|lp| Some((lp, trip_count(func, lp)?)))
+    else {
+      break;
+    };
+    done.insert(lp.header);
+    let size = size(func, lp);
+    if trip * size <= FULL_SIZE {
+      unroll_fully(func, lp, trip);
+    } else if let Some(factor) = (2..=MAX_FACTOR)
+      .rev()
+      .find(|&factor| trip % factor == 0 && factor * size <= PARTIAL_SIZE)
+    {
+      unroll_by(func, lp, factor);
+    } else {
+      continue;
+    }
+    func.remove_unreachable_blocks();
+    func.remove_trivial_phis();
+    changed = true;
+  }
+  changed
+}
+
+/// How many times the body of `lp` runs, if the header alone leaves it,
+/// comparing an induction variable starting at a constant to a constant.
+fn trip_count(func: &Function, lp: &Loop) -> Option<usize> {
+  let Inst::CondBr { cond, then, els } = func.inst(func.terminator(lp.header)?).kind else {
+    return None;
+  };
+  let stay = match (lp.contains(then), lp.contains(els)) {
+    (true, false) => true,
+    (false, true) => false,
+    _ => return None,
+  };
+  if lp.latch == lp.header || func.successors(lp.latch) != [lp.header] {
+    return None;
+  }
+  let exits_elsewhere = lp
+    .blocks
+    .iter()
+    .filter(|&&block| block != lp.header)
+    .any(|&block| {
+      func
+        .successors(block)
+        .iter()
+        .any(|&succ| !lp.contains(succ))
+    });
+  if exits_elsewhere {
+    return None;
+  }
+  let Inst::Cmp(op, l, r) = func.inst(cond.as_inst()?).kind else {
+    return None;
+  };
+  let inductions = lp.inductions(func);
+  let (iv, op, bound) = inductions.iter().find_map(|iv| match (l, r) {
+    (l, Value::Int(bound)) if l == Value::Inst(iv.phi) => Some((iv, op, bound)),
+    (Value::Int(bound), r) if r == Value::Inst(iv.phi) => Some((iv, op.swap(), bound)),
+    _ => None,
+  })?;
+  let Value::Int(mut i) = iv.init else {
+    return None;
+  };
+  let mut trip = 0;
+  while op.eval(i, bound) == stay {
+    trip += 1;
+    if trip > MAX_TRIP_COUNT {
+      return None;
+    }
+    i = i.wrapping_add(iv.step);
+  }
+  Some(trip)
+}
+
+fn size(func: &Function, lp: &Loop) -> usize {
+  lp.blocks
+    .iter()
+    .flat_map(|&block| &func.block(block).insts)
+    .filter(|&&id| !func.inst(id).kind.is_phi())
+    .count()
+}
+
+/// A copy of one iteration of a loop.
+struct Iteration {
+  header: BlockId,
+  latch: BlockId,
+  /// what the header phis take from it for the next iteration
+  next: Vec<Value>,
+}
+
+/// Copy the blocks of `lp` into the layout at `pos`, for an iteration
+/// known to go around the loop again, with the header phis taking
+/// `values`. The copy of the latch still jumps to the header.
+fn copy_iteration(func: &mut Function, lp: &Loop, values: &[Value], pos: usize) -> Iteration {
+  let phis = lp.phis(func);
+  let blocks: HashMap<BlockId, BlockId> = lp
+    .blocks
+    .iter()
+    .map(|&block| {
+      let name = func.block(block).name.clone();
+      (block, func.create_block(&name))
+    })
+    .collect();
+  let mut insts: HashMap<InstId, Value> =
+    phis.iter().copied().zip(values.iter().copied()).collect();
+  for &block in &lp.blocks {
+    for &id in &func.block(block).insts.clone() {
+      if !phis.contains(&id) {
+        let data = func.inst(id).clone();
+        let copy = func.push(blocks[&block], data.kind, data.ty);
+        insts.insert(id, Value::Inst(copy));
+      }
+    }
+  }
+  let map = |val: Value| match val {
+    Value::Inst(id) => insts.get(&id).copied().unwrap_or(val),
+    val => val,
+  };
+  for &block in &lp.blocks {
+    for id in func.block(blocks[&block]).insts.clone() {
+      let kind = &mut func.inst_mut(id).kind;
+      for operand in kind.operands_mut() {
+        *operand = map(*operand);
+      }
+      for succ in kind.successors_mut() {
+        if *succ != lp.header && lp.contains(*succ) {
+          *succ = blocks[succ];
+        }
+      }
+      if let Inst::Phi(incoming) = kind {
+        for (pred, _) in incoming {
+          *pred = blocks[pred];
+        }
+      }
+    }
+  }
+  // the copy of the header goes on into the loop
+  let header = blocks[&lp.header];
+  let term = func.terminator(header).unwrap();
+  func.inst_mut(term).kind = Inst::Br(blocks[&body(func, lp)]);
+  let next = phis
+    .iter()
+    .map(|&phi| map(incoming(func, phi, lp.latch)))
+    .collect();
+  func
+    .layout
+    .splice(pos..pos, lp.blocks.iter().map(|block| blocks[block]));
+  Iteration {
+    header,
+    latch: blocks[&lp.latch],
+    next,
+  }
+}
+
+/// Replace `lp` with `trip` copies of its body, in a row before the header,
+/// which is left to leave.
+fn unroll_fully(func: &mut Function, lp: &Loop, trip: usize) {
+  let phis = lp.phis(func);
+  let mut values: Vec<Value> = phis
+    .iter()
+    .map(|&phi| incoming(func, phi, lp.preheader))
+    .collect();
+  let mut pred = lp.preheader;
+  for _ in 0..trip {
+    let pos = func.layout.iter().position(|&b| b == lp.header).unwrap();
+    let copy = copy_iteration(func, lp, &values, pos);
+    retarget(func, pred, lp.header, copy.header);
+    pred = copy.latch;
+    values = copy.next;
+  }
+  for (&phi, val) in phis.iter().zip(values) {
+    func.inst_mut(phi).kind = Inst::Phi(vec![(pred, val)]);
+  }
+  let term = func.terminator(lp.header).unwrap();
+  let body = body(func, lp);
+  let exit = func
+    .successors(lp.header)
+    .into_iter()
+    .find(|&succ| succ != body)
+    .unwrap();
+  func.inst_mut(term).kind = Inst::Br(exit);
+}
+
+/// Put `factor - 1` more copies of the body of `lp` after its latch, none
+/// of which test whether to leave.
+fn unroll_by(func: &mut Function, lp: &Loop, factor: usize) {
+  let phis = lp.phis(func);
+  let mut values: Vec<Value> = phis
+    .iter()
+    .map(|&phi| incoming(func, phi, lp.latch))
+    .collect();
+  let mut copies = Vec::new();
+  for _ in 1..factor {
+    let pred = copies
+      .last()
+      .map_or(lp.latch, |copy: &Iteration| copy.latch);
+    let pos = func.layout.iter().position(|&b| b == pred).unwrap() + 1;
+    let copy = copy_iteration(func, lp, &values, pos);
+    values = copy.next.clone();
+    copies.push(copy);
+  }
+  // the latch is copied as it was, so it jumps on only now
+  let mut pred = lp.latch;
+  for copy in &copies {
+    retarget(func, pred, lp.header, copy.header);
+    pred = copy.latch;
+  }
+  for (&phi, val) in phis.iter().zip(values) {
+    let Inst::Phi(incoming) = &mut func.inst_mut(phi).kind else {
+      unreachable!()
+    };
+    for (from, from_val) in incoming.iter_mut() {
+      if *from == lp.latch {
+        *from = pred;
+        *from_val = val;
+      }
+    }
+  }
+}
+
+/// Where the header of `lp` goes when it stays in the loop.
+fn body(func: &Function, lp: &Loop) -> BlockId {
+  func
+    .successors(lp.header)
+    .into_iter()
+    .find(|&succ| lp.contains(succ))
+    .unwrap()
+}
+
+fn incoming(func: &Function, phi: InstId, pred: BlockId) -> Value {
+  let Inst::Phi(incoming) = &func.inst(phi).kind else {
+    unreachable!()
+  };
+  incoming.iter().find(|(from, _)| *from == pred).unwrap().1
+}
+
+fn retarget(func: &mut Function, block: BlockId, from: BlockId, to: BlockId) {
+  let term = func.terminator(block).unwrap();
+  for succ in func.inst_mut(term).kind.successors_mut() {
+    if *succ == from {
+      *succ = to;
+    }
+  }
+}
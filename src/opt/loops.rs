@@ -0,0 +1,227 @@
+//! Loops as the loop passes see them: natural loops with a single latch
+//! and a preheader, a block doing nothing but jumping to the header, for
+//! what must run once before the loop.
+use std::collections::HashSet;
+
+use super::fold;
+use crate::{
+  analysis::{Cfg, DomTree, LoopForest},
+  ir::{BinOp, BlockId, Function, Inst, InstId, Type, Value},
+};
+
+pub struct Loop {
+  pub header: BlockId,
+  pub latch: BlockId,
+  pub preheader: BlockId,
+  /// blocks of the loop, the header included, in layout order
+  pub blocks: Vec<BlockId>,
+  /// whether no loop is nested in this one
+  pub innermost: bool,
+}
+
+/// A basic induction variable: a header phi starting at `init` and stepped
+/// by a constant `update` on every iteration.
+pub struct Induction {
+  pub phi: InstId,
+  pub init: Value,
+  pub step: i32,
+  pub update: InstId,
+}
+
+/// The loops of `func` with a single latch, inner loops first, and
+/// whether preheaders had to be made for them.
+pub fn find_loops(func: &mut Function) -> (Vec<Loop>, bool) {
+  let found = {
+    let cfg = Cfg::new(&*func);
+    let dom = DomTree::new(&cfg);
+    let forest = LoopForest::new(&cfg, &dom);
+    forest
+      .loops
+      .iter()
+      .enumerate()
+      .map(|(i, lp)| {
+        let latches = lp
+          .latches
+          .iter()
+          .map(|&b| cfg.blocks[b])
+          .collect::<Vec<_>>();
+        let blocks = lp.blocks.iter().map(|&b| cfg.blocks[b]).collect::<Vec<_>>();
+        (
+          cfg.blocks[lp.header],
+          latches,
+          blocks,
+          lp.parent,
+          forest.is_innermost(i),
+        )
+      })
+      .collect::<Vec<_>>()
+  };
+  let mut blocks: Vec<Vec<BlockId>> = found.iter().map(|lp| lp.2.clone()).collect();
+  let mut preds = func.predecessors();
+  let mut changed = false;
+  let mut loops = Vec::new();
+  for (i, (header, latches, _, _, innermost)) in found.iter().enumerate() {
+    let [latch] = latches[..] else {
+      continue;
+    };
+    let (inside, outside): (Vec<_>, Vec<_>) = preds[header.0 as usize]
+      .iter()
+      .partition(|pred| blocks[i].contains(pred));
+    let preheader = match outside[..] {
+      [pred] if func.successors(pred) == [*header] => pred,
+      _ => {
+        let preheader = make_preheader(func, *header, &outside);
+        preds.push(outside);
+        preds[header.0 as usize] = inside.into_iter().chain([preheader]).collect();
+        // it is in every loop around this one
+        let mut parent = found[i].3;
+        while let Some(outer) = parent {
+          blocks[outer].push(preheader);
+          parent = found[outer].3;
+        }
+        changed = true;
+        preheader
+      }
+    };
+    loops.push(Loop {
+      header: *header,
+      latch,
+      preheader,
+      blocks: blocks[i].clone(),
+      innermost: *innermost,
+    });
+  }
+  // in layout order again, with the new preheaders
+  for lp in &mut loops {
+    lp.blocks = func
+      .layout
+      .iter()
+      .copied()
+      .filter(|block| lp.blocks.contains(block))
+      .collect();
+  }
+  (loops, changed)
+}
+
+/// Send the edges entering a loop from `outside` through a new block, taking over what
+/// the header phis took from them.
+fn make_preheader(func: &mut Function, header: BlockId, outside: &[BlockId]) -> BlockId {
+  let preheader = func.create_block("preheader");
+  let pos = func.layout.iter().position(|&b| b == header).unwrap();
+  func.layout.insert(pos, preheader);
+  for &pred in outside {
+    let term = func.terminator(pred).unwrap();
+    for succ in func.inst_mut(term).kind.successors_mut() {
+      if *succ == header {
+        *succ = preheader;
+      }
+    }
+  }
+  for id in func.block(header).insts.clone() {
+    let Inst::Phi(incoming) = &func.inst(id).kind else {
+      break;
+    };
+    let (entering, mut kept): (Vec<_>, Vec<_>) = incoming
+      .iter()
+      .copied()
+      .partition(|(pred, _)| outside.contains(pred));
+    let values = entering.iter().map(|&(_, val)| val).collect::<HashSet<_>>();
+    let val = match values.into_iter().collect::<Vec<_>>()[..] {
+      [val] => val,
+      _ => {
+        let ty = func.inst(id).ty.clone();
+        Value::Inst(func.push(preheader, Inst::Phi(entering), ty))
+      }
+    };
+    kept.push((preheader, val));
+    func.inst_mut(id).kind = Inst::Phi(kept);
+  }
+  func.push(preheader, Inst::Br(header), Type::Void);
+  preheader
+}
+
+impl Loop {
+  pub fn contains(&self, block: BlockId) -> bool {
+    self.blocks.contains(&block)
+  }
+
+  /// The instructions in the loop.
+  pub fn insts(&self, func: &Function) -> HashSet<InstId> {
+    self
+      .blocks
+      .iter()
+      .flat_map(|&block| func.block(block).insts.iter().copied())
+      .collect()
+  }
+
+  pub fn phis(&self, func: &Function) -> Vec<InstId> {
+    func
+      .block(self.header)
+      .insts
+      .iter()
+      .copied()
+      .take_while(|&id| func.inst(id).kind.is_phi())
+      .collect()
+  }
+
+  pub fn inductions(&self, func: &Function) -> Vec<Induction> {
+    let mut inductions = Vec::new();
+    for phi in self.phis(func) {
+      let Inst::Phi(incoming) = &func.inst(phi).kind else {
+        unreachable!()
+      };
+      let incoming_from = |block| {
+        incoming
+          .iter()
+          .find(|(pred, _)| *pred == block)
+          .map(|&(_, val)| val)
+      };
+      let (Some(init), Some(Value::Inst(update))) =
+        (incoming_from(self.preheader), incoming_from(self.latch))
+      else {
+        continue;
+      };
+      let this = Value::Inst(phi);
+      let step = match func.inst(update).kind {
+        Inst::Binary(BinOp::Add, l, Value::Int(step)) if l == this => step,
+        Inst::Binary(BinOp::Add, Value::Int(step), r) if r == this => step,
+        Inst::Binary(BinOp::Sub, l, Value::Int(step)) if l == this => step.wrapping_neg(),
+        _ => continue,
+      };
+      inductions.push(Induction {
+        phi,
+        init,
+        step,
+        update,
+      });
+    }
+    inductions
+  }
+
+  /// `kind` folded if it can be, or else placed at the end of the
+  /// preheader.
+  pub fn emit_before(&self, func: &mut Function, kind: Inst, ty: Type) -> Value {
+    if let Some(val) = fold(&kind) {
+      return val;
+    }
+    let pos = func.block(self.preheader).insts.len() - 1;
+    Value::Inst(func.insert(self.preheader, pos, kind, ty))
+  }
+
+  /// Place `kind` right after `inst`, an instruction in the loop.
+  pub fn insert_after(&self, func: &mut Function, inst: InstId, kind: Inst, ty: Type) -> InstId {
+    let block = self
+      .blocks
+      .iter()
+      .copied()
+      .find(|&block| func.block(block).insts.contains(&inst))
+      .unwrap();
+    let pos = func
+      .block(block)
+      .insts
+      .iter()
+      .position(|&id| id == inst)
+      .unwrap();
+    func.insert(block, pos + 1, kind, ty)
+  }
+}
@@ -0,0 +1,334 @@
+//! Optimizations of our SSA IR, written in Rust instead of borrowed from
+//! LLVM.
+//!
+//! Most passes rewrite one function at a time, the inliner and strength
+//! reduction a whole module, and all tell whether they changed anything. A
+//! [`PassManager`] runs a [`Pipeline`] of them over a module before
+//! [`crate::codegen::Generator::gen_ir`] sees it, verifying the IR after
+//! each pass and printing it after those asked for.
+mod call_graph;
+mod dce;
+mod div_rem;
+mod dse;
+mod gvn;
+mod inline;
+mod loop_reduce;
+mod loop_unroll;
+mod loops;
+mod sccp;
+mod simplify_cfg;
+mod tail_call_elim;
+
+use std::{fmt, io::Write};
+
+use itertools::Itertools;
+
+use crate::{
+  error::{Error, Result},
+  ir::{self, BinOp, BlockId, CastOp, Function, Inst, Module, Value},
+  util::OptLevel,
+};
+
+pub use inline::DEFAULT_LIMIT as DEFAULT_INLINE_LIMIT;
+
+/// One of our passes, named as `--ssa-passes` and `--print-after` name it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+  /// sparse conditional constant propagation
+  Sccp,
+  /// dead code elimination
+  Dce,
+  /// dead store elimination
+  Dse,
+  /// global value numbering
+  Gvn,
+  /// CFG simplification
+  SimplifyCfg,
+  /// inlining of calls to small functions
+  Inline,
+  /// tail recursion elimination
+  TailCallElim,
+  /// unrolling of loops running a known number of times
+  LoopUnroll,
+  /// strength reduction of induction variable arithmetic
+  LoopReduce,
+  /// division and remainder by constants with shifts and multiplications
+  DivRem,
+}
+
+impl Pass {
+  pub const ALL: [Pass; 10] = [
+    Pass::Sccp,
+    Pass::Dce,
+    Pass::Dse,
+    Pass::Gvn,
+    Pass::SimplifyCfg,
+    Pass::Inline,
+    Pass::TailCallElim,
+    Pass::LoopUnroll,
+    Pass::LoopReduce,
+    Pass::DivRem,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Pass::Sccp => "sccp",
+      Pass::Dce => "dce",
+      Pass::Dse => "dse",
+      Pass::Gvn => "gvn",
+      Pass::SimplifyCfg => "simplifycfg",
+      Pass::Inline => "inline",
+      Pass::TailCallElim => "tailcallelim",
+      Pass::LoopUnroll => "loop-unroll",
+      Pass::LoopReduce => "loop-reduce",
+      Pass::DivRem => "divrem",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Result<Self> {
+    Pass::ALL
+      .into_iter()
+      .find(|pass| pass.name() == name)
+      .ok_or_else(|| Error::UnknownPass {
+        name: name.to_string(),
+        known: Pass::ALL.iter().join(", "),
+      })
+  }
+}
+
+impl fmt::Display for Pass {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+/// Which passes run, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pipeline {
+  pub passes: Vec<Pass>,
+}
+
+impl Pipeline {
+  pub fn for_level(level: OptLevel) -> Self {
+    use Pass::*;
+    let passes = match level {
+      OptLevel::O0 => Vec::new(),
+      OptLevel::O1 => vec![SimplifyCfg, Sccp, Dce, SimplifyCfg],
+      // callees are cleaned up before the inliner weighs them, loads gvn
+      // forwards stored constants to feed another sccp, dse needs the
+      // addresses gvn merges and leaves dce the geps and allocas nothing
+      // stores to any more. Unrolled loops are folded and merged before
+      // what is left of them is strength reduced
+      OptLevel::O2 | OptLevel::O3 => vec![
+        SimplifyCfg,
+        Sccp,
+        Dce,
+        SimplifyCfg,
+        TailCallElim,
+        Inline,
+        SimplifyCfg,
+        Sccp,
+        Gvn,
+        Sccp,
+        SimplifyCfg,
+        LoopUnroll,
+        Sccp,
+        SimplifyCfg,
+        LoopReduce,
+        DivRem,
+        Dse,
+        Dce,
+        SimplifyCfg,
+      ],
+    };
+    Self { passes }
+  }
+
+  /// Parse a comma separated `--ssa-passes` list.
+  pub fn parse(spec: &str) -> Result<Self> {
+    let passes = spec
+      .split(',')
+      .map(str::trim)
+      .filter(|name| !name.is_empty())
+      .map(Pass::from_name)
+      .collect::<Result<Vec<_>>>()?;
+    Ok(Self { passes })
+  }
+}
+
+/// A decision a pass explains with `--remarks`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remark {
+  pub pass: Pass,
+  /// the function the decision was made in
+  pub function: String,
+  pub message: String,
+}
+
+impl fmt::Display for Remark {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "remark: {}: {} [{}]",
+      self.function, self.message, self.pass
+    )
+  }
+}
+
+/// Runs a [`Pipeline`] over every function of a module.
+#[derive(Debug, Clone)]
+pub struct PassManager {
+  pipeline: Pipeline,
+  print_after: Vec<Pass>,
+  inline_limit: usize,
+  remarks: bool,
+}
+
+impl Default for PassManager {
+  fn default() -> Self {
+    Self::new(Pipeline::default())
+  }
+}
+
+impl PassManager {
+  pub fn new(pipeline: Pipeline) -> Self {
+    Self {
+      pipeline,
+      print_after: Vec::new(),
+      inline_limit: DEFAULT_INLINE_LIMIT,
+      remarks: false,
+    }
+  }
+
+  /// Print the module after every run of these passes, as
+  /// `--print-after` does.
+  pub fn set_print_after(&mut self, passes: Vec<Pass>) {
+    self.print_after = passes;
+  }
+
+  /// The largest cost of a call the inliner inlines, as
+  /// `-finline-limit` sets it.
+  pub fn set_inline_limit(&mut self, limit: usize) {
+    self.inline_limit = limit;
+  }
+
+  /// Explain the decisions of the passes in `dump` too, as `--remarks`
+  /// does.
+  pub fn set_remarks(&mut self, remarks: bool) {
+    self.remarks = remarks;
+  }
+
+  /// Run the passes, writing the requested dumps to `dump`. Fails if a
+  /// pass leaves invalid IR behind.
+  pub fn run(&self, module: &mut Module, dump: &mut dyn Write) -> Result<bool> {
+    let mut changed = false;
+    for &pass in &self.pipeline.passes {
+      let mut remarks = Vec::new();
+      changed |= self.run_pass(pass, module, &mut remarks);
+      if self.remarks {
+        for remark in remarks {
+          writeln!(dump, "{remark}")?;
+        }
+      }
+      ir::verify(module).map_err(|e| match e {
+        Error::IrVerify { function, message } => Error::IrVerify {
+          function,
+          message: format!("{message} (after {pass})"),
+        },
+        e => e,
+      })?;
+      if self.print_after.contains(&pass) {
+        write!(dump, "; *** IR Dump After {pass} ***\n{module}")?;
+      }
+    }
+    Ok(changed)
+  }
+
+  fn run_pass(&self, pass: Pass, module: &mut Module, remarks: &mut Vec<Remark>) -> bool {
+    let run: fn(&mut Function) -> bool = match pass {
+      Pass::Inline => return inline::run(module, self.inline_limit, remarks),
+      Pass::LoopReduce => return loop_reduce::run(module),
+      Pass::Sccp => sccp::run,
+      Pass::Dce => dce::run,
+      Pass::Dse => dse::run,
+      Pass::Gvn => gvn::run,
+      Pass::SimplifyCfg => simplify_cfg::run,
+      Pass::TailCallElim => tail_call_elim::run,
+      Pass::LoopUnroll => loop_unroll::run,
+      Pass::DivRem => div_rem::run,
+    };
+    let mut changed = false;
+    for func in module
+      .functions
+      .iter_mut()
+      .filter(|func| !func.is_declaration())
+    {
+      changed |= run(func);
+    }
+    changed
+  }
+}
+
+/// Drop the incoming values of the phis of `block` from `pred`.
+fn remove_incoming(func: &mut Function, block: BlockId, pred: BlockId) {
+  for id in func.block(block).insts.clone() {
+    match &mut func.inst_mut(id).kind {
+      Inst::Phi(incoming) => incoming.retain(|(from, _)| *from != pred),
+      _ => break,
+    }
+  }
+}
+
+/// Make phis of `block` take from `to` what they took from `from`.
+fn rename_incoming(func: &mut Function, block: BlockId, from: BlockId, to: BlockId) {
+  for id in func.block(block).insts.clone() {
+    match &mut func.inst_mut(id).kind {
+      Inst::Phi(incoming) => {
+        for (pred, _) in incoming.iter_mut().filter(|(pred, _)| *pred == from) {
+          *pred = to;
+        }
+      }
+      _ => break,
+    }
+  }
+}
+
+/// Evaluate a pure instruction on constant operands as the interpreter
+/// does; `None` if some operand is not a constant, or for a division by
+/// zero.
+fn fold(inst: &Inst) -> Option<Value> {
+  Some(match *inst {
+    Inst::Binary(op, Value::Int(l), Value::Int(r)) => Value::Int(match op {
+      BinOp::Add => l.wrapping_add(r),
+      BinOp::Sub => l.wrapping_sub(r),
+      BinOp::Mul => l.wrapping_mul(r),
+      BinOp::SDiv if r != 0 => l.wrapping_div(r),
+      BinOp::SRem if r != 0 => l.wrapping_rem(r),
+      BinOp::Shl => l.wrapping_shl(r as u32),
+      BinOp::AShr => l.wrapping_shr(r as u32),
+      BinOp::LShr => (l as u32).wrapping_shr(r as u32) as i32,
+      BinOp::SMulH => ((l as i64 * r as i64) >> 32) as i32,
+      _ => return None,
+    }),
+    Inst::Binary(op, Value::Float(l), Value::Float(r)) => {
+      let (l, r) = (f32::from_bits(l), f32::from_bits(r));
+      Value::float(match op {
+        BinOp::FAdd => l + r,
+        BinOp::FSub => l - r,
+        BinOp::FMul => l * r,
+        BinOp::FDiv => l / r,
+        _ => return None,
+      })
+    }
+    Inst::FNeg(Value::Float(v)) => Value::float(-f32::from_bits(v)),
+    Inst::Cmp(op, Value::Int(l), Value::Int(r)) => Value::Bool(op.eval(l, r)),
+    Inst::Cmp(op, Value::Float(l), Value::Float(r)) => {
+      Value::Bool(op.eval(f32::from_bits(l), f32::from_bits(r)))
+    }
+    Inst::Cmp(op, Value::Bool(l), Value::Bool(r)) => Value::Bool(op.eval(l, r)),
+    Inst::Cast(CastOp::ZExt, Value::Bool(v)) => Value::Int(v as i32),
+    Inst::Cast(CastOp::SiToFp, Value::Int(v)) => Value::float(v as f32),
+    Inst::Cast(CastOp::FpToSi, Value::Float(v)) => Value::Int(f32::from_bits(v) as i32),
+    _ => return None,
+  })
+}
@@ -0,0 +1,218 @@
+//! Sparse conditional constant propagation, after Wegman and Zadeck.
+//!
+//! Values are assumed constant until shown otherwise and blocks are only
+//! looked at once an edge into them may be taken, so constants carried
+//! around loops and branches that always go the same way are both found.
+use std::collections::{HashMap, HashSet};
+
+use super::fold;
+use crate::ir::{BlockId, Function, Inst, InstId, Type, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+  /// no definition seen yet
+  Unknown,
+  Const(Value),
+  /// may be more than one value
+  Varying,
+}
+
+impl Lattice {
+  fn meet(self, other: Lattice) -> Lattice {
+    match (self, other) {
+      (Lattice::Unknown, other) | (other, Lattice::Unknown) => other,
+      (Lattice::Const(a), Lattice::Const(b)) if a == b => self,
+      _ => Lattice::Varying,
+    }
+  }
+}
+
+struct Solver<'f> {
+  func: &'f Function,
+  blocks: HashMap<InstId, BlockId>,
+  users: HashMap<InstId, Vec<InstId>>,
+  values: HashMap<InstId, Lattice>,
+  /// edges that may be taken
+  edges: HashSet<(BlockId, BlockId)>,
+  reached: HashSet<BlockId>,
+  /// targets of edges newly found takeable
+  edge_work: Vec<BlockId>,
+  inst_work: Vec<InstId>,
+}
+
+impl<'f> Solver<'f> {
+  fn new(func: &'f Function) -> Self {
+    let mut users: HashMap<_, Vec<_>> = HashMap::new();
+    for id in func.inst_ids() {
+      for operand in func.inst(id).kind.operands() {
+        if let Value::Inst(def) = operand {
+          users.entry(def).or_default().push(id);
+        }
+      }
+    }
+    Self {
+      func,
+      blocks: func.inst_blocks(),
+      users,
+      values: HashMap::new(),
+      edges: HashSet::new(),
+      reached: HashSet::new(),
+      edge_work: Vec::new(),
+      inst_work: Vec::new(),
+    }
+  }
+
+  fn value(&self, val: Value) -> Lattice {
+    match val {
+      Value::Inst(id) => self.values.get(&id).copied().unwrap_or(Lattice::Unknown),
+      Value::Param(_) | Value::Global(_) => Lattice::Varying,
+      _ => Lattice::Const(val),
+    }
+  }
+
+  fn solve(&mut self) {
+    let entry = self.func.entry();
+    self.reach(entry);
+    loop {
+      if let Some(to) = self.edge_work.pop() {
+        if self.reached.contains(&to) {
+          // only the phis can see the new edge
+          for &id in &self.func.block(to).insts {
+            if !self.func.inst(id).kind.is_phi() {
+              break;
+            }
+            self.visit(id);
+          }
+        } else {
+          self.reach(to);
+        }
+      } else if let Some(id) = self.inst_work.pop() {
+        if self.reached.contains(&self.blocks[&id]) {
+          self.visit(id);
+        }
+      } else {
+        break;
+      }
+    }
+  }
+
+  fn reach(&mut self, block: BlockId) {
+    self.reached.insert(block);
+    for &id in &self.func.block(block).insts {
+      self.visit(id);
+    }
+  }
+
+  fn take_edge(&mut self, from: BlockId, to: BlockId) {
+    if self.edges.insert((from, to)) {
+      self.edge_work.push(to);
+    }
+  }
+
+  fn visit(&mut self, id: InstId) {
+    let block = self.blocks[&id];
+    let data = self.func.inst(id);
+    let new = match &data.kind {
+      Inst::Phi(incoming) => incoming
+        .iter()
+        .filter(|(pred, _)| self.edges.contains(&(*pred, block)))
+        .fold(Lattice::Unknown, |acc, (_, val)| acc.meet(self.value(*val))),
+      Inst::Br(target) => return self.take_edge(block, *target),
+      Inst::CondBr { cond, then, els } => {
+        match self.value(*cond) {
+          Lattice::Const(Value::Bool(true)) => self.take_edge(block, *then),
+          Lattice::Const(Value::Bool(false)) => self.take_edge(block, *els),
+          Lattice::Unknown => {}
+          _ => {
+            self.take_edge(block, *then);
+            self.take_edge(block, *els);
+          }
+        }
+        return;
+      }
+      kind @ (Inst::Binary(..) | Inst::FNeg(_) | Inst::Cmp(..) | Inst::Cast(..)) => {
+        let mut folded = kind.clone();
+        let mut unknown = false;
+        let mut varying = false;
+        for operand in folded.operands_mut() {
+          match self.value(*operand) {
+            Lattice::Const(val) => *operand = val,
+            Lattice::Unknown => unknown = true,
+            Lattice::Varying => varying = true,
+          }
+        }
+        if varying {
+          Lattice::Varying
+        } else if unknown {
+          Lattice::Unknown
+        } else {
+          fold(&folded).map_or(Lattice::Varying, Lattice::Const)
+        }
+      }
+      _ if data.ty == Type::Void => return,
+      // loads, calls, allocas and geps
+      _ => Lattice::Varying,
+    };
+    let old = self.value(Value::Inst(id));
+    let new = old.meet(new);
+    if new != old {
+      self.values.insert(id, new);
+      self
+        .inst_work
+        .extend(self.users.get(&id).into_iter().flatten());
+    }
+  }
+}
+
+pub fn run(func: &mut Function) -> bool {
+  let mut solver = Solver::new(func);
+  solver.solve();
+  let Solver {
+    values,
+    edges,
+    reached,
+    ..
+  } = solver;
+  let mut changed = false;
+  let before = func.layout.len();
+  func.layout.retain(|block| reached.contains(block));
+  changed |= func.layout.len() != before;
+  let constants: HashMap<_, _> = values
+    .into_iter()
+    .filter_map(|(id, val)| match val {
+      Lattice::Const(val) => Some((Value::Inst(id), val)),
+      _ => None,
+    })
+    .collect();
+  for block in func.layout.clone() {
+    let before = func.block(block).insts.len();
+    func
+      .block_mut(block)
+      .insts
+      .retain(|&id| !constants.contains_key(&Value::Inst(id)));
+    changed |= func.block(block).insts.len() != before;
+    for id in func.block(block).insts.clone() {
+      let kind = &mut func.inst_mut(id).kind;
+      for operand in kind.operands_mut() {
+        if let Some(&val) = constants.get(operand) {
+          *operand = val;
+        }
+      }
+      match kind {
+        Inst::Phi(incoming) => incoming.retain(|(pred, _)| edges.contains(&(*pred, block))),
+        Inst::CondBr { then, els, .. } => {
+          let taken = [*then, *els]
+            .into_iter()
+            .filter(|&target| edges.contains(&(block, target)))
+            .collect::<Vec<_>>();
+          if let [target] = taken[..] {
+            *kind = Inst::Br(target);
+            changed = true;
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+  changed
+}
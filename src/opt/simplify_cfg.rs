@@ -0,0 +1,149 @@
+//! CFG simplification: fold branches that always go the same way, drop
+//! blocks the entry can not reach and phis left with one value, merge a
+//! block into its only predecessor, and skip blocks that only jump.
+use super::{remove_incoming, rename_incoming};
+use crate::ir::{Function, Inst, Value};
+
+pub fn run(func: &mut Function) -> bool {
+  let mut changed = false;
+  loop {
+    let mut round = fold_branches(func);
+    round |= func.remove_unreachable_blocks();
+    round |= func.remove_trivial_phis();
+    round |= merge_blocks(func);
+    round |= skip_empty_blocks(func);
+    if !round {
+      return changed;
+    }
+    changed = true;
+  }
+}
+
+/// Turn conditional branches on constants or to a single target into
+/// plain ones.
+fn fold_branches(func: &mut Function) -> bool {
+  let mut changed = false;
+  for block in func.layout.clone() {
+    let Some(term) = func.terminator(block) else {
+      continue;
+    };
+    let Inst::CondBr { cond, then, els } = func.inst(term).kind else {
+      continue;
+    };
+    let (target, dropped) = match cond {
+      _ if then == els => (then, None),
+      Value::Bool(true) => (then, Some(els)),
+      Value::Bool(false) => (els, Some(then)),
+      _ => continue,
+    };
+    func.inst_mut(term).kind = Inst::Br(target);
+    if let Some(dropped) = dropped {
+      remove_incoming(func, dropped, block);
+    }
+    changed = true;
+  }
+  changed
+}
+
+/// Append a block to its only predecessor when it is the only successor
+/// of that predecessor.
+fn merge_blocks(func: &mut Function) -> bool {
+  let mut preds = func.predecessors();
+  let mut changed = false;
+  for block in func.layout.clone().into_iter().skip(1) {
+    let [pred] = preds[block.0 as usize][..] else {
+      continue;
+    };
+    if pred == block || func.successors(pred) != [block] {
+      continue;
+    }
+    // phis with a single predecessor are trivial
+    let mut insts = func.block(block).insts.clone();
+    let phis = insts
+      .iter()
+      .take_while(|&&id| func.inst(id).kind.is_phi())
+      .count();
+    for id in insts.drain(..phis).collect::<Vec<_>>() {
+      let Inst::Phi(incoming) = &func.inst(id).kind else {
+        unreachable!()
+      };
+      let val = incoming[0].1;
+      func.replace_all_uses(Value::Inst(id), val);
+    }
+    let term = func.terminator(pred).unwrap();
+    func.remove_inst(pred, term);
+    func.block_mut(pred).insts.extend(insts);
+    func.block_mut(block).insts.clear();
+    for succ in func.successors(pred) {
+      rename_incoming(func, succ, block, pred);
+      for p in &mut preds[succ.0 as usize] {
+        if *p == block {
+          *p = pred;
+        }
+      }
+    }
+    func.layout.retain(|&b| b != block);
+    changed = true;
+  }
+  changed
+}
+
+/// Send the predecessors of a block holding nothing but a jump straight
+/// to its target. Blocks whose predecessors already jump to the target are
+/// kept when the target has phis, which could not tell the paths apart.
+fn skip_empty_blocks(func: &mut Function) -> bool {
+  let mut preds = func.predecessors();
+  let mut changed = false;
+  for block in func.layout.clone().into_iter().skip(1) {
+    let [term] = func.block(block).insts[..] else {
+      continue;
+    };
+    let Inst::Br(target) = func.inst(term).kind else {
+      continue;
+    };
+    let block_preds = preds[block.0 as usize].clone();
+    let target_has_phis = func
+      .block(target)
+      .insts
+      .first()
+      .is_some_and(|&id| func.inst(id).kind.is_phi());
+    if target == block
+      || block_preds.is_empty()
+      || target_has_phis
+        && block_preds
+          .iter()
+          .any(|pred| preds[target.0 as usize].contains(pred))
+    {
+      continue;
+    }
+    for &pred in &block_preds {
+      let term = func.terminator(pred).unwrap();
+      for succ in func.inst_mut(term).kind.successors_mut() {
+        if *succ == block {
+          *succ = target;
+        }
+      }
+    }
+    for id in func.block(target).insts.clone() {
+      let Inst::Phi(incoming) = &mut func.inst_mut(id).kind else {
+        break;
+      };
+      let Some(pos) = incoming.iter().position(|(pred, _)| *pred == block) else {
+        continue;
+      };
+      let (_, val) = incoming.remove(pos);
+      incoming.extend(block_preds.iter().map(|&pred| (pred, val)));
+    }
+    let target_preds = &mut preds[target.0 as usize];
+    target_preds.retain(|&pred| pred != block);
+    for pred in block_preds {
+      if !target_preds.contains(&pred) {
+        target_preds.push(pred);
+      }
+    }
+    preds[block.0 as usize].clear();
+    func.layout.retain(|&b| b != block);
+    changed = true;
+  }
+  changed
+}
@@ -0,0 +1,227 @@
+//! Tail recursion elimination: a function returning what a call to itself
+//! returns jumps back to its start instead, with the arguments as the new
+//! parameters. Returning `x + f(...)` or `x * f(...)` works too, by
+//! carrying the sum or product so far along and applying it to every other
+//! return.
+use super::{remove_incoming, rename_incoming};
+use crate::ir::{BinOp, BlockId, Function, Inst, InstId, Type, Value};
+
+/// A block ending the function with a call to itself.
+struct TailCall {
+  block: BlockId,
+  call: InstId,
+  /// the addition or multiplication applied to the result
+  accumulate: Option<InstId>,
+}
+
+pub fn run(func: &mut Function) -> bool {
+  let Some((sites, op)) = tail_calls(func) else {
+    return false;
+  };
+  let entry = func.entry();
+  // allocas stay behind in the entry, which runs once
+  let header = func.create_block("tailrecurse");
+  func.layout.insert(1, header);
+  let insts = std::mem::take(&mut func.block_mut(entry).insts);
+  let (allocas, body): (Vec<_>, Vec<_>) = insts
+    .into_iter()
+    .partition(|&id| matches!(func.inst(id).kind, Inst::Alloca(_)));
+  func.block_mut(entry).insts = allocas;
+  func.block_mut(header).insts = body;
+  func.push(entry, Inst::Br(header), Type::Void);
+  for succ in func.successors(header) {
+    rename_incoming(func, succ, entry, header);
+  }
+  let mut params = Vec::new();
+  for (i, param) in func.params.clone().into_iter().enumerate() {
+    let phi = func.insert(header, i, Inst::Phi(Vec::new()), param.ty);
+    func.replace_all_uses(Value::Param(i), Value::Inst(phi));
+    params.push(phi);
+  }
+  let acc = op.map(|op| {
+    let identity = Value::Int(if op == BinOp::Mul { 1 } else { 0 });
+    let phi = func.insert(
+      header,
+      params.len(),
+      Inst::Phi(vec![(entry, identity)]),
+      Type::I32,
+    );
+    // every return but the tail calls applies what was carried along
+    for block in func.layout.clone() {
+      let term = func.terminator(block).unwrap();
+      if let Inst::Ret(Some(val)) = func.inst(term).kind {
+        let pos = func.block(block).insts.len() - 1;
+        let total = func.insert(
+          block,
+          pos,
+          Inst::Binary(op, Value::Inst(phi), val),
+          Type::I32,
+        );
+        func.inst_mut(term).kind = Inst::Ret(Some(Value::Inst(total)));
+      }
+    }
+    phi
+  });
+  for (i, &phi) in params.iter().enumerate() {
+    let Inst::Phi(incoming) = &mut func.inst_mut(phi).kind else {
+      unreachable!()
+    };
+    incoming.push((entry, Value::Param(i)));
+  }
+  for mut site in sites {
+    if site.block == entry {
+      site.block = header;
+    }
+    let Inst::Call { args, .. } = func.inst(site.call).kind.clone() else {
+      unreachable!()
+    };
+    let term = func.terminator(site.block).unwrap();
+    if let Inst::Br(target) = func.inst(term).kind {
+      remove_incoming(func, target, site.block);
+    }
+    let insts = &mut func.block_mut(site.block).insts;
+    let pos = insts.iter().position(|&id| id == site.call).unwrap();
+    insts.truncate(pos);
+    if let Some(acc) = acc {
+      let next = match site.accumulate {
+        Some(binary) => {
+          let Inst::Binary(op, l, r) = func.inst(binary).kind else {
+            unreachable!()
+          };
+          let operand = if r == Value::Inst(site.call) { l } else { r };
+          Value::Inst(func.push(
+            site.block,
+            Inst::Binary(op, Value::Inst(acc), operand),
+            Type::I32,
+          ))
+        }
+        None => Value::Inst(acc),
+      };
+      let Inst::Phi(incoming) = &mut func.inst_mut(acc).kind else {
+        unreachable!()
+      };
+      incoming.push((site.block, next));
+    }
+    for (&phi, arg) in params.iter().zip(args) {
+      let Inst::Phi(incoming) = &mut func.inst_mut(phi).kind else {
+        unreachable!()
+      };
+      incoming.push((site.block, arg));
+    }
+    func.push(site.block, Inst::Br(header), Type::Void);
+  }
+  func.remove_unreachable_blocks();
+  func.remove_trivial_phis();
+  true
+}
+
+/// The tail calls of `func`, and the operation they accumulate with if
+/// any; `None` if there are none or some can not become jumps.
+fn tail_calls(func: &Function) -> Option<(Vec<TailCall>, Option<BinOp>)> {
+  let mut uses = vec![0; func.insts.len()];
+  for id in func.inst_ids() {
+    for operand in func.inst(id).kind.operands() {
+      if let Value::Inst(def) = operand {
+        uses[def.0 as usize] += 1;
+      }
+    }
+  }
+  let mut sites = Vec::new();
+  let mut ops = Vec::new();
+  for &block in &func.layout {
+    let Some(returned) = returned_value(func, block) else {
+      continue;
+    };
+    let insts = &func.block(block).insts;
+    let mut tail = insts.iter().rev().skip(1);
+    let (call, accumulate) = match returned.and_then(Value::as_inst) {
+      Some(last) if tail.clone().next() == Some(&last) && uses[last.0 as usize] == 1 => {
+        match func.inst(last).kind {
+          Inst::Binary(op @ (BinOp::Add | BinOp::Mul), l, r) => {
+            tail.next();
+            let Some(&call) = tail.next() else {
+              continue;
+            };
+            if l == r || ![l, r].contains(&Value::Inst(call)) || uses[call.0 as usize] != 1 {
+              continue;
+            }
+            ops.push(op);
+            (call, Some(last))
+          }
+          _ => (last, None),
+        }
+      }
+      Some(_) => continue,
+      None => match tail.next() {
+        Some(&call) if returned.is_none() => (call, None),
+        _ => continue,
+      },
+    };
+    if !matches!(&func.inst(call).kind, Inst::Call { callee, .. } if *callee == func.name) {
+      continue;
+    }
+    sites.push(TailCall {
+      block,
+      call,
+      accumulate,
+    });
+  }
+  if sites.is_empty() || !ops.iter().all(|&op| op == ops[0]) {
+    return None;
+  }
+  // a callee reusing the frame must not be handed its allocas
+  let has_allocas = func
+    .inst_ids()
+    .any(|id| matches!(func.inst(id).kind, Inst::Alloca(_)));
+  if has_allocas
+    && sites.iter().any(|site| {
+      let Inst::Call { args, .. } = &func.inst(site.call).kind else {
+        unreachable!()
+      };
+      args.iter().any(|&arg| !is_outside_frame(func, arg))
+    })
+  {
+    return None;
+  }
+  Some((sites, ops.first().copied()))
+}
+
+/// What `block` returns, when it returns or jumps to a block doing nothing
+/// but return: `Some(None)` for `ret void`.
+fn returned_value(func: &Function, block: BlockId) -> Option<Option<Value>> {
+  let term = func.terminator(block)?;
+  let target = match func.inst(term).kind {
+    Inst::Ret(val) => return Some(val),
+    Inst::Br(target) => target,
+    _ => return None,
+  };
+  match func.block(target).insts[..] {
+    [ret] => match func.inst(ret).kind {
+      Inst::Ret(val) => Some(val),
+      _ => None,
+    },
+    [phi, ret] => match (&func.inst(phi).kind, &func.inst(ret).kind) {
+      (Inst::Phi(incoming), Inst::Ret(Some(val))) if *val == Value::Inst(phi) => incoming
+        .iter()
+        .find(|(pred, _)| *pred == block)
+        .map(|&(_, val)| Some(val)),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+/// Whether `val` is no pointer into an alloca of `func`.
+fn is_outside_frame(func: &Function, mut val: Value) -> bool {
+  loop {
+    match val {
+      Value::Inst(id) => match &func.inst(id).kind {
+        Inst::Gep { base, .. } => val = *base,
+        Inst::Alloca(_) => return false,
+        // only geps make pointers out of pointers
+        _ => return true,
+      },
+      _ => return true,
+    }
+  }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Write};
 
 use crate::error::{Error, Result};
 use colored::*;
@@ -38,6 +38,7 @@ pub fn to_source_span(range: Range) -> SourceSpan {
 
 #[allow(dead_code)]
 fn dump_node_internal(
+  out: &mut String,
   node: &Node,
   prefix: &str,
   content: &str,
@@ -49,7 +50,8 @@ fn dump_node_internal(
   let start = node.start_position();
   let end = node.end_position();
   let kind = node.kind();
-  println!(
+  writeln!(
+    out,
     "{}{}{}: `{}` {} - {}{}",
     prefix,
     if is_init {
@@ -71,7 +73,8 @@ fn dump_node_internal(
     } else {
       "".to_owned().normal()
     }
-  );
+  )
+  .unwrap();
   let node_to_idx: HashMap<_, _> = {
     let mut cursor = node.walk();
     node
@@ -89,6 +92,7 @@ fn dump_node_internal(
     match i {
       itertools::Position::First(n) | itertools::Position::Middle(n) => {
         dump_node_internal(
+          out,
           &n,
           &prefix,
           content,
@@ -99,6 +103,7 @@ fn dump_node_internal(
       }
       itertools::Position::Last(n) | itertools::Position::Only(n) => {
         dump_node_internal(
+          out,
           &n,
           &prefix,
           content,
@@ -113,5 +118,49 @@ fn dump_node_internal(
 
 #[allow(dead_code)]
 pub fn dump_node(node: &Node, content: &str) {
-  dump_node_internal(node, "", content, None, true, true);
+  let mut out = String::new();
+  dump_node_internal(&mut out, node, "", content, None, true, true);
+  print!("{out}");
+}
+
+/// Same tree as [`dump_node`], without colors, for writing to files.
+pub fn dump_node_to_string(node: &Node, content: &str) -> String {
+  let mut out = String::new();
+  colored::control::set_override(false);
+  dump_node_internal(&mut out, node, "", content, None, true, true);
+  colored::control::unset_override();
+  out
+}
+
+/// One line per leaf token: `line:column kind text`.
+pub fn dump_tokens_to_string(node: &Node, content: &str) -> String {
+  let mut out = String::new();
+  let mut cursor = node.walk();
+  let mut visited_children = false;
+  loop {
+    if !visited_children && cursor.goto_first_child() {
+      continue;
+    }
+    let node = cursor.node();
+    if node.child_count() == 0 {
+      let start = node.start_position();
+      writeln!(
+        out,
+        "{}:{} {} {:?}",
+        start.row + 1,
+        start.column + 1,
+        node.kind(),
+        get_text(node, content)
+      )
+      .unwrap();
+    }
+    if cursor.goto_next_sibling() {
+      visited_children = false;
+    } else if cursor.goto_parent() {
+      visited_children = true;
+    } else {
+      break;
+    }
+  }
+  out
 }
@@ -0,0 +1,383 @@
+//! Delta debugging on the syntax tree: every pass proposes candidate edits,
+//! which are tried in shrinking chunks and kept whenever the program stays
+//! interesting.
+use std::{cmp::Reverse, ops::Range};
+
+use itertools::Itertools;
+use tree_sitter::{Node, Tree};
+
+use crate::parser::{get_text, parse};
+
+/// Replace `range` of the source with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+  pub range: Range<usize>,
+  pub text: String,
+}
+
+impl Edit {
+  fn delete(range: Range<usize>) -> Self {
+    Self {
+      range,
+      text: String::new(),
+    }
+  }
+  fn replace(node: Node, text: impl Into<String>) -> Self {
+    Self {
+      range: node.byte_range(),
+      text: text.into(),
+    }
+  }
+}
+
+/// One reduction step; its edits are applied together.
+pub type Candidate = Vec<Edit>;
+
+/// Finds the candidates of one kind of reduction.
+pub type Pass = fn(&Tree, &str) -> Vec<Candidate>;
+
+/// Coarse passes first, so the fine ones have less to chew on.
+pub const PASSES: [Pass; 5] = [
+  delete_items,
+  simplify_statements,
+  inline_constants,
+  simplify_expressions,
+  delete_init_items,
+];
+
+const STATEMENTS: &[&str] = &[
+  "declaration",
+  "assignment",
+  "expression_statement",
+  "compound_statement",
+  "if_statement",
+  "while_statement",
+  "break_statement",
+  "continue_statement",
+  "return_statement",
+  "comment",
+];
+
+const EXPRESSIONS: &[&str] = &[
+  "binary_expression",
+  "unary_expression",
+  "call_expression",
+  "subscript_expression",
+  "parenthesized_expression",
+  "identifier",
+  "int_literal",
+  "float_literal",
+];
+
+/// The result of a reduction.
+#[derive(Debug, Clone)]
+pub struct Reduced {
+  pub source: String,
+  /// how many candidates were tested
+  pub tests: usize,
+}
+
+/// Shrink `source` while `interesting` holds for it.
+///
+/// Only candidates that parse and make the program strictly shorter are
+/// tested, so this always terminates.
+pub fn reduce(source: &str, mut interesting: impl FnMut(&str) -> bool) -> Reduced {
+  let mut reduced = Reduced {
+    source: source.to_string(),
+    tests: 0,
+  };
+  loop {
+    let before = reduced.source.len();
+    for pass in PASSES {
+      run_pass(&mut reduced, pass, &mut interesting);
+    }
+    if reduced.source.len() == before {
+      return reduced;
+    }
+  }
+}
+
+fn run_pass(reduced: &mut Reduced, pass: Pass, interesting: &mut impl FnMut(&str) -> bool) {
+  let mut chunk = usize::MAX;
+  loop {
+    let tree = parse(&reduced.source).expect("the current program always parses");
+    let candidates = pass(&tree, &reduced.source);
+    if candidates.is_empty() {
+      return;
+    }
+    chunk = chunk.min(candidates.len());
+    let mut progressed = false;
+    for group in candidates.chunks(chunk) {
+      let next = apply(&reduced.source, &group.concat());
+      if next.len() >= reduced.source.len() || !parses_cleanly(&next) {
+        continue;
+      }
+      reduced.tests += 1;
+      if interesting(&next) {
+        reduced.source = next;
+        progressed = true;
+        // positions moved, look for candidates again
+        break;
+      }
+    }
+    if !progressed {
+      if chunk == 1 {
+        return;
+      }
+      chunk /= 2;
+    }
+  }
+}
+
+fn parses_cleanly(source: &str) -> bool {
+  matches!(parse(source), Ok(tree) if !tree.root_node().has_error())
+}
+
+/// Apply edits, skipping those inside an edit already applied.
+pub fn apply(source: &str, edits: &[Edit]) -> String {
+  let edits = edits
+    .iter()
+    .sorted_by_key(|edit| (edit.range.start, Reverse(edit.range.end)))
+    .collect_vec();
+  let mut res = String::new();
+  let mut pos = 0;
+  for edit in edits {
+    if edit.range.start < pos {
+      continue;
+    }
+    res.push_str(&source[pos..edit.range.start]);
+    res.push_str(&edit.text);
+    pos = edit.range.end;
+  }
+  res.push_str(&source[pos..]);
+  res
+}
+
+/// Every node in preorder, with the field it is under.
+fn nodes(tree: &Tree) -> Vec<(Node<'_>, Option<&'static str>)> {
+  let mut res = Vec::new();
+  let mut cursor = tree.walk();
+  loop {
+    res.push((cursor.node(), cursor.field_name()));
+    if cursor.goto_first_child() {
+      continue;
+    }
+    loop {
+      if cursor.goto_next_sibling() {
+        break;
+      }
+      if !cursor.goto_parent() {
+        return res;
+      }
+    }
+  }
+}
+
+fn has_ancestor(node: Node, kinds: &[&str]) -> bool {
+  let mut node = node;
+  while let Some(parent) = node.parent() {
+    if kinds.contains(&parent.kind()) {
+      return true;
+    }
+    node = parent;
+  }
+  false
+}
+
+/// Extend `range` to its lines if nothing else is on them.
+fn whole_lines(source: &str, range: Range<usize>) -> Range<usize> {
+  let before = &source[..range.start];
+  let start = before.trim_end_matches(|c| c == ' ' || c == '\t').len();
+  let after = &source[range.end..];
+  let rest = after.trim_start_matches(|c| c == ' ' || c == '\t');
+  if (start == 0 || before[..start].ends_with('\n')) && rest.starts_with('\n') {
+    start..source.len() - rest.len() + 1
+  } else {
+    range
+  }
+}
+
+/// Named children, comments dropped.
+fn items(node: Node<'_>) -> Vec<Node<'_>> {
+  let mut cursor = node.walk();
+  node
+    .named_children(&mut cursor)
+    .filter(|child| child.kind() != "comment")
+    .collect_vec()
+}
+
+/// Remove statements, declarations and functions other than `main`.
+fn delete_items(tree: &Tree, source: &str) -> Vec<Candidate> {
+  nodes(tree)
+    .into_iter()
+    .filter_map(|(node, _)| {
+      let parent = node.parent()?;
+      match (parent.kind(), node.kind()) {
+        ("translation_unit", "function_definition") => {
+          let name = node.child_by_field_name("name")?;
+          (get_text(name, source) != "main")
+            .then(|| vec![Edit::delete(whole_lines(source, node.byte_range()))])
+        }
+        ("translation_unit" | "compound_statement", kind) if STATEMENTS.contains(&kind) => {
+          let mut range = node.byte_range();
+          // an assignment statement leaves its `;` behind otherwise
+          if let Some(next) = node.next_sibling() {
+            if kind == "assignment" && next.kind() == ";" {
+              range.end = next.end_byte();
+            }
+          }
+          Some(vec![Edit::delete(whole_lines(source, range))])
+        }
+        _ => None,
+      }
+    })
+    .collect_vec()
+}
+
+/// Replace branches and loops with their bodies, drop `else`.
+fn simplify_statements(tree: &Tree, source: &str) -> Vec<Candidate> {
+  let mut res = Vec::new();
+  for (node, _) in nodes(tree) {
+    match node.kind() {
+      "if_statement" => {
+        let consequence = node.child_by_field_name("consequence").unwrap();
+        res.push(vec![Edit::replace(node, get_text(consequence, source))]);
+        if let Some(alternative) = node.child_by_field_name("alternative") {
+          res.push(vec![Edit::replace(node, get_text(alternative, source))]);
+          res.push(vec![Edit::replace(
+            node,
+            &source[node.start_byte()..consequence.end_byte()],
+          )]);
+        }
+      }
+      "while_statement" => {
+        let body = node.child_by_field_name("body").unwrap();
+        res.push(vec![Edit::replace(node, get_text(body, source))]);
+      }
+      _ => {}
+    }
+  }
+  res
+}
+
+/// Replace every use of a scalar constant with its value, and drop it.
+fn inline_constants(tree: &Tree, source: &str) -> Vec<Candidate> {
+  let nodes = nodes(tree);
+  let mut res = Vec::new();
+  for (node, _) in &nodes {
+    if node.kind() != "declaration" || node.child_by_field_name("const").is_none() {
+      continue;
+    }
+    let declarators = items(*node)
+      .into_iter()
+      .filter(|child| child.kind() == "declarator")
+      .collect_vec();
+    let declarator = match declarators.as_slice() {
+      [declarator] if declarator.child_by_field_name("dimension").is_none() => *declarator,
+      _ => continue,
+    };
+    let init = match declarator.child_by_field_name("init").map(items) {
+      Some(init) if init.len() == 1 && EXPRESSIONS.contains(&init[0].kind()) => init[0],
+      _ => continue,
+    };
+    let value = match init.kind() {
+      "int_literal" | "float_literal" => get_text(init, source).to_string(),
+      _ => format!("({})", get_text(init, source)),
+    };
+    let name = get_text(declarator.child_by_field_name("name").unwrap(), source);
+    let mut candidate = nodes
+      .iter()
+      .filter(|(use_, field)| {
+        use_.kind() == "identifier"
+          && get_text(*use_, source) == name
+          && !matches!(field, Some("name" | "function"))
+          && !node.byte_range().contains(&use_.start_byte())
+      })
+      .map(|(use_, _)| Edit::replace(*use_, value.clone()))
+      .collect_vec();
+    if candidate.is_empty() {
+      continue;
+    }
+    candidate.push(Edit::delete(node.byte_range()));
+    res.push(candidate);
+  }
+  res
+}
+
+/// Replace expressions with `0`, `1`, or one of their operands.
+fn simplify_expressions(tree: &Tree, source: &str) -> Vec<Candidate> {
+  let mut res = Vec::new();
+  for (node, field) in nodes(tree) {
+    if !EXPRESSIONS.contains(&node.kind()) || matches!(field, Some("name" | "function" | "left")) {
+      continue;
+    }
+    let parent = match node.parent() {
+      Some(parent) => parent,
+      None => continue,
+    };
+    // array names and dimensions have to stay what they are
+    if parent.kind() == "subscript_expression" && field == Some("argument")
+      || has_ancestor(
+        node,
+        &[
+          "declarator_array_dimension",
+          "parameter_array_dimension",
+          "parameter",
+        ],
+      )
+    {
+      continue;
+    }
+    let text = get_text(node, source);
+    let mut replacements = Vec::new();
+    match node.kind() {
+      "binary_expression" => {
+        replacements.push(get_text(node.child_by_field_name("left").unwrap(), source));
+        replacements.push(get_text(node.child_by_field_name("right").unwrap(), source));
+      }
+      "unary_expression" => {
+        replacements.push(get_text(
+          node.child_by_field_name("argument").unwrap(),
+          source,
+        ));
+      }
+      "parenthesized_expression" => {
+        if let Some(inner) = items(node).first() {
+          replacements.push(get_text(*inner, source));
+        }
+      }
+      _ => {}
+    }
+    replacements.extend(["0", "1"]);
+    for replacement in replacements {
+      if replacement.len() < text.len() {
+        res.push(vec![Edit::replace(node, replacement)]);
+      }
+    }
+  }
+  res
+}
+
+/// Remove elements of initializer lists.
+fn delete_init_items(tree: &Tree, _source: &str) -> Vec<Candidate> {
+  let mut res = Vec::new();
+  for (node, _) in nodes(tree) {
+    if node.kind() != "init_list" {
+      continue;
+    }
+    let items = items(node);
+    for (i, item) in items.iter().enumerate() {
+      // take a neighbouring comma along
+      let range = if let Some(next) = items.get(i + 1) {
+        item.start_byte()..next.start_byte()
+      } else if i > 0 {
+        items[i - 1].end_byte()..item.end_byte()
+      } else {
+        res.push(vec![Edit::replace(node, "{}")]);
+        continue;
+      };
+      res.push(vec![Edit::delete(range)]);
+    }
+  }
+  res
+}
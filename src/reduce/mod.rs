@@ -0,0 +1,66 @@
+//! Shrinking a failing program while it keeps failing the same way.
+mod delta;
+
+use std::path::Path;
+
+use crate::{
+  error::{Error, Result},
+  fuzz::{check_program, Finding, Oracle},
+  util::{get_temp_path, OptLevel},
+};
+
+pub use self::delta::{apply, reduce, Candidate, Edit, Pass, Reduced, PASSES};
+
+/// A reduced program and the failure it still shows.
+#[derive(Debug, Clone)]
+pub struct Reduction {
+  pub source: String,
+  pub finding: Finding,
+  /// how many candidates were compiled and run
+  pub tests: usize,
+}
+
+/// Whether `candidate` is the failure of `original`, not some other one.
+///
+/// Panics and codegen errors must have the same message, verifier failures
+/// and mismatches only the same kind, since their details move as the program
+/// shrinks.
+pub fn same_failure(original: &Finding, candidate: &Finding) -> bool {
+  match (original, candidate) {
+    (Finding::Panic(a), Finding::Panic(b)) => a == b,
+    (Finding::CompileError(a), Finding::CompileError(b)) => a == b,
+    (Finding::Verifier(_), Finding::Verifier(_)) => true,
+    (Finding::Mismatch(_), Finding::Mismatch(_)) => true,
+    _ => false,
+  }
+}
+
+/// Reduce the program at `path` as long as `check_program` reports the same
+/// failure for it.
+///
+/// Codegen recurses on the syntax tree, so call this on a big stack.
+pub fn reduce_program(path: &Path, oracle: Oracle, opt_level: OptLevel) -> Result<Reduction> {
+  let source = std::fs::read_to_string(path)?;
+  let finding = match check_program(path, oracle, opt_level)? {
+    Some(Finding::InvalidProgram | Finding::OracleError(_)) | None => {
+      return Err(Error::NothingToReduce)
+    }
+    Some(finding) => finding,
+  };
+  let candidate_path = get_temp_path(path, "sy");
+  let reduced = reduce(&source, |candidate| {
+    if std::fs::write(&candidate_path, candidate).is_err() {
+      return false;
+    }
+    matches!(
+      check_program(&candidate_path, oracle, opt_level),
+      Ok(Some(found)) if same_failure(&finding, &found)
+    )
+  });
+  std::fs::remove_file(&candidate_path).ok();
+  Ok(Reduction {
+    source: reduced.source,
+    finding,
+    tests: reduced.tests,
+  })
+}
@@ -0,0 +1,291 @@
+//! Rust implementation of `sylib.c`, shared by the execution engines that do
+//! not link the C runtime.
+use std::time::{Duration, Instant};
+
+/// What a finished program produced, in the shape the test harness compares.
+#[derive(Debug, Clone)]
+pub struct ProgramOutput {
+  pub stdout: Vec<u8>,
+  /// the value of `main`, truncated like a process exit status
+  pub exit_code: i32,
+  /// what the C runtime would print to stderr at exit
+  pub timing: String,
+}
+
+/// Input, output and timers of one program run.
+#[derive(Debug, Default)]
+pub struct Runtime {
+  input: Vec<u8>,
+  pos: usize,
+  output: Vec<u8>,
+  // (start line, stop line, elapsed) of every starttime/stoptime pair
+  timers: Vec<(i32, i32, Duration)>,
+  timer_start: Option<(i32, Instant)>,
+}
+
+impl Runtime {
+  pub fn new(input: impl Into<Vec<u8>>) -> Self {
+    Self {
+      input: input.into(),
+      ..Default::default()
+    }
+  }
+
+  fn peek(&self) -> Option<u8> {
+    self.input.get(self.pos).copied()
+  }
+
+  fn skip_whitespace(&mut self) {
+    while matches!(self.peek(), Some(c) if c.is_ascii_whitespace()) {
+      self.pos += 1;
+    }
+  }
+
+  /// `scanf("%d")`, 0 on EOF or garbage
+  pub fn getint(&mut self) -> i32 {
+    self.skip_whitespace();
+    let start = self.pos;
+    if matches!(self.peek(), Some(b'+' | b'-')) {
+      self.pos += 1;
+    }
+    while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+      self.pos += 1;
+    }
+    let text = std::str::from_utf8(&self.input[start..self.pos]).unwrap();
+    // scanf wraps on overflow the same way
+    text.parse::<i64>().map_or(0, |v| v as i32)
+  }
+
+  /// `scanf("%c")`, -1 on EOF
+  pub fn getch(&mut self) -> i32 {
+    match self.peek() {
+      Some(c) => {
+        self.pos += 1;
+        c as i32
+      }
+      None => -1,
+    }
+  }
+
+  /// `scanf("%a")`, accepting both hex and decimal floats
+  pub fn getfloat(&mut self) -> f32 {
+    self.skip_whitespace();
+    let start = self.pos;
+    while matches!(
+      self.peek(),
+      Some(c) if c.is_ascii_hexdigit() || matches!(c, b'x' | b'X' | b'p' | b'P' | b'.' | b'+' | b'-')
+    ) {
+      // a sign is only valid at the start or after an exponent mark
+      if matches!(self.peek(), Some(b'+' | b'-'))
+        && self.pos != start
+        && !matches!(self.input[self.pos - 1], b'p' | b'P' | b'e' | b'E')
+      {
+        break;
+      }
+      self.pos += 1;
+    }
+    let text = std::str::from_utf8(&self.input[start..self.pos]).unwrap();
+    parse_c_float(text).unwrap_or(0.0) as f32
+  }
+
+  pub fn getarray(&mut self, a: &mut [i32]) -> i32 {
+    let n = self.getint();
+    for v in a.iter_mut().take(n as usize) {
+      *v = self.getint();
+    }
+    n
+  }
+
+  pub fn getfarray(&mut self, a: &mut [f32]) -> i32 {
+    let n = self.getint();
+    for v in a.iter_mut().take(n as usize) {
+      *v = self.getfloat();
+    }
+    n
+  }
+
+  pub fn putint(&mut self, a: i32) {
+    self.output.extend(a.to_string().as_bytes());
+  }
+
+  pub fn putch(&mut self, a: i32) {
+    self.output.push(a as u8);
+  }
+
+  pub fn putfloat(&mut self, a: f32) {
+    self.output.extend(format_hex_float(a as f64).as_bytes());
+  }
+
+  pub fn putarray(&mut self, n: i32, a: &[i32]) {
+    self.output.extend(format!("{n}:").as_bytes());
+    // the jit calls this through extern "C", where a bad count must not panic
+    for v in a.iter().take(n.max(0) as usize) {
+      self.output.extend(format!(" {v}").as_bytes());
+    }
+    self.output.push(b'\n');
+  }
+
+  pub fn putfarray(&mut self, n: i32, a: &[f32]) {
+    self.output.extend(format!("{n}:").as_bytes());
+    for &v in a.iter().take(n.max(0) as usize) {
+      self
+        .output
+        .extend(format!(" {}", format_hex_float(v as f64)).as_bytes());
+    }
+    self.output.push(b'\n');
+  }
+
+  pub fn starttime(&mut self, lineno: i32) {
+    self.timer_start = Some((lineno, Instant::now()));
+  }
+
+  pub fn stoptime(&mut self, lineno: i32) {
+    if let Some((start_line, start)) = self.timer_start.take() {
+      self.timers.push((start_line, lineno, start.elapsed()));
+    }
+  }
+
+  pub fn output(&self) -> &[u8] {
+    &self.output
+  }
+
+  /// Wrap up the run after `main` returned `ret`.
+  pub fn finish(self, ret: i32) -> ProgramOutput {
+    let timing = self.timing_report();
+    ProgramOutput {
+      stdout: self.output,
+      exit_code: ret & 0xff,
+      timing,
+    }
+  }
+
+  /// What `after_main` in `sylib.c` prints to stderr.
+  pub fn timing_report(&self) -> String {
+    let mut res = String::new();
+    let mut total = Duration::ZERO;
+    for (l1, l2, elapsed) in &self.timers {
+      res.push_str(&format!(
+        "Timer@{l1:04}-{l2:04}: {}\n",
+        format_duration(*elapsed)
+      ));
+      total += *elapsed;
+    }
+    res.push_str(&format!("TOTAL: {}\n", format_duration(total)));
+    res
+  }
+}
+
+/// One `Timer@` line of a timing report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerRecord {
+  pub start_line: i32,
+  pub stop_line: i32,
+  pub elapsed: Duration,
+}
+
+/// Parse the `Timer@` lines of what `after_main` prints, skipping the rest
+/// of stderr.
+pub fn parse_timing_report(report: &str) -> Vec<TimerRecord> {
+  report
+    .lines()
+    .filter_map(|line| {
+      let (lines, elapsed) = line.trim().strip_prefix("Timer@")?.split_once(": ")?;
+      let (start_line, stop_line) = lines.split_once('-')?;
+      Some(TimerRecord {
+        start_line: start_line.parse().ok()?,
+        stop_line: stop_line.parse().ok()?,
+        elapsed: parse_duration(elapsed)?,
+      })
+    })
+    .collect()
+}
+
+/// Inverse of [`format_duration`].
+fn parse_duration(text: &str) -> Option<Duration> {
+  let mut parts = text.strip_suffix("us")?.split('-');
+  let mut field = |unit: char| parts.next()?.strip_suffix(unit)?.parse::<u64>().ok();
+  let (h, m, s) = (field('H')?, field('M')?, field('S')?);
+  let us = parts.next()?.parse::<u64>().ok()?;
+  Some(Duration::from_secs(h * 3600 + m * 60 + s) + Duration::from_micros(us))
+}
+
+fn format_duration(duration: Duration) -> String {
+  let secs = duration.as_secs();
+  format!(
+    "{}H-{}M-{}S-{}us",
+    secs / 3600,
+    secs / 60 % 60,
+    secs % 60,
+    duration.subsec_micros()
+  )
+}
+
+/// `printf("%a")` of a double, as glibc prints it.
+pub fn format_hex_float(v: f64) -> String {
+  if v.is_nan() {
+    return if v.is_sign_negative() { "-nan" } else { "nan" }.to_string();
+  }
+  let sign = if v.is_sign_negative() { "-" } else { "" };
+  if v.is_infinite() {
+    return format!("{sign}inf");
+  }
+  if v == 0.0 {
+    return format!("{sign}0x0p+0");
+  }
+  let bits = v.to_bits();
+  let biased_exp = ((bits >> 52) & 0x7ff) as i64;
+  let mantissa = bits & ((1 << 52) - 1);
+  let (lead, exp) = if biased_exp == 0 {
+    (0, -1022)
+  } else {
+    (1, biased_exp - 1023)
+  };
+  let digits = format!("{mantissa:013x}");
+  let digits = digits.trim_end_matches('0');
+  if digits.is_empty() {
+    format!("{sign}0x{lead}p{exp:+}")
+  } else {
+    format!("{sign}0x{lead}.{digits}p{exp:+}")
+  }
+}
+
+/// Parse a C float literal or `strtod` input: decimal or hexadecimal.
+pub fn parse_c_float(text: &str) -> Option<f64> {
+  let (negative, text) = match text.strip_prefix('-') {
+    Some(rest) => (true, rest),
+    None => (false, text.strip_prefix('+').unwrap_or(text)),
+  };
+  let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+    Some(hex) => parse_hex_float(hex)?,
+    None => text
+      .trim_end_matches(|c| c == 'f' || c == 'F')
+      .parse::<f64>()
+      .ok()?,
+  };
+  Some(if negative { -value } else { value })
+}
+
+/// `hex` is the part after `0x`, like `1.8p+3`
+fn parse_hex_float(hex: &str) -> Option<f64> {
+  let (mantissa, exp) = match hex.find(|c| c == 'p' || c == 'P') {
+    Some(i) => (&hex[..i], hex[i + 1..].parse::<i32>().ok()?),
+    None => (hex, 0),
+  };
+  let (int_part, frac_part) = match mantissa.find('.') {
+    Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+    None => (mantissa, ""),
+  };
+  if int_part.is_empty() && frac_part.is_empty() {
+    return None;
+  }
+  let mut value = 0f64;
+  for c in int_part.chars() {
+    value = value * 16.0 + c.to_digit(16)? as f64;
+  }
+  let mut scale = 1.0 / 16.0;
+  for c in frac_part.chars() {
+    value += c.to_digit(16)? as f64 * scale;
+    scale /= 16.0;
+  }
+  Some(value * 2f64.powi(exp))
+}
@@ -1,18 +1,58 @@
-use std::{path::Path, process::Command};
+use std::{
+  path::{Path, PathBuf},
+  process::Command,
+};
 
-pub fn get_bc_exe_path(base: &Path) -> (String, String) {
-  let bc_path = format!("{}.bc", base.file_stem().unwrap().to_str().unwrap());
-  let exe_path = format!("./{}.exe", base.file_stem().unwrap().to_str().unwrap());
-  (bc_path, exe_path)
+use clap::ArgEnum;
+
+use crate::error::{Error, Result};
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+  #[clap(name = "0")]
+  O0,
+  #[clap(name = "1")]
+  O1,
+  #[clap(name = "2")]
+  O2,
+  #[clap(name = "3")]
+  O3,
+}
+
+/// A scratch file path that does not collide with other compiler processes.
+pub fn get_temp_path(base: &Path, extension: &str) -> PathBuf {
+  std::env::temp_dir().join(format!(
+    "{}-{}.{}",
+    base.file_stem().unwrap().to_str().unwrap(),
+    std::process::id(),
+    extension
+  ))
+}
+
+/// Run an external tool, turning a missing binary or a failure status into
+/// [`Error::ToolFailed`] with its stderr.
+pub fn run_tool(tool: &str, cmd: &mut Command) -> Result<()> {
+  let res = cmd.output().map_err(|e| Error::ToolFailed {
+    tool: tool.to_string(),
+    reason: e.to_string(),
+  })?;
+  if !res.status.success() {
+    return Err(Error::ToolFailed {
+      tool: tool.to_string(),
+      reason: String::from_utf8_lossy(&res.stderr).trim().to_string(),
+    });
+  }
+  Ok(())
 }
 
-pub fn compile_with_clang(bc_path: &str, exe_path: &str) {
-  Command::new("clang")
-    .args([
-      bc_path,
-      "./compiler2022/runtime/sylib.c",
-      &format!("-o{}", exe_path),
-    ])
-    .spawn()
-    .unwrap();
+/// Link an object file with the sysy runtime using the system C compiler.
+pub fn link_executable(obj_path: &Path, exe_path: &Path) -> Result<()> {
+  run_tool(
+    "cc",
+    Command::new("cc")
+      .arg(obj_path)
+      .arg("./compiler2022/runtime/sylib.c")
+      .arg("-o")
+      .arg(exe_path),
+  )
 }
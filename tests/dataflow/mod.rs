@@ -0,0 +1,344 @@
+//! Checks of `sysy::analysis` against the definitions, computed the slow
+//! way, and against what SSA guarantees.
+use std::{
+  collections::{HashMap, HashSet},
+  fmt::Debug,
+};
+
+use sysy::{
+  analysis::{
+    solve, Analysis, AvailableExpressions, Cfg, Direction, DomTree, Expr, FlowGraph, Liveness,
+    LoopForest, ReachingDefinitions,
+  },
+  ir::{self, Value},
+};
+
+/// Dominators, frontiers, loops and every analysis reaching a fixpoint.
+pub fn check_cfg<F: FlowGraph>(cfg: &Cfg<F>) -> Result<(), String> {
+  check_dominators(cfg)?;
+  check_loops(cfg)?;
+  check_fixpoint(cfg, &Liveness::new(cfg), "liveness")?;
+  check_fixpoint(cfg, &ReachingDefinitions::new(cfg), "reaching definitions")?;
+  check_fixpoint(
+    cfg,
+    &AvailableExpressions::new(cfg),
+    "available expressions",
+  )
+}
+
+/// Compare the tree with dominator sets from the textbook iteration, and
+/// the frontiers with their definition.
+fn check_dominators<F: FlowGraph>(cfg: &Cfg<F>) -> Result<(), String> {
+  let dom = DomTree::new(cfg);
+  let order = cfg.reverse_postorder();
+  let all: HashSet<usize> = order.iter().copied().collect();
+  let mut sets = vec![all.clone(); cfg.len()];
+  let mut changed = true;
+  while changed {
+    changed = false;
+    for &block in &order {
+      let mut set = if block == 0 {
+        HashSet::new()
+      } else {
+        all.clone()
+      };
+      for &pred in cfg.preds[block].iter().filter(|&&pred| all.contains(&pred)) {
+        set.retain(|b| sets[pred].contains(b));
+      }
+      set.insert(block);
+      if set != sets[block] {
+        sets[block] = set;
+        changed = true;
+      }
+    }
+  }
+  for &b in &order {
+    for &a in &order {
+      if dom.dominates(a, b) != sets[b].contains(&a) {
+        return Err(format!(
+          "dominance of {} over {} is wrong",
+          cfg.name(a),
+          cfg.name(b)
+        ));
+      }
+    }
+    // the closest strict dominator has the most dominators itself
+    let idom = sets[b]
+      .iter()
+      .filter(|&&a| a != b)
+      .max_by_key(|&&a| sets[a].len())
+      .copied();
+    if dom.idom(b) != idom {
+      return Err(format!("wrong immediate dominator of {}", cfg.name(b)));
+    }
+  }
+  let frontiers = dom.frontiers(cfg);
+  for &a in &order {
+    let expected: HashSet<usize> = order
+      .iter()
+      .copied()
+      .filter(|&b| {
+        cfg.preds[b]
+          .iter()
+          .any(|p| all.contains(p) && sets[*p].contains(&a))
+          && (a == b || !sets[b].contains(&a))
+      })
+      .collect();
+    if frontiers[a].iter().copied().collect::<HashSet<_>>() != expected {
+      return Err(format!("wrong dominance frontier of {}", cfg.name(a)));
+    }
+  }
+  Ok(())
+}
+
+/// Compare the loops with one natural loop per back edge, merged by
+/// header, and each loop's parent with the smallest loop around it.
+fn check_loops<F: FlowGraph>(cfg: &Cfg<F>) -> Result<(), String> {
+  let dom = DomTree::new(cfg);
+  let forest = LoopForest::new(cfg, &dom);
+  let mut expected: HashMap<usize, (HashSet<usize>, HashSet<usize>)> = HashMap::new();
+  for block in cfg.reverse_postorder() {
+    for &header in cfg.succs[block]
+      .iter()
+      .filter(|&&h| dom.dominates(h, block))
+    {
+      let (latches, blocks) = expected.entry(header).or_default();
+      latches.insert(block);
+      blocks.insert(header);
+      let mut stack = vec![block];
+      while let Some(b) = stack.pop() {
+        if blocks.insert(b) {
+          stack.extend(cfg.preds[b].iter().filter(|&&p| dom.is_reachable(p)));
+        }
+      }
+    }
+  }
+  if forest.loops.len() != expected.len() {
+    return Err(format!(
+      "{} loops found instead of {}",
+      forest.loops.len(),
+      expected.len()
+    ));
+  }
+  for lp in &forest.loops {
+    let name = cfg.name(lp.header);
+    let Some((latches, blocks)) = expected.get(&lp.header) else {
+      return Err(format!("{name} is no loop header"));
+    };
+    if lp.latches.iter().copied().collect::<HashSet<_>>() != *latches {
+      return Err(format!("wrong latches of the loop at {name}"));
+    }
+    if lp.blocks.iter().copied().collect::<HashSet<_>>() != *blocks {
+      return Err(format!("wrong blocks in the loop at {name}"));
+    }
+    let parent = expected
+      .iter()
+      .filter(|(&h, (_, outer))| h != lp.header && outer.contains(&lp.header))
+      .min_by_key(|(_, (_, outer))| outer.len())
+      .map(|(&h, _)| h);
+    if lp.parent.map(|outer| forest.loops[outer].header) != parent {
+      return Err(format!("wrong loop around the loop at {name}"));
+    }
+    for &block in &lp.blocks {
+      let innermost = forest.innermost(block).map(|i| &forest.loops[i]);
+      if innermost.map_or(true, |inner| inner.blocks.len() > lp.blocks.len()) {
+        return Err(format!("wrong innermost loop of {}", cfg.name(block)));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Every block's facts satisfy the equations of the analysis.
+fn check_fixpoint<F: FlowGraph, A: Analysis<F>>(
+  cfg: &Cfg<F>,
+  analysis: &A,
+  name: &str,
+) -> Result<(), String>
+where
+  A::Fact: Debug,
+{
+  let solution = solve(cfg, analysis);
+  let top = analysis.top(cfg);
+  let forward = A::DIRECTION == Direction::Forward;
+  for block in 0..cfg.len() {
+    let (input, output, neighbours) = if forward {
+      (
+        &solution.before[block],
+        &solution.after[block],
+        &cfg.preds[block],
+      )
+    } else {
+      (
+        &solution.after[block],
+        &solution.before[block],
+        &cfg.succs[block],
+      )
+    };
+    let at_boundary = if forward {
+      block == 0
+    } else {
+      neighbours.is_empty()
+    };
+    let mut expected = if at_boundary {
+      analysis.boundary(cfg)
+    } else {
+      top.clone()
+    };
+    for &other in neighbours {
+      if forward {
+        analysis.meet_edge(cfg, other, block, &mut expected, &solution.after[other]);
+      } else {
+        analysis.meet_edge(cfg, block, other, &mut expected, &solution.before[other]);
+      }
+    }
+    if *input != expected {
+      return Err(format!(
+        "{name}: {} starts from {input:?} instead of {expected:?}",
+        cfg.name(block)
+      ));
+    }
+    if analysis.transfer(cfg, block, input) != *output {
+      return Err(format!(
+        "{name}: {} does not transfer {input:?} to {output:?}",
+        cfg.name(block)
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// What the analyses must find in SSA form: values are live at their uses
+/// and only where their definition dominates, definitions reach their
+/// uses, and pure expressions are available where every path computes
+/// them.
+pub fn check_ssa_function(func: &ir::Function) -> Result<(), String> {
+  let cfg = Cfg::new(func);
+  check_cfg(&cfg)?;
+  let dom = DomTree::new(&cfg);
+  let blocks = func.inst_blocks();
+  let def_block = |val: Value| val.as_inst().map(|id| cfg.index(blocks[&id]));
+
+  let liveness = Liveness::new(&cfg);
+  let live = solve(&cfg, &liveness);
+  let live_in = |block: usize| {
+    liveness
+      .vars
+      .items(&live.before[block])
+      .copied()
+      .collect::<Vec<_>>()
+  };
+  if let Some(val) = live_in(0)
+    .iter()
+    .find(|val| !matches!(val, Value::Param(_)))
+  {
+    return Err(format!("{val:?} is live into the entry"));
+  }
+  for block in (0..cfg.len()).filter(|&block| dom.is_reachable(block)) {
+    for val in live_in(block) {
+      if let Some(def) = def_block(val).filter(|&def| def == block || !dom.dominates(def, block)) {
+        return Err(format!(
+          "{val:?} of {} is live into {}, which it does not strictly dominate",
+          cfg.name(def),
+          cfg.name(block)
+        ));
+      }
+    }
+  }
+
+  // every use needs the value live: phis at the end of the predecessor,
+  // the rest at the start of the block unless defined there
+  for block in (0..cfg.len()).filter(|&block| dom.is_reachable(block)) {
+    for &inst in &cfg.insts[block] {
+      let uses = func
+        .phi_uses(inst)
+        .into_iter()
+        .map(|(pred, val)| (val, &live.after[cfg.index(pred)]))
+        .chain(
+          func
+            .uses(inst)
+            .into_iter()
+            .filter(|&val| def_block(val) != Some(block))
+            .map(|val| (val, &live.before[block])),
+        );
+      for (val, facts) in uses {
+        if !liveness.vars.contains(facts, &val) {
+          return Err(format!(
+            "{val:?} is not live at its use in {}",
+            cfg.name(block)
+          ));
+        }
+      }
+    }
+  }
+
+  let reaching_definitions = ReachingDefinitions::new(&cfg);
+  let reaching = solve(&cfg, &reaching_definitions);
+  for block in (0..cfg.len()).filter(|&block| dom.is_reachable(block)) {
+    for &inst in &cfg.insts[block] {
+      for val in func.uses(inst) {
+        let Some(def) = val.as_inst() else { continue };
+        if def_block(val) != Some(block)
+          && !reaching_definitions
+            .defs
+            .contains(&reaching.before[block], &(val, def))
+        {
+          return Err(format!(
+            "the definition of {val:?} does not reach its use in {}",
+            cfg.name(block)
+          ));
+        }
+      }
+    }
+  }
+
+  // a pure expression is available exactly where every path from the
+  // entry computes it
+  let available_expressions = AvailableExpressions::new(&cfg);
+  let available = solve(&cfg, &available_expressions);
+  let mut computed_in: HashMap<Expr, HashSet<usize>> = HashMap::new();
+  for block in 0..cfg.len() {
+    for &inst in &cfg.insts[block] {
+      match func.expression(inst) {
+        Some(Expr::Load(_)) | None => {}
+        Some(expr) => {
+          computed_in.entry(expr).or_default().insert(block);
+        }
+      }
+    }
+  }
+  for (expr, blocks) in computed_in {
+    // blocks some path reaches without computing `expr` first
+    let mut missed = vec![false; cfg.len()];
+    let mut stack = vec![0];
+    while let Some(block) = stack.pop() {
+      if blocks.contains(&block) {
+        continue;
+      }
+      for &succ in &cfg.succs[block] {
+        if !missed[succ] {
+          missed[succ] = true;
+          stack.push(succ);
+        }
+      }
+    }
+    missed[0] = true;
+    for block in (0..cfg.len()).filter(|&block| dom.is_reachable(block)) {
+      let is_available = available_expressions
+        .exprs
+        .contains(&available.before[block], &expr);
+      if is_available == missed[block] {
+        return Err(format!(
+          "{expr:?} is wrongly {} in {}",
+          if is_available {
+            "available"
+          } else {
+            "unavailable"
+          },
+          cfg.name(block)
+        ));
+      }
+    }
+  }
+  Ok(())
+}
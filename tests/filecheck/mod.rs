@@ -0,0 +1,257 @@
+//! A small FileCheck: `CHECK`, `CHECK-NEXT`, `CHECK-NOT` and `CHECK-LABEL`
+//! directives written in comments, `//` in SysY and `;` in our IR, matched
+//! against printed IR.
+//!
+//! Patterns are literal text, except for `{{regex}}`, `[[NAME:regex]]`
+//! which captures a variable, and `[[NAME]]` which uses one. Runs of spaces
+//! match any horizontal whitespace.
+use std::{collections::HashMap, fmt, ops::Range};
+
+use regex::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+  Check,
+  Next,
+  Not,
+  Label,
+}
+
+impl fmt::Display for Kind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Kind::Check => "CHECK",
+      Kind::Next => "CHECK-NEXT",
+      Kind::Not => "CHECK-NOT",
+      Kind::Label => "CHECK-LABEL",
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Directive {
+  pub kind: Kind,
+  pub pattern: String,
+  /// 1-based line in the file the directive is written in
+  pub line: usize,
+}
+
+/// The directives in the comments of `source` starting with `marker`, in
+/// order.
+pub fn parse_directives(source: &str, marker: &str) -> Vec<Directive> {
+  source
+    .lines()
+    .enumerate()
+    .filter_map(|(i, line)| {
+      let comment = line[line.find(marker)? + marker.len()..].trim_start();
+      let (kind, pattern) = [
+        (Kind::Next, "CHECK-NEXT:"),
+        (Kind::Not, "CHECK-NOT:"),
+        (Kind::Label, "CHECK-LABEL:"),
+        (Kind::Check, "CHECK:"),
+      ]
+      .into_iter()
+      .find_map(|(kind, prefix)| Some((kind, comment.strip_prefix(prefix)?)))?;
+      Some(Directive {
+        kind,
+        pattern: pattern.trim().to_string(),
+        line: i + 1,
+      })
+    })
+    .collect()
+}
+
+/// Turn a pattern into a regex, with the variables captured so far
+/// substituted; returns it with the names of the variables it defines.
+fn compile(pattern: &str, vars: &HashMap<String, String>) -> Result<(Regex, Vec<String>), String> {
+  // `^` and `$` match at line boundaries
+  let mut regex = "(?m)".to_string();
+  let mut defined = Vec::new();
+  let mut rest = pattern;
+  while !rest.is_empty() {
+    if let Some(after) = rest.strip_prefix("{{") {
+      let end = after.find("}}").ok_or("unterminated `{{`")?;
+      regex.push_str(&format!("(?:{})", &after[..end]));
+      rest = &after[end + 2..];
+    } else if let Some(after) = rest.strip_prefix("[[") {
+      let end = after.find("]]").ok_or("unterminated `[[`")?;
+      let var = &after[..end];
+      match var.split_once(':') {
+        Some((name, var_regex)) => {
+          regex.push_str(&format!("(?P<{name}>{var_regex})"));
+          defined.push(name.to_string());
+        }
+        None => {
+          let value = vars
+            .get(var)
+            .ok_or_else(|| format!("variable `{var}` is used before it is defined"))?;
+          regex.push_str(&regex::escape(value));
+        }
+      }
+      rest = &after[end + 2..];
+    } else {
+      let end = ["{{", "[["]
+        .iter()
+        .filter_map(|open| rest.find(open))
+        .min()
+        .unwrap_or(rest.len());
+      let mut in_space = false;
+      for c in rest[..end].chars() {
+        if c == ' ' || c == '\t' {
+          if !in_space {
+            regex.push_str("[ \\t]+");
+          }
+          in_space = true;
+        } else {
+          regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+          in_space = false;
+        }
+      }
+      rest = &rest[end..];
+    }
+  }
+  let regex = Regex::new(&regex).map_err(|e| e.to_string())?;
+  Ok((regex, defined))
+}
+
+struct Checker<'a> {
+  input: &'a str,
+  vars: HashMap<String, String>,
+}
+
+impl<'a> Checker<'a> {
+  /// The first match of `directive` in `range` of the input.
+  fn find(
+    &mut self,
+    directive: &Directive,
+    range: Range<usize>,
+  ) -> Result<Option<Range<usize>>, String> {
+    let (regex, defined) =
+      compile(&directive.pattern, &self.vars).map_err(|e| self.error(directive, &e))?;
+    // search the whole input, so `^` does not match in the middle of a line
+    let captures = match regex.captures_at(&self.input[..range.end], range.start) {
+      Some(captures) => captures,
+      None => return Ok(None),
+    };
+    for name in defined {
+      self
+        .vars
+        .insert(name.clone(), captures[name.as_str()].to_string());
+    }
+    let found = captures.get(0).unwrap();
+    Ok(Some(found.range()))
+  }
+
+  fn line_of(&self, pos: usize) -> usize {
+    self.input[..pos].matches('\n').count()
+  }
+
+  fn line_at(&self, pos: usize) -> &'a str {
+    let start = self.input[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let end = self.input[pos..]
+      .find('\n')
+      .map_or(self.input.len(), |i| pos + i);
+    &self.input[start..end]
+  }
+
+  fn error(&self, directive: &Directive, message: &str) -> String {
+    format!(
+      "line {}: {}: {message}\n  pattern: {}",
+      directive.line, directive.kind, directive.pattern
+    )
+  }
+
+  /// Check the directives between two labels against `range`.
+  fn check_block(
+    &mut self,
+    directives: &[Directive],
+    range: Range<usize>,
+    mut last_line: Option<usize>,
+  ) -> Result<(), String> {
+    let mut pos = range.start;
+    let mut nots: Vec<&Directive> = Vec::new();
+    for directive in directives {
+      if directive.kind == Kind::Not {
+        nots.push(directive);
+        continue;
+      }
+      let found = match self.find(directive, pos..range.end)? {
+        Some(found) => found,
+        None => {
+          return Err(format!(
+            "{}\n  scanning from: {}",
+            self.error(directive, "expected string not found"),
+            self.line_at(pos).trim()
+          ))
+        }
+      };
+      if directive.kind == Kind::Next {
+        let line = self.line_of(found.start);
+        if last_line.map(|last| last + 1) != Some(line) {
+          return Err(format!(
+            "{}\n  found on: {}",
+            self.error(directive, "match is not on the line after the previous one"),
+            self.line_at(found.start).trim()
+          ));
+        }
+      }
+      self.check_nots(&nots, pos..found.start)?;
+      nots.clear();
+      pos = found.end;
+      last_line = Some(self.line_of(found.end));
+    }
+    self.check_nots(&nots, pos..range.end)
+  }
+
+  fn check_nots(&mut self, nots: &[&Directive], range: Range<usize>) -> Result<(), String> {
+    for directive in nots {
+      if let Some(found) = self.find(directive, range.clone())? {
+        return Err(format!(
+          "{}\n  found: {}",
+          self.error(directive, "excluded string found"),
+          self.line_at(found.start).trim()
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Match `directives` against `input`, describing the first failure.
+///
+/// Labels are matched first and split the input into blocks, so the other
+/// directives can not match across them.
+pub fn check(directives: &[Directive], input: &str) -> Result<(), String> {
+  if directives.is_empty() {
+    return Err("no CHECK directives".to_string());
+  }
+  let mut checker = Checker {
+    input,
+    vars: HashMap::new(),
+  };
+  // (index of the label, where it matched)
+  let mut labels = Vec::new();
+  let mut pos = 0;
+  for (i, directive) in directives.iter().enumerate() {
+    if directive.kind != Kind::Label {
+      continue;
+    }
+    match checker.find(directive, pos..input.len())? {
+      Some(found) => {
+        pos = found.end;
+        labels.push((i, found));
+      }
+      None => return Err(checker.error(directive, "label not found")),
+    }
+  }
+  let mut start = 0;
+  let mut block_start = 0;
+  let mut last_line = None;
+  for (i, found) in labels {
+    checker.check_block(&directives[start..i], block_start..found.start, last_line)?;
+    start = i + 1;
+    block_start = found.end;
+    last_line = Some(checker.line_of(found.end));
+  }
+  checker.check_block(&directives[start..], block_start..input.len(), last_line)
+}
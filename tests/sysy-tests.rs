@@ -1,32 +1,114 @@
 extern crate libtest_mimic;
 
-use inkwell::context::Context;
+mod dataflow;
+mod filecheck;
+
+use inkwell::{context::Context, targets::FileType};
 use itertools::Itertools;
 use libtest_mimic::{run_tests, Arguments, Outcome, Test};
-use sysy::{codegen::Generator, parser::parse, util::get_bc_exe_path};
+use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme};
+use sysy::{
+  analysis::Cfg,
+  codegen::{target::TargetSpec, Generator},
+  difftest::{build_reference, difftest, run_executable},
+  fuzz::{check_codegen, generate_program, panic_message, GenConfig},
+  interp::{interpret, Limits},
+  ir,
+  jit::run_main,
+  opt,
+  parser::parse,
+  runtime::ProgramOutput,
+  util::OptLevel,
+};
 
 use std::{
   env,
   error::Error,
   ffi::OsStr,
-  fs,
-  io::Write,
+  fmt, fs,
   path::{Path, PathBuf},
-  process::{Command, Stdio},
-  sync::mpsc,
+  process::Command,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc, Mutex,
+  },
+};
+
+/// Seeds of the generated programs checked on every run.
+const GENERATED_SEEDS: std::ops::Range<u64> = 0..20;
+
+/// Bounds on running our IR, so a miscompiled loop fails instead of hanging.
+const SSA_LIMITS: Limits = Limits {
+  steps: 1_000_000_000,
+  call_depth: 500,
 };
 
+/// Targets every `tests/cross` case is compiled for.
+const CROSS_TARGETS: [&str; 2] = ["armv7-unknown-linux-gnueabihf", "riscv64-unknown-linux-gnu"];
+
+#[derive(Debug, Clone)]
+enum TestCase {
+  /// compile and run, then compare with the `.out` file
+  Functional(PathBuf),
+  /// compile to assembly for `triple`, then compare with `<stem>.<triple>.s`,
+  /// ignored until that file is blessed
+  CrossAsm { path: PathBuf, triple: &'static str },
+  /// generate a program from the seed; it must parse and interpret cleanly
+  Generated(u64),
+  /// compile a program with errors, then compare the rendered diagnostic
+  /// with the `.stderr` file
+  Diagnostic(PathBuf),
+  /// compile, then match the IR against the `CHECK` comments of the source
+  FileCheck(PathBuf),
+  /// lower to our SSA IR, check it survives printing and parsing, then run
+  /// it and compare with the `.out` file
+  SsaRoundTrip(PathBuf),
+  /// lower to our SSA IR, then match it against the `CHECK` comments
+  SsaFileCheck(PathBuf),
+  /// check dominators and dataflow analyses on the CFGs of both our SSA IR
+  /// and the LLVM IR
+  Dataflow(PathBuf),
+  /// run our passes at `-O2` on the SSA IR, then run it and compare with
+  /// the `.out` file
+  Optimized(PathBuf),
+  /// run the passes named by the `RUN` line of a `.sir` file, then match
+  /// the result against its `CHECK` comments
+  OptFileCheck(PathBuf),
+}
+
 fn main() {
-  let args = Arguments::from_args();
+  // `--bless` is ours, not libtest's
+  let (bless, args): (Vec<_>, Vec<_>) = env::args_os().partition(|arg| arg == "--bless");
+  if !bless.is_empty() {
+    env::set_var("SYSY_BLESS", "1");
+  }
+  let args = Arguments::from_iter(args);
 
   let tests = collect_tests();
-  run_tests(&args, tests, run_test).exit();
+  let changed = Arc::new(Mutex::new(Vec::new()));
+  let conclusion = {
+    let changed = changed.clone();
+    run_tests(&args, tests, move |test| run_test(test, &changed))
+  };
+  let changed = changed.lock().unwrap();
+  if !changed.is_empty() {
+    println!("expected outputs changed:");
+    for change in changed.iter().sorted() {
+      println!("  {change}");
+    }
+  }
+  conclusion.exit();
+}
+
+/// Set `SYSY_BLESS=1` or pass `--bless` to overwrite expected outputs with
+/// the actual ones.
+fn bless_enabled() -> bool {
+  env::var_os("SYSY_BLESS").is_some()
 }
 
-/// Creates one test for each `.rs` file in the current directory or
-/// sub-directories of the current directory.
-fn collect_tests() -> Vec<Test<PathBuf>> {
-  fn visit_dir(path: &Path, tests: &mut Vec<Test<PathBuf>>) -> Result<(), Box<dyn Error>> {
+/// Creates one test for each `.sy` file in the test directories.
+fn collect_tests() -> Vec<Test<TestCase>> {
+  fn visit_dir(path: &Path, paths: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
     for entry in fs::read_dir(path)? {
       let entry = entry?;
       let file_type = entry.file_type()?;
@@ -35,30 +117,26 @@ fn collect_tests() -> Vec<Test<PathBuf>> {
       let path = entry.path();
       if file_type.is_file() {
         if path.extension() == Some(OsStr::new("sy")) {
-          let name = path
-            .strip_prefix(env::current_dir()?)?
-            .display()
-            .to_string();
-
-          tests.push(Test {
-            name,
-            kind: "sysy".into(),
-            is_ignored: false,
-            is_bench: false,
-            data: path,
-          })
+          paths.push(path);
         }
       } else if file_type.is_dir() {
         // Handle directories
-        visit_dir(&path, tests)?;
+        visit_dir(&path, paths)?;
       }
     }
 
     Ok(())
   }
+  fn test_name(path: &Path) -> String {
+    path
+      .strip_prefix(env::current_dir().unwrap())
+      .unwrap()
+      .display()
+      .to_string()
+  }
 
-  // We recursively look for `.rs` files, starting from the current
-  // directory.
+  // We recursively look for `.sy` files, starting from the test
+  // directories.
   let mut tests = Vec::new();
   let current_dir = env::current_dir().expect("invalid working directory");
   let functional_dir = {
@@ -66,87 +144,182 @@ fn collect_tests() -> Vec<Test<PathBuf>> {
     tmp.extend(["compiler2022", "runtime", "functional"]);
     tmp
   };
-  let _performance_dir = {
+  let cross_dir = {
+    let mut tmp = current_dir.clone();
+    tmp.extend(["tests", "cross"]);
+    tmp
+  };
+  let cases_dir = {
+    let mut tmp = current_dir.clone();
+    tmp.extend(["tests", "cases"]);
+    tmp
+  };
+  let ir_dir = {
+    let mut tmp = current_dir.clone();
+    tmp.extend(["tests", "ir"]);
+    tmp
+  };
+  let ssa_dir = {
+    let mut tmp = current_dir.clone();
+    tmp.extend(["tests", "ssa"]);
+    tmp
+  };
+  let opt_dir = {
     let mut tmp = current_dir;
-    tmp.extend(["compiler2022", "runtime", "performance"]);
+    tmp.extend(["tests", "opt"]);
     tmp
   };
-  visit_dir(&functional_dir, &mut tests).expect("unexpected IO error");
-  // visit_dir(&performance_dir, &mut tests).expect("unexpected IO error");
+  let mut functional = Vec::new();
+  visit_dir(&functional_dir, &mut functional).expect("unexpected IO error");
+  for path in functional {
+    tests.push(Test {
+      name: format!("{} [ssa]", test_name(&path)),
+      kind: "ssa".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::SsaRoundTrip(path.clone()),
+    });
+    tests.push(Test {
+      name: format!("{} [dataflow]", test_name(&path)),
+      kind: "dataflow".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::Dataflow(path.clone()),
+    });
+    tests.push(Test {
+      name: format!("{} [opt]", test_name(&path)),
+      kind: "opt".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::Optimized(path.clone()),
+    });
+    tests.push(Test {
+      name: test_name(&path),
+      kind: "sysy".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::Functional(path),
+    });
+  }
+  let mut cross = Vec::new();
+  visit_dir(&cross_dir, &mut cross).expect("unexpected IO error");
+  for path in cross {
+    for triple in CROSS_TARGETS {
+      // waiting for a golden blessed from a build with every target
+      let unblessed = !bless_enabled() && !golden_path(&path, triple).exists();
+      tests.push(Test {
+        name: format!("{} [{triple}]", test_name(&path)),
+        kind: "cross".into(),
+        is_ignored: unblessed,
+        is_bench: false,
+        data: TestCase::CrossAsm {
+          path: path.clone(),
+          triple,
+        },
+      });
+    }
+  }
+
+  let mut cases = Vec::new();
+  visit_dir(&cases_dir, &mut cases).expect("unexpected IO error");
+  for path in cases {
+    tests.push(Test {
+      name: test_name(&path),
+      kind: "diagnostic".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::Diagnostic(path),
+    });
+  }
+  let mut ir = Vec::new();
+  visit_dir(&ir_dir, &mut ir).expect("unexpected IO error");
+  for path in ir {
+    tests.push(Test {
+      name: test_name(&path),
+      kind: "filecheck".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::FileCheck(path),
+    });
+  }
+  let mut ssa = Vec::new();
+  visit_dir(&ssa_dir, &mut ssa).expect("unexpected IO error");
+  for path in ssa {
+    tests.push(Test {
+      name: test_name(&path),
+      kind: "filecheck".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::SsaFileCheck(path),
+    });
+  }
+  // IR to optimize is written in our text format rather than in SysY
+  let mut opt = fs::read_dir(&opt_dir)
+    .expect("unexpected IO error")
+    .map(|entry| entry.expect("unexpected IO error").path())
+    .filter(|path| path.extension() == Some(OsStr::new("sir")))
+    .collect_vec();
+  opt.sort();
+  for path in opt {
+    tests.push(Test {
+      name: test_name(&path),
+      kind: "filecheck".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::OptFileCheck(path),
+    });
+  }
+
+  for seed in GENERATED_SEEDS {
+    tests.push(Test {
+      name: format!("generated seed {seed}"),
+      kind: "fuzz".into(),
+      is_ignored: false,
+      is_bench: false,
+      data: TestCase::Generated(seed),
+    });
+  }
 
   tests
 }
 
-/// Performs a couple of tidy tests.
-fn run_test(test: &Test<PathBuf>) -> Outcome {
+/// `changed` collects the `.out` files rewritten by `--bless`.
+fn run_test(test: &Test<TestCase>, changed: &Arc<Mutex<Vec<String>>>) -> Outcome {
   let (rx, tx) = mpsc::channel();
   let test = test.clone();
+  let changed = changed.clone();
   let handle = std::thread::Builder::new()
     .stack_size(16 * 1024 * 1024)
     .spawn(move || {
       let res = std::panic::catch_unwind(|| {
-        let path = &test.data;
-        let input_file = {
-          let mut tmp = path.clone();
-          tmp.set_extension("in");
-          if tmp.exists() {
-            let content = std::fs::read_to_string(tmp).unwrap();
-            Some(content)
-          } else {
-            None
+        let res = match &test.data {
+          TestCase::Functional(path) if bless_enabled() => {
+            let change = bless_functional_test(path)?;
+            changed.lock().unwrap().extend(change);
+            Ok(())
           }
+          TestCase::Functional(path) => run_functional_test(path),
+          TestCase::CrossAsm { path, triple } => run_cross_test(path, triple),
+          TestCase::Generated(seed) => run_generated_test(*seed),
+          TestCase::Diagnostic(path) => run_diagnostic_test(path),
+          TestCase::FileCheck(path) => run_filecheck_test(path),
+          TestCase::SsaRoundTrip(path) => run_ssa_round_trip_test(path),
+          TestCase::SsaFileCheck(path) => run_ssa_filecheck_test(path),
+          TestCase::Dataflow(path) => return run_dataflow_test(path),
+          TestCase::Optimized(path) => run_optimized_test(path),
+          TestCase::OptFileCheck(path) => run_opt_filecheck_test(path),
         };
-        let expected_out_path = {
-          let mut tmp = path.clone();
-          tmp.set_extension("out");
-          tmp
-        };
-        let expected_output = std::fs::read_to_string(expected_out_path).unwrap();
-        let input = std::fs::read_to_string(path).unwrap();
-        let tree = parse(&input).unwrap();
-        let ctx = Context::create();
-        let mut gen = Generator::new(&ctx, &input, &input);
-        gen.gen(&tree).unwrap();
-        let base = Path::new(&path);
-        let (bc_path, exe_path) = get_bc_exe_path(base);
-        gen.write(&bc_path);
-        Command::new("llvm-dis").arg(&bc_path).output().unwrap();
-        Command::new("clang")
-          .args([
-            &bc_path,
-            "./compiler2022/runtime/sylib.c",
-            &format!("-o{}", exe_path),
-          ])
-          .output()
-          .unwrap();
-        let run_cmd = {
-          let mut cmd = Command::new(exe_path);
-          if let Some(input) = input_file {
-            let mut proc = cmd
-              .stdin(Stdio::piped())
-              .stdout(Stdio::piped())
-              .spawn()
-              .unwrap();
-            let mut stdin = proc.stdin.take().unwrap();
-            stdin.write_all(input.as_bytes()).unwrap();
-            drop(stdin);
-            proc.wait_with_output().unwrap()
-          } else {
-            cmd.output().unwrap()
-          }
-        };
-        let stdout = run_cmd.stdout;
-        let stdout = String::from_utf8(stdout).unwrap();
-        let ret_code = run_cmd.status.code().unwrap();
-        let actual_output = format!("{}\n{}", stdout.trim(), ret_code);
-        let actual_output = actual_output.lines().map(|l| l.trim()).join("\n");
-        let expected_output = expected_output.lines().map(|l| l.trim()).join("\n");
-        assert_eq!(expected_output.trim(), actual_output.trim());
+        res.map(|()| Outcome::Passed)
       });
       let test_res = match res {
-        Ok(_) => Outcome::Passed,
-        Err(_) => Outcome::Failed {
-          msg: Some(format!("{} failed!", test.data.display())),
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(msg)) => Outcome::Failed { msg: Some(msg) },
+        Err(payload) => Outcome::Failed {
+          msg: Some(format!(
+            "{} panicked: {}",
+            test.name,
+            panic_message(&*payload)
+          )),
         },
       };
       rx.send(test_res).unwrap();
@@ -154,3 +327,542 @@ fn run_test(test: &Test<PathBuf>) -> Outcome {
   handle.unwrap().join().unwrap();
   tx.recv().unwrap()
 }
+
+/// A scratch directory for the artifacts of one test, so tests running in
+/// parallel never share files. It is removed on drop unless kept.
+struct TestDir {
+  path: PathBuf,
+  keep: bool,
+}
+
+impl TestDir {
+  fn new(path: &Path) -> Self {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!(
+      "sysy-tests-{}-{}-{}",
+      std::process::id(),
+      NEXT.fetch_add(1, Ordering::Relaxed),
+      path.file_stem().unwrap().to_string_lossy()
+    ));
+    fs::create_dir_all(&path).expect("can not create a test directory");
+    Self { path, keep: false }
+  }
+
+  fn join(&self, file: &str) -> PathBuf {
+    self.path.join(file)
+  }
+
+  /// Leave the artifacts behind for a look at what went wrong.
+  fn keep(&mut self) -> &Path {
+    self.keep = true;
+    &self.path
+  }
+}
+
+impl Drop for TestDir {
+  fn drop(&mut self) {
+    if !self.keep {
+      fs::remove_dir_all(&self.path).ok();
+    }
+  }
+}
+
+/// Why a functional test failed, by the stage it failed in.
+#[derive(Debug)]
+enum Failure {
+  /// parsing, codegen, or the module verifier
+  Compile(String),
+  Link(String),
+  /// killed by a signal, timed out, or a runtime error of the engine
+  Crash {
+    reason: String,
+    stderr: String,
+  },
+  WrongOutput {
+    difference: String,
+    stderr: String,
+  },
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Failure::Compile(msg) => write!(f, "compile failure: {msg}"),
+      Failure::Link(msg) => write!(f, "link failure: {msg}"),
+      Failure::Crash { reason, stderr } => {
+        write!(f, "runtime crash: {reason}\nstderr:\n{stderr}")
+      }
+      Failure::WrongOutput { difference, stderr } => {
+        write!(f, "wrong output: {difference}\nstderr:\n{stderr}")
+      }
+    }
+  }
+}
+
+/// Set `SYSY_ENGINE=jit` to run functional tests in-process instead of
+/// linking with clang, or `SYSY_ENGINE=interp` to check the interpreter.
+/// `SYSY_ENGINE=via-ssa` jits LLVM IR generated from our SSA IR.
+/// `SYSY_ENGINE=difftest` ignores `.out` files and compares against the
+/// source built as C by clang instead.
+fn engine() -> String {
+  env::var("SYSY_ENGINE").unwrap_or_else(|_| "clang".to_string())
+}
+
+fn run_functional_test(path: &Path) -> Result<(), String> {
+  let input = fs::read(path.with_extension("in")).unwrap_or_default();
+  if engine() == "difftest" {
+    return match difftest(path, &input, OptLevel::O0) {
+      Ok(None) => Ok(()),
+      Ok(Some(divergence)) => Err(divergence.to_string()),
+      Err(e) => Err(format!("{e:?}")),
+    };
+  }
+  let mut dir = TestDir::new(path);
+  let res = (|| -> Result<(), Failure> {
+    let content = fs::read_to_string(path).unwrap();
+    let expected = fs::read_to_string(path.with_extension("out")).unwrap();
+    let output = match engine().as_str() {
+      "clang" => execute_with_clang(path, &content, input, &dir)?,
+      "jit" => execute_with_jit(path, &content, input)?,
+      "interp" => execute_with_interp(path, &content, input)?,
+      "via-ssa" => execute_via_ssa(path, &content, input)?,
+      engine => panic!("unknown engine {engine}"),
+    };
+    check_output(&expected, &output)
+  })();
+  res.map_err(|failure| format!("{failure}\nartifacts kept in {}", dir.keep().display()))
+}
+
+/// Write the `.out` file from the source built as C by clang, the same
+/// reference `sysy difftest` trusts. Returns what changed, if anything.
+fn bless_functional_test(path: &Path) -> Result<Option<String>, String> {
+  let input = fs::read(path.with_extension("in")).unwrap_or_default();
+  let out_path = path.with_extension("out");
+  let dir = TestDir::new(path);
+  let exe_path = dir.join("reference.exe");
+  build_reference(path, &exe_path).map_err(|e| format!("{e:?}"))?;
+  let output = run_executable(&exe_path, &input).map_err(|e| format!("{e:?}"))?;
+  let mut expected = String::from_utf8_lossy(&output.stdout).to_string();
+  if !expected.is_empty() && !expected.ends_with('\n') {
+    expected.push('\n');
+  }
+  expected.push_str(&format!("{}\n", output.exit_code));
+  let change = match fs::read_to_string(&out_path) {
+    // keep the file as it is when only whitespace differs
+    Ok(old) => match check_output(&old, &output) {
+      Ok(()) => return Ok(None),
+      Err(Failure::WrongOutput { difference, .. }) => {
+        format!("{}: {difference}", out_path.display())
+      }
+      Err(failure) => unreachable!("{failure}"),
+    },
+    Err(_) => format!("{}: new", out_path.display()),
+  };
+  fs::write(&out_path, expected).map_err(|e| e.to_string())?;
+  Ok(Some(change))
+}
+
+/// Compare with the `.out` file: stdout, then the exit code on its own line,
+/// ignoring whitespace around lines.
+fn check_output(expected: &str, output: &ProgramOutput) -> Result<(), Failure> {
+  let actual = format!(
+    "{}\n{}",
+    String::from_utf8_lossy(&output.stdout).trim(),
+    output.exit_code
+  );
+  let normalize = |text: &str| text.lines().map(|l| l.trim()).join("\n").trim().to_string();
+  let (expected, actual) = (normalize(expected), normalize(&actual));
+  let difference = expected
+    .lines()
+    .zip_longest(actual.lines())
+    .map(|pair| (pair.clone().left(), pair.right()))
+    .enumerate()
+    .find(|(_, (expected, actual))| expected != actual);
+  match difference {
+    None => Ok(()),
+    Some((line, (expected, actual))) => Err(Failure::WrongOutput {
+      difference: format!(
+        "line {}: expected `{}`, got `{}`",
+        line + 1,
+        expected.unwrap_or("<eof>"),
+        actual.unwrap_or("<eof>")
+      ),
+      stderr: output.timing.clone(),
+    }),
+  }
+}
+
+/// Parse, generate and verify, catching codegen panics.
+fn compile<'ctx>(
+  ctx: &'ctx Context,
+  name: &'ctx str,
+  content: &'ctx str,
+) -> Result<Generator<'ctx>, Failure> {
+  check_codegen(ctx, name, content, OptLevel::O0)
+    .map_err(|finding| Failure::Compile(finding.to_string()))
+}
+
+fn execute_with_clang(
+  path: &Path,
+  content: &str,
+  input: Vec<u8>,
+  dir: &TestDir,
+) -> Result<ProgramOutput, Failure> {
+  let name = path.to_string_lossy();
+  let ctx = Context::create();
+  let gen = compile(&ctx, &name, content)?;
+  let bc_path = dir.join("main.bc");
+  let exe_path = dir.join("main.exe");
+  gen.write(bc_path.to_str().unwrap());
+  // keep the IR around for failures
+  gen.write_ir(&dir.join("main.ll")).ok();
+  let link = Command::new("clang")
+    .arg(&bc_path)
+    .arg("./compiler2022/runtime/sylib.c")
+    .arg("-o")
+    .arg(&exe_path)
+    .output()
+    .map_err(|e| Failure::Link(format!("can not run clang: {e}")))?;
+  if !link.status.success() {
+    return Err(Failure::Link(
+      String::from_utf8_lossy(&link.stderr).to_string(),
+    ));
+  }
+  let output = run_executable(&exe_path, &input).map_err(|e| Failure::Crash {
+    reason: e.to_string(),
+    stderr: String::new(),
+  })?;
+  // exit codes are 0..=255, a signal leaves none
+  if output.exit_code == -1 {
+    return Err(Failure::Crash {
+      reason: "killed by a signal".to_string(),
+      stderr: output.timing,
+    });
+  }
+  Ok(output)
+}
+
+fn execute_with_jit(path: &Path, content: &str, input: Vec<u8>) -> Result<ProgramOutput, Failure> {
+  let name = path.to_string_lossy();
+  let ctx = Context::create();
+  let gen = compile(&ctx, &name, content)?;
+  run_main(&gen, input).map_err(|e| Failure::Crash {
+    reason: format!("{e:?}"),
+    stderr: String::new(),
+  })
+}
+
+fn execute_with_interp(
+  path: &Path,
+  content: &str,
+  input: Vec<u8>,
+) -> Result<ProgramOutput, Failure> {
+  let tree = parse(content).map_err(|e| Failure::Compile(format!("{e:?}")))?;
+  let name = path.to_string_lossy();
+  interpret(&name, content, &tree, input).map_err(|e| Failure::Crash {
+    reason: format!("{e:?}"),
+    stderr: String::new(),
+  })
+}
+
+/// Lower to our SSA IR, then generate LLVM IR from it.
+fn execute_via_ssa(path: &Path, content: &str, input: Vec<u8>) -> Result<ProgramOutput, Failure> {
+  let name = path.to_string_lossy();
+  let module = lower_to_ssa(&name, content).map_err(Failure::Compile)?;
+  let ctx = Context::create();
+  let mut gen = Generator::new(&ctx, &name, content);
+  gen
+    .gen_ir(&module)
+    .map_err(|e| Failure::Compile(format!("{e:?}")))?;
+  run_main(&gen, input).map_err(|e| Failure::Crash {
+    reason: format!("{e:?}"),
+    stderr: String::new(),
+  })
+}
+
+/// Parse, lower and verify.
+fn lower_to_ssa(name: &str, content: &str) -> Result<ir::Module, String> {
+  let tree = parse(content).map_err(|e| format!("{e:?}"))?;
+  let module = ir::lower(name, content, &tree).map_err(|e| format!("{e:?}"))?;
+  ir::verify(&module).map_err(|e| format!("{e:?}\n{module}"))?;
+  Ok(module)
+}
+
+/// The printed IR must parse back to a valid module that prints the same,
+/// and running it must give the expected output.
+fn run_ssa_round_trip_test(path: &Path) -> Result<(), String> {
+  let content = fs::read_to_string(path).unwrap();
+  let module = lower_to_ssa(&path.to_string_lossy(), &content)?;
+  let text = module.to_string();
+  let parsed = ir::parse_module(&text).map_err(|e| format!("{e:?}\n{text}"))?;
+  ir::verify(&parsed).map_err(|e| format!("{e:?}\n{text}"))?;
+  let reprinted = parsed.to_string();
+  if let Some((line, (expected, actual))) = text
+    .lines()
+    .zip_longest(reprinted.lines())
+    .map(|pair| (pair.clone().left(), pair.right()))
+    .enumerate()
+    .find(|(_, (expected, actual))| expected != actual)
+  {
+    return Err(format!(
+      "reprinted IR differs at line {}:\n  printed:   {}\n  reprinted: {}",
+      line + 1,
+      expected.unwrap_or("<eof>"),
+      actual.unwrap_or("<eof>")
+    ));
+  }
+  let input = fs::read(path.with_extension("in")).unwrap_or_default();
+  let expected = fs::read_to_string(path.with_extension("out")).unwrap();
+  let output = ir::execute_with_limits(&module, input, SSA_LIMITS).map_err(|e| format!("{e:?}"))?;
+  check_output(&expected, &output).map_err(|failure| failure.to_string())
+}
+
+/// The SSA IR is printed straight from lowering.
+fn run_ssa_filecheck_test(path: &Path) -> Result<(), String> {
+  let content = fs::read_to_string(path).unwrap();
+  let text = lower_to_ssa(&path.to_string_lossy(), &content)?.to_string();
+  filecheck::check(&filecheck::parse_directives(&content, "//"), &text)
+    .map_err(|e| format!("{}:{e}\nIR:\n{text}", path.display()))
+}
+
+/// Our `-O2` passes must leave IR that still prints and parses back, and
+/// runs as the program did.
+fn run_optimized_test(path: &Path) -> Result<(), String> {
+  let content = fs::read_to_string(path).unwrap();
+  let mut module = lower_to_ssa(&path.to_string_lossy(), &content)?;
+  opt::PassManager::new(opt::Pipeline::for_level(OptLevel::O2))
+    .run(&mut module, &mut std::io::sink())
+    .map_err(|e| format!("{e:?}"))?;
+  let text = module.to_string();
+  ir::parse_module(&text)
+    .and_then(|parsed| ir::verify(&parsed))
+    .map_err(|e| format!("{e:?}\n{text}"))?;
+  let input = fs::read(path.with_extension("in")).unwrap_or_default();
+  let expected = fs::read_to_string(path.with_extension("out")).unwrap();
+  let output =
+    ir::execute_with_limits(&module, input, SSA_LIMITS).map_err(|e| format!("{e:?}\n{text}"))?;
+  check_output(&expected, &output).map_err(|failure| failure.to_string())
+}
+
+/// The `RUN` line lists the passes and may add `--print-after=<passes>`,
+/// `--remarks` and `-finline-limit=<N>`. Dumps and remarks come before the
+/// final module in what is checked.
+fn run_opt_filecheck_test(path: &Path) -> Result<(), String> {
+  let content = fs::read_to_string(path).unwrap();
+  let run = content
+    .lines()
+    .find_map(|line| line.strip_prefix("; RUN:"))
+    .ok_or("no `; RUN:` line")?;
+  let mut pipeline = opt::Pipeline::default();
+  let mut print_after = Vec::new();
+  let mut inline_limit = opt::DEFAULT_INLINE_LIMIT;
+  let mut remarks = false;
+  for arg in run.split_whitespace() {
+    if let Some(passes) = arg.strip_prefix("--print-after=") {
+      print_after.extend(
+        opt::Pipeline::parse(passes)
+          .map_err(|e| format!("{e:?}"))?
+          .passes,
+      );
+    } else if let Some(limit) = arg.strip_prefix("-finline-limit=") {
+      inline_limit = limit.parse().map_err(|e| format!("{e}"))?;
+    } else if arg == "--remarks" {
+      remarks = true;
+    } else {
+      pipeline = opt::Pipeline::parse(arg).map_err(|e| format!("{e:?}"))?;
+    }
+  }
+  let mut module = ir::parse_module(&content).map_err(|e| format!("{e:?}"))?;
+  ir::verify(&module).map_err(|e| format!("{e:?}"))?;
+  let mut pm = opt::PassManager::new(pipeline);
+  pm.set_print_after(print_after);
+  pm.set_inline_limit(inline_limit);
+  pm.set_remarks(remarks);
+  let mut dump = Vec::new();
+  pm.run(&mut module, &mut dump)
+    .map_err(|e| format!("{e:?}"))?;
+  let text = String::from_utf8(dump).unwrap() + &module.to_string();
+  filecheck::check(&filecheck::parse_directives(&content, ";"), &text)
+    .map_err(|e| format!("{}:{e}\nIR:\n{text}", path.display()))
+}
+
+/// Analyses must agree with their definitions on every function, and
+/// with SSA on our IR. Programs our LLVM codegen can not compile yet, such
+/// as ones with array parameters, are ignored once our IR passes.
+fn run_dataflow_test(path: &Path) -> Result<Outcome, String> {
+  let content = fs::read_to_string(path).unwrap();
+  let name = path.to_string_lossy();
+  let module = lower_to_ssa(&name, &content)?;
+  for func in module
+    .functions
+    .iter()
+    .filter(|func| !func.is_declaration())
+  {
+    dataflow::check_ssa_function(func).map_err(|e| format!("{} in our IR: {e}", func.name))?;
+  }
+  let ctx = Context::create();
+  let Ok(gen) = compile(&ctx, &name, &content) else {
+    return Ok(Outcome::Ignored);
+  };
+  for func in gen
+    .module()
+    .get_functions()
+    .filter(|func| func.count_basic_blocks() > 0)
+  {
+    dataflow::check_cfg(&Cfg::new(&func))
+      .map_err(|e| format!("{} in LLVM IR: {e}", func.get_name().to_string_lossy()))?;
+  }
+  Ok(Outcome::Passed)
+}
+
+/// Drop comments and toolchain identification so goldens only hold code.
+fn normalize_asm(asm: &str, triple: &str) -> String {
+  let comment = if triple.starts_with("arm") { '@' } else { '#' };
+  asm
+    .lines()
+    .map(|l| {
+      let code = l
+        .char_indices()
+        .tuple_windows()
+        .find(|((_, a), (_, b))| a.is_whitespace() && *b == comment)
+        .map_or(l, |((i, _), _)| &l[..i]);
+      code.trim_end()
+    })
+    .filter(|l| {
+      let l = l.trim_start();
+      !(l.is_empty() || l.starts_with(comment) || l.starts_with(".ident") || l.starts_with(".file"))
+    })
+    .join("\n")
+}
+
+/// `<stem>.<triple>.s`, the assembly expected for `triple`.
+fn golden_path(path: &Path, triple: &str) -> PathBuf {
+  path.with_extension(format!("{triple}.s"))
+}
+
+fn run_cross_test(path: &Path, triple: &str) -> Result<(), String> {
+  let golden_path = golden_path(path, triple);
+  let input = fs::read_to_string(path).unwrap();
+  let name = path.to_str().unwrap();
+  let tree = parse(&input).unwrap();
+  let ctx = Context::create();
+  let spec = TargetSpec {
+    triple: Some(triple.to_string()),
+    cpu: None,
+    features: None,
+  };
+  let mut gen = Generator::new_with_target(&ctx, name, &input, spec).unwrap();
+  gen.gen(&tree).unwrap();
+  let dir = TestDir::new(path);
+  let asm_path = dir.join(&format!("main.{triple}.s"));
+  gen
+    .write_machine_code(OptLevel::O2, FileType::Assembly, &asm_path)
+    .unwrap();
+  let actual = normalize_asm(&fs::read_to_string(&asm_path).unwrap(), triple);
+  if bless_enabled() {
+    fs::write(&golden_path, actual + "\n").unwrap();
+    return Ok(());
+  }
+  let expected = fs::read_to_string(&golden_path).map_err(|e| {
+    format!(
+      "can not read {}: {e}, run with SYSY_BLESS=1 to create it",
+      golden_path.display()
+    )
+  })?;
+  let expected = normalize_asm(&expected, triple);
+  match expected
+    .lines()
+    .zip_longest(actual.lines())
+    .map(|pair| (pair.clone().left(), pair.right()))
+    .enumerate()
+    .find(|(_, (expected, actual))| expected != actual)
+  {
+    None => Ok(()),
+    Some((line, (expected, actual))) => Err(format!(
+      "{} differs at line {}:\n  expected: {}\n  actual:   {}",
+      golden_path.display(),
+      line + 1,
+      expected.unwrap_or("<eof>"),
+      actual.unwrap_or("<eof>")
+    )),
+  }
+}
+
+/// Checks the program generator itself: its programs must be valid, and
+/// free of the runtime errors the interpreter detects.
+fn run_generated_test(seed: u64) -> Result<(), String> {
+  let program = generate_program(seed, &GenConfig::default());
+  let tree = parse(&program).map_err(|e| e.to_string())?;
+  if tree.root_node().has_error() {
+    return Err(format!(
+      "seed {seed} generated an invalid program:\n{program}"
+    ));
+  }
+  interpret("generated.sy", &program, &tree, Vec::new())
+    .map_err(|e| format!("seed {seed}: {e}\n{program}"))?;
+  Ok(())
+}
+
+/// Render without color or terminal-dependent width, as the snapshots hold.
+fn render_diagnostic(diagnostic: &dyn Diagnostic) -> String {
+  let mut out = String::new();
+  GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+    .with_width(80)
+    .render_report(&mut out, diagnostic)
+    .unwrap();
+  out
+}
+
+/// A case without a `.stderr` snapshot must compile cleanly.
+fn run_diagnostic_test(path: &Path) -> Result<(), String> {
+  let snapshot_path = path.with_extension("stderr");
+  // name the source relative to the repository, so snapshots are portable
+  let name = path
+    .strip_prefix(env::current_dir().unwrap())
+    .unwrap_or(path)
+    .to_string_lossy()
+    .to_string();
+  let content = fs::read_to_string(path).unwrap();
+  let res = parse(&content).and_then(|tree| {
+    let ctx = Context::create();
+    let mut gen = Generator::new(&ctx, &name, &content);
+    gen.gen(&tree)
+  });
+  let actual = res.err().map(|e| render_diagnostic(&e));
+  if bless_enabled() {
+    match actual {
+      Some(actual) => fs::write(&snapshot_path, actual).unwrap(),
+      None => fs::remove_file(&snapshot_path).unwrap_or(()),
+    }
+    return Ok(());
+  }
+  let expected = fs::read_to_string(&snapshot_path).ok();
+  match (expected, actual) {
+    (None, None) => Ok(()),
+    (Some(expected), Some(actual)) if expected == actual => Ok(()),
+    (None, Some(actual)) => Err(format!(
+      "no snapshot {}, run with --bless to create it from:\n{actual}",
+      snapshot_path.display()
+    )),
+    (Some(_), None) => Err(format!(
+      "compiled without errors, but {} expects a diagnostic",
+      snapshot_path.display()
+    )),
+    (Some(expected), Some(actual)) => Err(format!(
+      "diagnostic differs from {}, run with --bless if this is intended\nexpected:\n{expected}\nactual:\n{actual}",
+      snapshot_path.display()
+    )),
+  }
+}
+
+/// The IR is printed straight from codegen, before any optimization.
+fn run_filecheck_test(path: &Path) -> Result<(), String> {
+  let content = fs::read_to_string(path).unwrap();
+  let name = path.to_string_lossy();
+  let ctx = Context::create();
+  let gen =
+    check_codegen(&ctx, &name, &content, OptLevel::O0).map_err(|finding| finding.to_string())?;
+  let ir = gen.module().print_to_string().to_string();
+  filecheck::check(&filecheck::parse_directives(&content, "//"), &ir)
+    .map_err(|e| format!("{}:{e}\nIR:\n{ir}", path.display()))
+}